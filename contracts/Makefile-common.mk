@@ -2,13 +2,17 @@ build:
 	@RUSTFLAGS='-C link-arg=-s' cargo build --target wasm32-unknown-unknown --release
 	@cp ../target/wasm32-unknown-unknown/release/*.wasm ../res/
 
+build-testnet:
+	@RUSTFLAGS='-C link-arg=-s' cargo build --target wasm32-unknown-unknown --release --features testnet
+	@cp ../target/wasm32-unknown-unknown/release/*.wasm ../res/
+
 build-quick:
 	@RUSTFLAGS='-C link-arg=-s' cargo build --target wasm32-unknown-unknown
 	@cp ../target/wasm32-unknown-unknown/release/*.wasm ../res/
 
 
 build-all:
-	@RUSTFLAGS='-C link-arg=-s' cargo build --all --target wasm32-unknown-unknown --release
+	@RUSTFLAGS='-C link-arg=-s' cargo build --workspace --exclude xtask --target wasm32-unknown-unknown --release
 	@cp ../target/wasm32-unknown-unknown/release/*.wasm ../res/
 
 
@@ -16,5 +20,8 @@ test:
 # to test specific test run: cargo test <test name>
 	@cargo test
 
+test-testnet:
+	@cargo test --features testnet
+
 test-unit-debug:
 	@RUST_BACKTRACE=1 cargo test --lib  -- --nocapture
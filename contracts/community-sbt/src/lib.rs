@@ -1,6 +1,6 @@
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::{LazyOption, UnorderedSet};
-use near_sdk::{env, near_bindgen, require, AccountId, PanicOnDefault};
+use near_sdk::collections::{LazyOption, LookupMap, UnorderedMap, UnorderedSet};
+use near_sdk::{env, near_bindgen, require, AccountId, Gas, PanicOnDefault, Promise};
 
 use cost::{MINT_COST, MINT_GAS};
 use sbt::*;
@@ -23,6 +23,14 @@ pub struct Contract {
     pub metadata: LazyOption<ContractMetadata>,
     /// time to live in ms. Used for token expiry
     pub ttl: u64,
+
+    /// pending membership applications, see `apply`.
+    pub applications: UnorderedMap<u64, Application>,
+    pub next_application_id: u64,
+    /// if true, deposit of a rejected application is not refunded.
+    pub forfeit_rejected_deposit: bool,
+    /// metadata of token classes, see `admin_set_class_metadata`.
+    pub class_metadata: LookupMap<ClassId, ClassMetadata>,
 }
 
 // Implement the contract structure
@@ -49,6 +57,10 @@ impl Contract {
 
             metadata: LazyOption::new(StorageKey::ContractMetadata, Some(&metadata)),
             ttl,
+            applications: UnorderedMap::new(StorageKey::Applications),
+            next_application_id: 1,
+            forfeit_rejected_deposit: false,
+            class_metadata: LookupMap::new(StorageKey::ClassMetadata),
         }
     }
 
@@ -62,10 +74,144 @@ impl Contract {
         self.admins.contains(&addr)
     }
 
+    pub fn application(&self, id: u64) -> Option<Application> {
+        self.applications.get(&id)
+    }
+
+    /// Returns pending applications with their IDs. `from_index` defaults to 0,
+    /// `limit` defaults to 100.
+    pub fn applications(
+        &self,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> Vec<(u64, Application)> {
+        let from_index = from_index.unwrap_or(0);
+        let limit = limit.unwrap_or(100);
+        let keys = self.applications.keys_as_vector();
+        let values = self.applications.values_as_vector();
+        (from_index..std::cmp::min(from_index + limit, keys.len()))
+            .map(|i| (keys.get(i).unwrap(), values.get(i).unwrap()))
+            .collect()
+    }
+
+    /// Returns metadata of the given token class, including the app specific `attributes`.
+    pub fn class_metadata(&self, class: ClassId) -> Option<ClassMetadata> {
+        self.class_metadata.get(&class)
+    }
+
+    /// NEP-330 source metadata of the deployed contract.
+    pub fn contract_source_metadata(&self) -> ContractSourceMetadata {
+        contract_source_metadata!(Standard::new(STANDARD_NAME, SPEC_VERSION))
+    }
+
+    /**********
+     * TRANSACTIONS
+     **********/
+
+    /// Applies for a community membership SBT of the given `class` (0 means the default
+    /// class, 1). Returns the application ID.
+    /// Requires attaching at least 0.01 NEAR, which pays the SBT mint when the application
+    /// is approved. The rest is refunded on approval. A rejected application deposit is
+    /// refunded, unless the `forfeit_rejected_deposit` policy is set.
+    #[payable]
+    pub fn apply(&mut self, class: ClassId, memo: Option<String>) -> u64 {
+        let deposit = env::attached_deposit();
+        require!(
+            deposit >= MINT_COST,
+            "Requires attached deposit of at least 0.01 NEAR"
+        );
+        let applicant = env::predecessor_account_id();
+        self.assert_receiver(&applicant);
+        let id = self.next_application_id;
+        self.next_application_id += 1;
+        self.applications.insert(
+            &id,
+            &Application {
+                applicant,
+                class: if class == 0 { 1 } else { class },
+                memo,
+                deposit: deposit.into(),
+                submitted_at: env::block_timestamp_ms(),
+            },
+        );
+        id
+    }
+
     /**********
      * ADMIN
      **********/
 
+    /// Approves the applications and mints SBTs (with the default TTL) for the applicants in
+    /// a single registry call. Applicants are refunded the deposit above the mint cost.
+    /// Unknown application IDs are ignored.
+    pub fn approve_applications(&mut self, ids: Vec<u64>) {
+        self.assert_issuer();
+        let now_ms = env::block_timestamp_ms();
+        let mut token_spec = Vec::new();
+        for id in ids {
+            let a = match self.applications.remove(&id) {
+                None => continue,
+                Some(a) => a,
+            };
+            let refund = a.deposit.0 - MINT_COST;
+            if refund > 0 {
+                Promise::new(a.applicant.clone()).transfer(refund);
+            }
+            token_spec.push((
+                a.applicant,
+                vec![TokenMetadata {
+                    class: a.class,
+                    issued_at: Some(now_ms),
+                    expires_at: Some(now_ms + self.ttl),
+                    reference: None,
+                    reference_hash: None,
+                    attributes_root: None,
+                }],
+            ));
+        }
+        if token_spec.is_empty() {
+            return;
+        }
+        let num = token_spec.len() as u64;
+        self.registry_mint(token_spec, MINT_COST * num as u128, Gas(MINT_GAS.0 * num));
+    }
+
+    /// Rejects the applications. Deposits are refunded to the applicants, unless the
+    /// `forfeit_rejected_deposit` policy is set. Unknown application IDs are ignored.
+    pub fn reject_applications(&mut self, ids: Vec<u64>) {
+        self.assert_issuer();
+        for id in ids {
+            if let Some(a) = self.applications.remove(&id) {
+                if !self.forfeit_rejected_deposit {
+                    Promise::new(a.applicant).transfer(a.deposit.0);
+                }
+            }
+        }
+    }
+
+    /// Sets the policy for rejected applications: if `forfeit` is true, deposit of rejected
+    /// applications is not refunded.
+    pub fn admin_set_forfeit_rejected_deposit(&mut self, forfeit: bool) {
+        self.assert_issuer();
+        self.forfeit_rejected_deposit = forfeit;
+    }
+
+    /// Sets (or removes if `metadata` is None) metadata of the token `class`.
+    /// Panics if the metadata is not valid, eg attributes exceed the size limits.
+    pub fn admin_set_class_metadata(&mut self, class: ClassId, metadata: Option<ClassMetadata>) {
+        self.assert_issuer();
+        require!(class > 0, "class must be bigger than 0");
+        match metadata {
+            Some(m) => {
+                m.assert_valid();
+                self.class_metadata.insert(&class, &m);
+            }
+            None => {
+                self.class_metadata.remove(&class);
+            }
+        }
+    }
+
     /// Mints a new SBT for the given receiver.
     /// If `metadata.expires_at` is None then we set it to max: ` now+self.ttl`.
     /// Panics if `metadata.expires_at > now+self.ttl`.
@@ -78,15 +224,11 @@ impl Contract {
     ) {
         require!(
             env::attached_deposit() == MINT_COST,
-            "Requires attached deposit of exactly 0.007 NEAR"
+            "Requires attached deposit of exactly 0.01 NEAR"
         );
 
         self.assert_issuer();
-        if str::ends_with(env::current_account_id().as_ref(), "near") {
-            require!(str::ends_with(receiver.as_ref(), "near"))
-        } else {
-            require!(str::ends_with(receiver.as_ref(), "testnet"))
-        }
+        self.assert_receiver(&receiver);
 
         let now_ms = env::block_timestamp_ms();
         let default_expires_at = now_ms + self.ttl;
@@ -106,10 +248,8 @@ impl Contract {
         if let Some(memo) = memo {
             env::log_str(&format!("SBT mint memo: {}", memo));
         }
-        ext_registry::ext(self.registry.clone())
-            .with_attached_deposit(MINT_COST) // no extra cost needed
-            .with_static_gas(MINT_GAS)
-            .sbt_mint(vec![(receiver, vec![metadata])]);
+        // no extra cost needed
+        self.registry_mint(vec![(receiver, vec![metadata])], MINT_COST, MINT_GAS);
     }
 
     /// sbt_renew will update the expire time of provided tokens.
@@ -123,7 +263,7 @@ impl Contract {
         );
         require!(!tokens.is_empty(), "tokens must be a non empty list");
         let expires_at_ms = env::block_timestamp_ms() + ttl * 1000;
-        ext_registry::ext(self.registry.clone()).sbt_renew(tokens, expires_at_ms);
+        self.registry_renew(tokens, expires_at_ms);
 
         if let Some(memo) = memo {
             env::log_str(&format!("SBT renew memo: {}", memo));
@@ -172,6 +312,14 @@ impl Contract {
      * INTERNAL
      **********/
 
+    fn assert_receiver(&self, receiver: &AccountId) {
+        if str::ends_with(env::current_account_id().as_ref(), "near") {
+            require!(str::ends_with(receiver.as_ref(), "near"))
+        } else {
+            require!(str::ends_with(receiver.as_ref(), "testnet"))
+        }
+    }
+
     fn assert_issuer(&self) {
         require!(
             self.admins.contains(&env::predecessor_account_id()),
@@ -180,6 +328,12 @@ impl Contract {
     }
 }
 
+impl SBTIssuer for Contract {
+    fn registry(&self) -> AccountId {
+        self.registry.clone()
+    }
+}
+
 #[near_bindgen]
 impl SBTContract for Contract {
     fn sbt_metadata(&self) -> ContractMetadata {
@@ -190,10 +344,11 @@ impl SBTContract for Contract {
 #[cfg(test)]
 mod tests {
     use cost::MILI_NEAR;
+    use cost::MINT_COST;
     use near_sdk::{test_utils::VMContextBuilder, testing_env, AccountId, Balance, VMContext};
-    use sbt::ContractMetadata;
+    use sbt::{ClassMetadata, ContractMetadata};
 
-    use crate::Contract;
+    use crate::{Application, Contract};
 
     const START: u64 = 10;
     const MINT_DEPOSIT: Balance = 6 * MILI_NEAR;
@@ -310,4 +465,83 @@ mod tests {
         let (_, mut ctr) = setup(&alice(), MINT_DEPOSIT);
         ctr.admin_change_ttl(START + 1);
     }
+
+    #[test]
+    fn applications() {
+        let (mut ctx, mut ctr) = setup(&alice(), MINT_COST + MILI_NEAR);
+        ctx.current_account_id = AccountId::new_unchecked("community.near".to_string());
+        testing_env!(ctx.clone());
+        assert_eq!(ctr.apply(0, Some("hello".to_owned())), 1);
+        ctx.predecessor_account_id = bob();
+        testing_env!(ctx.clone());
+        assert_eq!(ctr.apply(2, None), 2);
+        assert_eq!(
+            ctr.application(1),
+            Some(Application {
+                applicant: alice(),
+                class: 1,
+                memo: Some("hello".to_owned()),
+                deposit: (MINT_COST + MILI_NEAR).into(),
+                submitted_at: 0,
+            })
+        );
+        assert_eq!(ctr.applications(None, None).len(), 2);
+        assert_eq!(ctr.applications(Some(1), Some(5))[0].0, 2);
+
+        ctx.predecessor_account_id = admin();
+        testing_env!(ctx.clone());
+        ctr.approve_applications(vec![1, 10]);
+        ctr.reject_applications(vec![2]);
+        assert_eq!(ctr.application(1), None);
+        assert_eq!(ctr.applications(None, None), vec![]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Requires attached deposit of at least 0.01 NEAR")]
+    fn apply_not_enough_deposit() {
+        let (_, mut ctr) = setup(&alice(), MINT_DEPOSIT);
+        ctr.apply(1, None);
+    }
+
+    fn class_metadata_fixture() -> ClassMetadata {
+        ClassMetadata {
+            name: "member".to_string(),
+            symbol: None,
+            icon: None,
+            reference: None,
+            reference_hash: None,
+            attributes: [("vote_weight", "2"), ("badge_color", "#f00")]
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn class_metadata() {
+        let (_, mut ctr) = setup(&admin(), 0);
+        assert_eq!(ctr.class_metadata(1), None);
+        ctr.admin_set_class_metadata(1, Some(class_metadata_fixture()));
+        let m = ctr.class_metadata(1).unwrap();
+        assert_eq!(m.attributes["vote_weight"], "2");
+        assert_eq!(ctr.class_metadata(2), None);
+        ctr.admin_set_class_metadata(1, None);
+        assert_eq!(ctr.class_metadata(1), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "attribute value can have at most 128 bytes")]
+    fn class_metadata_too_long_attribute() {
+        let (_, mut ctr) = setup(&admin(), 0);
+        let mut m = class_metadata_fixture();
+        m.attributes.insert("note".to_string(), "x".repeat(129));
+        ctr.admin_set_class_metadata(1, Some(m));
+    }
+
+    #[test]
+    #[should_panic(expected = "must be issuer")]
+    fn approve_applications_non_issuer() {
+        let (_, mut ctr) = setup(&alice(), MINT_COST);
+        ctr.approve_applications(vec![1]);
+    }
 }
@@ -1,9 +1,28 @@
-use near_sdk::borsh::{self, BorshSerialize};
-use near_sdk::BorshStorageKey;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::Serialize;
+use near_sdk::{AccountId, BorshStorageKey};
+use sbt::ClassId;
 
 /// Helper structure for keys of the persistent collections.
 #[derive(BorshSerialize, BorshStorageKey)]
 pub enum StorageKey {
     Admins,
     ContractMetadata,
+    Applications,
+    ClassMetadata,
+}
+
+/// Membership application submitted with `apply`.
+#[derive(BorshSerialize, BorshDeserialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(test, derive(Debug, PartialEq, Clone))]
+pub struct Application {
+    pub applicant: AccountId,
+    pub class: ClassId,
+    pub memo: Option<String>,
+    /// attached deposit, used to pay the SBT mint on approval
+    pub deposit: U128,
+    /// unix timestamp in miliseconds
+    pub submitted_at: u64,
 }
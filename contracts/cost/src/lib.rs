@@ -3,10 +3,21 @@ use near_sdk::{Balance, Gas};
 pub const MICRO_NEAR: Balance = 1_000_000_000_000_000_000;
 pub const MILI_NEAR: Balance = 1000 * MICRO_NEAR;
 
-pub const MINT_COST: Balance = 7 * MILI_NEAR; // 0.007 NEAR
+pub const MINT_COST: Balance = 10 * MILI_NEAR; // 0.01 NEAR
 pub const MINT_GAS: Gas = Gas(7 * Gas::ONE_TERA.0);
 pub const BAN_COST: Balance = 5 * MILI_NEAR;
 pub const BLACKLIST_GAS: Gas = Gas(6 * Gas::ONE_TERA.0);
+/// Min deposit attached to `sbt_soul_transfer` to let anyone continue the transfer. The bond
+/// is the reward for the account completing the transfer.
+pub const SOUL_TRANSFER_BOND: Balance = 10 * MILI_NEAR;
+/// Min bond an issuer must lock (`issuer_lock_bond`) before it can be registered. The bond
+/// can be slashed by the authority on proven misbehavior.
+pub const ISSUER_BOND: Balance = 1000 * MILI_NEAR; // 1 NEAR
+/// Deposit covering the registry storage of a `mark_used` marker.
+pub const MARK_USED_COST: Balance = MILI_NEAR;
+/// Deposit covering the registry storage of a single stamp (`sbt_mint_stamps`), for
+/// recipient accounts of at most 64 characters.
+pub const STAMP_COST: Balance = 2 * MILI_NEAR;
 
 /// calculates amount of gas required by registry for `sbt_renew` call.
 #[inline]
@@ -14,3 +25,7 @@ pub fn renew_gas(num_tokens: usize) -> Gas {
     // 2tera + num_tokens * 0.2tera * num_tokens
     Gas(2 * Gas::ONE_TERA.0 + num_tokens as u64 * 200_000_000_000)
 }
+
+/// Minimum gas required by the registry `is_human_call` for itself and the refund callback.
+/// The rest of the attached gas is forwarded to the receiver call.
+pub const IS_HUMAN_GAS: Gas = Gas(12 * Gas::ONE_TERA.0);
@@ -55,6 +55,11 @@ impl Contract {
 
     // token queries should go through the registry contract
 
+    /// NEP-330 source metadata of the deployed contract.
+    pub fn contract_source_metadata(&self) -> ContractSourceMetadata {
+        contract_source_metadata!(Standard::new(STANDARD_NAME, SPEC_VERSION))
+    }
+
     /**********
      * ADMIN
      **********/
@@ -74,16 +79,14 @@ impl Contract {
             expires_at: Some(now_ms + self.sbt_ttl_ms),
             reference: None,
             reference_hash: None,
+            attributes_root: None,
         };
 
         if let Some(memo) = memo {
             env::log_str(&format!("SBT mint memo: {}", memo));
         }
 
-        ext_registry::ext(self.registry.clone())
-            .with_attached_deposit(MINT_COST)
-            .with_static_gas(MINT_GAS)
-            .sbt_mint(vec![(receiver, vec![metadata])])
+        self.registry_mint(vec![(receiver, vec![metadata])], MINT_COST, MINT_GAS)
             .then(
                 Self::ext(env::current_account_id())
                     .with_static_gas(Gas::ONE_TERA * 3)
@@ -123,3 +126,9 @@ impl Contract {
         );
     }
 }
+
+impl SBTIssuer for Contract {
+    fn registry(&self) -> AccountId {
+        self.registry.clone()
+    }
+}
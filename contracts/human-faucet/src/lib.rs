@@ -0,0 +1,340 @@
+//! Faucet dripping (testnet) NEAR only to verified humans, with a per-account cooldown.
+//! It's the reference integration of the registry human checks:
+//! * `drip` is called through the registry `is_human_call`: the registry verifies the caller
+//!   and forwards the call, so the faucet only has to check that the call comes from the
+//!   registry.
+//! * `request_drip` calls the registry `is_human` view and drips in the callback.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::LookupMap;
+use near_sdk::json_types::U128;
+use near_sdk::serde::Serialize;
+use near_sdk::serde_json::{self, Value};
+use near_sdk::{
+    env, ext_contract, near_bindgen, require, AccountId, Balance, Gas, PanicOnDefault, Promise,
+    PromiseResult,
+};
+
+pub const IS_HUMAN_GAS: Gas = Gas(5 * Gas::ONE_TERA.0);
+pub const ON_IS_HUMAN_GAS: Gas = Gas(5 * Gas::ONE_TERA.0);
+
+#[near_bindgen]
+#[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
+pub struct Contract {
+    /// I Am Human registry verifying humans.
+    pub registry: AccountId,
+    pub admin: AccountId,
+    /// amount of NEAR sent by a single drip.
+    pub drip: Balance,
+    /// min time (in miliseconds) between two drips to the same account.
+    pub cooldown_ms: u64,
+    /// account -> time of the last drip (unix timestamp in miliseconds).
+    pub last_drips: LookupMap<AccountId, u64>,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq))]
+pub struct Config {
+    pub registry: AccountId,
+    pub admin: AccountId,
+    pub drip: U128,
+    pub cooldown_ms: u64,
+}
+
+#[near_bindgen]
+impl Contract {
+    #[init]
+    pub fn new(registry: AccountId, admin: AccountId, drip: U128, cooldown_ms: u64) -> Self {
+        Self {
+            registry,
+            admin,
+            drip: drip.0,
+            cooldown_ms,
+            last_drips: LookupMap::new(b"d"),
+        }
+    }
+
+    /**********
+     * QUERIES
+     **********/
+
+    pub fn config(&self) -> Config {
+        Config {
+            registry: self.registry.clone(),
+            admin: self.admin.clone(),
+            drip: self.drip.into(),
+            cooldown_ms: self.cooldown_ms,
+        }
+    }
+
+    /// Returns true if the cooldown of the `account` passed (the account must still be a
+    /// verified human to get a drip).
+    pub fn can_drip(&self, account: AccountId) -> bool {
+        self.next_drip_at(account) <= env::block_timestamp_ms()
+    }
+
+    /// Returns the time (unix timestamp in miliseconds) since when the `account` can get the
+    /// next drip, or 0 if it never got a drip.
+    pub fn next_drip_at(&self, account: AccountId) -> u64 {
+        self.last_drips
+            .get(&account)
+            .map_or(0, |t| t + self.cooldown_ms)
+    }
+
+    /**********
+     * TRANSACTIONS
+     **********/
+
+    /// Sends the drip to the `caller`. Must be called by the registry `is_human_call`, which
+    /// guarantees that the `caller` is a human:
+    /// `near call <registry> is_human_call '{"ctr": "<faucet>", "function": "drip", "payload": "{}"}'`.
+    /// The `payload` is ignored. Returns the dripped amount.
+    #[allow(unused_variables)]
+    pub fn drip(&mut self, caller: AccountId, payload: Value) -> U128 {
+        require!(
+            env::predecessor_account_id() == self.registry,
+            "must be called by the registry is_human_call"
+        );
+        let now = env::block_timestamp_ms();
+        self.assert_can_drip(&caller, now);
+        self.last_drips.insert(&caller, &now);
+        self.send_drip(caller)
+    }
+
+    /// Checks the caller with the registry `is_human` and sends the drip if the caller is a
+    /// human. The cooldown is reserved immediately, so parallel requests can't drip twice.
+    /// The callback returns the dripped amount (0 if the caller is not a human).
+    pub fn request_drip(&mut self) -> Promise {
+        let account = env::predecessor_account_id();
+        let now = env::block_timestamp_ms();
+        self.assert_can_drip(&account, now);
+        let prev_drip = self.last_drips.insert(&account, &now);
+        ext_registry::ext(self.registry.clone())
+            .with_static_gas(IS_HUMAN_GAS)
+            .is_human(account.clone())
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(ON_IS_HUMAN_GAS)
+                    .on_is_human(account, prev_drip),
+            )
+    }
+
+    #[private]
+    pub fn on_is_human(&mut self, account: AccountId, prev_drip: Option<u64>) -> U128 {
+        let human = match env::promise_result(0) {
+            PromiseResult::Successful(v) => serde_json::from_slice::<bool>(&v).unwrap_or(false),
+            _ => false,
+        };
+        if human && self.available_balance() >= self.drip {
+            return self.send_drip(account);
+        }
+        env::log_str(&format!("drip to {} rejected", account));
+        // release the reserved cooldown
+        match prev_drip {
+            Some(t) => self.last_drips.insert(&account, &t),
+            None => self.last_drips.remove(&account),
+        };
+        U128(0)
+    }
+
+    /// Updates the drip amount and the cooldown. Must be called by the admin.
+    pub fn admin_set_drip(&mut self, drip: U128, cooldown_ms: u64) {
+        require!(env::predecessor_account_id() == self.admin, "not an admin");
+        self.drip = drip.0;
+        self.cooldown_ms = cooldown_ms;
+    }
+}
+
+impl Contract {
+    fn assert_can_drip(&self, account: &AccountId, now: u64) {
+        require!(
+            self.next_drip_at(account.clone()) <= now,
+            "drip cooldown has not passed yet"
+        );
+        require!(self.available_balance() >= self.drip, "faucet is empty");
+    }
+
+    /// contract balance not locked for the storage.
+    fn available_balance(&self) -> Balance {
+        let storage = env::storage_usage() as Balance * env::storage_byte_cost();
+        env::account_balance().saturating_sub(storage)
+    }
+
+    fn send_drip(&self, account: AccountId) -> U128 {
+        env::log_str(&format!("drip {} yNEAR to {}", self.drip, account));
+        Promise::new(account).transfer(self.drip);
+        U128(self.drip)
+    }
+}
+
+#[ext_contract(ext_registry)]
+pub trait Registry {
+    fn is_human(&self, account: AccountId) -> bool;
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::mock::VmAction;
+    use near_sdk::test_utils::{self, VMContextBuilder};
+    use near_sdk::{testing_env, RuntimeFeesConfig, VMConfig, VMContext, ONE_NEAR};
+
+    use super::*;
+
+    const DRIP: Balance = ONE_NEAR;
+    const COOLDOWN_MS: u64 = 1000;
+    const MS: u64 = 1_000_000;
+
+    fn registry() -> AccountId {
+        AccountId::new_unchecked("registry.near".to_string())
+    }
+
+    fn faucet() -> AccountId {
+        AccountId::new_unchecked("faucet.near".to_string())
+    }
+
+    fn admin() -> AccountId {
+        AccountId::new_unchecked("admin.near".to_string())
+    }
+
+    fn alice() -> AccountId {
+        AccountId::new_unchecked("alice.near".to_string())
+    }
+
+    fn setup(predecessor: &AccountId) -> (VMContext, Contract) {
+        let ctx = VMContextBuilder::new()
+            .current_account_id(faucet())
+            .predecessor_account_id(predecessor.clone())
+            .account_balance(10 * ONE_NEAR)
+            .block_timestamp(MS)
+            .build();
+        testing_env!(ctx.clone());
+        let ctr = Contract::new(registry(), admin(), DRIP.into(), COOLDOWN_MS);
+        (ctx, ctr)
+    }
+
+    fn transfers() -> Vec<(AccountId, Balance)> {
+        test_utils::get_created_receipts()
+            .into_iter()
+            .flat_map(|r| {
+                r.actions.into_iter().filter_map(move |a| match a {
+                    VmAction::Transfer { deposit } => Some((r.receiver_id.clone(), deposit)),
+                    _ => None,
+                })
+            })
+            .collect()
+    }
+
+    /// sets the context of the callback receiving the `result`.
+    fn with_promise_result(ctx: &VMContext, result: PromiseResult) {
+        let mut ctx = ctx.clone();
+        ctx.predecessor_account_id = faucet();
+        testing_env!(
+            ctx,
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![result]
+        );
+    }
+
+    #[test]
+    fn drip_through_is_human_call() {
+        let (mut ctx, mut ctr) = setup(&registry());
+        assert!(ctr.can_drip(alice()));
+        assert_eq!(ctr.drip(alice(), Value::Null), U128(DRIP));
+        assert_eq!(transfers(), vec![(alice(), DRIP)]);
+        assert!(!ctr.can_drip(alice()));
+        assert_eq!(ctr.next_drip_at(alice()), 1 + COOLDOWN_MS);
+
+        ctx.block_timestamp = (1 + COOLDOWN_MS) * MS;
+        testing_env!(ctx);
+        assert!(ctr.can_drip(alice()));
+        assert_eq!(ctr.drip(alice(), Value::Null), U128(DRIP));
+    }
+
+    #[test]
+    #[should_panic(expected = "drip cooldown has not passed yet")]
+    fn drip_cooldown() {
+        let (_, mut ctr) = setup(&registry());
+        ctr.drip(alice(), Value::Null);
+        ctr.drip(alice(), Value::Null);
+    }
+
+    #[test]
+    #[should_panic(expected = "must be called by the registry is_human_call")]
+    fn drip_not_registry() {
+        let (_, mut ctr) = setup(&alice());
+        ctr.drip(alice(), Value::Null);
+    }
+
+    #[test]
+    #[should_panic(expected = "faucet is empty")]
+    fn drip_empty() {
+        let (mut ctx, mut ctr) = setup(&registry());
+        ctx.account_balance = DRIP;
+        testing_env!(ctx);
+        ctr.drip(alice(), Value::Null);
+    }
+
+    #[test]
+    fn request_drip() {
+        let (ctx, mut ctr) = setup(&alice());
+        ctr.request_drip();
+        // the cooldown is reserved until the callback
+        assert!(!ctr.can_drip(alice()));
+        let calls: Vec<String> = test_utils::get_created_receipts()
+            .into_iter()
+            .flat_map(|r| r.actions)
+            .filter_map(|a| match a {
+                VmAction::FunctionCall { function_name, .. } => Some(function_name),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(calls, vec!["is_human", "on_is_human"]);
+
+        with_promise_result(&ctx, PromiseResult::Successful(b"true".to_vec()));
+        assert_eq!(ctr.on_is_human(alice(), None), U128(DRIP));
+        assert_eq!(transfers(), vec![(alice(), DRIP)]);
+        assert!(!ctr.can_drip(alice()));
+    }
+
+    #[test]
+    fn request_drip_not_human() {
+        let (ctx, mut ctr) = setup(&alice());
+        ctr.request_drip();
+        with_promise_result(&ctx, PromiseResult::Successful(b"false".to_vec()));
+        assert_eq!(ctr.on_is_human(alice(), None), U128(0));
+        assert_eq!(transfers(), vec![]);
+        assert_eq!(test_utils::get_logs(), vec!["drip to alice.near rejected"]);
+        // the reserved cooldown is released
+        assert!(ctr.can_drip(alice()));
+
+        with_promise_result(&ctx, PromiseResult::Failed);
+        ctr.last_drips.insert(&alice(), &1);
+        assert_eq!(ctr.on_is_human(alice(), Some(0)), U128(0));
+        assert_eq!(ctr.last_drips.get(&alice()), Some(0));
+    }
+
+    #[test]
+    fn admin_set_drip() {
+        let (_, mut ctr) = setup(&admin());
+        ctr.admin_set_drip(U128(5), 10);
+        assert_eq!(
+            ctr.config(),
+            Config {
+                registry: registry(),
+                admin: admin(),
+                drip: U128(5),
+                cooldown_ms: 10,
+            }
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "not an admin")]
+    fn admin_set_drip_not_admin() {
+        let (_, mut ctr) = setup(&alice());
+        ctr.admin_set_drip(U128(5), 10);
+    }
+}
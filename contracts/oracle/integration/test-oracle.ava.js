@@ -49,7 +49,7 @@ test("Should fail: mint sbt, oracle is not an issuer", async (t) => {
   const mint_result = await claimer.call(oracle_contract, "sbt_mint",
     { 'claim_b64': claim_b64,
       'claim_sig' : sig_b64 },
-    { attachedDeposit: NEAR.parse("0.008 N").toString() },
+    { attachedDeposit: NEAR.parse("0.01 N").toString() },
     { gas: Gas.parse('20 Tgas') }).catch((error) => { console.log('Transaction error:', error);});
   t.deepEqual(mint_result, {Err: 'registry.sbt_mint failed'});
   const is_used_identity = await oracle_contract.view("is_used_identity", { 'external_id': external_id});
@@ -63,7 +63,7 @@ test("Should pass: mint sbt, oracle is an issuer", async (t) => {
   const mint_result =  await claimer.call(oracle_contract, "sbt_mint",
     { 'claim_b64': claim_b64,
       'claim_sig' : sig_b64 },
-    { attachedDeposit: NEAR.parse("0.008 N").toString() },
+    { attachedDeposit: NEAR.parse("0.01 N").toString() },
     { gas: Gas.parse('20 Tgas') }).catch((error) => { console.log('Transaction error:', error);});
   t.not(mint_result, undefined);
   const is_used_identity = await oracle_contract.view("is_used_identity", { 'external_id': external_id});
@@ -79,7 +79,7 @@ test("Should pass: mint sbt token and revoke (burn)", async (t) => {
   const mint_result =  await claimer.call(oracle_contract, "sbt_mint",
     { 'claim_b64': claim_b64,
       'claim_sig' : sig_b64 },
-    { attachedDeposit: NEAR.parse("0.008 N").toString() },
+    { attachedDeposit: NEAR.parse("0.01 N").toString() },
     { gas: Gas.parse('20 Tgas') }).catch((error) => { console.log('Transaction error:', error);});
   t.not(mint_result, undefined);
   const is_used_identity = await oracle_contract.view("is_used_identity", { 'external_id': external_id});
@@ -101,7 +101,7 @@ test("Should pass: mint sbt token and kyc token", async (t) => {
   const mint_result =  await claimer.call(oracle_contract, "sbt_mint",
     { 'claim_b64': claim_b64_with_kyc,
       'claim_sig' : sig_b64_with_kyc },
-    { attachedDeposit: NEAR.parse("0.015 N").toString() },
+    { attachedDeposit: NEAR.parse("0.019 N").toString() },
     { gas: Gas.parse('20 Tgas') }).catch((error) => { console.log('Transaction error:', error);});
   t.not(mint_result, undefined);
   console.log("mint result",mint_result.Ok);
@@ -20,6 +20,7 @@ pub mod tests {
             external_id: "0xb4bf0f23c702efb8a9da87a94095e28de3d21cc3".to_owned(),
             timestamp: 0,
             verified_kyc: false,
+            verification_level: None,
         };
 
         let borsh_serialized: Vec<u8> = borsh_input.try_to_vec().unwrap();
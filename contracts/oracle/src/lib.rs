@@ -1,9 +1,10 @@
 use ed25519_dalek::{PublicKey, Signature, Verifier, PUBLIC_KEY_LENGTH};
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::{LazyOption, UnorderedSet};
+use near_sdk::collections::{LazyOption, LookupMap, UnorderedSet};
 use near_sdk::serde::Serialize;
 use near_sdk::{
-    env, near_bindgen, require, AccountId, Balance, Gas, PanicOnDefault, Promise, PromiseError,
+    env, near_bindgen, require, AccountId, Balance, CryptoHash, Gas, PanicOnDefault, Promise,
+    PromiseError,
 };
 
 use cost::*;
@@ -16,14 +17,24 @@ use uint::hex;
 pub use crate::errors::*;
 pub use crate::storage::*;
 pub use crate::util::*;
+pub use crate::verification::*;
 
 mod errors;
 mod storage;
 mod util;
+mod verification;
 
 pub const CLASS_FV_SBT: ClassId = 1;
 pub const CLASS_KYC_SBT: ClassId = 2;
 
+/// SBT time to live in miliseconds: 1.5 years.
+#[cfg(not(feature = "testnet"))]
+pub const SBT_TTL_MS: u64 = 1000 * 3600 * 24 * 548;
+/// SBT time to live in miliseconds. On testnet we use a short ttl (1 day) to make expiry
+/// testing easier.
+#[cfg(feature = "testnet")]
+pub const SBT_TTL_MS: u64 = 1000 * 3600 * 24;
+
 // Total storage deposit cost without KYC
 pub const MINT_TOTAL_COST: Balance = MINT_COST + MILI_NEAR;
 pub const MINT_TOTAL_COST_WITH_KYC: Balance = 2 * MINT_COST + MILI_NEAR;
@@ -47,6 +58,13 @@ pub struct Contract {
 
     /// used for backend key rotation
     pub admins: UnorderedSet<AccountId>,
+
+    /// verification levels supported in claims, ordered from the lowest to the highest.
+    pub verification_levels: Vec<VerificationLevel>,
+    /// SBTs minted for external identities using claims with a verification level.
+    pub identity_tokens: LookupMap<Vec<u8>, IdentityToken>,
+    /// data IDs of the yielded mint requests awaiting the verification, by user.
+    pub pending_mints: LookupMap<AccountId, CryptoHash>,
 }
 
 // Implement the contract structure
@@ -76,10 +94,24 @@ impl Contract {
             registry,
             metadata: LazyOption::new(StorageKey::ContractMetadata, Some(&metadata)),
             claim_ttl,
-            sbt_ttl_ms: 1000 * 3600 * 24 * 548, // 1.5years in ms
+            sbt_ttl_ms: SBT_TTL_MS,
             authority_pubkey: pubkey_from_b64(authority),
             used_identities: UnorderedSet::new(StorageKey::UsedIdentities),
             admins,
+            verification_levels: vec![
+                VerificationLevel {
+                    name: "basic+liveness".to_owned(),
+                    class: CLASS_FV_SBT,
+                    ttl_ms: SBT_TTL_MS,
+                },
+                VerificationLevel {
+                    name: "KYC".to_owned(),
+                    class: CLASS_KYC_SBT,
+                    ttl_ms: SBT_TTL_MS,
+                },
+            ],
+            identity_tokens: LookupMap::new(StorageKey::IdentityTokens),
+            pending_mints: LookupMap::new(StorageKey::PendingMints),
         }
     }
 
@@ -101,6 +133,16 @@ impl Contract {
         MINT_TOTAL_COST
     }
 
+    /// Returns supported claim verification levels, ordered from the lowest to the highest.
+    pub fn verification_levels(&self) -> Vec<VerificationLevel> {
+        self.verification_levels.clone()
+    }
+
+    /// NEP-330 source metadata of the deployed contract.
+    pub fn contract_source_metadata(&self) -> ContractSourceMetadata {
+        contract_source_metadata!(Standard::new(STANDARD_NAME, SPEC_VERSION))
+    }
+
     // all SBT queries should be done through registry
 
     /**********
@@ -111,6 +153,9 @@ impl Contract {
     /// @claim_b64: standard base64 borsh serialized Claim (same bytes as used for the claim signature)
     /// If `metadata.expires_at` is None then we set it to ` now+self.ttl`.
     /// Panics if `metadata.expires_at > now+self.ttl`.
+    /// If the claim has a `verification_level`, a single SBT of the class configured for that
+    /// level is minted. If the external identity already has an SBT of a lower level, the
+    /// call upgrades it: the new SBT is minted and the old one is burned.
     // TODO: update result to return TokenId
     #[handle_result]
     #[payable]
@@ -127,84 +172,19 @@ impl Contract {
             ));
         }
 
-        let sig = b64_decode("claim_sig", claim_sig)?;
-        let claim_bytes = b64_decode("claim_b64", claim_b64)?;
-        // let claim = Claim::deserialize(&mut &claim_bytes[..])
-        let claim = Claim::try_from_slice(&claim_bytes)
-            .map_err(|_| CtrError::Borsh("claim".to_string()))?;
-
-        if claim.verified_kyc {
+        let (claim, claim_bytes, sig) = decode_claim(claim_b64, claim_sig)?;
+        if claim.verified_kyc && claim.verification_level.is_none() {
             require!(
                 env::attached_deposit() == MINT_TOTAL_COST_WITH_KYC,
-                "Requires attached deposit of exactly 0.015 NEAR"
+                "Requires attached deposit of exactly 0.019 NEAR"
             );
         } else {
             require!(
                 env::attached_deposit() == MINT_TOTAL_COST,
-                "Requires attached deposit of exactly 0.008 NEAR"
+                "Requires attached deposit of exactly 0.01 NEAR"
             );
         }
-
-        verify_claim(&self.authority_pubkey, claim_bytes, sig)?;
-
-        let now = env::block_timestamp() / SECOND;
-        if claim.timestamp > now {
-            return Err(CtrError::BadRequest(
-                "claim.timestamp in the future".to_string(),
-            ));
-        }
-        if now >= claim.timestamp + self.claim_ttl {
-            return Err(CtrError::BadRequest("claim expired".to_string()));
-        }
-
-        if claim.claimer != user {
-            return Err(CtrError::BadRequest(
-                "claimer is not the transaction signer".to_string(),
-            ));
-        }
-        let external_id = normalize_external_id(claim.external_id)?;
-
-        if self.used_identities.contains(&external_id) {
-            return Err(CtrError::DuplicatedID("external_id".to_string()));
-        }
-
-        let now_ms = env::block_timestamp_ms();
-        let mut tokens_metadata: Vec<TokenMetadata> = Vec::new();
-        tokens_metadata.push(TokenMetadata {
-            class: CLASS_FV_SBT,
-            issued_at: Some(now_ms),
-            expires_at: Some(now_ms + self.sbt_ttl_ms),
-            reference: None,
-            reference_hash: None,
-        });
-        //KYC token to be minted. Class is set to `2` to differentiate the token
-        if claim.verified_kyc {
-            tokens_metadata.push(TokenMetadata {
-                class: CLASS_KYC_SBT,
-                issued_at: Some(now_ms),
-                expires_at: Some(now_ms + self.sbt_ttl_ms),
-                reference: None,
-                reference_hash: None,
-            });
-        }
-
-        self.used_identities.insert(&external_id);
-
-        if let Some(memo) = memo {
-            env::log_str(&format!("SBT mint memo: {}", memo));
-        }
-
-        let result = ext_registry::ext(self.registry.clone())
-            .with_attached_deposit(Self::get_required_sbt_mint_deposit(claim.verified_kyc))
-            .with_static_gas(MINT_GAS)
-            .sbt_mint(vec![(claim.claimer, tokens_metadata)])
-            .then(
-                Self::ext(env::current_account_id())
-                    .with_static_gas(Gas::ONE_TERA * 3)
-                    .sbt_mint_callback(hex::encode(external_id)),
-            );
-
-        Ok(result)
+        self.mint_claim(user, claim, claim_bytes, sig, memo)
     }
 
     // We use our own result type, because NEAR stopped to support standard `Result` return
@@ -217,14 +197,38 @@ impl Contract {
     // Other solution (probably the right one) is to schedule another callback to "self" which
     // will panic.
     // Ideally, though, NEAR will start considering Result types again.
+    // On success, if the claim had a verification level, we record the minted token to allow
+    // later upgrades, and burn the `upgraded_token`.
     #[private]
     pub fn sbt_mint_callback(
         &mut self,
         external_id: String,
+        level: Option<String>,
+        claimer: Option<AccountId>,
+        upgraded_token: Option<TokenId>,
         #[callback_result] last_result: Result<Vec<TokenId>, PromiseError>,
     ) -> CallbackResult<TokenId, &str> {
         match last_result {
-            Ok(v) => CallbackResult::Ok(v[0]),
+            Ok(v) => {
+                if let (Some(level), Some(owner)) = (level, claimer) {
+                    self.identity_tokens.insert(
+                        &hex::decode(external_id).unwrap(),
+                        &IdentityToken {
+                            level,
+                            owner,
+                            token: v[0],
+                        },
+                    );
+                }
+                if let Some(token) = upgraded_token {
+                    ext_registry::ext(self.registry.clone())
+                        .with_static_gas(MINT_GAS)
+                        .sbt_revoke(vec![token], true);
+                }
+                CallbackResult::Ok(v[0])
+            }
+            // upgrade failed: the identity keeps the old token.
+            Err(_) if upgraded_token.is_some() => CallbackResult::Err("registry.sbt_mint failed"),
             Err(_) => {
                 // registry mint failed, need to rollback. We can't panic here in order to
                 // preserve state change.
@@ -261,6 +265,7 @@ impl Contract {
                 expires_at: Some(now + self.sbt_ttl_ms),
                 reference: None,
                 reference_hash: None,
+                attributes_root: None,
             };
             ext_registry::ext(self.registry.clone())
                 .with_attached_deposit(MINT_COST)
@@ -269,7 +274,7 @@ impl Contract {
                 .then(
                     Self::ext(env::current_account_id())
                         .with_static_gas(Gas::ONE_TERA * 3)
-                        .sbt_mint_callback(hex::encode(external_id)),
+                        .sbt_mint_callback(hex::encode(external_id), None, None, None),
                 )
         }
     */
@@ -280,11 +285,147 @@ impl Contract {
         self.authority_pubkey = pubkey_from_b64(authority);
     }
 
+    /// Sets supported claim verification levels, ordered from the lowest to the highest.
+    /// Level names must be unique.
+    pub fn admin_set_verification_levels(&mut self, levels: Vec<VerificationLevel>) {
+        self.assert_admin();
+        for (i, l) in levels.iter().enumerate() {
+            require!(
+                levels[i + 1..].iter().all(|o| o.name != l.name),
+                "duplicated verification level"
+            );
+        }
+        self.verification_levels = levels;
+    }
+
     pub fn add_admin(&mut self, admin: AccountId) {
         self.assert_admin();
         self.admins.insert(&admin);
     }
 
+    /// Verifies the `claim` of the `user` and mints the SBTs. The caller must check the
+    /// attached deposit.
+    fn mint_claim(
+        &mut self,
+        user: AccountId,
+        claim: Claim,
+        claim_bytes: Vec<u8>,
+        sig: Vec<u8>,
+        memo: Option<String>,
+    ) -> Result<Promise, CtrError> {
+        verify_claim(&self.authority_pubkey, claim_bytes, sig)?;
+
+        let now = env::block_timestamp() / SECOND;
+        if claim.timestamp > now {
+            return Err(CtrError::BadRequest(
+                "claim.timestamp in the future".to_string(),
+            ));
+        }
+        if now >= claim.timestamp + self.claim_ttl {
+            return Err(CtrError::BadRequest("claim expired".to_string()));
+        }
+
+        if claim.claimer != user {
+            return Err(CtrError::BadRequest(
+                "claimer is not the transaction signer".to_string(),
+            ));
+        }
+        let external_id = normalize_external_id(claim.external_id)?;
+        let level = match claim.verification_level {
+            None => None,
+            Some(name) => match self.level_rank(&name) {
+                None => {
+                    return Err(CtrError::BadRequest(
+                        "unknown claim.verification_level".to_string(),
+                    ))
+                }
+                Some(rank) => Some(rank),
+            },
+        };
+
+        // token to burn when upgrading the identity to a higher verification level
+        let mut upgraded_token = None;
+        if self.used_identities.contains(&external_id) {
+            match (level, self.identity_tokens.get(&external_id)) {
+                (Some(rank), Some(t))
+                    if t.owner == claim.claimer
+                        && self.level_rank(&t.level).map_or(true, |r| r < rank) =>
+                {
+                    upgraded_token = Some(t.token)
+                }
+                _ => return Err(CtrError::DuplicatedID("external_id".to_string())),
+            }
+        }
+
+        let now_ms = env::block_timestamp_ms();
+        let mut tokens_metadata: Vec<TokenMetadata> = Vec::new();
+        if let Some(rank) = level {
+            let l = &self.verification_levels[rank];
+            tokens_metadata.push(TokenMetadata {
+                class: l.class,
+                issued_at: Some(now_ms),
+                expires_at: Some(now_ms + l.ttl_ms),
+                reference: None,
+                reference_hash: None,
+                attributes_root: None,
+            });
+        } else {
+            tokens_metadata.push(TokenMetadata {
+                class: CLASS_FV_SBT,
+                issued_at: Some(now_ms),
+                expires_at: Some(now_ms + self.sbt_ttl_ms),
+                reference: None,
+                reference_hash: None,
+                attributes_root: None,
+            });
+            //KYC token to be minted. Class is set to `2` to differentiate the token
+            if claim.verified_kyc {
+                tokens_metadata.push(TokenMetadata {
+                    class: CLASS_KYC_SBT,
+                    issued_at: Some(now_ms),
+                    expires_at: Some(now_ms + self.sbt_ttl_ms),
+                    reference: None,
+                    reference_hash: None,
+                    attributes_root: None,
+                });
+            }
+        }
+
+        self.used_identities.insert(&external_id);
+
+        if let Some(memo) = memo {
+            env::log_str(&format!("SBT mint memo: {}", memo));
+        }
+
+        let callback_gas = match upgraded_token {
+            None => Gas::ONE_TERA * 3,
+            Some(_) => Gas::ONE_TERA * 3 + MINT_GAS,
+        };
+        let result = ext_registry::ext(self.registry.clone())
+            .with_attached_deposit(Self::get_required_sbt_mint_deposit(
+                claim.verified_kyc && level.is_none(),
+            ))
+            .with_static_gas(MINT_GAS)
+            .sbt_mint(vec![(claim.claimer.clone(), tokens_metadata)])
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(callback_gas)
+                    .sbt_mint_callback(
+                        hex::encode(external_id),
+                        level.map(|rank| self.verification_levels[rank].name.clone()),
+                        Some(claim.claimer),
+                        upgraded_token,
+                    ),
+            );
+
+        Ok(result)
+    }
+
+    /// position of the verification level in `verification_levels`.
+    fn level_rank(&self, name: &str) -> Option<usize> {
+        self.verification_levels.iter().position(|l| l.name == name)
+    }
+
     #[inline]
     fn assert_admin(&self) {
         require!(
@@ -297,6 +438,18 @@ impl Contract {
     // - fn sbt_renew
 }
 
+/// Decodes the base64 claim and signature. Returns the claim with its bytes and the signature.
+fn decode_claim(
+    claim_b64: String,
+    claim_sig: String,
+) -> Result<(Claim, Vec<u8>, Vec<u8>), CtrError> {
+    let sig = b64_decode("claim_sig", claim_sig)?;
+    let claim_bytes = b64_decode("claim_b64", claim_b64)?;
+    let claim =
+        Claim::try_from_slice(&claim_bytes).map_err(|_| CtrError::Borsh("claim".to_string()))?;
+    Ok((claim, claim_bytes, sig))
+}
+
 fn verify_claim(
     pubkey: &[u8; PUBLIC_KEY_LENGTH],
     claim: Vec<u8>,
@@ -319,6 +472,7 @@ impl SBTContract for Contract {
 }
 
 #[derive(Serialize)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq))]
 #[serde(crate = "near_sdk::serde")]
 pub enum CallbackResult<T, E> {
     Ok(T),
@@ -334,8 +488,9 @@ mod tests {
     extern crate rand;
 
     use crate::*;
+    use near_sdk::json_types::U128;
     use near_sdk::test_utils::VMContextBuilder;
-    use near_sdk::{testing_env, VMContext};
+    use near_sdk::{testing_env, PromiseOrValue, VMContext};
 
     use ed25519_dalek::{Keypair, Signer};
     use rand::rngs::OsRng;
@@ -418,6 +573,7 @@ mod tests {
             external_id: external_id.to_string(),
             timestamp,
             verified_kyc: is_verified_kyc,
+            verification_level: None,
         }
     }
 
@@ -466,7 +622,7 @@ mod tests {
     */
 
     #[test]
-    #[should_panic(expected = "Requires attached deposit of exactly 0.008 NEAR")]
+    #[should_panic(expected = "Requires attached deposit of exactly 0.01 NEAR")]
     fn mint_not_enough_storage_deposit() {
         let signer = acc_claimer();
         let (mut ctx, mut ctr, k) = setup(&signer, &acc_u1());
@@ -481,7 +637,7 @@ mod tests {
     }
 
     #[test]
-    #[should_panic(expected = "Requires attached deposit of exactly 0.015 NEAR")]
+    #[should_panic(expected = "Requires attached deposit of exactly 0.019 NEAR")]
     fn mint_with_kyc_not_enough_storage_deposit() {
         let signer = acc_claimer();
         let (mut ctx, mut ctr, k) = setup(&signer, &acc_u1());
@@ -606,6 +762,239 @@ mod tests {
         }
     }
 
+    #[test]
+    fn verification_levels() {
+        let signer = acc_claimer();
+        let (mut ctx, mut ctr, k) = setup(&signer, &acc_u1());
+        ctx.block_timestamp = start() + SECOND;
+        testing_env!(ctx.clone());
+        let mut c = mk_claim(start() / SECOND, "0x1a", false);
+
+        c.verification_level = Some("other".to_owned());
+        let (c_str, sig) = sign_claim(&c, &k);
+        assert_bad_request(
+            ctr.sbt_mint(c_str, sig, None),
+            "unknown claim.verification_level",
+        );
+
+        c.verification_level = Some("basic+liveness".to_owned());
+        let (c_str, sig) = sign_claim(&c, &k);
+        assert!(ctr.sbt_mint(c_str.clone(), sig.clone(), None).is_ok());
+        assert!(ctr.is_used_identity("0x1a".to_owned()));
+        // simulate successful registry mint
+        ctr.sbt_mint_callback(
+            "1a".to_owned(),
+            Some("basic+liveness".to_owned()),
+            Some(signer.clone()),
+            None,
+            Ok(vec![3]),
+        );
+        let external_id = normalize_external_id("0x1a".to_owned()).unwrap();
+        assert_eq!(
+            ctr.identity_tokens.get(&external_id),
+            Some(IdentityToken {
+                level: "basic+liveness".to_owned(),
+                owner: signer.clone(),
+                token: 3
+            })
+        );
+        // same level can't be minted again
+        match ctr.sbt_mint(c_str, sig, None) {
+            Err(CtrError::DuplicatedID(_)) => (),
+            Err(error) => panic!("expected DuplicatedID, got: {:?}", error),
+            Ok(_) => panic!("expected DuplicatedID, got: Ok"),
+        }
+
+        // upgrade to KYC
+        c.verification_level = Some("KYC".to_owned());
+        let (c_str, sig) = sign_claim(&c, &k);
+        assert!(ctr.sbt_mint(c_str.clone(), sig.clone(), None).is_ok());
+        // failed upgrade keeps the old token and the used identity
+        ctr.sbt_mint_callback(
+            "1a".to_owned(),
+            Some("KYC".to_owned()),
+            Some(signer.clone()),
+            Some(3),
+            Err(PromiseError::Failed),
+        );
+        assert!(ctr.is_used_identity("0x1a".to_owned()));
+        assert_eq!(ctr.identity_tokens.get(&external_id).unwrap().token, 3);
+
+        ctr.sbt_mint_callback(
+            "1a".to_owned(),
+            Some("KYC".to_owned()),
+            Some(signer),
+            Some(3),
+            Ok(vec![4]),
+        );
+        let t = ctr.identity_tokens.get(&external_id).unwrap();
+        assert_eq!(t.level, "KYC");
+        assert_eq!(t.token, 4);
+        // can't downgrade
+        c.verification_level = Some("basic+liveness".to_owned());
+        let (c_str, sig) = sign_claim(&c, &k);
+        match ctr.sbt_mint(c_str, sig, None) {
+            Err(CtrError::DuplicatedID(_)) => (),
+            Err(error) => panic!("expected DuplicatedID, got: {:?}", error),
+            Ok(_) => panic!("expected DuplicatedID, got: Ok"),
+        }
+    }
+
+    /// Returns (receiver, amount) of the transfers created in the current transaction.
+    fn created_transfers() -> Vec<(AccountId, Balance)> {
+        near_sdk::test_utils::get_created_receipts()
+            .into_iter()
+            .flat_map(|r| {
+                r.actions.into_iter().filter_map(move |a| match a {
+                    near_sdk::mock::VmAction::Transfer { deposit } => {
+                        Some((r.receiver_id.clone(), deposit))
+                    }
+                    _ => None,
+                })
+            })
+            .collect()
+    }
+
+    /// Requests a mint for the claimer and sets the context to the resumed callback.
+    fn setup_mint_request() -> (VMContext, Contract, Keypair, CryptoHash) {
+        let signer = acc_claimer();
+        let (mut ctx, mut ctr, k) = setup(&signer, &signer);
+        ctx.attached_deposit = MINT_TOTAL_COST_WITH_KYC;
+        testing_env!(ctx.clone());
+        ctr.sbt_mint_request(None);
+        assert!(ctr.has_mint_request(signer.clone()));
+        let data_id = ctr.pending_mints.get(&signer).unwrap();
+
+        ctx.predecessor_account_id = ctx.current_account_id.clone();
+        ctx.attached_deposit = 0;
+        ctx.block_timestamp = start() + SECOND;
+        testing_env!(ctx.clone());
+        (ctx, ctr, k, data_id)
+    }
+
+    fn assert_callback_err(r: PromiseOrValue<CallbackResult<TokenId, String>>, expected: &str) {
+        match r {
+            PromiseOrValue::Value(v) => assert_eq!(v, CallbackResult::Err(expected.to_owned())),
+            PromiseOrValue::Promise(_) => panic!("expected {}, got a mint", expected),
+        }
+        assert_eq!(
+            created_transfers(),
+            vec![(acc_claimer(), MINT_TOTAL_COST_WITH_KYC)],
+            "deposit must be refunded"
+        );
+    }
+
+    #[test]
+    fn mint_request() {
+        let signer = acc_claimer();
+        let (mut ctx, mut ctr, k, data_id) = setup_mint_request();
+
+        // the oracle backend submits the verification result
+        ctx.predecessor_account_id = acc_admin();
+        testing_env!(ctx.clone());
+        let (_, claim_b64, claim_sig) = mk_claim_sign(start() / SECOND, "0x1a", &k, false);
+        let result = VerificationResult {
+            claim_b64,
+            claim_sig,
+        };
+        assert!(ctr.submit_verification(signer.clone(), Some(result.clone())));
+        assert!(
+            !ctr.submit_verification(signer.clone(), Some(result.clone())),
+            "request is already resumed"
+        );
+        assert!(!ctr.submit_verification(acc_u1(), Some(result)));
+        let payload = resumed_payload(&data_id).unwrap();
+
+        ctx.predecessor_account_id = ctx.current_account_id.clone();
+        testing_env!(ctx);
+        let r = ctr.on_mint_verification(
+            signer.clone(),
+            U128(MINT_TOTAL_COST_WITH_KYC),
+            None,
+            Ok(near_sdk::serde_json::from_slice(&payload).unwrap()),
+        );
+        assert!(matches!(r, PromiseOrValue::Promise(_)));
+        assert!(!ctr.has_mint_request(signer.clone()));
+        assert!(ctr.is_used_identity("0x1a".to_owned()));
+        assert_eq!(
+            created_transfers(),
+            vec![(signer, MINT_TOTAL_COST_WITH_KYC - MINT_TOTAL_COST)],
+            "unused deposit must be refunded"
+        );
+    }
+
+    #[test]
+    fn mint_request_timeout() {
+        let (_, mut ctr, _, _) = setup_mint_request();
+        let r = ctr.on_mint_verification(
+            acc_claimer(),
+            U128(MINT_TOTAL_COST_WITH_KYC),
+            None,
+            Err(PromiseError::Failed),
+        );
+        assert_callback_err(r, "verification timed out");
+        assert!(!ctr.has_mint_request(acc_claimer()));
+    }
+
+    #[test]
+    fn mint_request_rejected() {
+        let (ctx, mut ctr, k, _) = setup_mint_request();
+        let r = ctr.on_mint_verification(
+            acc_claimer(),
+            U128(MINT_TOTAL_COST_WITH_KYC),
+            None,
+            Ok(None),
+        );
+        assert_callback_err(r, "verification rejected");
+
+        // claim of another user
+        testing_env!(ctx);
+        let mut c = mk_claim(start() / SECOND, "0x1a", false);
+        c.claimer = acc_u1();
+        let (claim_b64, claim_sig) = sign_claim(&c, &k);
+        let r = ctr.on_mint_verification(
+            acc_claimer(),
+            U128(MINT_TOTAL_COST_WITH_KYC),
+            None,
+            Ok(Some(VerificationResult {
+                claim_b64,
+                claim_sig,
+            })),
+        );
+        assert_callback_err(r, "claimer is not the transaction signer");
+        assert!(!ctr.is_used_identity("0x1a".to_owned()));
+    }
+
+    #[test]
+    #[should_panic(expected = "mint request is already pending")]
+    fn mint_request_pending() {
+        let (mut ctx, mut ctr, _, _) = setup_mint_request();
+        ctx.predecessor_account_id = acc_claimer();
+        ctx.attached_deposit = MINT_TOTAL_COST_WITH_KYC;
+        testing_env!(ctx);
+        ctr.sbt_mint_request(None);
+    }
+
+    #[test]
+    #[should_panic(expected = "not an admin")]
+    fn submit_verification_not_admin() {
+        let (mut ctx, mut ctr, _, _) = setup_mint_request();
+        ctx.predecessor_account_id = acc_claimer();
+        testing_env!(ctx);
+        ctr.submit_verification(acc_claimer(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "duplicated verification level")]
+    fn set_duplicated_verification_levels() {
+        let (mut ctx, mut ctr, _) = setup(&acc_claimer(), &acc_admin());
+        ctx.predecessor_account_id = acc_admin();
+        testing_env!(ctx);
+        let mut levels = ctr.verification_levels();
+        levels.push(levels[0].clone());
+        ctr.admin_set_verification_levels(levels);
+    }
+
     #[test]
     fn test_pubkey() {
         let pk_bytes = pubkey_from_b64("kSj7W/TdN9RGLgdJA8ac7i/WdQdm2lwQ1IPGlO1L3xc=".to_string());
@@ -1,5 +1,7 @@
-use near_sdk::borsh::{self, BorshSerialize};
-use near_sdk::BorshStorageKey;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{AccountId, BorshStorageKey};
+use sbt::{ClassId, TokenId};
 
 /// Helper structure for keys of the persistent collections.
 #[derive(BorshSerialize, BorshStorageKey)]
@@ -9,4 +11,27 @@ pub enum StorageKey {
     ContractMetadata,
     UsedIdentities,
     Admins,
+    IdentityTokens,
+    PendingMints,
+}
+
+/// Verification level of a claim and the SBT class minted for it.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq))]
+#[serde(crate = "near_sdk::serde")]
+pub struct VerificationLevel {
+    /// level name, as used in `Claim.verification_level`
+    pub name: String,
+    pub class: ClassId,
+    /// SBT time to live in miliseconds
+    pub ttl_ms: u64,
+}
+
+/// SBT minted for an external identity based on a claim with a verification level.
+#[derive(BorshSerialize, BorshDeserialize)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq))]
+pub struct IdentityToken {
+    pub level: String,
+    pub owner: AccountId,
+    pub token: TokenId,
 }
@@ -9,7 +9,7 @@ pub use crate::errors::*;
 
 type CtrResult<T> = Result<T, CtrError>;
 
-#[derive(BorshSerialize, BorshDeserialize)]
+#[derive(BorshSerialize)]
 #[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq))]
 pub struct Claim {
     pub claimer: AccountId,
@@ -19,6 +19,31 @@ pub struct Claim {
     pub timestamp: u64,
     /// indicates whether the user has passed a KYC or not
     pub verified_kyc: bool,
+    /// name of the verification level (eg "basic+liveness", "KYC") the claimer passed.
+    /// When set, the oracle mints the class configured for that level, and `verified_kyc` is
+    /// ignored. Claims serialized before this field was added decode with `None`.
+    pub verification_level: Option<String>,
+}
+
+impl BorshDeserialize for Claim {
+    fn deserialize(buf: &mut &[u8]) -> std::io::Result<Self> {
+        let claimer = AccountId::deserialize(buf)?;
+        let external_id = String::deserialize(buf)?;
+        let timestamp = u64::deserialize(buf)?;
+        let verified_kyc = bool::deserialize(buf)?;
+        let verification_level = if buf.is_empty() {
+            None
+        } else {
+            Option::<String>::deserialize(buf)?
+        };
+        Ok(Claim {
+            claimer,
+            external_id,
+            timestamp,
+            verified_kyc,
+            verification_level,
+        })
+    }
 }
 
 pub(crate) fn normalize_external_id(id: String) -> Result<Vec<u8>, CtrError> {
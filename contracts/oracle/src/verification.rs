@@ -0,0 +1,215 @@
+//! Mint requests awaiting an off-chain verification, using the NEAR yield/resume protocol
+//! feature (NEP-519).
+//!
+//! 1. The user calls `sbt_mint_request` before (or while) passing the off-chain verification.
+//!    The call creates a yielded `on_mint_verification` callback and returns its result, so
+//!    the transaction outcome is the mint result.
+//! 2. The oracle backend, once the verification is done, calls `submit_verification` with the
+//!    signed claim (or `None` if the verification failed). It resumes the callback.
+//! 3. `on_mint_verification` mints the SBTs like `sbt_mint`, and refunds the part of the
+//!    deposit not used by the mint. If the verification is rejected, or is not submitted
+//!    before the protocol yield timeout (~200 blocks), the whole deposit is refunded.
+
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::serde_json::{self, json};
+use near_sdk::{env, near_bindgen, require, AccountId, CryptoHash, Gas, PromiseOrValue};
+
+use crate::*;
+
+/// Gas for the `on_mint_verification` callback: the claim checks, the registry mint and its
+/// callback.
+pub const VERIFICATION_CALLBACK_GAS: Gas = Gas(10 * Gas::ONE_TERA.0 + 2 * MINT_GAS.0);
+
+/// Result of the off-chain verification, submitted by the oracle backend.
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq, Clone))]
+#[serde(crate = "near_sdk::serde")]
+pub struct VerificationResult {
+    /// standard base64 borsh serialized Claim, as in `sbt_mint`.
+    pub claim_b64: String,
+    pub claim_sig: String,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Requests an SBT mint for the transaction signer, to be completed when the oracle
+    /// backend submits the verification result (see `submit_verification`). The transaction
+    /// result is the `on_mint_verification` result.
+    /// Requires attached deposit of exactly `MINT_TOTAL_COST_WITH_KYC`, the unused part is
+    /// refunded. A user can have only one pending request.
+    #[payable]
+    pub fn sbt_mint_request(&mut self, memo: Option<String>) {
+        let user = env::signer_account_id();
+        require!(
+            is_supported_account(user.as_ref().chars()),
+            "only root and implicit accounts are allowed to get SBT"
+        );
+        let deposit = env::attached_deposit();
+        require!(
+            deposit == MINT_TOTAL_COST_WITH_KYC,
+            "Requires attached deposit of exactly 0.019 NEAR"
+        );
+        require!(
+            !self.pending_mints.contains_key(&user),
+            "mint request is already pending"
+        );
+        let args = serde_json::to_vec(&json!({
+            "user": user,
+            "deposit": U128(deposit),
+            "memo": memo,
+        }))
+        .unwrap();
+        let data_id = yield_create("on_mint_verification", &args, VERIFICATION_CALLBACK_GAS);
+        self.pending_mints.insert(&user, &data_id);
+        env::log_str(&format!("SBT mint verification requested by {}", user));
+    }
+
+    /// Submits the off-chain verification result of the `user` mint request. `None` rejects
+    /// the request. Returns false if the user has no pending request, or the request was
+    /// already resolved. Must be called by an admin.
+    pub fn submit_verification(
+        &mut self,
+        user: AccountId,
+        result: Option<VerificationResult>,
+    ) -> bool {
+        self.assert_admin();
+        match self.pending_mints.get(&user) {
+            None => false,
+            Some(data_id) => yield_resume(&data_id, &serde_json::to_vec(&result).unwrap()),
+        }
+    }
+
+    /// Returns true if the user has a mint request awaiting the verification.
+    pub fn has_mint_request(&self, user: AccountId) -> bool {
+        self.pending_mints.contains_key(&user)
+    }
+
+    /// Yielded callback of `sbt_mint_request`, resumed by `submit_verification` or by the
+    /// protocol on timeout. Mints the SBTs of a valid claim and refunds the deposit which is
+    /// not used for the mint.
+    #[private]
+    pub fn on_mint_verification(
+        &mut self,
+        user: AccountId,
+        deposit: U128,
+        memo: Option<String>,
+        #[callback_result] result: Result<Option<VerificationResult>, PromiseError>,
+    ) -> PromiseOrValue<CallbackResult<TokenId, String>> {
+        self.pending_mints.remove(&user);
+        let deposit = deposit.0;
+        let err = match result {
+            Err(_) => "verification timed out".to_owned(),
+            Ok(None) => "verification rejected".to_owned(),
+            Ok(Some(r)) => match self.mint_verified(user.clone(), r, memo) {
+                Ok((p, required)) => {
+                    if deposit > required {
+                        Promise::new(user).transfer(deposit - required);
+                    }
+                    return PromiseOrValue::Promise(p);
+                }
+                Err(e) => e.to_string(),
+            },
+        };
+        Promise::new(user).transfer(deposit);
+        PromiseOrValue::Value(CallbackResult::Err(err))
+    }
+}
+
+impl Contract {
+    /// Mints the SBTs of the verified claim. Returns the mint promise and the used deposit.
+    fn mint_verified(
+        &mut self,
+        user: AccountId,
+        r: VerificationResult,
+        memo: Option<String>,
+    ) -> Result<(Promise, Balance), CtrError> {
+        let (claim, claim_bytes, sig) = decode_claim(r.claim_b64, r.claim_sig)?;
+        let required = Self::get_required_sbt_mint_deposit(
+            claim.verified_kyc && claim.verification_level.is_none(),
+        );
+        let p = self.mint_claim(user, claim, claim_bytes, sig, memo)?;
+        Ok((p, required))
+    }
+}
+
+/// Creates a yielded call of the contract `method` and returns its result from the current
+/// function. Returns the data ID to resume the call with.
+#[cfg(target_arch = "wasm32")]
+fn yield_create(method: &str, args: &[u8], gas: Gas) -> CryptoHash {
+    const DATA_ID_REGISTER: u64 = 0;
+    let idx = unsafe {
+        near_sys::promise_yield_create(
+            method.len() as u64,
+            method.as_ptr() as u64,
+            args.len() as u64,
+            args.as_ptr() as u64,
+            gas.0,
+            0,
+            DATA_ID_REGISTER,
+        )
+    };
+    env::promise_return(idx);
+    env::read_register(DATA_ID_REGISTER)
+        .expect("yield data ID not found")
+        .try_into()
+        .unwrap()
+}
+
+/// Resumes the yielded call with the `payload`. Returns false if the call was already
+/// resumed or timed out.
+#[cfg(target_arch = "wasm32")]
+fn yield_resume(data_id: &CryptoHash, payload: &[u8]) -> bool {
+    unsafe {
+        near_sys::promise_yield_resume(
+            data_id.len() as u64,
+            data_id.as_ptr() as u64,
+            payload.len() as u64,
+            payload.as_ptr() as u64,
+        ) == 1
+    }
+}
+
+// The mocked blockchain doesn't support yield/resume: native builds record the yielded calls.
+#[cfg(not(target_arch = "wasm32"))]
+thread_local! {
+    static YIELDS: std::cell::RefCell<Vec<(CryptoHash, Option<Vec<u8>>)>> = Default::default();
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn yield_create(_method: &str, _args: &[u8], _gas: Gas) -> CryptoHash {
+    YIELDS.with(|y| {
+        let mut y = y.borrow_mut();
+        let data_id = env::sha256_array(&(y.len() as u64).to_le_bytes());
+        y.push((data_id, None));
+        data_id
+    })
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn yield_resume(data_id: &CryptoHash, payload: &[u8]) -> bool {
+    YIELDS.with(|y| {
+        match y
+            .borrow_mut()
+            .iter_mut()
+            .find(|(id, p)| id == data_id && p.is_none())
+        {
+            Some((_, p)) => {
+                *p = Some(payload.to_vec());
+                true
+            }
+            None => false,
+        }
+    })
+}
+
+/// Payload the yielded call was resumed with.
+#[cfg(all(test, not(target_arch = "wasm32")))]
+pub(crate) fn resumed_payload(data_id: &CryptoHash) -> Option<Vec<u8>> {
+    YIELDS.with(|y| {
+        y.borrow()
+            .iter()
+            .find(|(id, _)| id == data_id)
+            .and_then(|(_, p)| p.clone())
+    })
+}
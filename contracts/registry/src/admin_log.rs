@@ -0,0 +1,73 @@
+//! On-chain audit log of the admin actions: the registry keeps the last `ADMIN_LOG_SIZE`
+//! authority (and guardian) calls in a ring buffer, so consumers not running an indexer can
+//! review the recent admin changes with the `admin_log` view. The log storage is bounded and
+//! paid by the registry.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::Base64VecU8;
+use near_sdk::serde::Serialize;
+use near_sdk::{env, near_bindgen, AccountId};
+
+use crate::*;
+
+/// Number of the last admin actions kept in the log.
+pub const ADMIN_LOG_SIZE: u64 = 100;
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq, Clone))]
+pub struct AdminLogEntry {
+    /// sequence number of the action, starting from 0.
+    pub seq: u64,
+    pub method: String,
+    /// sha256 hash of the call arguments (the raw function input).
+    pub args_hash: Base64VecU8,
+    /// block timestamp in miliseconds.
+    pub timestamp: u64,
+    pub caller: AccountId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memo: Option<String>,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Returns up to `limit` (default and max `ADMIN_LOG_SIZE`) logged admin actions, in the
+    /// execution order, starting from the `from` sequence number (default: the oldest kept
+    /// action). Actions older than the last `ADMIN_LOG_SIZE` are not kept.
+    pub fn admin_log(&self, from: Option<u64>, limit: Option<u32>) -> Vec<AdminLogEntry> {
+        let oldest = self.admin_log_count.saturating_sub(ADMIN_LOG_SIZE);
+        let from = from.unwrap_or(oldest).max(oldest);
+        let limit = limit.map_or(ADMIN_LOG_SIZE, |l| (l as u64).min(ADMIN_LOG_SIZE));
+        let to = from.saturating_add(limit).min(self.admin_log_count);
+        (from..to)
+            .filter_map(|seq| self.admin_log.get(&(seq % ADMIN_LOG_SIZE)))
+            .collect()
+    }
+}
+
+impl Contract {
+    /// Panics if the caller is not the authority, and logs the admin `method` call.
+    pub(crate) fn assert_admin_action(&mut self, method: &str, memo: Option<&str>) {
+        self.assert_authority();
+        self.log_admin_action(method, memo);
+    }
+
+    /// Adds the current call of the `method` to the admin log, replacing the oldest entry
+    /// when the log is full.
+    pub(crate) fn log_admin_action(&mut self, method: &str, memo: Option<&str>) {
+        let seq = self.admin_log_count;
+        let args = env::input().unwrap_or_default();
+        self.admin_log.insert(
+            &(seq % ADMIN_LOG_SIZE),
+            &AdminLogEntry {
+                seq,
+                method: method.to_owned(),
+                args_hash: env::sha256(&args).into(),
+                timestamp: env::block_timestamp_ms(),
+                caller: env::predecessor_account_id(),
+                memo: memo.map(str::to_owned),
+            },
+        );
+        self.admin_log_count += 1;
+    }
+}
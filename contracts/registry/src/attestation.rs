@@ -0,0 +1,58 @@
+//! Cross-chain attestations of token ownership for light client bridges, see
+//! `sbt::AttestationProof`.
+
+use near_sdk::borsh::BorshSerialize;
+use near_sdk::json_types::Base64VecU8;
+use near_sdk::{env, near_bindgen, AccountId};
+use sbt::{Attestation, AttestationProof, StateKeys, TokenId, ATTESTATION_FORMAT_VERSION};
+
+use crate::*;
+
+#[near_bindgen]
+impl Contract {
+    /// Returns a Borsh encoded `sbt::AttestationProof` of the `issuer` `token`: the canonical
+    /// attestation of the token owner at the current block, and the registry storage keys a
+    /// light client bridge proves to verify it on another chain. Returns None if the token
+    /// doesn't exist (or is burned), or a soul transfer of the owner is in progress.
+    pub fn sbt_attestation(&self, issuer: AccountId, token: TokenId) -> Option<Base64VecU8> {
+        let issuer_id = self.sbt_issuers.get(&issuer)?;
+        let key = IssuerTokenId { issuer_id, token };
+        let t = self.issuer_tokens.get(&key)?;
+        if self.ongoing_soul_tx.contains_key(&t.owner) {
+            return None;
+        }
+        let now = MilliSeconds::now();
+        let class = t.metadata.class_id();
+        let valid = self.status_of(issuer_id, token, &t) == TokenStatus::Active
+            && t.metadata.is_valid_at(now)
+            && !self.is_banned_at(&t.owner, now);
+        let keys = StateKeys {
+            issuer: lookup_state_key(StorageKey::SbtIssuersRev, &issuer_id),
+            token: TokenStore::state_key(&key),
+            balance: self
+                .balances
+                .state_key(&balance_key(t.owner.clone(), issuer_id, class)),
+            status: lookup_state_key(StorageKey::TokenStatuses, &key),
+        };
+        let attestation = Attestation {
+            version: ATTESTATION_FORMAT_VERSION,
+            registry: env::current_account_id(),
+            block_height: env::block_height(),
+            timestamp: now.0,
+            issuer,
+            issuer_id,
+            token,
+            owner: t.owner,
+            class,
+            issued_at: t.metadata.issued_at(),
+            expires_at: t.metadata.expires_at(),
+            valid,
+        };
+        Some(
+            AttestationProof { attestation, keys }
+                .try_to_vec()
+                .unwrap()
+                .into(),
+        )
+    }
+}
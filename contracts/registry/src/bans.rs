@@ -0,0 +1,118 @@
+//! Temporary bans set by the authority (eg time-boxed sanctions from the community
+//! moderation). A temporary ban is a banlist entry with an expire time: once expired, the
+//! account is not considered banned anymore, and the entry is removed lazily by the next
+//! transaction involving the account.
+//! Bans set by a soul transfer are permanent.
+
+use near_sdk::{env, near_bindgen, require, AccountId};
+
+use crate::*;
+
+#[near_bindgen]
+impl Contract {
+    /// Bans the `account` until `until` (unix timestamp in miliseconds), or permanently when
+    /// `until` is None. Can extend (or make permanent) an existing temporary ban. Panics if
+    /// the account is already permanently banned. Emits `Ban` event and `AdminBan` registry
+    /// event with the optional `memo`. Must be called by the authority.
+    pub fn admin_ban(&mut self, account: AccountId, until: Option<u64>, memo: Option<String>) {
+        self.assert_admin_action("admin_ban", memo.as_deref());
+        self.assert_memo(&memo);
+        self.lift_expired_ban(&account);
+        require!(
+            !self.banlist.contains(&account) || self.ban_expirations.contains_key(&account),
+            "account is already permanently banned"
+        );
+        match until {
+            Some(until) => {
+                require!(
+                    until > env::block_timestamp_ms(),
+                    "until must be in the future"
+                );
+                self.ban_expirations.insert(&account, &until);
+            }
+            None => {
+                self.ban_expirations.remove(&account);
+            }
+        }
+        if self.banlist.insert(&account) {
+            self.emit_event(Nep393Event::Ban(vec![&account]));
+        }
+        self.emit_registry_event(RegistryEvent::AdminBan {
+            account: &account,
+            until,
+            memo: memo.as_deref(),
+        });
+    }
+
+    /// Lifts the temporary ban of the `account` before it expires. Permanent bans can't be
+    /// lifted. Emits `BanLifted` registry event with the optional `memo`. Must be called by
+    /// the authority.
+    pub fn admin_lift_ban(&mut self, account: AccountId, memo: Option<String>) {
+        self.assert_admin_action("admin_lift_ban", memo.as_deref());
+        self.assert_memo(&memo);
+        require!(
+            self.ban_expirations.remove(&account).is_some(),
+            "account is not temporarily banned"
+        );
+        self.banlist.remove(&account);
+        self.emit_registry_event(RegistryEvent::BanLifted {
+            account: &account,
+            memo: memo.as_deref(),
+        });
+    }
+
+    /// Returns the time (unix timestamp in miliseconds) when the temporary ban of the
+    /// `account` expires. Returns None for permanent bans and accounts which are not banned.
+    pub fn ban_expires_at(&self, account: AccountId) -> Option<u64> {
+        self.ban_expirations.get(&account)
+    }
+
+    /// Returns banned accounts with the ban expire time (None for permanent bans), so
+    /// moderation dashboards and other registries can sync the banlist. `from_index` is the
+    /// number of accounts to skip (default 0), `limit` is the max number of returned accounts
+    /// (default and max 1000). Expired temporary bans are listed until they are lifted by the
+    /// next transaction involving the account. The order is not specified: unbanning an
+    /// account moves the last listed account to its position.
+    pub fn banned_accounts(
+        &self,
+        from_index: Option<u64>,
+        limit: Option<u32>,
+    ) -> Vec<(AccountId, Option<u64>)> {
+        let limit = limit
+            .unwrap_or(registry::MAX_LIMIT)
+            .min(registry::MAX_LIMIT);
+        require!(limit > 0, "limit must be bigger than 0");
+        let accounts = self.banlist.as_vector();
+        let from = from_index.unwrap_or(0).min(accounts.len());
+        let to = (from + limit as u64).min(accounts.len());
+        (from..to)
+            .filter_map(|i| accounts.get(i))
+            .map(|a| {
+                let until = self.ban_expirations.get(&a);
+                (a, until)
+            })
+            .collect()
+    }
+
+    /// Returns the number of banlist entries, including expired temporary bans which were not
+    /// lifted yet (see `banned_accounts`).
+    pub fn banned_count(&self) -> u64 {
+        self.banlist.len()
+    }
+}
+
+impl Contract {
+    /// Removes the expired temporary ban of the `account` from the banlist.
+    pub(crate) fn lift_expired_ban(&mut self, account: &AccountId) {
+        if let Some(until) = self.ban_expirations.get(account) {
+            if until <= env::block_timestamp_ms() {
+                self.ban_expirations.remove(account);
+                self.banlist.remove(account);
+                self.emit_registry_event(RegistryEvent::BanLifted {
+                    account,
+                    memo: None,
+                });
+            }
+        }
+    }
+}
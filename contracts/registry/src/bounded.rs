@@ -0,0 +1,69 @@
+//! Gas bounded iteration of the token sweeps (soul transfer, soul merge, recovery, prune and
+//! invalidation of banned tokens). Rather than processing a fixed number of tokens per call,
+//! a sweep stops when the gas left can't cover another step and the work done after the loop
+//! (`reserve`). The caller continues the sweep in the next transaction.
+
+use std::iter::Fuse;
+
+use near_sdk::{env, Gas};
+
+/// Gas kept for the work done after a sweep loop: supply updates, events and the continuation
+/// state.
+pub(crate) const SWEEP_GAS_RESERVE: Gas = Gas(20 * Gas::ONE_TERA.0);
+
+/// Max number of balances read ahead of a sweep. The gas, not this limit, usually ends the
+/// sweep; it bounds the gas spent on reading tokens which won't be processed.
+pub(crate) const MAX_SWEEP_BATCH: usize = 40;
+
+/// Iterator adapter stopping the iteration when the gas left is lower than the `reserve` plus
+/// the gas of the most expensive step so far. The first item is always yielded, so every call
+/// makes progress.
+pub(crate) struct BoundedIter<I> {
+    inner: Fuse<I>,
+    reserve: u64,
+    /// gas used at the start of the previous step.
+    last_used: u64,
+    max_step: u64,
+    started: bool,
+    stopped: bool,
+}
+
+impl<I: Iterator> BoundedIter<I> {
+    pub(crate) fn new(inner: I, reserve: Gas) -> Self {
+        Self {
+            inner: inner.fuse(),
+            reserve: reserve.0,
+            last_used: 0,
+            max_step: 0,
+            started: false,
+            stopped: false,
+        }
+    }
+
+    /// Returns true if the iteration was stopped because of the gas, with items left.
+    pub(crate) fn stopped(&self) -> bool {
+        self.stopped
+    }
+}
+
+impl<I: Iterator> Iterator for BoundedIter<I> {
+    type Item = I::Item;
+
+    fn next(&mut self) -> Option<I::Item> {
+        if self.stopped {
+            return None;
+        }
+        let used = env::used_gas().0;
+        if self.started {
+            self.max_step = self.max_step.max(used - self.last_used);
+            let left = env::prepaid_gas().0.saturating_sub(used);
+            if left < self.reserve + self.max_step {
+                self.stopped = self.inner.next().is_some();
+                return None;
+            }
+        }
+        self.started = true;
+        self.last_used = used;
+        self.inner.next()
+    }
+}
@@ -0,0 +1,73 @@
+//! Used contexts: an app (eg a UBI or an airdrop contract) marks that a human used a context
+//! (eg a claim in an epoch). The marker is keyed by the human proof token (the IAH token of
+//! the first IAH class) rather than the account, so it follows the token in a soul transfer:
+//! the human can't use the same context again from another account.
+//! Markers are namespaced by the app (the `mark_used` caller), so an app can't affect
+//! contexts of another app.
+
+use near_sdk::borsh::BorshSerialize;
+use near_sdk::{env, near_bindgen, require, AccountId, CryptoHash, Promise};
+use sbt::*;
+
+use crate::*;
+
+/// Max length (in bytes) of a context.
+pub const MAX_CONTEXT_LEN: usize = 64;
+
+#[near_bindgen]
+impl Contract {
+    /// Marks the `context` as used by the human owning the `account` in the caller (app)
+    /// namespace. Returns false if the human already used the context (from any account).
+    /// Panics if the `account` is not a human. A new marker requires attaching enough NEAR
+    /// to cover its storage (`cost::MARK_USED_COST`). The deposit is refunded when the
+    /// context was already used.
+    #[payable]
+    pub fn mark_used(&mut self, account: AccountId, context: String) -> bool {
+        require!(
+            context.len() <= MAX_CONTEXT_LEN,
+            format!("context must be at most {} bytes", MAX_CONTEXT_LEN)
+        );
+        require!(self.is_human(account.clone()), "account is not a human");
+        let key = self
+            .used_context_key(&env::predecessor_account_id(), &account, &context)
+            .unwrap();
+        let storage_start = env::storage_usage();
+        if !self.used_contexts.insert(&key) {
+            let deposit = env::attached_deposit();
+            if deposit > 0 {
+                Promise::new(env::predecessor_account_id()).transfer(deposit);
+            }
+            return false;
+        }
+        assert_storage_deposit(storage_start, env::attached_deposit());
+        true
+    }
+
+    /// Returns true if the human owning the `account` used the `context` in the `app`
+    /// namespace, see `mark_used`.
+    pub fn is_used(&self, app: AccountId, account: AccountId, context: String) -> bool {
+        self.used_context_key(&app, &account, &context)
+            .map_or(false, |key| self.used_contexts.contains(&key))
+    }
+}
+
+impl Contract {
+    /// Returns the used context marker key, or None if the `account` doesn't have the human
+    /// proof token.
+    fn used_context_key(
+        &self,
+        app: &AccountId,
+        account: &AccountId,
+        context: &str,
+    ) -> Option<CryptoHash> {
+        let issuer_id = self.sbt_issuers.get(&self.iah_classes.0)?;
+        let class_id = self.iah_classes.1[0];
+        let token = self
+            .balances
+            .get(&balance_key(account.clone(), issuer_id, class_id))?;
+        let data = (app, context, IssuerTokenId { issuer_id, token })
+            .try_to_vec()
+            .unwrap();
+        Some(env::sha256_array(&data))
+    }
+}
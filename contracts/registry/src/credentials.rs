@@ -0,0 +1,187 @@
+//! Credential aliases: the authority maps semantic credential names (eg "face-verification")
+//! to concrete `(issuer, class)` pairs, so apps don't need to hardcode issuer accounts and
+//! class numbers.
+//!
+//! Composite credentials are derived from holding several classes, eg "OG human" = a proof of
+//! humanity issued before a date AND a face verification, so a new credential doesn't require
+//! re-minting tokens to the holders.
+
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{near_bindgen, require, AccountId};
+
+use crate::*;
+
+/// Max number of `(issuer, class)` pairs of a credential alias.
+pub const MAX_CREDENTIAL_CLASSES: usize = 10;
+
+/// Requirement of a composite credential: a valid token of the issuer class, optionally
+/// issued before a date.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq, Clone))]
+pub struct CredentialTerm {
+    pub issuer: AccountId,
+    pub class: ClassId,
+    /// the token `issued_at` must be before this Unix timestamp in milliseconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub issued_before: Option<u64>,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets (or removes, when `None`) the credential alias `name`. An account has the
+    /// credential if it has a valid token of any of the `(issuer, class)` pairs.
+    /// Must be called by the authority.
+    pub fn admin_set_credential(
+        &mut self,
+        name: String,
+        classes: Option<Vec<(AccountId, ClassId)>>,
+    ) {
+        self.assert_admin_action("admin_set_credential", None);
+        require!(!name.is_empty(), "name must be a non empty string");
+        require!(
+            !self.composite_credentials.contains_key(&name),
+            "name is already used by a composite credential"
+        );
+        match classes {
+            None => {
+                self.credentials.remove(&name);
+            }
+            Some(classes) => {
+                require!(
+                    !classes.is_empty() && classes.len() <= MAX_CREDENTIAL_CLASSES,
+                    format!(
+                        "credential must map to between 1 and {} classes",
+                        MAX_CREDENTIAL_CLASSES
+                    )
+                );
+                let classes: Vec<(IssuerId, ClassId)> = classes
+                    .into_iter()
+                    .map(|(issuer, class)| (self.assert_issuer(&issuer), class))
+                    .collect();
+                self.credentials.insert(&name, &classes);
+            }
+        }
+    }
+
+    /// Sets (or removes, when `None`) the composite credential `name`. An account has the
+    /// credential if it satisfies all the `terms`. Must be called by the authority.
+    pub fn admin_set_composite_credential(
+        &mut self,
+        name: String,
+        terms: Option<Vec<CredentialTerm>>,
+    ) {
+        self.assert_admin_action("admin_set_composite_credential", None);
+        require!(!name.is_empty(), "name must be a non empty string");
+        require!(
+            !self.credentials.contains_key(&name),
+            "name is already used by a credential alias"
+        );
+        match terms {
+            None => {
+                self.composite_credentials.remove(&name);
+            }
+            Some(terms) => {
+                require!(
+                    !terms.is_empty() && terms.len() <= MAX_CREDENTIAL_CLASSES,
+                    format!(
+                        "credential must map to between 1 and {} classes",
+                        MAX_CREDENTIAL_CLASSES
+                    )
+                );
+                let terms: Vec<(IssuerId, ClassId, Option<u64>)> = terms
+                    .into_iter()
+                    .map(|t| (self.assert_issuer(&t.issuer), t.class, t.issued_before))
+                    .collect();
+                self.composite_credentials.insert(&name, &terms);
+            }
+        }
+    }
+
+    /// Returns the terms of the composite credential (empty if the credential is not
+    /// defined).
+    pub fn composite_credential(&self, name: String) -> Vec<CredentialTerm> {
+        self.composite_credentials
+            .get(&name)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(issuer_id, class, issued_before)| CredentialTerm {
+                issuer: self.issuer_account(issuer_id),
+                class,
+                issued_before,
+            })
+            .collect()
+    }
+
+    /// Returns `(issuer, class)` pairs of the credential alias (empty if the alias is not
+    /// defined).
+    pub fn resolve_credential(&self, name: String) -> Vec<(AccountId, ClassId)> {
+        self.credentials
+            .get(&name)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(issuer_id, class)| (self.issuer_account(issuer_id), class))
+            .collect()
+    }
+
+    /// Returns true if the account is not banned and has a valid (not expired) token of
+    /// any class of the credential alias, or satisfies all the terms of the composite
+    /// credential.
+    pub fn has_credential(&self, account: AccountId, name: String) -> bool {
+        self._has_credential(account, name, MilliSeconds::now())
+    }
+}
+
+impl Contract {
+    /// `has_credential` evaluating the bans and token validity at `now`.
+    pub(crate) fn _has_credential(
+        &self,
+        account: AccountId,
+        name: String,
+        now: MilliSeconds,
+    ) -> bool {
+        if self.is_banned_at(&account, now) || self.ongoing_soul_tx.contains_key(&account) {
+            return false;
+        }
+        if let Some(terms) = self.composite_credentials.get(&name) {
+            return terms.into_iter().all(|(issuer_id, class, issued_before)| {
+                self.valid_token(&account, issuer_id, class, now)
+                    .map_or(false, |t| match (issued_before, t.metadata.issued_at()) {
+                        (None, _) => true,
+                        (Some(before), Some(issued_at)) => issued_at < before,
+                        (Some(_), None) => false,
+                    })
+            });
+        }
+        self.credentials
+            .get(&name)
+            .unwrap_or_default()
+            .into_iter()
+            .any(|(issuer_id, class)| self.has_valid_token(&account, issuer_id, class, now))
+    }
+
+    /// Returns true if the account has a token of the issuer class valid at `now`.
+    pub(crate) fn has_valid_token(
+        &self,
+        account: &AccountId,
+        issuer_id: IssuerId,
+        class: ClassId,
+        now: MilliSeconds,
+    ) -> bool {
+        self.valid_token(account, issuer_id, class, now).is_some()
+    }
+
+    /// Returns the account token of the issuer class if it's valid at `now`.
+    pub(crate) fn valid_token(
+        &self,
+        account: &AccountId,
+        issuer_id: IssuerId,
+        class: ClassId,
+        now: MilliSeconds,
+    ) -> Option<TokenData> {
+        self.balances
+            .get(&balance_key(account.clone(), issuer_id, class))
+            .and_then(|token| self.issuer_tokens.get(&IssuerTokenId { issuer_id, token }))
+            .filter(|t| t.metadata.is_valid_at(now))
+    }
+}
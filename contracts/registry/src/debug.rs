@@ -0,0 +1,59 @@
+//! Raw state dump views, only available in the `testnet` or `debug` build. Auditors and
+//! migration tooling use them to diff the registry state between versions.
+
+use near_sdk::borsh::BorshSerialize;
+use near_sdk::json_types::Base64VecU8;
+use near_sdk::serde::Serialize;
+use near_sdk::{near_bindgen, AccountId};
+use sbt::*;
+
+use crate::*;
+
+/// Borsh serialized key and value of a persistent map entry (without the collection prefix).
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq))]
+pub struct RawRecord {
+    pub key: Base64VecU8,
+    pub value: Base64VecU8,
+}
+
+fn raw_record<K: BorshSerialize, V: BorshSerialize>(key: &K, value: &V) -> RawRecord {
+    RawRecord {
+        key: key.try_to_vec().unwrap().into(),
+        value: value.try_to_vec().unwrap().into(),
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Returns raw `issuer_tokens` records of the issuer, for token IDs in
+    /// `[from, from + limit)`. Burned tokens are skipped.
+    pub fn debug_dump_tokens(
+        &self,
+        issuer: AccountId,
+        from: TokenId,
+        limit: u32,
+    ) -> Vec<RawRecord> {
+        let issuer_id = self.assert_issuer(&issuer);
+        let last = self.next_token_ids.get(&issuer_id).unwrap_or(0);
+        (from..std::cmp::min(from.saturating_add(limit as u64), last + 1))
+            .filter_map(|token| {
+                let key = IssuerTokenId { issuer_id, token };
+                self.issuer_tokens
+                    .get_stored(&key)
+                    .map(|t| raw_record(&key, &t))
+            })
+            .collect()
+    }
+
+    /// Returns raw `balances` records of the owner.
+    pub fn debug_dump_balances(&self, owner: AccountId) -> Vec<RawRecord> {
+        // iter_from starts from exclusive "left end", issuer and class IDs start from 1.
+        self.balances
+            .iter_from(balance_key(owner.clone(), 0, 0))
+            .take_while(|(key, _)| key.owner == owner)
+            .map(|(key, token)| raw_record(&key, &token))
+            .collect()
+    }
+}
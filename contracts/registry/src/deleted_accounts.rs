@@ -0,0 +1,89 @@
+//! Handling of deleted and recreated accounts. A NEAR account can be deleted and then created
+//! again by a different person, who would inherit the SBTs of the previous owner. The
+//! authority flags such accounts: flagged accounts are not considered humans until the IAH
+//! issuer attests the owner continuity, or the authority purges the old tokens.
+
+use near_sdk::{env, near_bindgen, require, AccountId};
+
+use crate::*;
+
+#[near_bindgen]
+impl Contract {
+    /// Flags the `account` as possibly deleted and recreated by a different owner. Until the
+    /// flag is cleared (by `sbt_attest_continuity` or `admin_purge_deleted_account`),
+    /// `is_human` returns false for the account. Must be called by the authority.
+    pub fn admin_flag_recreated_account(&mut self, account: AccountId) {
+        self.assert_admin_action("admin_flag_recreated_account", None);
+        self.recreated_accounts
+            .insert(&account, &env::block_timestamp_ms());
+    }
+
+    /// Returns the time (unix timestamp in miliseconds) when the account was flagged as
+    /// recreated, or None if the account is not flagged.
+    pub fn recreated_account_flagged_at(&self, account: AccountId) -> Option<u64> {
+        self.recreated_accounts.get(&account)
+    }
+
+    /// Attests that the flagged `account` is still controlled by the same owner, clearing
+    /// the flag. Must be called by the IAH issuer, after verifying the owner off-chain.
+    pub fn sbt_attest_continuity(&mut self, account: AccountId) {
+        require!(
+            env::predecessor_account_id() == self.iah_classes.0,
+            "must be called by the IAH issuer"
+        );
+        require!(
+            self.recreated_accounts.remove(&account).is_some(),
+            "account is not flagged as recreated"
+        );
+        self.log(format!(
+            "continuity of {} attested by {}",
+            account, self.iah_classes.0
+        ));
+    }
+
+    /// Burns tokens of the deleted (and possibly recreated) `account`, from all issuers.
+    /// Burns at most `limit` tokens and returns the number of burned tokens and a boolean:
+    /// `true` when all account tokens were burned. Must be called again until `true` is
+    /// returned. Once completed, the recreated flag is cleared. The account is not banned:
+    /// the new owner can receive new SBTs. Emits a `Burn` event per issuer.
+    /// Must be called by the authority.
+    pub fn admin_purge_deleted_account(&mut self, account: AccountId, limit: u32) -> (u32, bool) {
+        self.assert_admin_action("admin_purge_deleted_account", None);
+        require!(limit > 0, "limit must be bigger than 0");
+        require!(
+            !self.ongoing_soul_tx.contains_key(&account),
+            "soul transfer is in progress"
+        );
+        // iter_from starts from exclusive "left end", issuer and class IDs start from 1.
+        let batch: Vec<(BalanceKey, TokenId)> = self
+            .balances
+            .iter_from(balance_key(account.clone(), 0, 0))
+            .take_while(|(key, _)| key.owner == account)
+            .take(limit as usize + 1)
+            .collect();
+        let done = batch.len() <= limit as usize;
+        let mut burned: Vec<(IssuerId, Vec<TokenId>)> = Vec::new();
+        for (key, token) in batch.into_iter().take(limit as usize) {
+            match burned.last_mut() {
+                Some((issuer_id, tokens)) if *issuer_id == key.issuer_id => tokens.push(token),
+                _ => burned.push((key.issuer_id, vec![token])),
+            }
+        }
+
+        let mut num_burned = 0;
+        for (issuer_id, tokens) in burned {
+            num_burned += tokens.len() as u32;
+            self.burn_tokens(issuer_id, &tokens, TokenOp::Burn);
+            self.emit_event(Nep393Event::Burn(SbtTokensEvent {
+                issuer: self.issuer_account(issuer_id),
+                tokens,
+                storage: None,
+                reason: None,
+            }));
+        }
+        if done {
+            self.recreated_accounts.remove(&account);
+        }
+        (num_burned, done)
+    }
+}
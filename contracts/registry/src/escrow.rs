@@ -0,0 +1,155 @@
+//! Mint storage escrow: a payer (eg a community treasury) deposits NEAR for an issuer, and
+//! the issuer mints with `sbt_mint_with_payer` without attaching a deposit. This allows to
+//! sponsor the storage while a separate (oracle) account signs the mint transactions.
+//! An issuer can also prepay its own storage (`issuer_storage_deposit`): `sbt_mint` calls
+//! without an attached deposit draw down from the issuer prepaid balance.
+
+use near_sdk::collections::LookupMap;
+use near_sdk::json_types::U128;
+use near_sdk::{env, near_bindgen, require, AccountId, Balance, Promise};
+
+use crate::*;
+
+/// (account, issuer) -> NEAR balance, eg the mint escrow.
+pub(crate) type IssuerBalances = LookupMap<(AccountId, IssuerId), Balance>;
+
+#[near_bindgen]
+impl Contract {
+    /// Deposits the attached NEAR into the caller escrow for the `issuer`. Only the `issuer`
+    /// can use the escrow, to pay the storage of tokens minted with `sbt_mint_with_payer`.
+    /// The storage of a new escrow entry is paid from the deposit.
+    /// Returns the escrow balance.
+    #[payable]
+    pub fn sbt_escrow_deposit(&mut self, issuer: AccountId) -> U128 {
+        let issuer_id = self.assert_issuer(&issuer);
+        deposit_balance(
+            &mut self.mint_escrow,
+            (env::predecessor_account_id(), issuer_id),
+        )
+    }
+
+    /// Withdraws `amount` (all, if not specified) from the caller escrow for the `issuer`.
+    /// Escrows for a removed issuer can be withdrawn too.
+    pub fn sbt_escrow_withdraw(&mut self, issuer: AccountId, amount: Option<U128>) -> Promise {
+        let key = self
+            .issuer_balance_key(&self.mint_escrow, env::predecessor_account_id(), &issuer)
+            .expect("issuer not found");
+        withdraw_balance(&mut self.mint_escrow, key, amount)
+    }
+
+    /// Returns the `payer` escrow balance for the `issuer` (also a removed issuer).
+    pub fn escrow_balance(&self, payer: AccountId, issuer: AccountId) -> U128 {
+        let balance = match self.issuer_balance_key(&self.mint_escrow, payer, &issuer) {
+            None => 0,
+            Some(key) => self.mint_escrow.get(&key).unwrap_or(0),
+        };
+        balance.into()
+    }
+
+    /// Deposits the attached NEAR into the caller (issuer) prepaid storage balance.
+    /// `sbt_mint` calls without an attached deposit pay the storage from this balance.
+    /// Returns the prepaid balance.
+    #[payable]
+    pub fn issuer_storage_deposit(&mut self) -> U128 {
+        let issuer = env::predecessor_account_id();
+        let issuer_id = self.assert_issuer(&issuer);
+        deposit_balance(&mut self.mint_escrow, (issuer, issuer_id))
+    }
+
+    /// Withdraws `amount` from the caller (issuer) prepaid storage balance. A removed issuer
+    /// can withdraw its prepaid balance too.
+    pub fn issuer_storage_withdraw(&mut self, amount: U128) -> Promise {
+        let issuer = env::predecessor_account_id();
+        let key = self
+            .issuer_balance_key(&self.mint_escrow, issuer.clone(), &issuer)
+            .expect("issuer not found");
+        withdraw_balance(&mut self.mint_escrow, key, Some(amount))
+    }
+
+    /// Returns the issuer prepaid storage balance, see `issuer_storage_deposit`.
+    pub fn issuer_storage_balance(&self, issuer: AccountId) -> U128 {
+        self.escrow_balance(issuer.clone(), issuer)
+    }
+
+    /// Same as `sbt_mint`, but the storage is paid from the `payer` escrow for the caller
+    /// (see `sbt_escrow_deposit`) instead of the attached deposit.
+    /// Panics if the escrow balance doesn't cover the storage.
+    pub fn sbt_mint_with_payer(
+        &mut self,
+        token_spec: Vec<(AccountId, Vec<TokenMetadata>)>,
+        payer: AccountId,
+    ) -> Vec<TokenId> {
+        self.mint_from_escrow(&env::predecessor_account_id(), payer, token_spec)
+    }
+}
+
+impl Contract {
+    /// Returns the `balances` key of the `account` balance for the `issuer`: the registered
+    /// issuer ID, or an ID of the removed issuer if the account has a balance for it. None if
+    /// the issuer was never registered.
+    pub(crate) fn issuer_balance_key(
+        &self,
+        balances: &IssuerBalances,
+        account: AccountId,
+        issuer: &AccountId,
+    ) -> Option<(AccountId, IssuerId)> {
+        let registered = self.sbt_issuers.get(issuer);
+        let removed = self.removed_issuers.get(issuer).unwrap_or_default();
+        let issuer_id = registered
+            .into_iter()
+            .chain(removed.iter().copied())
+            .find(|id| balances.contains_key(&(account.clone(), *id)))
+            .or_else(|| registered.or_else(|| removed.last().copied()))?;
+        Some((account, issuer_id))
+    }
+
+    /// Mints tokens paying the storage from the `payer` escrow for the `issuer`.
+    pub(crate) fn mint_from_escrow(
+        &mut self,
+        issuer: &AccountId,
+        payer: AccountId,
+        token_spec: Vec<(AccountId, Vec<TokenMetadata>)>,
+    ) -> Vec<TokenId> {
+        let key = (payer, self.assert_issuer(issuer));
+        let balance = self.mint_escrow.get(&key).unwrap_or(0);
+        let (tokens, used) = self.mint_with_deposit(issuer, token_spec, balance, None);
+        self.mint_escrow.insert(&key, &(balance - used));
+        tokens
+    }
+}
+
+/// Adds the attached deposit to the `key` balance. The storage of a new entry is paid from
+/// the deposit. Returns the balance.
+pub(crate) fn deposit_balance(balances: &mut IssuerBalances, key: (AccountId, IssuerId)) -> U128 {
+    let storage_start = env::storage_usage();
+    let balance = balances.get(&key).unwrap_or(0);
+    balances.insert(&key, &balance);
+    let storage_cost = required_storage_deposit(storage_start);
+    require!(
+        env::attached_deposit() > storage_cost,
+        format!("deposit must be bigger than {} yNEAR", storage_cost)
+    );
+    let balance = balance + env::attached_deposit() - storage_cost;
+    balances.insert(&key, &balance);
+    balance.into()
+}
+
+/// Withdraws `amount` (all, if not specified) from the `key` balance to the key account.
+pub(crate) fn withdraw_balance(
+    balances: &mut IssuerBalances,
+    key: (AccountId, IssuerId),
+    amount: Option<U128>,
+) -> Promise {
+    let balance = balances.get(&key).unwrap_or(0);
+    let amount = amount.map_or(balance, |a| a.0);
+    require!(
+        amount > 0 && amount <= balance,
+        format!("amount must be between 1 and {} yNEAR", balance)
+    );
+    if amount == balance {
+        balances.remove(&key);
+    } else {
+        balances.insert(&key, &(balance - amount));
+    }
+    Promise::new(key.0).transfer(amount)
+}
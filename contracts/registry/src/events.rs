@@ -1 +1,246 @@
+use near_sdk::json_types::{Base64VecU8, U128};
+use near_sdk::serde::Serialize;
+use near_sdk::{env, require, AccountId};
+use sbt::{ClassId, NearEvent, Nep393Event, RevocationReason, TokenId};
 
+use crate::{AdminAction, Capabilities, Contract};
+
+/// Tamper evident digest of all events emitted by the registry. Auditors can recompute
+/// the digest from an indexer's event history and compare it with the contract state.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq))]
+pub struct EventDigest {
+    /// `root_n = sha256(root_{n-1} || event_n)`, where `event_n` is the n-th event log
+    /// line (including the `EVENT_JSON:` prefix) and `root_0` is 32 zero bytes.
+    pub root: Base64VecU8,
+    /// number of events included in the digest.
+    pub count: u64,
+}
+
+/// Computes the next event root by hashing the previous root with the event log line.
+pub fn chain_event_root(root: &[u8; 32], event: &str) -> [u8; 32] {
+    let mut data = Vec::with_capacity(32 + event.len());
+    data.extend_from_slice(root);
+    data.extend_from_slice(event.as_bytes());
+    env::sha256_array(&data)
+}
+
+pub const REGISTRY_STANDARD_NAME: &str = "i_am_human_registry";
+pub const REGISTRY_EVENTS_VERSION: &str = "1.0.0";
+/// Max length (in bytes) of the memo attached to admin actions.
+pub const MAX_MEMO_LEN: usize = 256;
+
+/// Registry specific (non NEP-393) events, used to audit admin actions.
+#[derive(Serialize)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq, Clone))]
+#[serde(tag = "event", content = "data")]
+#[serde(rename_all = "snake_case")]
+#[serde(crate = "near_sdk::serde")]
+pub enum RegistryEvent<'a> {
+    /// Owner balance was overwritten by the authority. `token: None` means that the balance
+    /// was removed.
+    FixBalance {
+        owner: &'a AccountId,
+        issuer: &'a AccountId,
+        class: ClassId,
+        token: Option<TokenId>,
+    },
+    /// Tokens moved in a single soul transfer call, grouped by issuer. Emitted by every call
+    /// which moved at least one token. The NEP-393 `soul_transfer` event is emitted once,
+    /// when the whole process is completed.
+    SoulTransferBatch {
+        from: &'a AccountId,
+        to: &'a AccountId,
+        tokens: Vec<(AccountId, Vec<TokenId>)>,
+    },
+    /// Soul transfer was aborted by the owner with `sbt_soul_transfer_cancel`. Tokens moved
+    /// before the cancellation stay with the recipient, the rest stays with `from`.
+    SoulTransferAborted { from: &'a AccountId },
+    /// Token not referenced by the owner balance was removed by the authority.
+    RemoveOrphanToken {
+        issuer: &'a AccountId,
+        token: TokenId,
+    },
+    /// New issuer was registered by the authority.
+    AddIssuer {
+        issuer: &'a AccountId,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        memo: Option<&'a str>,
+    },
+    /// Issuer capabilities were overwritten by the authority.
+    SetIssuerCapabilities {
+        issuer: &'a AccountId,
+        capabilities: Capabilities,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        memo: Option<&'a str>,
+    },
+    /// Registry was paused by the authority.
+    Pause {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        memo: Option<&'a str>,
+    },
+    /// Admin action was added to the timelock queue.
+    QueueAction {
+        id: u64,
+        action: &'a AdminAction,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        memo: Option<&'a str>,
+    },
+    /// Queued admin action was executed.
+    ExecuteAction {
+        id: u64,
+        action: &'a AdminAction,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        memo: Option<&'a str>,
+    },
+    /// Queued admin action was removed from the queue.
+    CancelAction {
+        id: u64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        memo: Option<&'a str>,
+    },
+    /// Account was banned by the authority, until the `until` time (unix timestamp in
+    /// miliseconds) or permanently when `until` is None.
+    AdminBan {
+        account: &'a AccountId,
+        until: Option<u64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        memo: Option<&'a str>,
+    },
+    /// Temporary ban was lifted by the authority or expired.
+    BanLifted {
+        account: &'a AccountId,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        memo: Option<&'a str>,
+    },
+    /// Tokens were frozen by the authority, see `admin_freeze_tokens`.
+    FreezeTokens {
+        issuer: &'a AccountId,
+        tokens: &'a [TokenId],
+        #[serde(skip_serializing_if = "Option::is_none")]
+        memo: Option<&'a str>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        reason: Option<RevocationReason>,
+    },
+    /// Frozen tokens were unfrozen by the authority.
+    UnfreezeTokens {
+        issuer: &'a AccountId,
+        tokens: &'a [TokenId],
+        #[serde(skip_serializing_if = "Option::is_none")]
+        memo: Option<&'a str>,
+    },
+    /// Issuer started unbonding its bond, see `issuer_unbond`.
+    IssuerUnbond { issuer: &'a AccountId },
+    /// Issuer bond was slashed by the authority and `amount` was sent to the `treasury`.
+    SlashIssuer {
+        issuer: &'a AccountId,
+        amount: U128,
+        treasury: &'a AccountId,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        memo: Option<&'a str>,
+    },
+    /// Lazy state migration (see `migrate_chunk`) was completed. `migrated` is the total
+    /// number of converted records.
+    MigrationCompleted { migrated: u64 },
+    /// Genesis import was finalized by the authority, see `finalize_genesis`.
+    FinalizeGenesis,
+    /// Stamps of the issuer class were minted, see `sbt_mint_stamps`.
+    MintStamps {
+        issuer: &'a AccountId,
+        class: ClassId,
+        recipients: &'a [AccountId],
+    },
+    /// Stamps of the issuer class were revoked, see `sbt_revoke_stamps`.
+    RevokeStamps {
+        issuer: &'a AccountId,
+        class: ClassId,
+        accounts: &'a [AccountId],
+    },
+    /// Token IDs `first..=last` were reserved by the issuer until `expires_at` (unix
+    /// timestamp in miliseconds), see `reserve_token_ids`.
+    ReserveTokenIds {
+        issuer: &'a AccountId,
+        first: TokenId,
+        last: TokenId,
+        expires_at: u64,
+    },
+    /// Expire time of the issuer `class` tokens was clamped to `expires_at` (unix timestamp
+    /// in miliseconds) by `apply_ttl_policy`.
+    ApplyTtlPolicy {
+        issuer: &'a AccountId,
+        class: ClassId,
+        expires_at: u64,
+        tokens: Vec<TokenId>,
+    },
+    /// Emergency guardian was proposed by the authority, see `propose_guardian`.
+    ProposeGuardian {
+        guardian: &'a AccountId,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        memo: Option<&'a str>,
+    },
+    /// Proposed guardian accepted the role, see `accept_guardian`.
+    AcceptGuardian { guardian: &'a AccountId },
+}
+
+impl RegistryEvent<'_> {
+    /// creates a string compatible with NEAR event standard
+    pub fn to_json_event_string(self) -> String {
+        let e = NearEvent {
+            standard: REGISTRY_STANDARD_NAME,
+            version: REGISTRY_EVENTS_VERSION,
+            event: self,
+        };
+        let s = serde_json::to_string(&e)
+            .ok()
+            .unwrap_or_else(|| env::abort());
+        format!("EVENT_JSON:{}", s)
+    }
+}
+
+impl Contract {
+    /// Emits the event and updates the event digest.
+    pub(crate) fn emit_event(&mut self, event: Nep393Event) {
+        self.log_event(event.to_json_event_string());
+    }
+
+    /// Emits the registry event and updates the event digest.
+    pub(crate) fn emit_registry_event(&mut self, event: RegistryEvent) {
+        self.log_event(event.to_json_event_string());
+    }
+
+    pub(crate) fn assert_memo(&self, memo: &Option<String>) {
+        if let Some(m) = memo {
+            require!(
+                m.len() <= MAX_MEMO_LEN,
+                format!("memo must be at most {} bytes", MAX_MEMO_LEN)
+            );
+        }
+    }
+
+    /// Logs a plain (non event) message, eg a skipped step or a failed callback. Messages
+    /// are not part of the event digest.
+    pub(crate) fn log(&mut self, msg: String) {
+        write_log(&msg);
+    }
+
+    fn log_event(&mut self, log: String) {
+        self.event_root = chain_event_root(&self.event_root, &log);
+        self.event_count += 1;
+        write_log(&log);
+    }
+}
+
+/// Logging facade of the registry: all logs go through `Contract::log`,
+/// `Contract::emit_event` and `Contract::emit_registry_event` (direct `env::log_str` calls
+/// are rejected by clippy, see `clippy.toml`).
+/// * View context: the facade requires `&mut Contract`. `#[near_bindgen]` views borrow the
+///   contract immutably, so a view can't emit a log (the runtime doesn't expose whether a
+///   call is a view: the host functions which would tell, eg `prepaid_gas`, panic in views).
+/// * Events are not deduplicated: equal events emitted in one receipt (eg two equal revoke
+///   batches) are distinct events. A retried callback runs in a new receipt, so handlers
+///   must not emit again for a step which was already applied.
+#[allow(clippy::disallowed_methods)]
+fn write_log(log: &str) {
+    env::log_str(log);
+}
@@ -0,0 +1,196 @@
+//! Day bucketed index of token expire times, used to notify owners about expiring tokens.
+//! Every (day, issuer) bucket is a doubly linked list of its tokens with a record per token,
+//! so indexing a token doesn't rewrite the bucket.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::serde::Serialize;
+use near_sdk::{near_bindgen, require, AccountId};
+use sbt::TokenId;
+
+use crate::*;
+
+pub const DAY_MS: u64 = 24 * 3600 * 1000;
+
+const DEFAULT_LIMIT: u32 = 100;
+
+/// Token expiring on a given day, see `expirations_on`.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq))]
+pub struct ExpiringToken {
+    pub owner: AccountId,
+    pub issuer: AccountId,
+    pub token: TokenId,
+    /// unix timestamp in miliseconds
+    pub expires_at: u64,
+}
+
+/// (day, issuer) bucket of the expirations index.
+#[derive(BorshSerialize, BorshDeserialize, Default)]
+pub(crate) struct ExpirationBucket {
+    /// number of tokens in the bucket.
+    pub len: u32,
+    /// first and last token of the bucket list, see `Contract::expiring_tokens`.
+    pub first: TokenId,
+    pub last: TokenId,
+}
+
+/// Returns day number (days since the unix epoch) of the given unix timestamp in miliseconds.
+#[inline]
+pub fn expiration_day(expires_at: u64) -> u64 {
+    expires_at / DAY_MS
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Returns tokens expiring on the given `day` (number of days since the unix epoch, i.e.
+    /// `expires_at / DAY_MS`), ordered by issuer. Revoked and burned tokens are not listed.
+    /// `from_index` is the number of tokens to skip (default 0), `limit` is the max number of
+    /// returned tokens (default 100). The order of tokens of the same issuer is not
+    /// specified, but it's stable as long as the bucket is not modified.
+    pub fn expirations_on(
+        &self,
+        day: u64,
+        from_index: Option<u32>,
+        limit: Option<u32>,
+    ) -> Vec<ExpiringToken> {
+        let limit = limit.unwrap_or(DEFAULT_LIMIT) as usize;
+        require!(limit > 0, "limit must be bigger than 0");
+        let mut skip = from_index.unwrap_or(0) as usize;
+        let mut resp = Vec::new();
+        for issuer_id in 1..self.next_issuer_id {
+            let len = match self.expirations.get(&(day, issuer_id)) {
+                None => continue,
+                Some(b) => b.len as usize,
+            };
+            if skip >= len {
+                skip -= len;
+                continue;
+            }
+            let issuer = self.issuer_account(issuer_id);
+            for token in self.expiring_tokens_on(day, issuer_id).skip(skip) {
+                let t = self.get_token(issuer_id, token);
+                resp.push(ExpiringToken {
+                    expires_at: t.metadata.expires_at().unwrap(),
+                    owner: t.owner,
+                    issuer: issuer.clone(),
+                    token,
+                });
+                if resp.len() == limit {
+                    return resp;
+                }
+            }
+            skip = 0;
+        }
+        resp
+    }
+}
+
+impl Contract {
+    /// Returns the tokens of the (`day`, issuer) bucket, in the order they were indexed.
+    pub(crate) fn expiring_tokens_on(
+        &self,
+        day: u64,
+        issuer_id: IssuerId,
+    ) -> impl Iterator<Item = TokenId> + '_ {
+        let first = self
+            .expirations
+            .get(&(day, issuer_id))
+            .map_or(0, |b| b.first);
+        std::iter::successors(Some(first).filter(|t| *t != 0), move |token| {
+            let (_, next) = self.expiring_tokens.get(&(day, issuer_id, *token))?;
+            Some(next).filter(|t| *t != 0)
+        })
+    }
+
+    /// Updates the expirations index when the token expire time changes from `old` to `new`.
+    /// Use `None` as `new` when a token is removed.
+    pub(crate) fn index_expiration(
+        &mut self,
+        issuer_id: IssuerId,
+        token: TokenId,
+        old: Option<u64>,
+        new: Option<u64>,
+    ) {
+        let old = old.map(expiration_day);
+        let new = new.map(expiration_day);
+        if old == new {
+            return;
+        }
+        if let Some(day) = old {
+            self.unindex_expiration(issuer_id, day, token);
+        }
+        if let Some(day) = new {
+            self.index_expirations(issuer_id, day, vec![token]);
+        }
+    }
+
+    /// Appends new tokens to the (`day`, issuer) bucket of the expirations index.
+    pub(crate) fn index_expirations(
+        &mut self,
+        issuer_id: IssuerId,
+        day: u64,
+        tokens: Vec<TokenId>,
+    ) {
+        if tokens.is_empty() {
+            return;
+        }
+        let key = (day, issuer_id);
+        let mut b = self.expirations.get(&key).unwrap_or_default();
+        let mut prev = b.last;
+        if prev == 0 {
+            b.first = tokens[0];
+        } else {
+            self.link_expiration(day, issuer_id, prev, |l| l.1 = tokens[0]);
+        }
+        for (i, token) in tokens.iter().enumerate() {
+            let next = tokens.get(i + 1).copied().unwrap_or(0);
+            self.expiring_tokens
+                .insert(&(day, issuer_id, *token), &(prev, next));
+            prev = *token;
+        }
+        b.last = prev;
+        b.len += tokens.len() as u32;
+        self.expirations.insert(&key, &b);
+    }
+
+    /// Removes the token from the (`day`, issuer) bucket of the expirations index.
+    fn unindex_expiration(&mut self, issuer_id: IssuerId, day: u64, token: TokenId) {
+        let (prev, next) = match self.expiring_tokens.remove(&(day, issuer_id, token)) {
+            None => return,
+            Some(l) => l,
+        };
+        let key = (day, issuer_id);
+        let mut b = self.expirations.get(&key).unwrap_or_default();
+        if prev == 0 {
+            b.first = next;
+        } else {
+            self.link_expiration(day, issuer_id, prev, |l| l.1 = next);
+        }
+        if next == 0 {
+            b.last = prev;
+        } else {
+            self.link_expiration(day, issuer_id, next, |l| l.0 = prev);
+        }
+        b.len = b.len.saturating_sub(1);
+        if b.len == 0 {
+            self.expirations.remove(&key);
+        } else {
+            self.expirations.insert(&key, &b);
+        }
+    }
+
+    /// Updates the (previous, next) links of the indexed token.
+    fn link_expiration(
+        &mut self,
+        day: u64,
+        issuer_id: IssuerId,
+        token: TokenId,
+        f: impl FnOnce(&mut (TokenId, TokenId)),
+    ) {
+        let key = (day, issuer_id, token);
+        let mut links = self.expiring_tokens.get(&key).unwrap_or_default();
+        f(&mut links);
+        self.expiring_tokens.insert(&key, &links);
+    }
+}
@@ -0,0 +1,143 @@
+//! Read replica export: `export_state` returns the registry issuers, tokens and balances in
+//! Borsh encoded chunks (`sbt::ExportChunk`, decoded with `sbt::decode_export_chunk`).
+//! Token statuses, bans and other state are not exported. Tokens of private accounts (see
+//! `set_private`) are exported redacted, same as in the token views.
+
+use near_sdk::borsh::{BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::Base64VecU8;
+use near_sdk::{near_bindgen, require};
+use sbt::*;
+
+use crate::privacy::redact;
+use crate::registry::MAX_LIMIT;
+use crate::*;
+
+#[near_bindgen]
+impl Contract {
+    /// Returns a Borsh encoded `sbt::ExportChunk` of the `section` records, starting from the
+    /// `from` cursor (the `next` cursor of the previous chunk, or None for the first chunk).
+    /// `limit` (default and max `MAX_LIMIT`) bounds the number of records. For the `tokens`
+    /// section it bounds the number of scanned token IDs, so a chunk can have fewer records
+    /// (IDs of burned tokens are skipped) and the export continues while `next` is set.
+    pub fn export_state(
+        &self,
+        section: ExportSection,
+        from: Option<Base64VecU8>,
+        limit: Option<u32>,
+    ) -> Base64VecU8 {
+        let limit = limit.unwrap_or(MAX_LIMIT);
+        require!(
+            limit > 0 && limit <= MAX_LIMIT,
+            format!("limit must be between 1 and {}", MAX_LIMIT)
+        );
+        let from = from.map(|c| c.0);
+        let (records, next) = match section {
+            ExportSection::Issuers => self.export_issuers(from, limit),
+            ExportSection::Tokens => self.export_tokens(from, limit),
+            ExportSection::Balances => self.export_balances(from, limit),
+        };
+        ExportChunk {
+            version: EXPORT_FORMAT_VERSION,
+            records,
+            next,
+        }
+        .try_to_vec()
+        .unwrap()
+        .into()
+    }
+}
+
+impl Contract {
+    /// cursor: next issuer ID.
+    fn export_issuers(
+        &self,
+        from: Option<Vec<u8>>,
+        limit: u32,
+    ) -> (ExportRecords, Option<Vec<u8>>) {
+        let start: IssuerId = from.map_or(1, |c| decode_cursor(&c));
+        let end = start.saturating_add(limit).min(self.next_issuer_id);
+        let records = (start..end)
+            .filter_map(|issuer_id| {
+                let account = self.issuer_id_map.get(&issuer_id)?;
+                let registered = self.sbt_issuers.get(&account) == Some(issuer_id);
+                Some(ExportedIssuer {
+                    issuer_id,
+                    account,
+                    registered,
+                })
+            })
+            .collect();
+        let next = (end < self.next_issuer_id).then(|| end.try_to_vec().unwrap());
+        (ExportRecords::Issuers(records), next)
+    }
+
+    /// cursor: (issuer ID, next token ID).
+    fn export_tokens(&self, from: Option<Vec<u8>>, limit: u32) -> (ExportRecords, Option<Vec<u8>>) {
+        let (mut issuer_id, mut token): (IssuerId, TokenId) =
+            from.map_or((1, 1), |c| decode_cursor(&c));
+        let mut records = Vec::new();
+        let mut scanned = 0;
+        while issuer_id < self.next_issuer_id {
+            let last = self.next_token_ids.get(&issuer_id).unwrap_or(0);
+            while token <= last {
+                if scanned == limit {
+                    let next = (issuer_id, token).try_to_vec().unwrap();
+                    return (ExportRecords::Tokens(records), Some(next));
+                }
+                scanned += 1;
+                if let Some(t) = self.issuer_tokens.get(&IssuerTokenId { issuer_id, token }) {
+                    let mut metadata = t.metadata.latest();
+                    if self.private_accounts.contains(&t.owner) {
+                        redact(&mut metadata);
+                    }
+                    records.push(ExportedToken {
+                        issuer_id,
+                        token,
+                        owner: t.owner,
+                        metadata,
+                    });
+                }
+                token += 1;
+            }
+            issuer_id += 1;
+            token = 1;
+        }
+        (ExportRecords::Tokens(records), None)
+    }
+
+    /// cursor: the last balance key of the previous chunk.
+    fn export_balances(
+        &self,
+        from: Option<Vec<u8>>,
+        limit: u32,
+    ) -> (ExportRecords, Option<Vec<u8>>) {
+        let iter: Box<dyn Iterator<Item = (BalanceKey, TokenId)>> = match from {
+            None => Box::new(self.balances.iter()),
+            // iter_from is exclusive
+            Some(c) => Box::new(self.balances.iter_from(decode_cursor(&c))),
+        };
+        let records: Vec<ExportedBalance> = iter
+            .take(limit as usize)
+            .map(|(k, token)| ExportedBalance {
+                owner: k.owner,
+                issuer_id: k.issuer_id,
+                class_id: k.class_id,
+                token,
+            })
+            .collect();
+        let next = match records.last() {
+            Some(b) if records.len() == limit as usize => {
+                let last = balance_key(b.owner.clone(), b.issuer_id, b.class_id);
+                self.balances
+                    .higher(&last)
+                    .map(|_| last.try_to_vec().unwrap())
+            }
+            _ => None,
+        };
+        (ExportRecords::Balances(records), next)
+    }
+}
+
+fn decode_cursor<T: BorshDeserialize>(cursor: &[u8]) -> T {
+    T::try_from_slice(cursor).unwrap_or_else(|_| panic!("invalid export cursor"))
+}
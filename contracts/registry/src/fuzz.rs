@@ -0,0 +1,319 @@
+//! Randomized state machine test: applies random sequences of mint, revoke, burn, renew and
+//! soul transfer (which bans the source account) to the registry and to a simple in-memory
+//! model, and checks that the registry state matches the model after every step.
+//! Sequences are generated from fixed seeds, so failures are reproducible.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use cost::ISSUER_BOND;
+use near_sdk::test_utils::VMContextBuilder;
+use near_sdk::{testing_env, AccountId, Gas, VMContext};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use sbt::*;
+
+use crate::{Contract, TokenStatus};
+
+const NUM_SEEDS: u64 = 16;
+const STEPS: usize = 60;
+const CLASSES: ClassId = 3;
+/// block timestamp at the start of each sequence, in miliseconds.
+const START_MS: u64 = 1_000_000;
+
+fn acc(name: &str) -> AccountId {
+    AccountId::new_unchecked(format!("{}.near", name))
+}
+
+fn issuers() -> Vec<AccountId> {
+    vec![acc("sbt1"), acc("sbt2")]
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct ModelToken {
+    owner: AccountId,
+    class: ClassId,
+    expires_at: Option<u64>,
+}
+
+#[derive(Default)]
+struct Model {
+    /// (issuer index, token) -> token
+    tokens: BTreeMap<(usize, TokenId), ModelToken>,
+    last_token: Vec<TokenId>,
+    banned: BTreeSet<AccountId>,
+    /// revoked tokens can only be burned.
+    revoked: BTreeSet<(usize, TokenId)>,
+    accounts: Vec<AccountId>,
+}
+
+impl Model {
+    fn has_class(&self, owner: &AccountId, issuer: usize, class: ClassId) -> bool {
+        self.tokens
+            .iter()
+            .any(|((i, _), t)| *i == issuer && t.owner == *owner && t.class == class)
+    }
+
+    fn tokens_of(&self, owner: &AccountId) -> Vec<(usize, TokenId)> {
+        self.tokens
+            .iter()
+            .filter(|(_, t)| t.owner == *owner)
+            .map(|(k, _)| *k)
+            .collect()
+    }
+
+    fn random_token(&self, rng: &mut StdRng) -> Option<(usize, TokenId)> {
+        if self.tokens.is_empty() {
+            return None;
+        }
+        let i = rng.gen_range(0, self.tokens.len());
+        self.tokens.keys().nth(i).copied()
+    }
+
+    fn random_account(&self, rng: &mut StdRng) -> AccountId {
+        self.accounts[rng.gen_range(0, self.accounts.len())].clone()
+    }
+}
+
+fn next_tx(ctx: &mut VMContext, predecessor: &AccountId) {
+    ctx.predecessor_account_id = predecessor.clone();
+    ctx.attached_deposit = near_sdk::ONE_NEAR;
+    ctx.prepaid_gas = Gas::ONE_TERA * 300;
+    testing_env!(ctx.clone());
+}
+
+/// Applies a random valid operation to both the registry and the model.
+fn step(rng: &mut StdRng, ctx: &mut VMContext, ctr: &mut Contract, m: &mut Model) {
+    let issuers = issuers();
+    let now_ms = ctx.block_timestamp / 1_000_000;
+    match rng.gen_range(0, 10) {
+        // mint
+        0..=3 => {
+            let issuer = rng.gen_range(0, issuers.len());
+            let mut spec: Vec<(AccountId, Vec<TokenMetadata>)> = Vec::new();
+            for _ in 0..rng.gen_range(1, 3) {
+                let owner = m.random_account(rng);
+                let class = rng.gen_range(1, CLASSES + 1);
+                let in_spec = spec
+                    .iter()
+                    .any(|(o, ms)| *o == owner && ms.iter().any(|x| x.class == class));
+                if m.banned.contains(&owner) || m.has_class(&owner, issuer, class) || in_spec {
+                    continue;
+                }
+                let expires_at = if rng.gen_bool(0.5) {
+                    Some(now_ms + rng.gen_range(1, 1000))
+                } else {
+                    None
+                };
+                let metadata = TokenMetadata {
+                    class,
+                    issued_at: None,
+                    expires_at,
+                    reference: None,
+                    reference_hash: None,
+                    attributes_root: None,
+                };
+                match spec.iter_mut().find(|(o, _)| *o == owner) {
+                    Some((_, ms)) => ms.push(metadata),
+                    None => spec.push((owner, vec![metadata])),
+                }
+            }
+            if spec.is_empty() {
+                return;
+            }
+            next_tx(ctx, &issuers[issuer]);
+            let ids = ctr.sbt_mint(spec.clone());
+            let first = m.last_token[issuer] + 1;
+            let mut token = first;
+            for (owner, ms) in spec {
+                for md in ms {
+                    m.tokens.insert(
+                        (issuer, token),
+                        ModelToken {
+                            owner: owner.clone(),
+                            class: md.class,
+                            expires_at: md.expires_at,
+                        },
+                    );
+                    token += 1;
+                }
+            }
+            assert_eq!(ids, (first..token).collect::<Vec<_>>());
+            m.last_token[issuer] = token - 1;
+        }
+        // revoke
+        4 => {
+            if let Some((issuer, token)) = m.random_token(rng) {
+                if !m.revoked.insert((issuer, token)) {
+                    return;
+                }
+                next_tx(ctx, &issuers[issuer]);
+                ctr.sbt_revoke(vec![token], false);
+                // registry sets expires_at to the block timestamp (in miliseconds)
+                m.tokens.get_mut(&(issuer, token)).unwrap().expires_at =
+                    Some(NanoSeconds(ctx.block_timestamp).to_ms().0);
+            }
+        }
+        // burn
+        5 => {
+            if let Some((issuer, token)) = m.random_token(rng) {
+                next_tx(ctx, &issuers[issuer]);
+                ctr.sbt_revoke(vec![token], true);
+                m.tokens.remove(&(issuer, token));
+                m.revoked.remove(&(issuer, token));
+            }
+        }
+        // renew
+        6 => {
+            if let Some((issuer, token)) = m.random_token(rng) {
+                if m.banned.contains(&m.tokens[&(issuer, token)].owner)
+                    || m.revoked.contains(&(issuer, token))
+                {
+                    return;
+                }
+                let expires_at = now_ms + rng.gen_range(1, 1000);
+                next_tx(ctx, &issuers[issuer]);
+                ctr.sbt_renew(vec![token], expires_at);
+                m.tokens.get_mut(&(issuer, token)).unwrap().expires_at = Some(expires_at);
+            }
+        }
+        // soul transfer (bans the source account)
+        7 => {
+            let from = m.random_account(rng);
+            if m.banned.contains(&from) {
+                return;
+            }
+            // recipient: a new account (most of the time) or a known one
+            let to = if rng.gen_bool(0.7) {
+                let a = acc(&format!("user{}", m.accounts.len()));
+                m.accounts.push(a.clone());
+                a
+            } else {
+                m.random_account(rng)
+            };
+            if to == from || m.banned.contains(&to) {
+                return;
+            }
+            let moved = m.tokens_of(&from);
+            // the registry overwrites recipient balances on conflicts, we don't model it.
+            if moved.iter().any(|k| {
+                let t = &m.tokens[k];
+                m.has_class(&to, k.0, t.class)
+            }) {
+                return;
+            }
+            next_tx(ctx, &from);
+            let (num, done) = ctr.sbt_soul_transfer(to.clone(), None, None);
+            assert!(done);
+            assert_eq!(num as usize, moved.len());
+            for k in moved {
+                m.tokens.get_mut(&k).unwrap().owner = to.clone();
+            }
+            m.banned.insert(from);
+        }
+        // time passes
+        _ => ctx.block_timestamp += rng.gen_range(1, 500) * 1_000_000,
+    }
+}
+
+fn check(ctx: &VMContext, ctr: &Contract, m: &Model) {
+    // views share the gas limit of the mocked transaction, so we reset the context often.
+    for (i, issuer) in issuers().into_iter().enumerate() {
+        testing_env!(ctx.clone());
+        let tokens: Vec<_> = m.tokens.iter().filter(|((ti, _), _)| *ti == i).collect();
+        assert_eq!(ctr.sbt_supply(issuer.clone(), None), tokens.len() as u64);
+        let now_ms = NanoSeconds(ctx.block_timestamp).to_ms().0;
+        let valid = tokens
+            .iter()
+            .filter(|(k, t)| !m.revoked.contains(k) && t.expires_at.map_or(true, |e| e >= now_ms))
+            .count();
+        assert_eq!(ctr.sbt_supply(issuer.clone(), Some(true)), valid as u64);
+        assert_eq!(ctr.last_token_id(issuer.clone()), m.last_token[i]);
+        for class in 1..=CLASSES {
+            let n = tokens.iter().filter(|(_, t)| t.class == class).count();
+            assert_eq!(ctr.sbt_supply_by_class(issuer.clone(), class), n as u64);
+        }
+        for token in 1..=m.last_token[i] {
+            testing_env!(ctx.clone());
+            let got = ctr.sbt(issuer.clone(), token).map(|t| ModelToken {
+                owner: t.owner,
+                class: t.metadata.class,
+                expires_at: t.metadata.expires_at,
+            });
+            assert_eq!(got.as_ref(), m.tokens.get(&(i, token)), "token {}", token);
+            let status = ctr.sbt_status(issuer.clone(), token);
+            if !m.tokens.contains_key(&(i, token)) {
+                assert_eq!(status, Some(TokenStatus::Burned), "token {}", token);
+            } else if m.revoked.contains(&(i, token)) {
+                assert_eq!(status, Some(TokenStatus::Revoked), "token {}", token);
+            }
+        }
+        for a in &m.accounts {
+            testing_env!(ctx.clone());
+            let n = tokens.iter().filter(|(_, t)| t.owner == *a).count();
+            assert_eq!(
+                ctr.sbt_supply_by_owner(a.clone(), issuer.clone(), None),
+                n as u64
+            );
+        }
+        testing_env!(ctx.clone());
+        assert_eq!(ctr.check_invariants(issuer, None, Some(1000)), vec![]);
+    }
+    for a in &m.accounts {
+        testing_env!(ctx.clone());
+        assert_eq!(ctr.is_banned(a.clone()), m.banned.contains(a));
+        let mut got: Vec<(AccountId, TokenId)> = ctr
+            .sbt_tokens_by_owner(a.clone(), None, None, None, Some(true))
+            .into_iter()
+            .flat_map(|(issuer, ts)| ts.into_iter().map(move |t| (issuer.clone(), t.token)))
+            .collect();
+        got.sort();
+        let mut expected: Vec<(AccountId, TokenId)> = m
+            .tokens_of(a)
+            .into_iter()
+            .map(|(i, t)| (issuers()[i].clone(), t))
+            .collect();
+        expected.sort();
+        assert_eq!(got, expected, "tokens of {}", a);
+        assert_eq!(
+            ctr.account_supply(a),
+            expected.len() as u64,
+            "supply of {}",
+            a
+        );
+    }
+}
+
+#[test]
+fn random_operations_match_model() {
+    for seed in 0..NUM_SEEDS {
+        near_sdk::mock::with_mocked_blockchain(|b| b.take_storage());
+        let mut rng = StdRng::seed_from_u64(seed);
+        let authority = acc("admin");
+        let mut ctx = VMContextBuilder::new()
+            .predecessor_account_id(authority.clone())
+            .block_timestamp(START_MS * 1_000_000)
+            .is_view(false)
+            .build();
+        testing_env!(ctx.clone());
+        let mut ctr = Contract::new(authority.clone(), acc("fractal"), vec![1]);
+        for issuer in issuers() {
+            ctx.predecessor_account_id = issuer.clone();
+            ctx.attached_deposit = ISSUER_BOND;
+            testing_env!(ctx.clone());
+            ctr.issuer_lock_bond();
+            ctx.predecessor_account_id = authority.clone();
+            ctx.attached_deposit = 0;
+            testing_env!(ctx.clone());
+            ctr.admin_add_sbt_issuer(issuer, None);
+        }
+        let mut m = Model {
+            last_token: vec![0; issuers().len()],
+            accounts: (0..4).map(|i| acc(&format!("user{}", i))).collect(),
+            ..Default::default()
+        };
+        for _ in 0..STEPS {
+            step(&mut rng, &mut ctx, &mut ctr, &mut m);
+            check(&ctx, &ctr, &m);
+        }
+    }
+}
@@ -0,0 +1,232 @@
+//! Gas and storage regression benchmarks of the registry hot paths: mint batches of 1, 10
+//! and 100 tokens, soul transfer (per token) and the main views.
+//!
+//! The calls are executed in the mocked blockchain, so the gas covers the host functions
+//! (storage reads and writes, logs, hashing), not the wasm execution. It's deterministic and
+//! dominated by the storage access, which is what the data layout changes move.
+//!
+//! `gas_regressions` fails if a measurement is more than `MAX_REGRESSION_PCT` above the
+//! committed baseline (`gas_baseline.json`). `cargo test -p registry gas_report -- --ignored
+//! --nocapture` prints the measurements, and with `GAS_BASELINE_UPDATE=1` rewrites the
+//! baseline after an intended change.
+
+use std::collections::BTreeMap;
+
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::test_utils::VMContextBuilder;
+use near_sdk::{env, testing_env, AccountId, Gas, RuntimeFeesConfig, VMConfig};
+
+use cost::{ISSUER_BOND, MINT_COST, SOUL_TRANSFER_BOND};
+use sbt::{SBTRegistry, TokenMetadata};
+
+use crate::Contract;
+
+/// Max allowed increase (in percent) of a measurement over the baseline.
+const MAX_REGRESSION_PCT: u64 = 20;
+const BASELINE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/gas_baseline.json");
+const START: u64 = 10;
+const MAX_GAS: u64 = 10_000 * Gas::ONE_TERA.0;
+
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(crate = "near_sdk::serde")]
+struct Measurement {
+    gas: u64,
+    /// storage bytes added by the call.
+    storage: u64,
+}
+
+fn acc(name: &str) -> AccountId {
+    AccountId::new_unchecked(format!("{}.near", name))
+}
+
+fn issuer() -> AccountId {
+    acc("issuer")
+}
+
+fn mk_metadata(class: u64) -> TokenMetadata {
+    TokenMetadata {
+        class,
+        issued_at: None,
+        expires_at: None,
+        reference: Some("abc".to_owned()),
+        reference_hash: Some(vec![61; 32].into()),
+        attributes_root: None,
+    }
+}
+
+fn set_ctx(predecessor: &AccountId, deposit: u128) {
+    let ctx = VMContextBuilder::new()
+        .current_account_id(acc("registry"))
+        .predecessor_account_id(predecessor.clone())
+        .block_timestamp(START)
+        .prepaid_gas(Gas(MAX_GAS))
+        .attached_deposit(deposit)
+        .build();
+    // the measurements are not capped by the transaction gas limit.
+    let mut config = VMConfig::test();
+    config.limit_config.max_gas_burnt = MAX_GAS;
+    testing_env!(
+        ctx,
+        config,
+        RuntimeFeesConfig::test(),
+        Default::default(),
+        vec![]
+    );
+}
+
+/// Runs `f` in a new thread, with a fresh mocked blockchain state.
+fn isolated<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    std::thread::spawn(f).join().expect("benchmark failed")
+}
+
+/// Registry with a single issuer, minting the proof of humanity class 1.
+fn setup() -> Contract {
+    set_ctx(&acc("admin"), 0);
+    let mut ctr = Contract::new(acc("admin"), issuer(), vec![1]);
+    set_ctx(&issuer(), ISSUER_BOND);
+    ctr.issuer_lock_bond();
+    set_ctx(&acc("admin"), 0);
+    ctr.admin_add_sbt_issuer(issuer(), None);
+    ctr
+}
+
+/// Executes `f` in a fresh transaction of the `predecessor` and returns its gas and storage
+/// usage.
+fn measure(predecessor: &AccountId, deposit: u128, f: impl FnOnce()) -> Measurement {
+    set_ctx(predecessor, deposit);
+    let (gas, storage) = (env::used_gas(), env::storage_usage());
+    f();
+    Measurement {
+        gas: (env::used_gas() - gas).0,
+        storage: env::storage_usage().saturating_sub(storage),
+    }
+}
+
+fn run() -> BTreeMap<String, Measurement> {
+    let mut res = BTreeMap::new();
+    for n in [1, 10, 100] {
+        res.insert(format!("mint_batch_{}", n), isolated(move || mint_batch(n)));
+    }
+    res.extend(isolated(owner_ops));
+    res
+}
+
+fn mint_batch(n: usize) -> Measurement {
+    let mut ctr = setup();
+    let batch = (0..n)
+        .map(|i| (acc(&format!("user{}", i)), vec![mk_metadata(1)]))
+        .collect();
+    measure(&issuer(), n as u128 * MINT_COST, || {
+        ctr.sbt_mint(batch);
+    })
+}
+
+/// Views of an account with 10 tokens, and the soul transfer of the tokens (per token).
+fn owner_ops() -> Vec<(String, Measurement)> {
+    let mut ctr = setup();
+    let alice = acc("alice");
+    set_ctx(&issuer(), 10 * MINT_COST);
+    ctr.sbt_mint(vec![(alice.clone(), (1..=10).map(mk_metadata).collect())]);
+
+    let mut res = vec![
+        (
+            "view_is_human",
+            measure(&alice, 0, || {
+                ctr.is_human(alice.clone());
+            }),
+        ),
+        (
+            "view_sbt_tokens_by_owner",
+            measure(&alice, 0, || {
+                ctr.sbt_tokens_by_owner(alice.clone(), None, None, None, None);
+            }),
+        ),
+        (
+            "view_sbt_supply_by_owner",
+            measure(&alice, 0, || {
+                ctr.sbt_supply_by_owner(alice.clone(), issuer(), None);
+            }),
+        ),
+    ];
+    let m = measure(&alice, SOUL_TRANSFER_BOND, || {
+        let ret = ctr.sbt_soul_transfer(acc("alice2"), None, None);
+        assert_eq!(ret, (10, true), "soul transfer must be complete");
+    });
+    res.push((
+        "soul_transfer_per_token",
+        Measurement {
+            gas: m.gas / 10,
+            storage: m.storage / 10,
+        },
+    ));
+    res.into_iter().map(|(k, m)| (k.to_owned(), m)).collect()
+}
+
+/// Returns the measurements more than `MAX_REGRESSION_PCT` above the baseline (or missing
+/// in the baseline).
+fn regressions(
+    res: &BTreeMap<String, Measurement>,
+    baseline: &BTreeMap<String, Measurement>,
+) -> Vec<String> {
+    let over = |v: u64, base: u64| v * 100 > base * (100 + MAX_REGRESSION_PCT);
+    res.iter()
+        .filter_map(|(name, m)| match baseline.get(name) {
+            None => Some(format!("{}: no baseline", name)),
+            Some(b) if over(m.gas, b.gas) || over(m.storage, b.storage) => Some(format!(
+                "{}: gas {} (baseline {}), storage {} (baseline {})",
+                name, m.gas, b.gas, m.storage, b.storage
+            )),
+            Some(_) => None,
+        })
+        .collect()
+}
+
+fn baseline() -> BTreeMap<String, Measurement> {
+    near_sdk::serde_json::from_str(include_str!("gas_baseline.json")).unwrap()
+}
+
+#[test]
+fn gas_regressions() {
+    let errs = regressions(&run(), &baseline());
+    assert!(
+        errs.is_empty(),
+        "gas or storage usage is more than {}% above the baseline:\n{}",
+        MAX_REGRESSION_PCT,
+        errs.join("\n")
+    );
+}
+
+#[test]
+fn regressions_threshold() {
+    let m = |gas, storage| Measurement { gas, storage };
+    let baseline = BTreeMap::from([("a".to_owned(), m(100, 10))]);
+    let res = BTreeMap::from([("a".to_owned(), m(120, 12))]);
+    assert!(regressions(&res, &baseline).is_empty());
+    let res = BTreeMap::from([("a".to_owned(), m(121, 5)), ("b".to_owned(), m(1, 1))]);
+    assert_eq!(
+        regressions(&res, &baseline),
+        vec![
+            "a: gas 121 (baseline 100), storage 5 (baseline 10)",
+            "b: no baseline"
+        ]
+    );
+}
+
+#[test]
+#[ignore]
+fn gas_report() {
+    let res = run();
+    for (name, m) in &res {
+        println!(
+            "{:<28} {:>8.3} Tgas {:>8} bytes",
+            name,
+            m.gas as f64 / Gas::ONE_TERA.0 as f64,
+            m.storage
+        );
+    }
+    if std::env::var_os("GAS_BASELINE_UPDATE").is_some() {
+        let json = near_sdk::serde_json::to_string_pretty(&res).unwrap();
+        std::fs::write(BASELINE, json + "\n").unwrap();
+        println!("baseline updated: {}", BASELINE);
+    }
+}
@@ -0,0 +1,147 @@
+//! Genesis import of the tokens of an earlier SBT deployment. Before `finalize_genesis`, the
+//! authority can import tokens on behalf of an issuer with the `CAP_IMPORT` capability,
+//! preserving the original token IDs and issuance dates. After `finalize_genesis` tokens can
+//! only be minted.
+
+use std::collections::HashMap;
+
+use near_sdk::{env, near_bindgen, require, AccountId};
+use sbt::{Nep393Event, SbtMint, StorageCharge, TokenData, TokenId, TokenMetadata};
+
+use crate::*;
+
+#[near_bindgen]
+impl Contract {
+    /// Imports the `issuer` tokens: `(owner, metadata, token ID)` triples. Token IDs must be
+    /// increasing and bigger than the last token ID of the issuer (see `last_token_id`), so
+    /// IDs of burned tokens are never reused. `issued_at` is preserved (it must not be in the
+    /// future), tokens without `issued_at` are issued now.
+    /// Emits the NEP-393 `mint` event. Must be called by the authority, with a deposit
+    /// covering the storage, before `finalize_genesis`. The issuer must have the `CAP_IMPORT`
+    /// capability.
+    #[payable]
+    pub fn admin_import_tokens(
+        &mut self,
+        issuer: AccountId,
+        tokens: Vec<(AccountId, TokenMetadata, TokenId)>,
+    ) {
+        self.assert_admin_action("admin_import_tokens", None);
+        require!(!self.genesis_finalized, "genesis is finalized");
+        self.assert_not_paused();
+        let issuer_id = self.assert_issuer(&issuer);
+        self.assert_capability(issuer_id, CAP_IMPORT);
+        require!(
+            tokens.len() <= MAX_MINT_BATCH,
+            format!(
+                "max {} tokens can be imported in a single call",
+                MAX_MINT_BATCH
+            )
+        );
+        let storage_start = env::storage_usage();
+        let now = MilliSeconds::now();
+        let mut last = self.next_token_ids.get(&issuer_id).unwrap_or(0);
+        let mut supply_by_class: HashMap<ClassId, u64> = HashMap::new();
+        let mut per_recipient: HashMap<AccountId, Vec<TokenId>> = HashMap::new();
+        let mut expirations: HashMap<u64, Vec<TokenId>> = HashMap::new();
+        let mut valid: HashMap<Option<u64>, u64> = HashMap::new();
+
+        for (owner, mut metadata, token) in tokens {
+            require!(
+                token > last,
+                format!("token ID {} must be bigger than {}", token, last)
+            );
+            last = token;
+            metadata.assert_valid();
+            self.assert_not_banned(&owner);
+            match metadata.issued_at {
+                Some(issued_at) => require!(
+                    MilliSeconds(issued_at) <= now,
+                    "issued_at must be in the past"
+                ),
+                None => metadata.issued_at = Some(now.0),
+            }
+            let prev = self.balances.insert(
+                &balance_key(owner.clone(), issuer_id, metadata.class),
+                &token,
+            );
+            require!(
+                prev.is_none(),
+                format!("{} already has SBT of class {}", owner, metadata.class)
+            );
+            require!(
+                self.account_supply(&owner) < self.max_tokens_per_account as u64,
+                format!(
+                    "{} can have at most {} tokens",
+                    owner, self.max_tokens_per_account
+                )
+            );
+            *supply_by_class.entry(metadata.class).or_default() += 1;
+            *valid.entry(metadata.expires_at).or_default() += 1;
+            if let Some(expires_at) = metadata.expires_at {
+                expirations
+                    .entry(expiration_day(expires_at))
+                    .or_default()
+                    .push(token);
+            }
+            self.update_account_supply(&owner, 1, true);
+            let skey = (owner.clone(), issuer_id);
+            let sowner = self.supply_by_owner.get(&skey).unwrap_or(0) + 1;
+            self.supply_by_owner.insert(&skey, &sowner);
+            self.issuer_tokens.insert(
+                &IssuerTokenId { issuer_id, token },
+                TokenData {
+                    owner: owner.clone(),
+                    metadata: metadata.into(),
+                },
+            );
+            per_recipient.entry(owner).or_default().push(token);
+        }
+        self.next_token_ids.insert(&issuer_id, &last);
+
+        for (day, tokens) in expirations {
+            self.index_expirations(issuer_id, day, tokens);
+        }
+        for (expires_at, n) in valid {
+            self.add_valid(issuer_id, expires_at, n);
+        }
+        let mut num_tokens = 0;
+        let mut new_classes = 0;
+        for (cls, new_supply) in supply_by_class {
+            let key = (issuer_id, cls);
+            let prev = self.supply_by_class.get(&key);
+            new_classes += prev.is_none() as u32;
+            self.supply_by_class
+                .insert(&key, &(prev.unwrap_or(0) + new_supply));
+            num_tokens += new_supply;
+        }
+        self.count_new_classes(issuer_id, new_classes);
+        let s = self.supply_by_issuer.get(&issuer_id).unwrap_or(0) + num_tokens;
+        self.supply_by_issuer.insert(&issuer_id, &s);
+
+        let charged = assert_storage_deposit(storage_start, env::attached_deposit());
+        let mut minted: Vec<(&AccountId, &Vec<TokenId>)> = per_recipient.iter().collect();
+        minted.sort_by(|a, b| a.0.cmp(b.0));
+        for e in (SbtMint {
+            issuer: &issuer,
+            tokens: minted,
+            storage: Some(StorageCharge::since(storage_start, charged)),
+        })
+        .split()
+        {
+            self.emit_event(Nep393Event::Mint(e));
+        }
+    }
+
+    /// Ends the genesis import: `admin_import_tokens` can't be called anymore. Emits the
+    /// `FinalizeGenesis` registry event. Must be called by the authority.
+    pub fn finalize_genesis(&mut self) {
+        self.assert_admin_action("finalize_genesis", None);
+        require!(!self.genesis_finalized, "genesis is already finalized");
+        self.genesis_finalized = true;
+        self.emit_registry_event(RegistryEvent::FinalizeGenesis);
+    }
+
+    pub fn is_genesis_finalized(&self) -> bool {
+        self.genesis_finalized
+    }
+}
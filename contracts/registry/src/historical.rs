@@ -0,0 +1,44 @@
+//! Timestamp pinned variants of the key views, so eligibility (eg at a governance voting
+//! deadline) can be reproduced later. Only the time dependent logic is evaluated at the
+//! given `timestamp` (unix timestamp in miliseconds): token `expires_at` and `issued_at`,
+//! and temporary ban expiration. Everything else (token ownership, revocations, freezes,
+//! permanent bans, flags) is the current registry state: to get the exact historical state,
+//! query the pinned view on an archival node at the block of the `timestamp`.
+
+use near_sdk::{near_bindgen, AccountId};
+use sbt::OwnedToken;
+
+use crate::*;
+
+#[near_bindgen]
+impl Contract {
+    /// `is_human` evaluated at the `timestamp`.
+    pub fn is_human_at(&self, account: AccountId, timestamp: u64) -> bool {
+        self._is_human(account, MilliSeconds(timestamp))
+    }
+
+    /// `sbt_tokens_by_owner` returning tokens valid at the `timestamp`.
+    pub fn sbt_tokens_by_owner_at(
+        &self,
+        account: AccountId,
+        issuer: Option<AccountId>,
+        from_class: Option<u64>,
+        limit: Option<u32>,
+        timestamp: u64,
+    ) -> Vec<(AccountId, Vec<OwnedToken>)> {
+        let tokens = self.tokens_by_owner_at(
+            account.clone(),
+            issuer,
+            from_class,
+            limit,
+            None,
+            MilliSeconds(timestamp),
+        );
+        self.redact_owned(&account, tokens)
+    }
+
+    /// `has_credential` evaluated at the `timestamp`.
+    pub fn has_credential_at(&self, account: AccountId, name: String, timestamp: u64) -> bool {
+        self._has_credential(account, name, MilliSeconds(timestamp))
+    }
+}
@@ -0,0 +1,41 @@
+//! Allowlist of the `is_human_call` receivers: the authority sets the contracts which can be
+//! called through the registry, with the max deposit and gas forwarded to each of them, so
+//! the registry can't be used as an open relay for arbitrary calls.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{near_bindgen, AccountId, Gas};
+
+use crate::*;
+
+/// Limits of the `is_human_call` calls to an allowed receiver.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq, Clone))]
+pub struct HumanCallPolicy {
+    /// max deposit (in yoctoNEAR) forwarded to the receiver.
+    pub max_deposit: U128,
+    /// max gas of the receiver call. If not set, the receiver call gets all the remaining
+    /// gas.
+    pub max_gas: Option<Gas>,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Allows `ctr` as an `is_human_call` receiver with the `policy` limits, or removes it
+    /// from the allowlist when `policy` is `None`. Must be called by the authority.
+    pub fn admin_set_human_call_target(&mut self, ctr: AccountId, policy: Option<HumanCallPolicy>) {
+        self.assert_admin_action("admin_set_human_call_target", None);
+        match policy {
+            None => self.human_call_targets.remove(&ctr),
+            Some(p) => self.human_call_targets.insert(&ctr, &p),
+        };
+    }
+
+    /// Returns the `is_human_call` policy of the receiver, or None if the receiver is not
+    /// allowed.
+    pub fn human_call_target(&self, ctr: AccountId) -> Option<HumanCallPolicy> {
+        self.human_call_targets.get(&ctr)
+    }
+}
@@ -0,0 +1,37 @@
+//! Off-chain humanity claims: canonical messages the account signs off-chain, so Web2
+//! services can check its IAH status without a transaction, see `sbt::HumanityClaim`.
+
+use near_sdk::json_types::Base64VecU8;
+use near_sdk::{env, near_bindgen, AccountId};
+use sbt::{HumanityClaim, HUMANITY_CLAIM_FORMAT_VERSION};
+
+use crate::*;
+
+/// Max validity of a humanity claim: 1 hour in miliseconds.
+pub const HUMANITY_CLAIM_TTL: u64 = 3_600_000;
+
+#[near_bindgen]
+impl Contract {
+    /// Returns the canonical `sbt::HumanityClaim` message of the `account` at the current
+    /// block, for the account to sign off-chain. The claim expires after
+    /// `HUMANITY_CLAIM_TTL`, or when the first of the account proof of humanity tokens
+    /// expires. Returns None if the account is not a human.
+    pub fn humanity_claim_payload(&self, account: AccountId) -> Option<Base64VecU8> {
+        let now = MilliSeconds::now();
+        if !self._is_human(account.clone(), now) {
+            return None;
+        }
+        let ttl_end = now.0 + HUMANITY_CLAIM_TTL;
+        let claim = HumanityClaim {
+            version: HUMANITY_CLAIM_FORMAT_VERSION,
+            registry: env::current_account_id(),
+            expires_at: self
+                .human_expires_at(&account)
+                .map_or(ttl_end, |e| e.min(ttl_end)),
+            account,
+            block_height: env::block_height(),
+            timestamp: now.0,
+        };
+        Some(claim.encode().into())
+    }
+}
@@ -0,0 +1,170 @@
+//! State consistency checks, useful after migrations and for monitoring a live registry.
+
+use std::collections::{HashMap, HashSet};
+
+use near_sdk::{near_bindgen, require, AccountId};
+use sbt::*;
+
+use crate::*;
+
+const DEFAULT_LIMIT: u32 = 100;
+
+#[near_bindgen]
+impl Contract {
+    /// Verifies that `issuer_tokens`, `balances` and supply counters agree for the `issuer`
+    /// tokens in the range `[from_token, from_token + limit)`. Returns list of found
+    /// discrepancies (empty list when the state is consistent).
+    /// + For each token in the range, checks that the owner balance points to the token.
+    /// + For each owner of a token in the range, checks that all owner balances of the issuer
+    ///   point to existing tokens owned by that account, and that `supply_by_owner` is equal
+    ///   to the number of those balances.
+    /// + Class and issuer supplies must not be smaller than the number of tokens found in the
+    ///   range. If the range covers all issuer tokens, then they must be equal.
+    /// + If the range covers all issuer tokens, the valid supply (`sbt_supply` with
+    ///   `only_valid`) must be equal to the number of valid tokens.
+    /// If `from_token` is not specified, 1 is used. If limit is not specified, default is
+    /// used: 100.
+    pub fn check_invariants(
+        &self,
+        issuer: AccountId,
+        from_token: Option<u64>,
+        limit: Option<u32>,
+    ) -> Vec<Discrepancy> {
+        let issuer_id = self.assert_issuer(&issuer);
+        let from_token = from_token.unwrap_or(1);
+        require!(from_token > 0, "from_token, if set, must be >= 1");
+        let limit = limit.unwrap_or(DEFAULT_LIMIT);
+        require!(limit > 0, "limit must be bigger than 0");
+        let last_token = self.next_token_ids.get(&issuer_id).unwrap_or(0);
+        let to_token = std::cmp::min(last_token + 1, from_token + limit as u64);
+
+        let mut discrepancies = Vec::new();
+        let mut owners = HashSet::new();
+        let mut supply_by_class: HashMap<ClassId, u64> = HashMap::new();
+        let mut supply = 0;
+        let mut valid = 0;
+        let now = MilliSeconds::now();
+        for token in from_token..to_token {
+            let t = match self.issuer_tokens.get(&IssuerTokenId { issuer_id, token }) {
+                None => continue,
+                Some(t) => t,
+            };
+            supply += 1;
+            if is_valid_status(self.status_of(issuer_id, token, &t))
+                && !t.metadata.is_expired_at(now)
+            {
+                valid += 1;
+            }
+            let class_id = t.metadata.class_id();
+            *supply_by_class.entry(class_id).or_default() += 1;
+            let balance = self
+                .balances
+                .get(&balance_key(t.owner.clone(), issuer_id, class_id));
+            if balance != Some(token) {
+                discrepancies.push(Discrepancy {
+                    token: Some(token),
+                    description: format!(
+                        "balance of {} (class {}) is {:?}, expected the token",
+                        t.owner, class_id, balance
+                    ),
+                });
+            }
+            owners.insert(t.owner);
+        }
+
+        let mut owners: Vec<AccountId> = owners.into_iter().collect();
+        owners.sort();
+        for owner in owners {
+            self.check_owner_balances(&owner, issuer_id, &mut discrepancies);
+        }
+
+        let full_range = from_token == 1 && to_token == last_token + 1;
+        let mut classes: Vec<(ClassId, u64)> = supply_by_class.into_iter().collect();
+        classes.sort();
+        for (class_id, s) in classes {
+            let stored = self
+                .supply_by_class
+                .get(&(issuer_id, class_id))
+                .unwrap_or(0);
+            if stored < s || (full_range && stored != s) {
+                discrepancies.push(Discrepancy {
+                    token: None,
+                    description: format!(
+                        "supply of class {} is {}, found {} tokens",
+                        class_id, stored, s
+                    ),
+                });
+            }
+        }
+        let stored = self.supply_by_issuer.get(&issuer_id).unwrap_or(0);
+        if stored < supply || (full_range && stored != supply) {
+            discrepancies.push(Discrepancy {
+                token: None,
+                description: format!("issuer supply is {}, found {} tokens", stored, supply),
+            });
+        }
+        let stored = self.valid_supply_of(issuer_id);
+        if full_range && self.migration.done && stored != valid {
+            discrepancies.push(Discrepancy {
+                token: None,
+                description: format!("valid supply is {}, found {} valid tokens", stored, valid),
+            });
+        }
+        discrepancies
+    }
+
+    fn check_owner_balances(
+        &self,
+        owner: &AccountId,
+        issuer_id: IssuerId,
+        discrepancies: &mut Vec<Discrepancy>,
+    ) {
+        let mut num_balances = 0;
+        // iter_from starts from exclusive "left end", class IDs start from 1.
+        for (key, token) in self
+            .balances
+            .iter_from(balance_key(owner.clone(), issuer_id, 0))
+        {
+            if key.owner != *owner || key.issuer_id != issuer_id {
+                break;
+            }
+            num_balances += 1;
+            match self.issuer_tokens.get(&IssuerTokenId { issuer_id, token }) {
+                None => discrepancies.push(Discrepancy {
+                    token: Some(token),
+                    description: format!(
+                        "balance of {} (class {}) points to a non existing token",
+                        owner, key.class_id
+                    ),
+                }),
+                Some(t) => {
+                    if t.owner != *owner || t.metadata.class_id() != key.class_id {
+                        discrepancies.push(Discrepancy {
+                            token: Some(token),
+                            description: format!(
+                                "balance of {} (class {}) points to a token of {} (class {})",
+                                owner,
+                                key.class_id,
+                                t.owner,
+                                t.metadata.class_id()
+                            ),
+                        });
+                    }
+                }
+            }
+        }
+        let stored = self
+            .supply_by_owner
+            .get(&(owner.clone(), issuer_id))
+            .unwrap_or(0);
+        if stored != num_balances {
+            discrepancies.push(Discrepancy {
+                token: None,
+                description: format!(
+                    "supply of {} is {}, found {} balances",
+                    owner, stored, num_balances
+                ),
+            });
+        }
+    }
+}
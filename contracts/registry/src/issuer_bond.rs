@@ -0,0 +1,158 @@
+//! Issuer bonds give verification providers economic accountability: an issuer must lock a
+//! bond (`issuer_lock_bond`) before the authority can register it, and the authority can
+//! slash the bond on proven misbehavior. Slashed NEAR is sent to the treasury.
+//! To get the bond back, the issuer starts unbonding. The issuer can't be removed
+//! (`AdminAction::RemoveIssuer`) until the unbonding period passes, so misbehavior found in
+//! the meantime can still be slashed. The remaining bond is withdrawn once the issuer is
+//! removed.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::Serialize;
+use near_sdk::{env, near_bindgen, require, AccountId, Promise};
+
+use cost::ISSUER_BOND;
+
+use crate::*;
+
+/// Time between `issuer_unbond` and the moment the issuer can be removed.
+pub const ISSUER_UNBONDING_PERIOD_MS: u64 = 14 * 24 * 3600 * 1000;
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq))]
+pub struct IssuerBond {
+    pub amount: U128,
+    /// time (unix timestamp in miliseconds) when the issuer started unbonding.
+    pub unbonding_since: Option<u64>,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Adds the attached deposit to the caller bond. The bond must be at least
+    /// `cost::ISSUER_BOND` before the authority can register the caller as an issuer.
+    /// Returns the bond amount.
+    #[payable]
+    pub fn issuer_lock_bond(&mut self) -> U128 {
+        let issuer = env::predecessor_account_id();
+        let deposit = env::attached_deposit();
+        require!(deposit > 0, "attach NEAR to lock the bond");
+        self.lock_issuer_bond(&issuer, deposit)
+    }
+
+    /// Starts unbonding of the caller bond. The issuer can be removed by the authority once
+    /// `ISSUER_UNBONDING_PERIOD_MS` passes. Emits `IssuerUnbond` registry event.
+    pub fn issuer_unbond(&mut self) {
+        let issuer = env::predecessor_account_id();
+        let mut bond = self
+            .issuer_bonds
+            .get(&issuer)
+            .expect("issuer bond not found");
+        require!(
+            bond.unbonding_since.is_none(),
+            "issuer bond is already unbonding"
+        );
+        bond.unbonding_since = Some(env::block_timestamp_ms());
+        self.issuer_bonds.insert(&issuer, &bond);
+        self.emit_registry_event(RegistryEvent::IssuerUnbond { issuer: &issuer });
+    }
+
+    /// Transfers the remaining bond back to the caller. Panics if the caller is a registered
+    /// issuer.
+    pub fn issuer_withdraw_bond(&mut self) -> Promise {
+        let issuer = env::predecessor_account_id();
+        require!(
+            self.sbt_issuers.get(&issuer).is_none(),
+            "registered issuer can't withdraw the bond"
+        );
+        let bond = self
+            .issuer_bonds
+            .remove(&issuer)
+            .expect("issuer bond not found");
+        Promise::new(issuer).transfer(bond.amount.0)
+    }
+
+    /// Slashes `amount` of the issuer bond and sends it to the treasury. Emits `SlashIssuer`
+    /// registry event with the optional `memo`. Must be called by the authority.
+    pub fn slash_issuer(
+        &mut self,
+        issuer: AccountId,
+        amount: U128,
+        memo: Option<String>,
+    ) -> Promise {
+        self.assert_admin_action("slash_issuer", memo.as_deref());
+        self.assert_memo(&memo);
+        let mut bond = self
+            .issuer_bonds
+            .get(&issuer)
+            .expect("issuer bond not found");
+        require!(
+            amount.0 > 0 && amount.0 <= bond.amount.0,
+            "amount must be positive and not bigger than the issuer bond"
+        );
+        bond.amount.0 -= amount.0;
+        self.issuer_bonds.insert(&issuer, &bond);
+        let treasury = self.treasury.clone();
+        self.emit_registry_event(RegistryEvent::SlashIssuer {
+            issuer: &issuer,
+            amount,
+            treasury: &treasury,
+            memo: memo.as_deref(),
+        });
+        Promise::new(treasury).transfer(amount.0)
+    }
+
+    /// Sets the receiver of slashed issuer bonds. Must be called by the authority.
+    pub fn admin_set_treasury(&mut self, treasury: AccountId) {
+        self.assert_admin_action("admin_set_treasury", None);
+        self.treasury = treasury;
+    }
+
+    /// Returns the issuer bond, or None if the account doesn't have a bond.
+    pub fn issuer_bond(&self, issuer: AccountId) -> Option<IssuerBond> {
+        self.issuer_bonds.get(&issuer)
+    }
+
+    /// Returns the receiver of slashed issuer bonds (the authority, unless set with
+    /// `admin_set_treasury`).
+    pub fn treasury(&self) -> AccountId {
+        self.treasury.clone()
+    }
+}
+
+impl Contract {
+    /// Adds `deposit` to the issuer bond. Returns the bond amount.
+    pub(crate) fn lock_issuer_bond(&mut self, issuer: &AccountId, deposit: u128) -> U128 {
+        let mut bond = self.issuer_bonds.get(issuer).unwrap_or(IssuerBond {
+            amount: U128(0),
+            unbonding_since: None,
+        });
+        require!(bond.unbonding_since.is_none(), "issuer bond is unbonding");
+        bond.amount.0 += deposit;
+        self.issuer_bonds.insert(issuer, &bond);
+        bond.amount
+    }
+
+    /// Panics if the `issuer` didn't lock at least `cost::ISSUER_BOND`.
+    pub(crate) fn assert_issuer_bond(&self, issuer: &AccountId) {
+        let amount = self.issuer_bonds.get(issuer).map_or(0, |b| b.amount.0);
+        require!(
+            amount >= ISSUER_BOND,
+            format!(
+                "issuer must lock a bond of at least {} yoctoNEAR",
+                ISSUER_BOND
+            )
+        );
+    }
+
+    /// Panics if the `issuer` has a bond and its unbonding period didn't pass.
+    pub(crate) fn assert_issuer_unbonded(&self, issuer: &AccountId) {
+        if let Some(bond) = self.issuer_bonds.get(issuer) {
+            let unbonded = bond.amount.0 == 0
+                || bond.unbonding_since.map_or(false, |t| {
+                    env::block_timestamp_ms() >= t + ISSUER_UNBONDING_PERIOD_MS
+                });
+            require!(unbonded, "issuer bond unbonding period has not passed yet");
+        }
+    }
+}
@@ -0,0 +1,93 @@
+//! Verified issuer registration: `admin_add_sbt_issuer_verified` calls the `sbt_metadata` of
+//! the issuer contract (the `sbt::SBTContract` interface) and registers the issuer only if
+//! the call returns valid contract metadata, which is stored (see `sbt_issuer_metadata`).
+//! Accounts which don't implement the interface (eg accounts without a contract) are
+//! rejected.
+
+use near_sdk::borsh::BorshSerialize;
+use near_sdk::{env, near_bindgen, require, serde_json, AccountId, Gas, Promise, PromiseResult};
+use sbt::ContractMetadata;
+
+use crate::*;
+
+/// Gas attached to the issuer `sbt_metadata` call.
+pub const ISSUER_METADATA_GAS: Gas = Gas(5 * Gas::ONE_TERA.0);
+const ISSUER_METADATA_CALLBACK_GAS: Gas = Gas(10 * Gas::ONE_TERA.0);
+/// Max size (in bytes, Borsh encoded) of the issuer metadata stored by the registry.
+pub const MAX_ISSUER_METADATA_LEN: usize = 2048;
+
+#[near_bindgen]
+impl Contract {
+    /// Same as `admin_add_sbt_issuer`, but first verifies that the `issuer` implements the SBT
+    /// contract interface: calls its `sbt_metadata` and adds the issuer only if it returns
+    /// valid `ContractMetadata` (non empty `spec`, `name` and `symbol`, at most
+    /// `MAX_ISSUER_METADATA_LEN` bytes). The metadata is stored by the registry. The promise
+    /// resolves to true if the issuer was added. Must be called by the authority.
+    pub fn admin_add_sbt_issuer_verified(
+        &mut self,
+        issuer: AccountId,
+        memo: Option<String>,
+    ) -> Promise {
+        self.assert_admin_action("admin_add_sbt_issuer_verified", memo.as_deref());
+        self.assert_memo(&memo);
+        require!(
+            self.sbt_issuers.get(&issuer).is_none(),
+            "issuer is already registered"
+        );
+        self.assert_issuers_cap();
+        self.assert_issuer_bond(&issuer);
+        Promise::new(issuer.clone())
+            .function_call("sbt_metadata".to_owned(), vec![], 0, ISSUER_METADATA_GAS)
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(ISSUER_METADATA_CALLBACK_GAS)
+                    .on_issuer_metadata(issuer, memo),
+            )
+    }
+
+    /// Adds the issuer if its `sbt_metadata` call returned valid metadata. Emits `AddIssuer`
+    /// registry event when the issuer was added.
+    #[private]
+    pub fn on_issuer_metadata(&mut self, issuer: AccountId, memo: Option<String>) -> bool {
+        let metadata = match env::promise_result(0) {
+            PromiseResult::Successful(v) => serde_json::from_slice::<ContractMetadata>(&v).ok(),
+            _ => None,
+        };
+        let metadata = match metadata.filter(valid_issuer_metadata) {
+            Some(m) => m,
+            None => {
+                self.log(format!(
+                    "issuer {} rejected: sbt_metadata didn't return valid contract metadata",
+                    issuer
+                ));
+                return false;
+            }
+        };
+        // the issuer could be added (or the bond released) while the call was in flight
+        if self.sbt_issuers.get(&issuer).is_some() {
+            return false;
+        }
+        self.assert_issuer_bond(&issuer);
+        let issuer_id = self.next_issuer_id;
+        self._add_sbt_issuer(&issuer);
+        self.issuer_metadata.insert(&issuer_id, &metadata);
+        self.emit_registry_event(RegistryEvent::AddIssuer {
+            issuer: &issuer,
+            memo: memo.as_deref(),
+        });
+        true
+    }
+
+    /// Returns the issuer metadata stored on the verified registration (see
+    /// `admin_add_sbt_issuer_verified`). None for issuers added without the verification.
+    pub fn sbt_issuer_metadata(&self, issuer: AccountId) -> Option<ContractMetadata> {
+        self.issuer_metadata.get(&self.sbt_issuers.get(&issuer)?)
+    }
+}
+
+fn valid_issuer_metadata(m: &ContractMetadata) -> bool {
+    !m.spec.is_empty()
+        && !m.name.is_empty()
+        && !m.symbol.is_empty()
+        && m.try_to_vec().unwrap().len() <= MAX_ISSUER_METADATA_LEN
+}
@@ -1,16 +1,124 @@
 use std::collections::{HashMap, HashSet};
 
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::{LookupMap, TreeMap, UnorderedMap, UnorderedSet};
-use near_sdk::{env, near_bindgen, require, AccountId, PanicOnDefault};
-
-use cost::MILI_NEAR;
+use near_sdk::collections::{LookupMap, LookupSet, UnorderedMap, UnorderedSet};
+use near_sdk::json_types::{Base64VecU8, U128};
+use near_sdk::{
+    env, near_bindgen, require, AccountId, Balance, CryptoHash, Gas, GasWeight, PanicOnDefault,
+    Promise, PromiseResult,
+};
+
+use cost::{IS_HUMAN_GAS, MILI_NEAR, SOUL_TRANSFER_BOND};
 use sbt::*;
 
+pub use crate::admin_log::*;
+use crate::bounded::*;
+pub use crate::contexts::*;
+pub use crate::credentials::*;
+#[cfg(any(feature = "testnet", feature = "debug"))]
+pub use crate::debug::*;
+use crate::escrow::*;
+pub use crate::events::*;
+pub use crate::expirations::*;
+pub use crate::human_calls::*;
+pub use crate::humanity_claim::*;
+pub use crate::issuer_bond::*;
+pub use crate::issuer_metadata::*;
+pub use crate::migrate::*;
+pub use crate::mint_refs::*;
+pub use crate::negative_classes::*;
+pub use crate::notifications::*;
+pub use crate::policies::*;
+pub use crate::reservations::*;
+pub use crate::social::*;
+pub use crate::status::*;
 use crate::storage::*;
-
+pub use crate::timelock::*;
+pub use crate::ttl_policy::*;
+pub use crate::upgrade::*;
+use crate::valid_supply::*;
+pub use crate::views::*;
+pub use crate::vouchers::*;
+
+mod admin_log;
+mod attestation;
+mod bans;
+mod bounded;
+mod contexts;
+mod credentials;
+#[cfg(any(feature = "testnet", feature = "debug"))]
+mod debug;
+mod deleted_accounts;
+mod escrow;
+mod events;
+mod expirations;
+mod export;
+#[cfg(test)]
+mod fuzz;
+#[cfg(test)]
+mod gas_bench;
+mod genesis;
+mod historical;
+mod human_calls;
+mod humanity_claim;
+mod invariants;
+mod issuer_bond;
+mod issuer_metadata;
+mod migrate;
+mod mint_refs;
+mod negative_classes;
+mod notifications;
+mod policies;
+mod privacy;
 mod registry;
+mod reservations;
+mod sanity;
+mod social;
+#[cfg(test)]
+mod soul_transfer_check;
+mod stamps;
+mod status;
 mod storage;
+#[cfg(feature = "testnet")]
+mod testnet;
+mod timelock;
+mod ttl_policy;
+mod upgrade;
+mod valid_supply;
+mod views;
+mod vouchers;
+
+/// Optional features implemented by this registry version, see `supported_extensions`.
+pub const SUPPORTED_EXTENSIONS: &[&str] = &[
+    EXT_SOUL_TRANSFER,
+    EXT_SOUL_MERGE,
+    EXT_RECOVERY,
+    EXT_IS_HUMAN_CALL,
+    EXT_IS_HUMAN_AT,
+    EXT_CLASSES_METADATA,
+    EXT_MINT_WITH_MSG,
+    EXT_MINT_REFS,
+    EXT_RENEW_BATCH,
+    EXT_VOUCHERS,
+    EXT_STAMPS,
+    EXT_ATTESTATION,
+    EXT_HUMANITY_CLAIM,
+    EXT_METADATA_HASH,
+    EXT_POLICIES,
+    EXT_CONTEXTS,
+    EXT_NEGATIVE_CLASSES,
+    EXT_REVOCATION_REASONS,
+    EXT_PRIVACY_MODE,
+    EXT_HUMAN_CALL_ALLOWLIST,
+    EXT_COMPOSITE_CREDENTIALS,
+    EXT_TOKEN_RESERVATIONS,
+    EXT_ADMIN_LOG,
+    EXT_CLASS_TTL_POLICY,
+    EXT_VALID_SUPPLY,
+];
+
+/// Gas for the `is_human_call` callback.
+const IS_HUMAN_CALLBACK_GAS: Gas = Gas(5 * Gas::ONE_TERA.0);
 
 #[near_bindgen]
 #[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
@@ -23,22 +131,191 @@ pub struct Contract {
     pub issuer_id_map: LookupMap<IssuerId, AccountId>, // reverse index
     /// registry of blacklisted accounts by issuer
     pub(crate) banlist: UnorderedSet<AccountId>,
+    /// temporarily banned accounts -> ban expire time (unix timestamp in ms), see `admin_ban`.
+    pub(crate) ban_expirations: LookupMap<AccountId, u64>,
     /// store ongoing soul transfers by "old owner"
     pub(crate) ongoing_soul_tx: LookupMap<AccountId, IssuerTokenId>,
+    /// secondary account -> primary account approved to merge the secondary account SBTs
+    pub(crate) merge_approvals: LookupMap<AccountId, AccountId>,
+    /// ongoing soul transfers initiated by an issuer: "old owner" -> issuer
+    pub(crate) issuer_soul_tx: LookupMap<AccountId, IssuerId>,
+    /// accounts being recovered (`sbt_recover` in progress) -> recovering issuer. The
+    /// account is locked until the recovery is completed.
+    pub(crate) recoveries: LookupMap<AccountId, IssuerId>,
+    /// issuer activity counters, see `issuer_stats`.
+    pub(crate) issuer_counters: LookupMap<IssuerId, IssuerCounters>,
+    /// issuer capabilities bitmask (see `CAP_*` constants) granted by the authority
+    pub(crate) issuer_capabilities: LookupMap<IssuerId, Capabilities>,
 
     pub(crate) supply_by_owner: LookupMap<(AccountId, IssuerId), u64>,
     pub(crate) supply_by_class: LookupMap<(IssuerId, ClassId), u64>,
     pub(crate) supply_by_issuer: LookupMap<IssuerId, u64>,
 
-    /// maps user balance key to tokenID
-    pub(crate) balances: TreeMap<BalanceKey, TokenId>,
-    pub(crate) issuer_tokens: LookupMap<IssuerTokenId, TokenData>,
+    /// maps user balance key to tokenID, sharded by (owner, issuer) class buckets.
+    pub(crate) balances: Balances,
+    pub(crate) issuer_tokens: TokenStore,
 
-    /// map of SBT contract -> next available token_id
+    /// map of SBT contract -> last assigned token_id (see `next_token_id`)
     pub(crate) next_token_ids: LookupMap<IssuerId, TokenId>,
     pub(crate) next_issuer_id: IssuerId,
 
     pub(crate) iah_classes: (AccountId, Vec<ClassId>),
+
+    /// rolling hash over all emitted events, see `EventDigest`.
+    pub(crate) event_root: [u8; 32],
+    pub(crate) event_count: u64,
+
+    /// max number of tokens renewed in a single call.
+    pub(crate) max_renew_batch: u32,
+    /// max number of tokens (from all issuers) an account can own, enforced on mint.
+    pub(crate) max_tokens_per_account: u32,
+
+    /// account -> last time (unix timestamp in ms) the account was involved in a mint, renew
+    /// or transfer.
+    pub(crate) last_activity: LookupMap<AccountId, u64>,
+
+    /// index of token expire times bucketed by (day, issuer), see `expirations_on`. The bucket
+    /// tokens are linked in `expiring_tokens`.
+    pub(crate) expirations: LookupMap<(u64, IssuerId), ExpirationBucket>,
+
+    /// uploaded contract code, by sha256 hash, see `upload_code`.
+    pub(crate) code_blobs: LookupMap<CryptoHash, Vec<u8>>,
+    pub(crate) staged_code: Option<StagedCode>,
+
+    /// when paused, all transactions changing tokens are blocked.
+    pub(crate) paused: bool,
+    /// authority actions waiting for the timelock, see `queue_action`.
+    pub(crate) pending_actions: UnorderedMap<u64, PendingAction>,
+    pub(crate) next_action_id: u64,
+    pub(crate) action_timelock_ms: u64,
+
+    /// max supply of a class set by the issuer, see `sbt_set_class_cap`.
+    pub(crate) class_caps: LookupMap<(IssuerId, ClassId), u64>,
+
+    /// min time to live (in miliseconds) of tokens of a class, set by the authority, see
+    /// `admin_set_class_min_ttl`.
+    pub(crate) class_min_ttls: LookupMap<(IssuerId, ClassId), u64>,
+
+    /// issuer policies of accounts allowed to receive minted tokens.
+    pub(crate) recipient_policies: LookupMap<IssuerId, RecipientPolicy>,
+
+    /// accounts flagged as deleted and recreated by a different owner (value: flag time in
+    /// miliseconds), see `admin_flag_recreated_account`.
+    pub(crate) recreated_accounts: LookupMap<AccountId, u64>,
+
+    /// credential aliases: name -> `(issuer, class)` pairs, see `admin_set_credential`.
+    pub(crate) credentials: LookupMap<String, Vec<(IssuerId, ClassId)>>,
+
+    /// mint storage escrow: (payer, issuer) -> balance, see `sbt_escrow_deposit`.
+    pub(crate) mint_escrow: LookupMap<(AccountId, IssuerId), Balance>,
+
+    /// deferred mint vouchers, see `sbt_create_vouchers`.
+    pub(crate) vouchers: LookupMap<u64, Voucher>,
+    pub(crate) next_voucher_id: u64,
+
+    /// progress of the lazy state migration, see `migrate_chunk`.
+    pub(crate) migration: MigrationProgress,
+
+    /// stored token statuses (revoked, frozen and burned tokens), see `TokenStatus`.
+    pub(crate) token_statuses: LookupMap<IssuerTokenId, TokenStatus>,
+
+    /// soul transfers which can be continued by anyone: owner -> bond.
+    pub(crate) soul_transfer_bonds: LookupMap<AccountId, SoulTransferBond>,
+
+    /// contracts registered to receive mint notifications, see `sbt_set_notifications`.
+    pub(crate) notification_receivers: LookupSet<AccountId>,
+
+    /// issuer bonds, see `issuer_lock_bond`.
+    pub(crate) issuer_bonds: LookupMap<AccountId, IssuerBond>,
+    /// receiver of slashed issuer bonds.
+    pub(crate) treasury: AccountId,
+
+    /// used context markers, see `mark_used`.
+    pub(crate) used_contexts: LookupSet<CryptoHash>,
+
+    /// min time (in miliseconds) between receiving tokens in a soul transfer and starting a
+    /// soul transfer from the recipient account. 0 disables the cooldown.
+    pub(crate) soul_transfer_cooldown_ms: u64,
+    /// soul transfer recipient -> end of the cooldown (unix timestamp in ms).
+    pub(crate) transfer_cooldowns: LookupMap<AccountId, u64>,
+
+    /// classes which are not transferable on soul transfer (key bound credentials), see
+    /// `sbt_set_class_transferable`.
+    pub(crate) key_bound_classes: LookupSet<(IssuerId, ClassId)>,
+
+    /// SocialDB badge set on mint, see `admin_set_social_badge`.
+    pub(crate) social_badge: Option<SocialBadgeConfig>,
+
+    /// eligibility policies: name -> policy, see `admin_set_policy`.
+    pub(crate) policies: LookupMap<String, Policy>,
+
+    /// set by `finalize_genesis`, ends the genesis import (see `admin_import_tokens`).
+    pub(crate) genesis_finalized: bool,
+
+    /// stamps (lightweight attestations): (owner, issuer, class) -> issued_at, see
+    /// `sbt_mint_stamps`.
+    pub(crate) stamps: LookupMap<BalanceKey, u64>,
+    pub(crate) stamp_supply: LookupMap<(IssuerId, ClassId), u64>,
+
+    /// issuer contract metadata stored on the verified registration, see
+    /// `admin_add_sbt_issuer_verified`.
+    pub(crate) issuer_metadata: LookupMap<IssuerId, ContractMetadata>,
+    /// owner -> completion callback of the owner soul transfer in progress.
+    pub(crate) soul_transfer_callbacks: LookupMap<AccountId, SoulTransferCallback>,
+    /// max number of registered issuers.
+    pub(crate) max_issuers: u32,
+    /// max number of classes an issuer can mint tokens of.
+    pub(crate) max_classes_per_issuer: u32,
+    /// emergency guardian: can only pause the registry.
+    pub(crate) guardian: Option<AccountId>,
+    /// hash of (issuer ID, external ref) -> first token minted with the ref, see
+    /// `sbt_mint_with_refs`.
+    pub(crate) mint_refs: LookupMap<CryptoHash, TokenId>,
+    /// `(issuer, class)` pairs whose valid tokens make `is_human` false, see
+    /// `admin_set_negative_classes`.
+    pub(crate) negative_classes: Vec<(IssuerId, ClassId)>,
+    /// reasons of revoked and frozen tokens (kept with the burned token tombstone), see
+    /// `sbt_revoke_with_reason`.
+    pub(crate) revocation_reasons: LookupMap<IssuerTokenId, RevocationReason>,
+    /// accounts which enabled the privacy mode, see `set_private`.
+    pub(crate) private_accounts: LookupSet<AccountId>,
+    /// allowed `is_human_call` receivers, see `admin_set_human_call_target`.
+    pub(crate) human_call_targets: LookupMap<AccountId, HumanCallPolicy>,
+    /// composite credentials: name -> (issuer, class, issued_before) terms, see
+    /// `admin_set_composite_credential`.
+    pub(crate) composite_credentials: LookupMap<String, Vec<(IssuerId, ClassId, Option<u64>)>>,
+    /// token ID reservations of the issuers, see `reserve_token_ids`.
+    pub(crate) token_reservations: LookupMap<IssuerId, Vec<TokenReservation>>,
+    /// ring buffer of the last `ADMIN_LOG_SIZE` admin actions: seq % ADMIN_LOG_SIZE -> entry.
+    pub(crate) admin_log: LookupMap<u64, AdminLogEntry>,
+    /// number of the logged admin actions.
+    pub(crate) admin_log_count: u64,
+    /// (issuer, class) -> max TTL policy of the class tokens, see `sbt_set_class_max_ttl`.
+    pub(crate) class_max_ttls: LookupMap<(IssuerId, ClassId), ClassTtlPolicy>,
+    /// issuer -> counters of the tokens which are not revoked, frozen nor burned, see
+    /// `sbt_supply`.
+    pub(crate) valid_supply: LookupMap<IssuerId, ValidSupply>,
+    /// (issuer, expire time bucket) -> (offset, number of tokens) of the `valid_supply`
+    /// tokens expiring in the bucket.
+    pub(crate) valid_expirations: LookupMap<(IssuerId, u32), Vec<(u32, u32)>>,
+    /// banned account -> (issuer, class) of the last balance checked by an unfinished
+    /// `admin_invalidate_banned_tokens`.
+    pub(crate) invalidation_cursors: LookupMap<AccountId, (IssuerId, ClassId)>,
+    /// (day, issuer, token) -> (previous, next) token of the `expirations` bucket list, 0 if
+    /// none.
+    pub(crate) expiring_tokens: LookupMap<(u64, IssuerId, TokenId), (TokenId, TokenId)>,
+    /// guardian proposed by the authority, see `propose_guardian`.
+    pub(crate) proposed_guardian: Option<AccountId>,
+    /// removed issuer -> the issuer IDs it had, so its escrows stay withdrawable.
+    pub(crate) removed_issuers: LookupMap<AccountId, Vec<IssuerId>>,
+    /// relayer allowances: (relayer, issuer) -> balance paying relayed voucher redemptions,
+    /// see `relayer_deposit`.
+    pub(crate) relayer_allowances: IssuerBalances,
+    /// issuer -> last token ID at the upgrade from the previous release: tokens which are not
+    /// counted in the valid supply until `migrate_chunk` backfills them, see `is_counted`.
+    pub(crate) legacy_token_ids: LookupMap<IssuerId, TokenId>,
+    /// account -> number of its tokens from all issuers, see `account_supply`.
+    pub(crate) supply_by_account: LookupMap<AccountId, u64>,
 }
 
 // Implement the contract structure
@@ -47,23 +324,102 @@ impl Contract {
     #[init]
     pub fn new(authority: AccountId, iah_issuer: AccountId, iah_classes: Vec<ClassId>) -> Self {
         require!(
-            iah_classes.len() > 0,
+            !iah_classes.is_empty(),
             "iah_classes must be a non empty list"
         );
+        let ctr = Self::empty(authority, iah_issuer, iah_classes);
+        ctr.sanity_check();
+        ctr
+    }
+
+    /// Returns the registry state without issuers nor tokens, see `new` and `migrate`.
+    pub(crate) fn empty(
+        authority: AccountId,
+        iah_issuer: AccountId,
+        iah_classes: Vec<ClassId>,
+    ) -> Self {
         Self {
-            authority,
+            authority: authority.clone(),
             sbt_issuers: UnorderedMap::new(StorageKey::SbtIssuers),
             issuer_id_map: LookupMap::new(StorageKey::SbtIssuersRev),
             banlist: UnorderedSet::new(StorageKey::Banlist),
+            ban_expirations: LookupMap::new(StorageKey::BanExpirations),
             supply_by_owner: LookupMap::new(StorageKey::SupplyByOwner),
             supply_by_class: LookupMap::new(StorageKey::SupplyByClass),
             supply_by_issuer: LookupMap::new(StorageKey::SupplyByIssuer),
-            balances: TreeMap::new(StorageKey::Balances),
-            issuer_tokens: LookupMap::new(StorageKey::IssuerTokens),
+            balances: Balances::new(),
+            issuer_tokens: TokenStore::new(),
             next_token_ids: LookupMap::new(StorageKey::NextTokenId),
             next_issuer_id: 1,
             ongoing_soul_tx: LookupMap::new(StorageKey::OngoingSoultTx),
+            merge_approvals: LookupMap::new(StorageKey::MergeApprovals),
+            issuer_soul_tx: LookupMap::new(StorageKey::IssuerSoulTx),
+            recoveries: LookupMap::new(StorageKey::Recoveries),
+            issuer_counters: LookupMap::new(StorageKey::IssuerCounters),
+            issuer_capabilities: LookupMap::new(StorageKey::IssuerCapabilities),
             iah_classes: (iah_issuer, iah_classes),
+            event_root: [0; 32],
+            event_count: 0,
+            max_renew_batch: 500,
+            max_tokens_per_account: DEFAULT_MAX_TOKENS_PER_ACCOUNT,
+            last_activity: LookupMap::new(StorageKey::LastActivity),
+            expirations: LookupMap::new(StorageKey::Expirations),
+            code_blobs: LookupMap::new(StorageKey::CodeBlobs),
+            staged_code: None,
+            paused: false,
+            pending_actions: UnorderedMap::new(StorageKey::PendingActions),
+            next_action_id: 1,
+            action_timelock_ms: DEFAULT_ACTION_TIMELOCK_MS,
+            class_caps: LookupMap::new(StorageKey::ClassCaps),
+            class_min_ttls: LookupMap::new(StorageKey::ClassMinTtls),
+            recipient_policies: LookupMap::new(StorageKey::RecipientPolicies),
+            recreated_accounts: LookupMap::new(StorageKey::RecreatedAccounts),
+            credentials: LookupMap::new(StorageKey::Credentials),
+            mint_escrow: LookupMap::new(StorageKey::MintEscrow),
+            vouchers: LookupMap::new(StorageKey::Vouchers),
+            next_voucher_id: 1,
+            migration: MigrationProgress {
+                done: true,
+                ..Default::default()
+            },
+            token_statuses: LookupMap::new(StorageKey::TokenStatuses),
+            soul_transfer_bonds: LookupMap::new(StorageKey::SoulTransferBonds),
+            notification_receivers: LookupSet::new(StorageKey::NotificationReceivers),
+            issuer_bonds: LookupMap::new(StorageKey::IssuerBonds),
+            treasury: authority,
+            used_contexts: LookupSet::new(StorageKey::UsedContexts),
+            soul_transfer_cooldown_ms: 0,
+            transfer_cooldowns: LookupMap::new(StorageKey::TransferCooldowns),
+            key_bound_classes: LookupSet::new(StorageKey::KeyBoundClasses),
+            social_badge: None,
+            policies: LookupMap::new(StorageKey::Policies),
+            genesis_finalized: false,
+            stamps: LookupMap::new(StorageKey::Stamps),
+            stamp_supply: LookupMap::new(StorageKey::StampSupply),
+            issuer_metadata: LookupMap::new(StorageKey::IssuerMetadata),
+            soul_transfer_callbacks: LookupMap::new(StorageKey::SoulTransferCallbacks),
+            max_issuers: DEFAULT_MAX_ISSUERS,
+            max_classes_per_issuer: DEFAULT_MAX_CLASSES_PER_ISSUER,
+            guardian: None,
+            mint_refs: LookupMap::new(StorageKey::MintRefs),
+            negative_classes: Vec::new(),
+            revocation_reasons: LookupMap::new(StorageKey::RevocationReasons),
+            private_accounts: LookupSet::new(StorageKey::PrivateAccounts),
+            human_call_targets: LookupMap::new(StorageKey::HumanCallTargets),
+            composite_credentials: LookupMap::new(StorageKey::CompositeCredentials),
+            token_reservations: LookupMap::new(StorageKey::TokenReservations),
+            admin_log: LookupMap::new(StorageKey::AdminLog),
+            admin_log_count: 0,
+            class_max_ttls: LookupMap::new(StorageKey::ClassMaxTtls),
+            valid_supply: LookupMap::new(StorageKey::ValidSupply),
+            valid_expirations: LookupMap::new(StorageKey::ValidExpirations),
+            invalidation_cursors: LookupMap::new(StorageKey::InvalidationCursors),
+            expiring_tokens: LookupMap::new(StorageKey::ExpiringTokens),
+            proposed_guardian: None,
+            removed_issuers: LookupMap::new(StorageKey::RemovedIssuers),
+            relayer_allowances: LookupMap::new(StorageKey::RelayerAllowances),
+            legacy_token_ids: LookupMap::new(StorageKey::LegacyTokenIds),
+            supply_by_account: LookupMap::new(StorageKey::SupplyByAccount),
         }
     }
 
@@ -71,43 +427,530 @@ impl Contract {
     // Queries
     //
 
+    /// NEP-330 source metadata of the deployed contract.
+    pub fn contract_source_metadata(&self) -> ContractSourceMetadata {
+        contract_source_metadata!(Standard::new(STANDARD_NAME, SPEC_VERSION))
+    }
+
+    /// Returns the names of the optional features supported by the registry (the `sbt::EXT_*`
+    /// constants), so other contracts can degrade gracefully with older deployments.
+    pub fn supported_extensions(&self) -> Vec<String> {
+        SUPPORTED_EXTENSIONS.iter().map(|e| e.to_string()).collect()
+    }
+
     pub fn sbt_contracts(&self) -> Vec<AccountId> {
         self.sbt_issuers.keys().collect()
     }
 
-    #[inline]
-    fn _is_banned(&self, account: &AccountId) -> bool {
-        self.banlist.contains(account)
+    /// Returns the number of registered (not removed) issuers. Issuer IDs are assigned
+    /// sequentially, starting from 1.
+    pub fn issuers_count(&self) -> u64 {
+        self.sbt_issuers.len()
     }
 
-    /// Returns true if the given account is human.
-    pub fn is_human(&self, account: AccountId) -> bool {
-        if self._is_banned(&account) {
+    /// Returns the compact issuer ID (used internally by the registry) of the given issuer.
+    pub fn issuer_id(&self, issuer: AccountId) -> Option<IssuerId> {
+        self.sbt_issuers.get(&issuer)
+    }
+
+    /// Returns the issuer account of the given issuer ID. Removed issuers are also returned.
+    pub fn issuer_by_id(&self, issuer_id: IssuerId) -> Option<AccountId> {
+        self.issuer_id_map.get(&issuer_id)
+    }
+
+    /// Returns the last token ID assigned by the issuer, or 0 if the issuer didn't mint any
+    /// token (or is not registered). Token IDs are assigned per issuer, sequentially starting
+    /// from 1, and are never reused: IDs of burned tokens are not assigned again.
+    pub fn last_token_id(&self, issuer: AccountId) -> TokenId {
+        match self.sbt_issuers.get(&issuer) {
+            None => 0,
+            Some(issuer_id) => self.next_token_ids.get(&issuer_id).unwrap_or(0),
+        }
+    }
+
+    /// Returns true if the token exists (including expired and revoked, but not burned
+    /// tokens). Cheaper than `sbt`: the token data is not deserialized.
+    pub fn sbt_exists(&self, issuer: AccountId, token: TokenId) -> bool {
+        match self.sbt_issuers.get(&issuer) {
+            None => false,
+            Some(issuer_id) => self
+                .issuer_tokens
+                .contains_key(&IssuerTokenId { issuer_id, token }),
+        }
+    }
+
+    /// Returns the `issuer` tokens with the given IDs, in the same order as `token_ids`: None
+    /// if the token doesn't exist (or was burned). Same as `sbt`, but for many tokens in a
+    /// single call, eg to fill gaps of an indexer. At most 1000 IDs can be queried at once.
+    /// Panics if the issuer is not registered.
+    pub fn sbts(&self, issuer: AccountId, token_ids: Vec<TokenId>) -> Vec<Option<Token>> {
+        require!(
+            token_ids.len() <= registry::MAX_LIMIT as usize,
+            format!(
+                "at most {} tokens can be queried at once",
+                registry::MAX_LIMIT
+            )
+        );
+        let issuer_id = self.assert_issuer(&issuer);
+        token_ids
+            .into_iter()
+            .map(|token| {
+                self.issuer_tokens
+                    .get(&IssuerTokenId { issuer_id, token })
+                    .map(|td| self.redact_token(td.to_token(token)))
+            })
+            .collect()
+    }
+
+    /// Returns the `sbt::token_metadata_hash` of the `issuer` token metadata, or None if the
+    /// token doesn't exist (or was burned). Clients can compare it with the hash of the
+    /// expected metadata rather than the metadata fields.
+    pub fn sbt_metadata_hash(&self, issuer: AccountId, token: TokenId) -> Option<Base64VecU8> {
+        let issuer_id = self.sbt_issuers.get(&issuer)?;
+        self.issuer_tokens
+            .get_with_hash(&IssuerTokenId { issuer_id, token })
+            .map(|(_, hash)| hash.to_vec().into())
+    }
+
+    /// Returns true if the account has at least one SBT (including expired and revoked,
+    /// but not burned tokens) from any issuer. Cheaper than `sbt_tokens_by_owner`: only the
+    /// first balance key is read. Same as other queries, returns false when the account
+    /// soul transfer is in progress.
+    pub fn has_any_sbt(&self, account: AccountId) -> bool {
+        if self.ongoing_soul_tx.contains_key(&account) {
             return false;
         }
-        let issuer = Some(self.iah_classes.0.clone());
-        // check if user has tokens from all classes
-        for cls in &self.iah_classes.1 {
-            let tokens = self.sbt_tokens_by_owner(
-                account.clone(),
-                issuer.clone(),
-                Some(*cls),
-                Some(1),
-                None,
-            );
-            // we need to check class, because the query can return a "next" token if a user
-            // doesn't have the token of requested class.
-            if !(tokens.len() > 0 && tokens[0].1[0].metadata.class == *cls) {
-                return false;
+        // `higher` is exclusive, issuer and class IDs start from 1.
+        self.balances
+            .higher(&balance_key(account.clone(), 0, 0))
+            .map_or(false, |k| k.owner == account)
+    }
+
+    /// Lists all classes the account holds, from all issuers, as
+    /// `(issuer, class, token ID, expires_at)` tuples, ordered by issuer ID and class.
+    /// Includes expired and revoked (but not burned) tokens. Lighter than
+    /// `sbt_tokens_by_owner`: token metadata is not returned. Same as other queries, returns
+    /// an empty list when the account soul transfer is in progress.
+    pub fn sbt_classes_by_owner(
+        &self,
+        account: AccountId,
+    ) -> Vec<(AccountId, ClassId, TokenId, Option<u64>)> {
+        if self.ongoing_soul_tx.contains_key(&account) {
+            return vec![];
+        }
+        // `iter_from` is exclusive, issuer and class IDs start from 1.
+        self.balances
+            .iter_from(balance_key(account.clone(), 0, 0))
+            .take_while(|(k, _)| k.owner == account)
+            .map(|(k, token)| {
+                let t = self.get_token(k.issuer_id, token);
+                (
+                    self.issuer_id_map.get(&k.issuer_id).unwrap(),
+                    k.class_id,
+                    token,
+                    t.metadata.expires_at(),
+                )
+            })
+            .collect()
+    }
+
+    /// Returns capabilities bitmask of the given issuer (see `CAP_*` constants).
+    pub fn issuer_capabilities(&self, issuer: AccountId) -> Capabilities {
+        let issuer_id = self.assert_issuer(&issuer);
+        self.capabilities(issuer_id)
+    }
+
+    /// Returns issuer health overview. `lookback_days` (default 30, max 366) is the period
+    /// of the `recently_expired` estimate.
+    pub fn issuer_stats(&self, issuer: AccountId, lookback_days: Option<u32>) -> IssuerStats {
+        let issuer_id = self.assert_issuer(&issuer);
+        let lookback_days = lookback_days.unwrap_or(30) as u64;
+        require!(lookback_days <= 366, "max lookback_days is 366");
+        let now = MilliSeconds::now();
+        let today = expiration_day(now.0);
+        let mut recently_expired = 0;
+        for day in today.saturating_sub(lookback_days)..=today {
+            if day < today {
+                recently_expired += self
+                    .expirations
+                    .get(&(day, issuer_id))
+                    .map_or(0, |b| b.len as u64);
+            } else {
+                recently_expired += self
+                    .expiring_tokens_on(day, issuer_id)
+                    .filter(|t| self.get_token(issuer_id, *t).metadata.is_expired_at(now))
+                    .count() as u64;
             }
         }
-        true
+        let counters = self.issuer_counters.get(&issuer_id).unwrap_or_default();
+        IssuerStats {
+            minted: self.next_token_ids.get(&issuer_id).unwrap_or(0),
+            live: self.supply_by_issuer.get(&issuer_id).unwrap_or(0),
+            revoked: counters.revoked,
+            recently_expired,
+            last_mint_ms: Some(counters.last_mint_ms).filter(|t| *t > 0),
+            paused: self.paused || self.capabilities(issuer_id) & CAP_MINT == 0,
+        }
+    }
+
+    /// Returns the max supply of the issuer class, if set.
+    pub fn class_cap(&self, issuer: AccountId, class: ClassId) -> Option<u64> {
+        let issuer_id = self.assert_issuer(&issuer);
+        self.class_caps.get(&(issuer_id, class))
+    }
+
+    /// Returns false if the issuer class tokens are burned, rather than moved, in a soul
+    /// transfer, see `sbt_set_class_transferable`.
+    pub fn class_transferable_on_soul_transfer(&self, issuer: AccountId, class: ClassId) -> bool {
+        let issuer_id = self.assert_issuer(&issuer);
+        !self.key_bound_classes.contains(&(issuer_id, class))
+    }
+
+    /// Returns the min time to live (in miliseconds) of the issuer class tokens, see
+    /// `admin_set_class_min_ttl`.
+    pub fn class_min_ttl(&self, issuer: AccountId, class: ClassId) -> Option<u64> {
+        let issuer_id = self.sbt_issuers.get(&issuer)?;
+        self.class_min_ttls.get(&(issuer_id, class))
+    }
+
+    /// Returns the recipient policy of the given issuer, if set.
+    pub fn recipient_policy(&self, issuer: AccountId) -> Option<RecipientPolicy> {
+        let issuer_id = self.sbt_issuers.get(&issuer)?;
+        self.recipient_policies.get(&issuer_id)
+    }
+
+    /// Returns the digest of all events emitted by the registry.
+    pub fn event_digest(&self) -> EventDigest {
+        EventDigest {
+            root: self.event_root.to_vec().into(),
+            count: self.event_count,
+        }
+    }
+
+    /// Same as `sbt_tokens`, but the result includes the state snapshot info, see
+    /// `sbt::Page`.
+    pub fn sbt_tokens_page(
+        &self,
+        issuer: AccountId,
+        from_token: Option<u64>,
+        limit: Option<u32>,
+        with_expired: Option<bool>,
+    ) -> Page<Vec<Token>> {
+        self.page(self.sbt_tokens(issuer, from_token, limit, with_expired))
+    }
+
+    /// Same as `sbt_tokens_by_owner`, but the result includes the state snapshot info, see
+    /// `sbt::Page`.
+    pub fn sbt_tokens_by_owner_page(
+        &self,
+        account: AccountId,
+        issuer: Option<AccountId>,
+        from_class: Option<u64>,
+        limit: Option<u32>,
+        with_expired: Option<bool>,
+    ) -> Page<Vec<(AccountId, Vec<OwnedToken>)>> {
+        self.page(self.sbt_tokens_by_owner(account, issuer, from_class, limit, with_expired))
+    }
+
+    /// Same as `sbt_tokens_page`, but returns the Borsh encoded `sbt::Page<Vec<Token>>`.
+    /// Skips the JSON serialization of the tokens, which cuts the view gas and the result
+    /// size of large pages, for indexers which can decode Borsh.
+    pub fn sbt_tokens_page_borsh(
+        &self,
+        issuer: AccountId,
+        from_token: Option<u64>,
+        limit: Option<u32>,
+        with_expired: Option<bool>,
+    ) -> Base64VecU8 {
+        self.sbt_tokens_page(issuer, from_token, limit, with_expired)
+            .try_to_vec()
+            .unwrap()
+            .into()
+    }
+
+    /// Same as `sbt_tokens_by_owner_page`, but returns the Borsh encoded
+    /// `sbt::Page<Vec<(AccountId, Vec<OwnedToken>)>>`, see `sbt_tokens_page_borsh`.
+    pub fn sbt_tokens_by_owner_page_borsh(
+        &self,
+        account: AccountId,
+        issuer: Option<AccountId>,
+        from_class: Option<u64>,
+        limit: Option<u32>,
+        with_expired: Option<bool>,
+    ) -> Base64VecU8 {
+        self.sbt_tokens_by_owner_page(account, issuer, from_class, limit, with_expired)
+            .try_to_vec()
+            .unwrap()
+            .into()
+    }
+
+    fn page<T>(&self, items: T) -> Page<T> {
+        Page {
+            block_height: env::block_height(),
+            timestamp: env::block_timestamp_ms(),
+            event_count: self.event_count,
+            items,
+        }
+    }
+
+    /// Returns summary of the account registry status.
+    pub fn account_summary(&self, account: AccountId) -> AccountSummary {
+        let human = self.is_human(account.clone());
+        AccountSummary {
+            banned: self._is_banned(&account),
+            human,
+            soul_transfer_in_progress: self.ongoing_soul_tx.contains_key(&account),
+            last_activity_ms: self.last_activity.get(&account),
+            human_expires_in: human
+                .then(|| self.human_expires_at(&account))
+                .flatten()
+                .map(|e| expires_in(MilliSeconds(e), MilliSeconds::now())),
+        }
+    }
+
+    /// Returns the remaining validity of the token in seconds: 0 if the token is expired,
+    /// revoked or frozen. Returns None if the token doesn't exist (or is burned) or never
+    /// expires.
+    pub fn sbt_expires_in(&self, issuer: AccountId, token: TokenId) -> Option<u64> {
+        let key = IssuerTokenId {
+            issuer_id: self.assert_issuer(&issuer),
+            token,
+        };
+        let t = self.issuer_tokens.get(&key)?;
+        if self.token_statuses.get(&key) == Some(TokenStatus::Frozen) {
+            return Some(0);
+        }
+        t.metadata
+            .expires_at()
+            .map(|e| expires_in(MilliSeconds(e), MilliSeconds::now()))
+    }
+
+    /// Verifies that `attribute` (eg "country=CH") is committed in the token
+    /// `attributes_root`, using the Merkle `proof` (sibling hashes from the leaf up to the
+    /// root, see `sbt::merkle`). Returns false if the token doesn't exist or doesn't have
+    /// the attributes root.
+    pub fn verify_attribute(
+        &self,
+        issuer: AccountId,
+        token: TokenId,
+        proof: Vec<[u8; 32]>,
+        attribute: String,
+    ) -> bool {
+        let issuer_id = self.assert_issuer(&issuer);
+        let root = match self
+            .issuer_tokens
+            .get(&IssuerTokenId { issuer_id, token })
+            .and_then(|t| t.metadata.latest().attributes_root)
+        {
+            None => return false,
+            Some(r) => r,
+        };
+        merkle::verify_proof(&root, &proof, merkle::leaf_hash(&attribute))
+    }
+
+    #[inline]
+    /// Returns true if the account is banned. Expired temporary bans are not considered.
+    fn _is_banned(&self, account: &AccountId) -> bool {
+        self.is_banned_at(account, MilliSeconds::now())
+    }
+
+    /// Returns true if the given account is human. Accounts flagged as recreated (see
+    /// `admin_flag_recreated_account`) or with a token of a negative class (see
+    /// `admin_set_negative_classes`) are not considered humans.
+    pub fn is_human(&self, account: AccountId) -> bool {
+        self._is_human(account, MilliSeconds::now())
     }
 
     //
     // Transactions
     //
 
+    /// Calls `ctr.function` with JSON arguments `{"caller": <caller>, "payload": <payload>}`
+    /// if the caller is a human, so the receiver can trust the `caller` human status.
+    /// + `payload` must be a valid JSON string.
+    /// + `ctr` must be allowed by the authority (see `admin_set_human_call_target`).
+    /// + The attached deposit, up to the receiver `max_deposit`, is forwarded to the receiver
+    ///   call. If the receiver call fails, the deposit is refunded to the caller.
+    /// + All remaining gas (minus gas reserved for the refund callback), up to the receiver
+    ///   `max_gas`, is forwarded to the receiver call. Requires more than
+    ///   `cost::IS_HUMAN_GAS` gas attached.
+    /// + Returns the receiver call result.
+    #[payable]
+    pub fn is_human_call(&mut self, ctr: AccountId, function: String, payload: String) -> Promise {
+        let caller = env::predecessor_account_id();
+        require!(self.is_human(caller.clone()), "caller is not a human");
+        require!(
+            near_sdk::serde_json::from_str::<near_sdk::serde_json::Value>(&payload).is_ok(),
+            "payload must be a valid JSON"
+        );
+        let policy = self
+            .human_call_targets
+            .get(&ctr)
+            .unwrap_or_else(|| panic!("{} is not an allowed is_human_call receiver", ctr));
+        let args = format!(r#"{{"caller":"{}","payload":{}}}"#, caller, payload);
+        let deposit = env::attached_deposit();
+        require!(
+            deposit <= policy.max_deposit.0,
+            format!(
+                "max {} yNEAR can be attached for {}",
+                policy.max_deposit.0, ctr
+            )
+        );
+        let available = env::prepaid_gas() - env::used_gas();
+        require!(available > IS_HUMAN_GAS, "not enough gas attached");
+        let (gas, weight) = match policy.max_gas {
+            None => (Gas(0), GasWeight(1)),
+            Some(max) => (std::cmp::min(max, available - IS_HUMAN_GAS), GasWeight(0)),
+        };
+        Promise::new(ctr)
+            .function_call_weight(function, args.into_bytes(), deposit, gas, weight)
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(IS_HUMAN_CALLBACK_GAS)
+                    .with_unused_gas_weight(0)
+                    .is_human_call_callback(caller, deposit.into()),
+            )
+    }
+
+    /// Refunds the `is_human_call` deposit to the caller if the receiver call failed,
+    /// otherwise returns the receiver call result.
+    #[private]
+    pub fn is_human_call_callback(&mut self, caller: AccountId, deposit: U128) {
+        match env::promise_result(0) {
+            PromiseResult::Successful(value) => env::value_return(&value),
+            _ => {
+                self.log(format!(
+                    "is_human_call: receiver call failed, refunding {} yNEAR to {}",
+                    deposit.0, caller
+                ));
+                if deposit.0 > 0 {
+                    Promise::new(caller).transfer(deposit.0);
+                }
+            }
+        }
+    }
+
+    /// Same as `sbt_mint`, but additionally returns the storage consumed by the call and the
+    /// deposit used to cover it, so issuers can do their own accounting.
+    #[payable]
+    pub fn sbt_mint_v2(&mut self, token_spec: Vec<(AccountId, Vec<TokenMetadata>)>) -> MintResult {
+        let storage_start = env::storage_usage();
+        let (tokens, deposit_used) = self.mint_with_deposit(
+            &env::predecessor_account_id(),
+            token_spec,
+            env::attached_deposit(),
+            None,
+        );
+        MintResult {
+            tokens,
+            storage_bytes: env::storage_usage().saturating_sub(storage_start),
+            deposit_used: deposit_used.into(),
+        }
+    }
+
+    /// Updates the expire time of the provided tokens. Each element of `tokens` is a pair:
+    /// `(token ID, new expires_at)`, where `expires_at` is a unix timestamp in miliseconds.
+    /// + Must be called by an SBT issuer. Only tokens issued by the caller are renewed.
+    /// + Returns result for each token. Tokens which don't exist (or were not issued by the
+    ///   caller), are owned by a banned account, or can't be renewed (revoked and frozen
+    ///   tokens) are skipped.
+    /// + Emits a single `Renew` event with all renewed tokens.
+    /// + Panics if the number of tokens exceeds the configured limit (see
+    ///   `admin_set_max_renew_batch`).
+    pub fn sbt_renew_batch(&mut self, tokens: Vec<(TokenId, u64)>) -> Vec<RenewResult> {
+        self.assert_not_paused();
+        let issuer = env::predecessor_account_id();
+        let issuer_id = self.assert_issuer(&issuer);
+        self.assert_capability(issuer_id, CAP_RENEW);
+        self.assert_renew_batch(tokens.len());
+        let mut renewed = Vec::new();
+        let mut results = Vec::with_capacity(tokens.len());
+        for (token, expires_at) in tokens {
+            let key = IssuerTokenId { issuer_id, token };
+            let mut t = match self.issuer_tokens.get(&key) {
+                None => {
+                    results.push(RenewResult::NotFound);
+                    continue;
+                }
+                Some(t) => t,
+            };
+            if self._is_banned(&t.owner) {
+                results.push(RenewResult::OwnerBanned);
+                continue;
+            }
+            if self
+                .status_of(issuer_id, token, &t)
+                .apply(TokenOp::Renew)
+                .is_none()
+            {
+                results.push(RenewResult::InvalidStatus);
+                continue;
+            }
+            self.transition_token(issuer_id, token, &t, TokenOp::Renew);
+            let mut m = t.metadata.latest();
+            self.assert_min_ttl(issuer_id, m.class, Some(expires_at));
+            self.assert_max_ttl(issuer_id, m.class, Some(expires_at));
+            self.index_expiration(issuer_id, token, m.expires_at, Some(expires_at));
+            self.move_valid(issuer_id, token, m.expires_at, Some(expires_at));
+            m.expires_at = Some(expires_at);
+            t.metadata = m.into();
+            self.record_activity(&t.owner);
+            self.issuer_tokens.insert(&key, t);
+            renewed.push(token);
+            results.push(RenewResult::Renewed);
+        }
+        if !renewed.is_empty() {
+            self.emit_event(Nep393Event::Renew(SbtTokensEvent {
+                issuer,
+                tokens: renewed,
+                storage: None,
+                reason: None,
+            }));
+        }
+        results
+    }
+
+    /// Sets (or removes, when `None`) the max supply of the caller `class`. Minting above the
+    /// cap panics. Burned tokens don't count into the supply. The cap can be set below the
+    /// current supply, which blocks new mints of the class until enough tokens are burned.
+    /// Must be called by an SBT issuer.
+    pub fn sbt_set_class_cap(&mut self, class: ClassId, cap: Option<u64>) {
+        let issuer_id = self.assert_issuer(&env::predecessor_account_id());
+        let key = (issuer_id, class);
+        match cap {
+            None => self.class_caps.remove(&key),
+            Some(c) => self.class_caps.insert(&key, &c),
+        };
+    }
+
+    /// Sets if the caller `class` tokens move with the soul in a soul transfer (the default),
+    /// or are burned during the transfer (`transferable_on_soul_transfer = false`). Use the
+    /// latter for credentials bound to the account keys or devices (eg device attestation).
+    /// Must be called by an SBT issuer.
+    pub fn sbt_set_class_transferable(
+        &mut self,
+        class: ClassId,
+        transferable_on_soul_transfer: bool,
+    ) {
+        let issuer_id = self.assert_issuer(&env::predecessor_account_id());
+        if transferable_on_soul_transfer {
+            self.key_bound_classes.remove(&(issuer_id, class));
+        } else {
+            self.key_bound_classes.insert(&(issuer_id, class));
+        }
+    }
+
+    /// Sets (or removes, when `None`) the caller policy of accounts which can receive its
+    /// tokens on mint. Useful to prevent minting credentials to throwaway implicit accounts.
+    /// Must be called by an SBT issuer.
+    pub fn sbt_set_recipient_policy(&mut self, policy: Option<RecipientPolicy>) {
+        let issuer_id = self.assert_issuer(&env::predecessor_account_id());
+        match policy {
+            None => self.recipient_policies.remove(&issuer_id),
+            Some(p) => self.recipient_policies.insert(&issuer_id, &p),
+        };
+    }
+
     /// Transfers atomically all SBT tokens from one account to another account.
     /// + The caller must be an SBT holder and the `to` must not be a banned account.
     /// + Returns the amount of tokens transferred and a boolean: `true` if the whole
@@ -118,22 +961,192 @@ impl Contract {
     ///   of were transfered and at least one token was trasnfered (caller had at least 1 sbt)
     /// + If caller does not have any tokens, nothing will be transfered, the caller
     ///    will be banned and Ban even will be emitted
+    /// + If at least `cost::SOUL_TRANSFER_BOND` is attached to the first call and the
+    ///   transfer doesn't complete, the deposit is kept as a bond and anyone can continue
+    ///   the transfer with `sbt_soul_transfer_continue`. The bond is refunded if the owner
+    ///   completes (or cancels) the transfer, or if the transfer completes in the first call.
+    ///   Any other deposit (below the bond, or attached to a subsequent call) is refunded.
+    /// + Panics if the caller received tokens in a soul transfer and the soul transfer
+    ///   cooldown didn't pass yet (see `transfer_cooldown_until`).
+    /// + `callback: (contract, method)` can be set in the first call: when the transfer
+    ///   completes, the registry calls the `method` of the `contract` with the
+    ///   `SoulTransferSummary` JSON arguments. The contract must be registered with
+    ///   `sbt_set_notifications`. Subsequent calls can omit the callback.
     #[payable]
     pub fn sbt_soul_transfer(
         &mut self,
         recipient: AccountId,
         #[allow(unused_variables)] memo: Option<String>,
+        callback: Option<(AccountId, String)>,
     ) -> (u32, bool) {
-        // TODO: test what is the max safe amount of updates
-        self._sbt_soul_transfer(recipient, 25)
+        if let Some((contract, method)) = callback {
+            self.set_soul_transfer_callback(&env::predecessor_account_id(), contract, method);
+        }
+        self._sbt_soul_transfer(recipient, MAX_SWEEP_BATCH)
     }
 
-    // execution of the sbt_soul_transfer in this function to parametrize `max_updates` in
+    // execution of the sbt_soul_transfer in this function to parametrize the batch `limit` in
     // order to facilitate tests.
     pub(crate) fn _sbt_soul_transfer(&mut self, recipient: AccountId, limit: usize) -> (u32, bool) {
         let owner = env::predecessor_account_id();
+        require!(
+            !self.issuer_soul_tx.contains_key(&owner),
+            "soul transfer initiated by an issuer is in progress"
+        );
+        let bond = self.soul_transfer_bonds.get(&owner);
+        if let Some(b) = &bond {
+            require!(
+                b.recipient == recipient,
+                "recipient must be the same as in the ongoing soul transfer"
+            );
+        }
+        let started = !self.ongoing_soul_tx.contains_key(&owner);
+        if started {
+            require!(
+                self.transfer_cooldown_until(owner.clone()).is_none(),
+                "soul transfer cooldown has not passed yet"
+            );
+            // the account is banned by the transfer, so it can't be merged anymore.
+            self.merge_approvals.remove(&owner);
+        }
+        let res = self.soul_transfer_from(owner.clone(), recipient.clone(), limit);
+        self.soul_transfer_callback_progress(&owner, &recipient, res);
+        // the deposit is refunded unless it's kept as the bond.
+        let mut refund = env::attached_deposit();
+        if res.1 {
+            if let Some(b) = bond {
+                self.soul_transfer_bonds.remove(&owner);
+                refund += b.bond;
+            }
+        } else if started && refund >= SOUL_TRANSFER_BOND {
+            self.soul_transfer_bonds.insert(
+                &owner,
+                &SoulTransferBond {
+                    recipient,
+                    bond: refund,
+                },
+            );
+            refund = 0;
+        }
+        if refund > 0 {
+            Promise::new(owner).transfer(refund);
+        }
+        res
+    }
+
+    /// Continues the soul transfer of the `from` account started by the owner with a bond
+    /// (see `sbt_soul_transfer`), so the transfer doesn't stay half-done when the owner
+    /// doesn't continue it. Can be called by anyone (eg a relayer). The caller completing
+    /// the transfer receives the bond. Same semantic as `sbt_soul_transfer`: returns the
+    /// number of transferred tokens and `true` when the transfer is completed.
+    pub fn sbt_soul_transfer_continue(&mut self, from: AccountId) -> (u32, bool) {
+        self._sbt_soul_transfer_continue(from, MAX_SWEEP_BATCH)
+    }
+
+    pub(crate) fn _sbt_soul_transfer_continue(
+        &mut self,
+        from: AccountId,
+        limit: usize,
+    ) -> (u32, bool) {
+        self.assert_not_paused();
+        let bond = self
+            .soul_transfer_bonds
+            .get(&from)
+            .expect("no bonded soul transfer in progress");
+        let res = self.soul_transfer_from(from.clone(), bond.recipient.clone(), limit);
+        self.soul_transfer_callback_progress(&from, &bond.recipient, res);
+        if res.1 {
+            self.soul_transfer_bonds.remove(&from);
+            Promise::new(env::predecessor_account_id()).transfer(bond.bond);
+        }
+        res
+    }
+
+    /// Aborts the caller's soul transfer which is in progress (not completed). Nothing is
+    /// rolled back: tokens already moved stay with the recipient, the remaining ones stay
+    /// with the caller, and the caller stays banned. Clears the transfer lock, so the caller
+    /// tokens are visible again in the queries. Refunds the soul transfer bond.
+    /// Emits `SoulTransferAborted` registry event.
+    /// Panics if there is no soul transfer in progress initiated by the caller.
+    pub fn sbt_soul_transfer_cancel(&mut self) {
+        self.assert_not_paused();
+        let owner = env::predecessor_account_id();
+        require!(
+            !self.issuer_soul_tx.contains_key(&owner),
+            "soul transfer initiated by an issuer is in progress"
+        );
+        // soul transfer always bans the owner, recovery doesn't.
+        require!(
+            self.ongoing_soul_tx.contains_key(&owner)
+                && self._is_banned(&owner)
+                && !self.merge_approvals.contains_key(&owner),
+            "no soul transfer in progress"
+        );
+        self.ongoing_soul_tx.remove(&owner);
+        self.soul_transfer_callbacks.remove(&owner);
+        if let Some(b) = self.soul_transfer_bonds.remove(&owner) {
+            Promise::new(owner.clone()).transfer(b.bond);
+        }
+        self.emit_registry_event(RegistryEvent::SoulTransferAborted { from: &owner });
+    }
+
+    /// Transfers all SBTs of `from` account to `to` on behalf of the `from` account. Used by
+    /// trusted issuers after an off-chain recovery process (when the user lost access to the
+    /// `from` account).
+    /// + Must be called by an issuer with the `CAP_SOUL_TRANSFER` capability granted by the
+    ///   authority.
+    /// + The transfer can only be continued by the same issuer, the `from` account can't
+    ///   continue it.
+    /// + Same semantic as `sbt_soul_transfer`: the `from` account is banned and the function
+    ///   must be called until it returns `true`.
+    #[payable]
+    pub fn sbt_soul_transfer_by_issuer(&mut self, from: AccountId, to: AccountId) -> (u32, bool) {
+        self._sbt_soul_transfer_by_issuer(from, to, MAX_SWEEP_BATCH)
+    }
+
+    pub(crate) fn _sbt_soul_transfer_by_issuer(
+        &mut self,
+        from: AccountId,
+        to: AccountId,
+        limit: usize,
+    ) -> (u32, bool) {
+        let issuer = env::predecessor_account_id();
+        let issuer_id = self.assert_issuer(&issuer);
+        self.assert_capability(issuer_id, CAP_SOUL_TRANSFER);
+        match self.issuer_soul_tx.get(&from) {
+            None => {
+                require!(
+                    !self.ongoing_soul_tx.contains_key(&from),
+                    "soul transfer initiated by the owner is in progress"
+                );
+                self.issuer_soul_tx.insert(&from, &issuer_id);
+            }
+            Some(id) => require!(
+                id == issuer_id,
+                "soul transfer was initiated by another issuer"
+            ),
+        };
+        self.log(format!(
+            "soul transfer {} -> {} executed by issuer {}",
+            from, to, issuer
+        ));
+        let res = self.soul_transfer_from(from.clone(), to, limit);
+        if res.1 {
+            self.issuer_soul_tx.remove(&from);
+        }
+        res
+    }
 
+    fn soul_transfer_from(
+        &mut self,
+        owner: AccountId,
+        recipient: AccountId,
+        limit: usize,
+    ) -> (u32, bool) {
+        self.assert_not_paused();
         let (resumed, start) = self.transfer_continuation(&owner, &recipient, true);
+        self.record_activity(&owner);
+        self.record_activity(&recipient);
 
         let batch: Vec<(BalanceKey, TokenId)> = self
             .balances
@@ -142,30 +1155,49 @@ impl Contract {
                 issuer_id: start.issuer_id,
                 class_id: start.token,
             })
+            .take_while(|(key, _)| key.owner == owner)
             .take(limit)
             .collect();
 
+        // key bound tokens are burned rather than moved
+        let mut dropped: Vec<(IssuerId, Vec<TokenId>)> = Vec::new();
+        for (key, token_id) in &batch {
+            if !self
+                .key_bound_classes
+                .contains(&(key.issuer_id, key.class_id))
+            {
+                continue;
+            }
+            match dropped.last_mut() {
+                Some((i, tokens)) if *i == key.issuer_id => tokens.push(*token_id),
+                _ => dropped.push((key.issuer_id, vec![*token_id])),
+            }
+        }
+        for (issuer_id, tokens) in dropped {
+            self.burn_tokens(issuer_id, &tokens, TokenOp::Burn);
+            self.emit_event(Nep393Event::Burn(SbtTokensEvent {
+                issuer: self.issuer_account(issuer_id),
+                tokens,
+                storage: None,
+                reason: None,
+            }));
+        }
+
         let mut key_new = BalanceKey {
             owner: recipient.clone(),
             issuer_id: 0,
             class_id: 0,
         };
-        let mut prev_issuer: IssuerId = 0;
         let mut token_counter = 0;
-        for (key, token_id) in &batch {
-            if key.owner != owner {
-                break;
-            }
+        let mut moved: Vec<(IssuerId, Vec<TokenId>)> = Vec::new();
+        let mut steps = BoundedIter::new(batch.iter(), SWEEP_GAS_RESERVE);
+        for (key, token_id) in &mut steps {
             token_counter += 1;
-
-            if prev_issuer != key.issuer_id {
-                prev_issuer = key.issuer_id;
-                // update user token supply map
-                if let Some(s) = self.supply_by_owner.remove(&(owner.clone(), prev_issuer)) {
-                    let key = &(recipient.clone(), prev_issuer);
-                    let supply_to = self.supply_by_owner.get(key).unwrap_or(0);
-                    self.supply_by_owner.insert(key, &(s + supply_to));
-                }
+            if self
+                .key_bound_classes
+                .contains(&(key.issuer_id, key.class_id))
+            {
+                continue;
             }
 
             self.balances.remove(key);
@@ -182,10 +1214,44 @@ impl Contract {
             };
             let mut td = self.issuer_tokens.get(&i_key).unwrap();
             td.owner = recipient.clone();
-            self.issuer_tokens.insert(&i_key, &td);
+            self.issuer_tokens.insert(&i_key, td);
+            match moved.last_mut() {
+                Some((i, tokens)) if *i == key.issuer_id => tokens.push(*token_id),
+                _ => moved.push((key.issuer_id, vec![*token_id])),
+            }
+        }
+
+        // update user token supply map. Only the moved tokens are moved: key bound tokens of a
+        // later batch are burned from the owner supply.
+        for (issuer_id, tokens) in &moved {
+            let n = tokens.len() as u64;
+            self.update_account_supply(&owner, n, false);
+            self.update_account_supply(&recipient, n, true);
+            let key = &(owner.clone(), *issuer_id);
+            let supply = self.supply_by_owner.get(key).unwrap_or(0).saturating_sub(n);
+            if supply == 0 {
+                self.supply_by_owner.remove(key);
+            } else {
+                self.supply_by_owner.insert(key, &supply);
+            }
+            let key = &(recipient.clone(), *issuer_id);
+            let supply_to = self.supply_by_owner.get(key).unwrap_or(0);
+            self.supply_by_owner.insert(key, &(supply_to + n));
+        }
+
+        if !moved.is_empty() {
+            let tokens = moved
+                .into_iter()
+                .map(|(issuer_id, tokens)| (self.issuer_account(issuer_id), tokens))
+                .collect();
+            self.emit_registry_event(RegistryEvent::SoulTransferBatch {
+                from: &owner,
+                to: &recipient,
+                tokens,
+            });
         }
 
-        let completed = token_counter != limit;
+        let completed = !steps.stopped() && token_counter != limit;
         if completed {
             if resumed {
                 // insert is happening when we need to continue, so don't need to remove if
@@ -195,7 +1261,16 @@ impl Contract {
             // we emit the event only once the operation is completed and only if some tokens were
             // transferred
             if resumed || token_counter > 0 {
-                emit_soul_transfer(&owner, &recipient);
+                if self.soul_transfer_cooldown_ms > 0 {
+                    self.transfer_cooldowns.insert(
+                        &recipient,
+                        &(env::block_timestamp_ms() + self.soul_transfer_cooldown_ms),
+                    );
+                }
+                self.emit_event(Nep393Event::SoulTransfer(SoulTransfer {
+                    from: &owner,
+                    to: &recipient,
+                }));
             }
         } else {
             let last = &batch[token_counter - 1];
@@ -211,27 +1286,191 @@ impl Contract {
         (token_counter as u32, completed)
     }
 
-    pub(crate) fn start_transfer_with_continuation(
-        &mut self,
-        owner: &AccountId,
-        recipient: &AccountId,
-        ban_owner: bool,
-    ) -> IssuerTokenId {
+    /// Approves `primary` account to merge all SBTs of the caller (the secondary account)
+    /// using `sbt_soul_merge`. The approval can be overwritten by a subsequent call.
+    /// Requires attaching enough NEAR to cover the approval storage.
+    #[payable]
+    pub fn sbt_soul_merge_approve(&mut self, primary: AccountId) {
+        let storage_start = env::storage_usage();
+        let secondary = env::predecessor_account_id();
+        require!(secondary != primary, "can't merge an account with itself");
+        self.assert_not_banned(&secondary);
+        self.merge_approvals.insert(&secondary, &primary);
+        assert_storage_deposit(storage_start, env::attached_deposit());
+    }
+
+    /// Merges all SBTs of the `secondary` account to the caller account. This is an inverse
+    /// of the soul transfer: the caller (primary account) absorbs tokens of another account
+    /// it controls.
+    /// + The `secondary` account must approve the merge first (`sbt_soul_merge_approve`).
+    /// + If both accounts have a token of the same issuer and class, then the token with the
+    ///   longer expire time is kept and the other one is burned (`Burn` event is emitted).
+    /// + The `secondary` account is banned (`Ban` event is emitted).
+    /// + Panics if the caller would have more than `max_tokens_per_account` tokens, or if a
+    ///   soul transfer of the caller (or an issuer soul transfer of either account) is in
+    ///   progress.
+    /// + Returns the amount of processed tokens and a boolean: `true` if the whole process
+    ///   has finished, `false` when the process has not finished and should be continued by
+    ///   a subsequent call.
+    /// + Emits `SoulTransfer` event once all tokens were processed and at least one token
+    ///   was merged.
+    #[payable]
+    pub fn sbt_soul_merge(&mut self, secondary: AccountId) -> (u32, bool) {
+        self._sbt_soul_merge(secondary, MAX_SWEEP_BATCH)
+    }
+
+    pub(crate) fn _sbt_soul_merge(&mut self, secondary: AccountId, limit: usize) -> (u32, bool) {
+        self.assert_not_paused();
+        let primary = env::predecessor_account_id();
         require!(
-            !self._is_banned(recipient),
-            "receiver account is banned. Cannot start the transfer"
+            self.merge_approvals.get(&secondary).as_ref() == Some(&primary),
+            "merge is not approved by the secondary account"
         );
-        if ban_owner {
-            // we only ban the source account in the soul transfer
-            // insert into banlist and assure the owner is not already banned.
+        for account in [&primary, &secondary] {
             require!(
-                self.banlist.insert(owner),
-                "from account is banned. Cannot start the transfer"
+                !self.issuer_soul_tx.contains_key(account),
+                "soul transfer initiated by an issuer is in progress"
             );
-            Nep393Event::Ban(vec![owner]).emit();
         }
+        // the secondary account lock is the merge continuation: soul transfer started by the
+        // secondary account clears its merge approval.
+        require!(
+            !self.ongoing_soul_tx.contains_key(&primary),
+            "soul transfer is in progress"
+        );
+        let (resumed, start) = self.transfer_continuation(&secondary, &primary, true);
+        self.record_activity(&secondary);
+        self.record_activity(&primary);
 
-        IssuerTokenId {
+        let batch: Vec<(BalanceKey, TokenId)> = self
+            .balances
+            .iter_from(balance_key(secondary.clone(), start.issuer_id, start.token))
+            .take_while(|(key, _)| key.owner == secondary)
+            .take(limit)
+            .collect();
+
+        // per issuer: (tokens moved to primary, burned tokens)
+        let mut per_issuer: HashMap<IssuerId, (u64, Vec<TokenId>)> = HashMap::new();
+        let mut primary_supply = self.account_supply(&primary);
+        let mut token_counter = 0;
+        let mut steps = BoundedIter::new(batch.iter(), SWEEP_GAS_RESERVE);
+        for (key, token) in &mut steps {
+            token_counter += 1;
+            let stats = per_issuer.entry(key.issuer_id).or_default();
+            let key_primary = balance_key(primary.clone(), key.issuer_id, key.class_id);
+            let mut t = self.get_token(key.issuer_id, *token);
+            if let Some(primary_token) = self.balances.get(&key_primary) {
+                let pt = self.get_token(key.issuer_id, primary_token);
+                // no expire time means the token never expires
+                let keep_primary = t.metadata.expires_at().unwrap_or(u64::MAX)
+                    <= pt.metadata.expires_at().unwrap_or(u64::MAX);
+                let burned = if keep_primary { *token } else { primary_token };
+                // removes the burned token balance and owner supply
+                self.burn_tokens(key.issuer_id, &[burned], TokenOp::Burn);
+                stats.1.push(burned);
+                if keep_primary {
+                    continue;
+                }
+            } else {
+                require!(
+                    primary_supply < self.max_tokens_per_account as u64,
+                    format!(
+                        "{} can have at most {} tokens",
+                        primary, self.max_tokens_per_account
+                    )
+                );
+                primary_supply += 1;
+            }
+            stats.0 += 1;
+            self.balances.remove(key);
+            self.balances.insert(&key_primary, token);
+            t.owner = primary.clone();
+            self.issuer_tokens.insert(
+                &IssuerTokenId {
+                    issuer_id: key.issuer_id,
+                    token: *token,
+                },
+                t,
+            );
+        }
+
+        let mut issuers: Vec<IssuerId> = per_issuer.keys().copied().collect();
+        issuers.sort();
+        for issuer_id in issuers {
+            let (moved, burned) = per_issuer.remove(&issuer_id).unwrap();
+            self.update_account_supply(&secondary, moved, false);
+            self.update_account_supply(&primary, moved, true);
+            let key = &(secondary.clone(), issuer_id);
+            let supply = self.supply_by_owner.get(key).unwrap_or(0) - moved;
+            if supply == 0 {
+                self.supply_by_owner.remove(key);
+            } else {
+                self.supply_by_owner.insert(key, &supply);
+            }
+            if moved > 0 {
+                let key = &(primary.clone(), issuer_id);
+                let supply = self.supply_by_owner.get(key).unwrap_or(0) + moved;
+                self.supply_by_owner.insert(key, &supply);
+            }
+            if !burned.is_empty() {
+                self.emit_event(Nep393Event::Burn(SbtTokensEvent {
+                    issuer: self.issuer_account(issuer_id),
+                    tokens: burned,
+                    storage: None,
+                    reason: None,
+                }));
+            }
+        }
+
+        let completed = !steps.stopped() && token_counter != limit;
+        if completed {
+            if resumed {
+                self.ongoing_soul_tx.remove(&secondary);
+            }
+            self.merge_approvals.remove(&secondary);
+            if resumed || token_counter > 0 {
+                self.emit_event(Nep393Event::SoulTransfer(SoulTransfer {
+                    from: &secondary,
+                    to: &primary,
+                }));
+            }
+        } else {
+            let last = &batch[token_counter - 1];
+            self.ongoing_soul_tx.insert(
+                &secondary,
+                &IssuerTokenId {
+                    issuer_id: last.0.issuer_id,
+                    token: last.0.class_id, // class_id, same as in the soul transfer
+                },
+            );
+        }
+
+        (token_counter as u32, completed)
+    }
+
+    pub(crate) fn start_transfer_with_continuation(
+        &mut self,
+        owner: &AccountId,
+        recipient: &AccountId,
+        ban_owner: bool,
+    ) -> IssuerTokenId {
+        self.lift_expired_ban(owner);
+        self.lift_expired_ban(recipient);
+        require!(
+            !self._is_banned(recipient),
+            "receiver account is banned. Cannot start the transfer"
+        );
+        if ban_owner {
+            // we only ban the source account in the soul transfer
+            // insert into banlist and assure the owner is not already banned.
+            require!(
+                self.banlist.insert(owner),
+                "from account is banned. Cannot start the transfer"
+            );
+            self.emit_event(Nep393Event::Ban(vec![owner]));
+        }
+
+        IssuerTokenId {
             issuer_id: 0,
             token: 0, // NOTE: this is class ID
         }
@@ -244,6 +1483,12 @@ impl Contract {
         to: &AccountId,
         ban_owner: bool,
     ) -> (bool, IssuerTokenId) {
+        if ban_owner {
+            require!(
+                !self.recoveries.contains_key(from),
+                "recovery is in progress"
+            );
+        }
         match self.ongoing_soul_tx.get(from) {
             // starting the process
             None => (
@@ -258,53 +1503,65 @@ impl Contract {
     // sbt_recover execution with `limit` parameter in
     // order to facilitate tests.
     fn _sbt_recover(&mut self, from: AccountId, to: AccountId, limit: usize) -> (u32, bool) {
+        self.assert_not_paused();
         let storage_start = env::storage_usage();
         let issuer = env::predecessor_account_id();
         let issuer_id = self.assert_issuer(&issuer);
+        self.assert_capability(issuer_id, CAP_RECOVER);
         self.assert_not_banned(&to);
+        match self.recoveries.get(&from) {
+            Some(id) => require!(
+                id == issuer_id,
+                "recovery initiated by another issuer is in progress"
+            ),
+            None => require!(
+                !self.ongoing_soul_tx.contains_key(&from),
+                "soul transfer is in progress"
+            ),
+        }
         // get the last transfered token and don't ban the owner.
         let (resumed, start) = self.transfer_continuation(&from, &to, false);
+        // iter_from starts from exclusive "left end", so a new recovery starts from the last
+        // key of the previous issuer, to include all classes of the caller (even class 0).
+        let start = if resumed {
+            balance_key(from.clone(), issuer_id, start.token)
+        } else {
+            balance_key(from.clone(), issuer_id - 1, ClassId::MAX)
+        };
+        self.record_activity(&from);
+        self.record_activity(&to);
 
         let mut tokens_recovered = 0;
-        let mut class_ids = Vec::new();
-
         let mut last_token_transfered = BalanceKey {
             owner: from.clone(),
             issuer_id,
             class_id: 0,
         };
 
-        for (key, token) in self
+        let batch: Vec<(BalanceKey, TokenId)> = self
             .balances
-            .iter_from(balance_key(from.clone(), start.issuer_id, start.token))
+            .iter_from(start)
+            .take_while(|(key, _)| key.owner == from && key.issuer_id == issuer_id)
             .take(limit)
-        {
-            if key.owner != from || key.issuer_id != issuer_id {
-                continue;
-            }
+            .collect();
+        let mut new_balance_key = balance_key(to.clone(), issuer_id, 0);
+        let mut steps = BoundedIter::new(batch.into_iter(), SWEEP_GAS_RESERVE);
+        for (key, token) in &mut steps {
             tokens_recovered += 1;
             let mut t = self.get_token(key.issuer_id, token);
-
-            class_ids.push(t.metadata.class_id());
-
             t.owner = to.clone();
             self.issuer_tokens
-                .insert(&IssuerTokenId { issuer_id, token }, &t);
-            last_token_transfered = key;
-        }
+                .insert(&IssuerTokenId { issuer_id, token }, t);
 
-        // update user balances
-        let mut old_balance_key = balance_key(from.clone(), issuer_id, 0);
-        let mut new_balance_key = balance_key(to.clone(), issuer_id, 0);
-        for class_id in class_ids {
-            old_balance_key.class_id = class_id;
-            let token_id = self.balances.remove(&old_balance_key).unwrap();
-            new_balance_key.class_id = class_id;
-            self.balances.insert(&new_balance_key, &token_id);
+            self.balances.remove(&key);
+            new_balance_key.class_id = key.class_id;
+            self.balances.insert(&new_balance_key, &token);
+            last_token_transfered = key;
         }
 
-        // update supply_by_owner map. We can't do it in the loop above becuse we can't modify
-        // self.balances while iterating over it
+        // update supply_by_owner map
+        self.update_account_supply(&from, tokens_recovered, false);
+        self.update_account_supply(&to, tokens_recovered, true);
         let supply_key = &(from.clone(), issuer_id);
         let old_supply_from = self.supply_by_owner.remove(supply_key).unwrap_or(0);
         if old_supply_from != tokens_recovered {
@@ -318,25 +1575,16 @@ impl Contract {
         self.supply_by_owner
             .insert(supply_key, &(old_supply_to + tokens_recovered));
 
-        let completed = tokens_recovered != limit as u64;
+        let completed = !steps.stopped() && tokens_recovered != limit as u64;
         if completed {
             if resumed {
                 // insert is happening when we need to continue, so don't need to remove if
                 // the process finishes in the same transaction.
                 self.ongoing_soul_tx.remove(&from);
-            }
-            // we emit the event only once the operation is completed and only if some tokens were
-            // recovered
-            if resumed || tokens_recovered > 0 {
-                // emit Recover event
-                SbtRecover {
-                    issuer: &issuer,
-                    old_owner: &from,
-                    new_owner: &to,
-                }
-                .emit();
+                self.recoveries.remove(&from);
             }
         } else {
+            self.recoveries.insert(&from, &issuer_id);
             self.ongoing_soul_tx.insert(
                 &from,
                 &IssuerTokenId {
@@ -345,21 +1593,19 @@ impl Contract {
                 },
             );
         }
-        // storage check
-        // we are using checked_sub, since the storage can decrease and we are running of risk of underflow
-        let storage_usage = env::storage_usage();
-        if storage_usage > storage_start {
-            let required_deposit =
-                (storage_usage - storage_start) as u128 * env::storage_byte_cost();
-            require!(
-                env::attached_deposit() >= required_deposit,
-                format!(
-                    "not enough NEAR storage depost, required: {}",
-                    required_deposit
-                )
-            );
+        // storage check, the storage can also decrease
+        let charged = assert_storage_deposit(storage_start, env::attached_deposit());
+        // we emit the event only once the operation is completed and only if some tokens were
+        // recovered
+        if completed && (resumed || tokens_recovered > 0) {
+            self.emit_event(Nep393Event::Recover(SbtRecover {
+                issuer: &issuer,
+                old_owner: &from,
+                new_owner: &to,
+                storage: Some(StorageCharge::since(storage_start, charged)),
+            }));
         }
-        return (tokens_recovered as u32, completed);
+        (tokens_recovered as u32, completed)
     }
 
     pub fn sbt_burn(
@@ -368,6 +1614,8 @@ impl Contract {
         tokens: Vec<TokenId>,
         #[allow(unused_variables)] memo: Option<String>,
     ) {
+        self.assert_not_paused();
+        let storage_start = env::storage_usage();
         let owner = env::predecessor_account_id();
         require!(
             !self.ongoing_soul_tx.contains_key(&owner),
@@ -375,97 +1623,552 @@ impl Contract {
         );
 
         let issuer_id = self.assert_issuer(&issuer);
-        let token_len = tokens.len() as u64;
         let mut token_ids = HashSet::new();
         for tid in tokens.iter() {
             require!(
-                !token_ids.contains(tid),
+                token_ids.insert(tid),
                 format!("duplicated token_id in tokens: {}", tid)
             );
-            token_ids.insert(tid);
-
-            let ct_key = &IssuerTokenId {
-                issuer_id,
-                token: *tid,
-            };
-            let t = self
-                .issuer_tokens
-                .get(ct_key)
-                .unwrap_or_else(|| panic!("tokenID={} not found", tid));
+            let t = self.get_token(issuer_id, *tid);
             require!(
                 t.owner == owner,
                 &format!("not an owner of tokenID={}", tid)
             );
+        }
+        self.burn_tokens(issuer_id, &tokens, TokenOp::Burn);
+
+        self.emit_event(Nep393Event::Burn(SbtTokensEvent {
+            issuer,
+            tokens,
+            storage: Some(StorageCharge::since(storage_start, 0)),
+            reason: None,
+        }));
+    }
+
+    //
+    // Authority
+    //
+
+    /// returns false if the `issuer` contract was already registered.
+    /// Panics if the `issuer` didn't lock the bond (see `issuer_lock_bond`).
+    /// Emits `AddIssuer` registry event with the optional `memo` (justification of the
+    /// action), when the issuer was added.
+    pub fn admin_add_sbt_issuer(&mut self, issuer: AccountId, memo: Option<String>) -> bool {
+        self.assert_admin_action("admin_add_sbt_issuer", memo.as_deref());
+        self.assert_memo(&memo);
+        if self.sbt_issuers.get(&issuer).is_some() {
+            return false;
+        }
+        self.assert_issuer_bond(&issuer);
+        let added = self._add_sbt_issuer(&issuer);
+        if added {
+            self.emit_registry_event(RegistryEvent::AddIssuer {
+                issuer: &issuer,
+                memo: memo.as_deref(),
+            });
+        }
+        added
+    }
+
+    /// Sets capabilities bitmask (see `CAP_*` constants) of the given issuer. Overwrites
+    /// previously granted capabilities. Issuers without explicitly set capabilities have
+    /// `CAP_DEFAULT` capabilities (mint, renew and recover). For example, a legacy issuer can
+    /// be restricted to renew only with `CAP_RENEW`.
+    /// Emits `SetIssuerCapabilities` registry event with the optional `memo`.
+    pub fn admin_set_issuer_capabilities(
+        &mut self,
+        issuer: AccountId,
+        capabilities: Capabilities,
+        memo: Option<String>,
+    ) {
+        self.assert_admin_action("admin_set_issuer_capabilities", memo.as_deref());
+        self.assert_memo(&memo);
+        let issuer_id = self.assert_issuer(&issuer);
+        self.issuer_capabilities.insert(&issuer_id, &capabilities);
+        self.emit_registry_event(RegistryEvent::SetIssuerCapabilities {
+            issuer: &issuer,
+            capabilities,
+            memo: memo.as_deref(),
+        });
+    }
+
+    /// Sets the max number of tokens which can be renewed in a single `sbt_renew` or
+    /// `sbt_renew_batch` call.
+    pub fn admin_set_max_renew_batch(&mut self, limit: u32) {
+        self.assert_admin_action("admin_set_max_renew_batch", None);
+        require!(limit > 0, "limit must be bigger than 0");
+        self.max_renew_batch = limit;
+    }
+
+    /// Sets the max number of tokens (from all issuers) an account can own. Mint panics if
+    /// a recipient would exceed the limit. Protects accounts from issuers minting so many
+    /// tokens that a soul transfer or recovery could never complete. The limit can't exceed
+    /// `MAX_TOKENS_PER_ACCOUNT`.
+    pub fn admin_set_max_tokens_per_account(&mut self, limit: u32) {
+        self.assert_admin_action("admin_set_max_tokens_per_account", None);
+        require!(
+            limit > 0 && limit <= MAX_TOKENS_PER_ACCOUNT,
+            format!("limit must be between 1 and {}", MAX_TOKENS_PER_ACCOUNT)
+        );
+        self.max_tokens_per_account = limit;
+    }
+
+    pub fn max_tokens_per_account(&self) -> u32 {
+        self.max_tokens_per_account
+    }
+
+    /// Sets the max number of registered issuers. Adding an issuer panics when the limit is
+    /// reached. Bounds the iterations over all issuers (eg `sbt_contracts`). Lowering the
+    /// limit below the number of registered issuers doesn't remove issuers.
+    pub fn admin_set_max_issuers(&mut self, limit: u32) {
+        self.assert_admin_action("admin_set_max_issuers", None);
+        require!(limit > 0, "limit must be bigger than 0");
+        self.max_issuers = limit;
+    }
+
+    pub fn max_issuers(&self) -> u32 {
+        self.max_issuers
+    }
+
+    /// Sets the max number of classes an issuer can mint tokens of. Mint panics if it would
+    /// use a new class above the limit. Bounds the per class records of an issuer.
+    pub fn admin_set_max_classes_per_issuer(&mut self, limit: u32) {
+        self.assert_admin_action("admin_set_max_classes_per_issuer", None);
+        require!(limit > 0, "limit must be bigger than 0");
+        self.max_classes_per_issuer = limit;
+    }
+
+    pub fn max_classes_per_issuer(&self) -> u32 {
+        self.max_classes_per_issuer
+    }
 
-            self.issuer_tokens.remove(ct_key);
-            let class_id = t.metadata.v1().class;
+    /// Sets the soul transfer cooldown: after a soul transfer completes, the recipient can't
+    /// start a soul transfer for `cooldown_ms` miliseconds. Prevents rapid chains of soul
+    /// transfers used to launder banned identities. 0 disables the cooldown.
+    pub fn admin_set_soul_transfer_cooldown(&mut self, cooldown_ms: u64) {
+        self.assert_admin_action("admin_set_soul_transfer_cooldown", None);
+        self.soul_transfer_cooldown_ms = cooldown_ms;
+    }
+
+    pub fn soul_transfer_cooldown(&self) -> u64 {
+        self.soul_transfer_cooldown_ms
+    }
+
+    /// Returns the time (unix timestamp in miliseconds) until which the `account` can't start
+    /// a soul transfer, or None if the account is not in the soul transfer cooldown.
+    pub fn transfer_cooldown_until(&self, account: AccountId) -> Option<u64> {
+        self.transfer_cooldowns
+            .get(&account)
+            .filter(|t| *t > env::block_timestamp_ms())
+    }
+
+    /// Sets (or removes, when `None`) the min time to live of the issuer `class` tokens:
+    /// mint and renew panic if the token `expires_at` is earlier than now + `min_ttl_ms`.
+    /// Prevents issuers from emitting instantly expiring proofs. Tokens without
+    /// `expires_at` are not affected.
+    pub fn admin_set_class_min_ttl(
+        &mut self,
+        issuer: AccountId,
+        class: ClassId,
+        min_ttl_ms: Option<u64>,
+    ) {
+        self.assert_admin_action("admin_set_class_min_ttl", None);
+        let key = (self.assert_issuer(&issuer), class);
+        match min_ttl_ms {
+            None => self.class_min_ttls.remove(&key),
+            Some(ttl) => self.class_min_ttls.insert(&key, &ttl),
+        };
+    }
+
+    /// Repairs owner balance of the given issuer and class: sets it to the `token`, or
+    /// removes it if `token` is `None`. Supply counters are not updated. When set, the token
+    /// must exist and be owned by `owner`. Use `check_invariants` to find inconsistencies.
+    /// Emits `fix_balance` registry event.
+    pub fn admin_fix_balance(
+        &mut self,
+        owner: AccountId,
+        issuer: AccountId,
+        class: ClassId,
+        token: Option<TokenId>,
+    ) {
+        self.assert_admin_action("admin_fix_balance", None);
+        let issuer_id = self.assert_issuer(&issuer);
+        let key = balance_key(owner.clone(), issuer_id, class);
+        match token {
+            None => {
+                self.balances.remove(&key);
+            }
+            Some(token) => {
+                let t = self.get_token(issuer_id, token);
+                require!(
+                    t.owner == owner && t.metadata.class_id() == class,
+                    "token owner or class doesn't match"
+                );
+                self.balances.insert(&key, &token);
+            }
+        }
+        self.emit_registry_event(RegistryEvent::FixBalance {
+            owner: &owner,
+            issuer: &issuer,
+            class,
+            token,
+        });
+    }
+
+    /// Removes a token which is not referenced by the owner balance (orphan token) and
+    /// decreases the owner, class and issuer supply. Panics if the token is not an orphan.
+    /// Emits `remove_orphan_token` registry event.
+    pub fn admin_remove_orphan_token(&mut self, issuer: AccountId, token: TokenId) {
+        self.assert_admin_action("admin_remove_orphan_token", None);
+        let issuer_id = self.assert_issuer(&issuer);
+        let t = self.get_token(issuer_id, token);
+        let class_id = t.metadata.class_id();
+        require!(
             self.balances
-                .remove(&balance_key(owner.clone(), issuer_id, class_id));
+                .get(&balance_key(t.owner.clone(), issuer_id, class_id))
+                != Some(token),
+            "token is referenced by the owner balance"
+        );
+        self.transition_token(issuer_id, token, &t, TokenOp::Burn);
+        self.issuer_tokens
+            .remove(&IssuerTokenId { issuer_id, token });
+        self.index_expiration(issuer_id, token, t.metadata.expires_at(), None);
+        self.update_account_supply(&t.owner, 1, false);
+        let key = (t.owner, issuer_id);
+        let supply = self.supply_by_owner.get(&key).unwrap_or(0);
+        self.supply_by_owner.insert(&key, &supply.saturating_sub(1));
+        let key = (issuer_id, class_id);
+        let supply = self.supply_by_class.get(&key).unwrap_or(0);
+        self.supply_by_class.insert(&key, &supply.saturating_sub(1));
+        let supply = self.supply_by_issuer.get(&issuer_id).unwrap_or(0);
+        self.supply_by_issuer
+            .insert(&issuer_id, &supply.saturating_sub(1));
+        self.emit_registry_event(RegistryEvent::RemoveOrphanToken {
+            issuer: &issuer,
+            token,
+        });
+    }
 
-            // update supply by class
-            let key = (issuer_id, class_id);
-            let mut supply = self.supply_by_class.get(&key).unwrap();
-            supply -= 1;
-            self.supply_by_class.insert(&key, &supply);
+    /// Revokes (sets `expires_at` to now) tokens of a banned `account`, so they immediately
+    /// stop counting as proofs (eg in `is_human`). Revokes at most `limit` tokens and returns
+    /// the number of revoked tokens and a boolean: `true` when all account tokens are
+    /// revoked. Must be called again until `true` is returned: a call checks at most
+    /// `MAX_SWEEP_BATCH` tokens (fewer when the gas runs out), and the next call continues
+    /// after the last checked token. Already expired and revoked tokens are skipped. Emits a
+    /// `Revoke` event per issuer.
+    pub fn admin_invalidate_banned_tokens(
+        &mut self,
+        account: AccountId,
+        limit: u32,
+    ) -> (u32, bool) {
+        self.assert_admin_action("admin_invalidate_banned_tokens", None);
+        require!(self._is_banned(&account), "account is not banned");
+        require!(limit > 0, "limit must be bigger than 0");
+        let now = MilliSeconds::now();
+        let mut revoked: Vec<(IssuerId, Vec<TokenId>)> = Vec::new();
+        let mut num_revoked = 0;
+        let mut done = true;
+        // iter_from starts from exclusive "left end", issuer and class IDs start from 1.
+        let (issuer_id, class_id) = self.invalidation_cursors.get(&account).unwrap_or((0, 0));
+        let mut balances: Vec<(BalanceKey, TokenId)> = self
+            .balances
+            .iter_from(balance_key(account.clone(), issuer_id, class_id))
+            .take_while(|(key, _)| key.owner == account)
+            .take(MAX_SWEEP_BATCH + 1)
+            .collect();
+        let more = balances.len() > MAX_SWEEP_BATCH;
+        balances.truncate(MAX_SWEEP_BATCH);
+        let mut last = None;
+        let mut steps = BoundedIter::new(balances.into_iter(), SWEEP_GAS_RESERVE);
+        for (key, token) in &mut steps {
+            let i_key = IssuerTokenId {
+                issuer_id: key.issuer_id,
+                token,
+            };
+            let mut t = match self.issuer_tokens.get(&i_key) {
+                None => {
+                    last = Some((key.issuer_id, key.class_id));
+                    continue;
+                }
+                Some(t) => t,
+            };
+            let expired = t.metadata.is_expired_at(now);
+            if expired || self.status_of(key.issuer_id, token, &t) == TokenStatus::Revoked {
+                last = Some((key.issuer_id, key.class_id));
+                continue;
+            }
+            if num_revoked == limit {
+                done = false;
+                break;
+            }
+            self.transition_token(key.issuer_id, token, &t, TokenOp::Revoke);
+            let mut m = t.metadata.latest();
+            self.index_expiration(key.issuer_id, token, m.expires_at, None);
+            m.expires_at = Some(now.0);
+            t.metadata = m.into();
+            self.issuer_tokens.insert(&i_key, t);
+            match revoked.last_mut() {
+                Some((issuer_id, tokens)) if *issuer_id == key.issuer_id => tokens.push(token),
+                _ => revoked.push((key.issuer_id, vec![token])),
+            }
+            num_revoked += 1;
+            last = Some((key.issuer_id, key.class_id));
+        }
+        done = done && !steps.stopped() && !more;
+        if done {
+            self.invalidation_cursors.remove(&account);
+        } else if let Some(cursor) = last {
+            self.invalidation_cursors.insert(&account, &cursor);
+        }
+        for (issuer_id, tokens) in revoked {
+            self.count_revoked(issuer_id, tokens.len() as u64);
+            self.emit_event(Nep393Event::Revoke(SbtTokensEvent {
+                issuer: self.issuer_account(issuer_id),
+                tokens,
+                storage: None,
+                reason: None,
+            }));
         }
+        (num_revoked, done)
+    }
 
-        // update supply by owner
-        let key = (owner, issuer_id);
-        let mut supply = self.supply_by_owner.get(&key).unwrap();
-        supply -= token_len;
-        self.supply_by_owner.insert(&key, &supply);
+    /// Pauses the registry: all transactions changing tokens (mint, renew, revoke, burn,
+    /// soul transfer, merge and recover) are blocked. Pause is immediate, while unpause must
+    /// go through the timelock (`AdminAction::Unpause`).
+    /// Must be called by the authority or the emergency guardian (see `guardian`), which
+    /// can't do anything else: it can't unpause nor queue admin actions.
+    /// Emits `Pause` registry event with the optional `memo`.
+    pub fn admin_pause(&mut self, memo: Option<String>) {
+        let caller = env::predecessor_account_id();
+        require!(
+            caller == self.authority || self.guardian.as_ref() == Some(&caller),
+            "not an admin nor the guardian"
+        );
+        self.assert_memo(&memo);
+        self.log_admin_action("admin_pause", memo.as_deref());
+        self.paused = true;
+        self.emit_registry_event(RegistryEvent::Pause {
+            memo: memo.as_deref(),
+        });
+    }
 
-        // update total supply by issuer
-        let mut supply = self.supply_by_issuer.get(&issuer_id).unwrap();
-        supply -= token_len;
-        self.supply_by_issuer.insert(&issuer_id, &supply);
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
 
-        SbtTokensEvent { issuer, tokens }.emit_burn();
+    /// Proposes the `guardian` as the emergency guardian (see `admin_pause`). The account
+    /// becomes the guardian when it calls `accept_guardian`. A new proposal replaces the
+    /// previous one. Must be called by the authority.
+    /// Emits `ProposeGuardian` registry event with the optional `memo`.
+    pub fn propose_guardian(&mut self, guardian: AccountId, memo: Option<String>) {
+        self.assert_admin_action("propose_guardian", memo.as_deref());
+        self.assert_memo(&memo);
+        self.emit_registry_event(RegistryEvent::ProposeGuardian {
+            guardian: &guardian,
+            memo: memo.as_deref(),
+        });
+        self.proposed_guardian = Some(guardian);
     }
 
-    //
-    // Authority
-    //
+    /// Makes the caller the emergency guardian. Must be called by the account proposed with
+    /// `propose_guardian`. Emits `AcceptGuardian` registry event.
+    pub fn accept_guardian(&mut self) {
+        let caller = env::predecessor_account_id();
+        require!(
+            self.proposed_guardian.as_ref() == Some(&caller),
+            "not the proposed guardian"
+        );
+        self.log_admin_action("accept_guardian", None);
+        self.proposed_guardian = None;
+        self.emit_registry_event(RegistryEvent::AcceptGuardian { guardian: &caller });
+        self.guardian = Some(caller);
+    }
 
-    /// returns false if the `issuer` contract was already registered.
-    pub fn admin_add_sbt_issuer(&mut self, issuer: AccountId) -> bool {
-        self.assert_authority();
-        self._add_sbt_issuer(&issuer)
+    /// Returns the emergency guardian, proposed by the authority (`propose_guardian`) and
+    /// accepted by the guardian (`accept_guardian`).
+    pub fn guardian(&self) -> Option<AccountId> {
+        self.guardian.clone()
     }
 
-    pub fn change_admin(&mut self, new_admin: AccountId) {
-        self.assert_authority();
-        self.authority = new_admin;
+    /// Returns the guardian proposed with `propose_guardian`, which didn't accept yet.
+    pub fn proposed_guardian(&self) -> Option<AccountId> {
+        self.proposed_guardian.clone()
     }
 
     //
     // Internal
     //
 
+    /// Returns true if the account is banned at `now`.
+    pub(crate) fn is_banned_at(&self, account: &AccountId, now: MilliSeconds) -> bool {
+        self.banlist.contains(account)
+            && self
+                .ban_expirations
+                .get(account)
+                .map_or(true, |until| MilliSeconds(until) > now)
+    }
+
+    /// `is_human` evaluating the bans and token validity at `now`.
+    pub(crate) fn _is_human(&self, account: AccountId, now: MilliSeconds) -> bool {
+        if self.is_banned_at(&account, now)
+            || self.recreated_accounts.contains_key(&account)
+            || self.has_negative_token(&account, now)
+        {
+            return false;
+        }
+        let issuer = Some(self.iah_classes.0.clone());
+        // check if user has tokens from all classes
+        for cls in &self.iah_classes.1 {
+            let tokens = self.tokens_by_owner_at(
+                account.clone(),
+                issuer.clone(),
+                Some(*cls),
+                Some(1),
+                None,
+                now,
+            );
+            // we need to check class, because the query can return a "next" token if a user
+            // doesn't have the token of requested class.
+            if tokens.is_empty() || tokens[0].1[0].metadata.class != *cls {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Returns the earliest `expires_at` of the `account` proof of humanity tokens, or None
+    /// if none of them expires.
+    fn human_expires_at(&self, account: &AccountId) -> Option<u64> {
+        let issuer_id = self.sbt_issuers.get(&self.iah_classes.0)?;
+        self.iah_classes
+            .1
+            .iter()
+            .filter_map(|cls| {
+                let token = self
+                    .balances
+                    .get(&balance_key(account.clone(), issuer_id, *cls))?;
+                self.issuer_tokens
+                    .get(&IssuerTokenId { issuer_id, token })?
+                    .metadata
+                    .expires_at()
+            })
+            .min()
+    }
+
+    /// `sbt_tokens_by_owner` evaluating token validity at `now`: tokens issued after `now`
+    /// or expired before it are not valid.
+    pub(crate) fn tokens_by_owner_at(
+        &self,
+        account: AccountId,
+        issuer: Option<AccountId>,
+        from_class: Option<u64>,
+        limit: Option<u32>,
+        with_expired: Option<bool>,
+        now: MilliSeconds,
+    ) -> Vec<(AccountId, Vec<OwnedToken>)> {
+        if from_class.is_some() {
+            require!(
+                issuer.is_some(),
+                "issuer must be defined if from_class is defined"
+            );
+        }
+        // we don't check banlist because we should still enable banned accounts to query their tokens
+        if self.ongoing_soul_tx.contains_key(&account) {
+            return vec![];
+        }
+
+        let issuer_id = match issuer {
+            None => 0,
+            // use self.sbt_contracts.get when changing to query by issuer_start
+            Some(addr) => self.assert_issuer(&addr),
+        };
+        let mut from_class = from_class.unwrap_or(0);
+        // iter_from starts from exclusive "left end"
+        from_class = from_class.saturating_sub(1);
+        let mut limit = limit.unwrap_or(registry::MAX_LIMIT);
+        require!(limit > 0, "limit must be bigger than 0");
+
+        let mut resp = Vec::new();
+        let mut tokens = Vec::new();
+        let mut prev_issuer = issuer_id;
+
+        let with_expired = with_expired.unwrap_or(false);
+
+        for (key, token_id) in
+            self.balances
+                .iter_from(balance_key(account.clone(), issuer_id, from_class))
+        {
+            // TODO: maybe we should continue the scan?
+            if key.owner != account {
+                break;
+            }
+            if prev_issuer != key.issuer_id {
+                if issuer_id != 0 {
+                    break;
+                }
+                if !tokens.is_empty() {
+                    let issuer = self.issuer_account(prev_issuer);
+                    resp.push((issuer, tokens));
+                    tokens = Vec::new();
+                }
+                prev_issuer = key.issuer_id;
+            }
+            let t: TokenData = self.get_token(key.issuer_id, token_id);
+            if !with_expired
+                && (!t.metadata.is_valid_at(now) || self.is_frozen(key.issuer_id, token_id))
+            {
+                continue;
+            }
+            tokens.push(OwnedToken {
+                token: token_id,
+                metadata: t.metadata.latest(),
+            });
+            limit -= 1;
+            if limit == 0 {
+                break;
+            }
+        }
+        if prev_issuer != 0 && !tokens.is_empty() {
+            let issuer = self.issuer_account(prev_issuer);
+            resp.push((issuer, tokens));
+        }
+        resp
+    }
+
     /// Queries a given token. Panics if token doesn't exist
     pub(crate) fn get_token(&self, issuer_id: IssuerId, token: TokenId) -> TokenData {
-        self.issuer_tokens
-            .get(&IssuerTokenId { issuer_id, token })
-            .unwrap_or_else(|| panic!("token {} not found", token))
+        match self.issuer_tokens.get(&IssuerTokenId { issuer_id, token }) {
+            Some(t) => t,
+            None if self.is_burned(issuer_id, token) => panic!("token {} is burned", token),
+            None => panic!("token {} not found", token),
+        }
     }
 
     /// updates the internal token counter based on how many tokens we want to mint (num), and
     /// returns the first valid TokenId for newly minted tokens.
+    /// Token IDs are assigned per issuer, sequentially from 1, and are never reused (the
+    /// counter is never decreased, also when tokens are burned).
     pub(crate) fn next_token_id(&mut self, issuer_id: IssuerId, num: u64) -> TokenId {
         let tid = self.next_token_ids.get(&issuer_id).unwrap_or(0);
-        self.next_token_ids.insert(&issuer_id, &(tid + num));
+        let last = tid.checked_add(num).expect("token ID overflow");
+        self.next_token_ids.insert(&issuer_id, &last);
         tid + 1
     }
 
+    #[inline]
+    pub(crate) fn assert_not_paused(&self) {
+        require!(!self.paused, "registry is paused");
+    }
+
     #[inline]
     pub(crate) fn assert_not_banned(&self, owner: &AccountId) {
         require!(
-            !self.banlist.contains(owner),
+            !self._is_banned(owner),
             format!("account {} is banned", owner)
         );
     }
 
-    /// note: use issuer_id() if you need issuer_id
+    /// note: use issuer_id() if you need issuer_id without panicking
     pub(crate) fn assert_issuer(&self, issuer: &AccountId) -> IssuerId {
         // TODO: use Result rather than panic
         self.sbt_issuers
@@ -473,7 +2176,21 @@ impl Contract {
             .expect("must be called by a registered SBT Issuer")
     }
 
-    pub(crate) fn issuer_by_id(&self, id: IssuerId) -> AccountId {
+    #[inline]
+    pub(crate) fn capabilities(&self, issuer_id: IssuerId) -> Capabilities {
+        self.issuer_capabilities
+            .get(&issuer_id)
+            .unwrap_or(CAP_DEFAULT)
+    }
+
+    pub(crate) fn assert_capability(&self, issuer_id: IssuerId, capability: Capabilities) {
+        require!(
+            self.capabilities(issuer_id) & capability == capability,
+            "issuer doesn't have the required capability"
+        );
+    }
+
+    pub(crate) fn issuer_account(&self, id: IssuerId) -> AccountId {
         self.issuer_id_map
             .get(&id)
             .expect("internal error: inconsistent sbt issuer map")
@@ -486,61 +2203,234 @@ impl Contract {
         )
     }
 
-    fn _add_sbt_issuer(&mut self, issuer: &AccountId) -> bool {
-        if self.sbt_issuers.get(issuer).is_some() {
-            return false;
-        }
+    /// Panics if another issuer can't be registered, see `admin_set_max_issuers`.
+    pub(crate) fn assert_issuers_cap(&self) {
+        require!(
+            self.sbt_issuers.len() < self.max_issuers as u64,
+            format!("at most {} issuers can be registered", self.max_issuers)
+        );
+    }
+
+    pub(crate) fn _add_sbt_issuer(&mut self, issuer: &AccountId) -> bool {
+        if self.sbt_issuers.get(issuer).is_some() {
+            return false;
+        }
+        self.assert_issuers_cap();
         self.sbt_issuers.insert(issuer, &self.next_issuer_id);
         self.issuer_id_map.insert(&self.next_issuer_id, issuer);
+        self.issuer_counters
+            .insert(&self.next_issuer_id, &IssuerCounters::default());
+        // created here, so the first mint of the issuer fits in the mint storage deposit.
+        self.valid_supply
+            .insert(&self.next_issuer_id, &ValidSupply::default());
         self.next_issuer_id += 1;
         true
     }
 
     fn _sbt_renew(&mut self, issuer: AccountId, tokens: Vec<TokenId>, expires_at: u64) {
+        self.assert_not_paused();
         let issuer_id = self.assert_issuer(&issuer);
+        self.assert_capability(issuer_id, CAP_RENEW);
+        self.assert_renew_batch(tokens.len());
         for token in &tokens {
             let token = *token;
             let mut t = self.get_token(issuer_id, token);
             self.assert_not_banned(&t.owner);
-            let mut m = t.metadata.v1();
+            self.transition_token(issuer_id, token, &t, TokenOp::Renew);
+            let mut m = t.metadata.latest();
+            self.assert_min_ttl(issuer_id, m.class, Some(expires_at));
+            self.assert_max_ttl(issuer_id, m.class, Some(expires_at));
+            self.index_expiration(issuer_id, token, m.expires_at, Some(expires_at));
+            self.move_valid(issuer_id, token, m.expires_at, Some(expires_at));
             m.expires_at = Some(expires_at);
             t.metadata = m.into();
+            self.record_activity(&t.owner);
             self.issuer_tokens
-                .insert(&IssuerTokenId { issuer_id, token }, &t);
+                .insert(&IssuerTokenId { issuer_id, token }, t);
+        }
+        self.emit_event(Nep393Event::Renew(SbtTokensEvent {
+            issuer,
+            tokens,
+            storage: None,
+            reason: None,
+        }));
+    }
+
+    /// Counts the `new_classes` the issuer started to mint. Panics if the issuer would exceed
+    /// `max_classes_per_issuer`.
+    pub(crate) fn count_new_classes(&mut self, issuer_id: IssuerId, new_classes: u32) {
+        if new_classes == 0 {
+            return;
+        }
+        let mut counters = self.issuer_counters.get(&issuer_id).unwrap_or_default();
+        counters.classes += new_classes;
+        require!(
+            counters.classes <= self.max_classes_per_issuer,
+            format!(
+                "issuer can mint tokens of at most {} classes",
+                self.max_classes_per_issuer
+            )
+        );
+        self.issuer_counters.insert(&issuer_id, &counters);
+    }
+
+    pub(crate) fn count_revoked(&mut self, issuer_id: IssuerId, num_tokens: u64) {
+        let mut counters = self.issuer_counters.get(&issuer_id).unwrap_or_default();
+        counters.revoked += num_tokens;
+        self.issuer_counters.insert(&issuer_id, &counters);
+    }
+
+    #[inline]
+    pub(crate) fn record_activity(&mut self, account: &AccountId) {
+        self.last_activity
+            .insert(account, &env::block_timestamp_ms());
+    }
+
+    fn assert_min_ttl(&self, issuer_id: IssuerId, class: ClassId, expires_at: Option<u64>) {
+        if let (Some(e), Some(ttl)) = (expires_at, self.class_min_ttls.get(&(issuer_id, class))) {
+            require!(
+                e >= env::block_timestamp_ms() + ttl,
+                format!(
+                    "class {} tokens must be valid for at least {}ms",
+                    class, ttl
+                )
+            );
+        }
+    }
+
+    /// Returns the number of tokens the account owns, from all issuers. Reads the
+    /// `supply_by_account` counter. Accounts with tokens of the previous release which are
+    /// not counted yet by `migrate_chunk` sum their `supply_by_owner` records.
+    pub(crate) fn account_supply(&self, account: &AccountId) -> u64 {
+        match self.supply_by_account.get(account) {
+            Some(supply) => supply,
+            None if !self.migration.done => (1..self.next_issuer_id)
+                .map(|issuer_id| {
+                    self.supply_by_owner
+                        .get(&(account.clone(), issuer_id))
+                        .unwrap_or(0)
+                })
+                .sum(),
+            None => 0,
+        }
+    }
+
+    /// Adds (or subtracts) `n` tokens to the account supply. Must be called with every
+    /// `supply_by_owner` change of the account, before the change.
+    pub(crate) fn update_account_supply(&mut self, account: &AccountId, n: u64, add: bool) {
+        let supply = self.account_supply(account);
+        let supply = if add {
+            supply + n
+        } else {
+            supply.saturating_sub(n)
+        };
+        if supply == 0 {
+            self.supply_by_account.remove(account);
+        } else {
+            self.supply_by_account.insert(account, &supply);
         }
-        SbtTokensEvent { issuer, tokens }.emit_renew();
     }
 
-    fn _sbt_mint(
+    fn assert_renew_batch(&self, num_tokens: usize) {
+        require!(
+            num_tokens <= self.max_renew_batch as usize,
+            format!("max {} tokens can be renewed at once", self.max_renew_batch)
+        );
+    }
+
+    pub(crate) fn _sbt_mint(
         &mut self,
         issuer: &AccountId,
         token_spec: Vec<(AccountId, Vec<TokenMetadata>)>,
     ) -> Vec<TokenId> {
+        self.mint_with_deposit(issuer, token_spec, env::attached_deposit(), None)
+            .0
+    }
+
+    /// Mints tokens paying the storage from `storage_deposit`. Returns the minted token IDs
+    /// and the part of the deposit used to cover the storage. Tokens get new IDs, or
+    /// consecutive IDs from `first_token` (already assigned, eg reserved, and not minted).
+    pub(crate) fn mint_with_deposit(
+        &mut self,
+        issuer: &AccountId,
+        token_spec: Vec<(AccountId, Vec<TokenMetadata>)>,
+        storage_deposit: Balance,
+        first_token: Option<TokenId>,
+    ) -> (Vec<TokenId>, Balance) {
+        self.assert_not_paused();
         let storage_start = env::storage_usage();
-        let storage_deposit = env::attached_deposit();
         require!(
             storage_deposit >= 6 * MILI_NEAR,
             "min required storage deposit: 0.006 NEAR"
         );
 
         let issuer_id = self.assert_issuer(issuer);
+        self.assert_capability(issuer_id, CAP_MINT);
+        let now = env::block_timestamp_ms();
+        let can_backdate = self.capabilities(issuer_id) & CAP_BACKDATE != 0;
+        let recipient_policy = self.recipient_policies.get(&issuer_id);
+        let badge_class = self.social_badge_class(issuer);
+        let mut badge_holders = Vec::new();
         let mut num_tokens = 0;
         for el in token_spec.iter() {
             num_tokens += el.1.len() as u64;
         }
-        let mut token = self.next_token_id(issuer_id, num_tokens);
+        if num_tokens > MAX_MINT_BATCH as u64 {
+            InputError::BatchTooLarge {
+                max: MAX_MINT_BATCH,
+            }
+            .panic();
+        }
+        let mut token = match first_token {
+            Some(t) => t,
+            None => self.next_token_id(issuer_id, num_tokens),
+        };
         let ret_token_ids = (token..token + num_tokens).collect();
         let mut supply_by_class = HashMap::new();
         let mut per_recipient: HashMap<AccountId, Vec<TokenId>> = HashMap::new();
+        let mut expirations: HashMap<u64, Vec<TokenId>> = HashMap::new();
+        let mut valid: HashMap<Option<u64>, u64> = HashMap::new();
 
         for (owner, metadatas) in token_spec {
             // no need to check ongoing_soult_tx, because it will automatically ban the source account
+            self.lift_expired_ban(&owner);
             self.assert_not_banned(&owner);
+            if let Some(p) = &recipient_policy {
+                require!(
+                    p.allows(&owner),
+                    format!("{} is not allowed by the issuer recipient policy", owner)
+                );
+            }
+            self.record_activity(&owner);
 
             let recipient_tokens = per_recipient.entry(owner.clone()).or_default();
             let metadatas_len = metadatas.len();
+            require!(
+                self.account_supply(&owner) + metadatas_len as u64
+                    <= self.max_tokens_per_account as u64,
+                format!(
+                    "{} can have at most {} tokens",
+                    owner, self.max_tokens_per_account
+                )
+            );
 
-            for metadata in metadatas {
+            for mut metadata in metadatas {
+                metadata.assert_valid();
+                metadata.issued_at = match metadata.issued_at {
+                    Some(issued_at) if can_backdate => {
+                        require!(
+                            issued_at <= now && now - issued_at <= MAX_BACKDATE_MS,
+                            format!(
+                                "issued_at must be in the past, max {}ms ago",
+                                MAX_BACKDATE_MS
+                            )
+                        );
+                        Some(issued_at)
+                    }
+                    _ => Some(now),
+                };
+                self.assert_min_ttl(issuer_id, metadata.class, metadata.expires_at);
+                self.assert_max_ttl(issuer_id, metadata.class, metadata.expires_at);
                 let prev = self.balances.insert(
                     &balance_key(owner.clone(), issuer_id, metadata.class),
                     &token,
@@ -558,52 +2448,84 @@ impl Contract {
                     Some(s) => *s += 1,
                 };
 
-                self.issuer_tokens.insert(
+                *valid.entry(metadata.expires_at).or_default() += 1;
+                if let Some(expires_at) = metadata.expires_at {
+                    expirations
+                        .entry(expiration_day(expires_at))
+                        .or_default()
+                        .push(token);
+                }
+                let class = metadata.class;
+                let prev = self.issuer_tokens.insert(
                     &IssuerTokenId { issuer_id, token },
-                    &TokenData {
+                    TokenData {
                         owner: owner.clone(),
                         metadata: metadata.into(),
                     },
                 );
+                require!(prev.is_none(), "internal error: token ID reused");
                 recipient_tokens.push(token);
+                if badge_class == Some(class) && !badge_holders.contains(&owner) {
+                    badge_holders.push(owner.clone());
+                }
 
                 token += 1;
             }
 
             // update supply by owner
+            self.update_account_supply(&owner, metadatas_len as u64, true);
             let skey = (owner, issuer_id);
             let sowner = self.supply_by_owner.get(&skey).unwrap_or(0) + metadatas_len as u64;
             self.supply_by_owner.insert(&skey, &sowner);
         }
 
+        for (day, tokens) in expirations {
+            self.index_expirations(issuer_id, day, tokens);
+        }
+        for (expires_at, n) in valid {
+            self.add_valid(issuer_id, expires_at, n);
+        }
+
+        let mut new_classes = 0;
         for (cls, new_supply) in supply_by_class {
             let key = (issuer_id, cls);
-            let s = self.supply_by_class.get(&key).unwrap_or(0) + new_supply;
+            let prev = self.supply_by_class.get(&key);
+            new_classes += prev.is_none() as u32;
+            let s = prev.unwrap_or(0) + new_supply;
+            if let Some(cap) = self.class_caps.get(&key) {
+                require!(
+                    s <= cap,
+                    format!("class {} supply cap ({} tokens) exhausted", cls, cap)
+                );
+            }
             self.supply_by_class.insert(&key, &s);
         }
+        self.count_new_classes(issuer_id, new_classes);
 
         let new_supply = self.supply_by_issuer.get(&issuer_id).unwrap_or(0) + num_tokens;
         self.supply_by_issuer.insert(&issuer_id, &new_supply);
+        if num_tokens > 0 {
+            let mut counters = self.issuer_counters.get(&issuer_id).unwrap_or_default();
+            counters.last_mint_ms = now;
+            self.issuer_counters.insert(&issuer_id, &counters);
+        }
 
+        let required_deposit = assert_storage_deposit(storage_start, storage_deposit);
         let mut minted: Vec<(&AccountId, &Vec<TokenId>)> = per_recipient.iter().collect();
         minted.sort_by(|a, b| a.0.cmp(b.0));
-        SbtMint {
+        // large mints are split into multiple events to stay within the log size limits.
+        for e in (SbtMint {
             issuer,
             tokens: minted,
+            storage: Some(StorageCharge::since(storage_start, required_deposit)),
+        })
+        .split()
+        {
+            self.emit_event(Nep393Event::Mint(e));
         }
-        .emit();
-
-        let required_deposit =
-            (env::storage_usage() - storage_start) as u128 * env::storage_byte_cost();
-        require!(
-            storage_deposit >= required_deposit,
-            format!(
-                "not enough NEAR storage depost, required: {}",
-                required_deposit
-            )
-        );
 
-        ret_token_ids
+        self.set_social_badges(badge_holders);
+        (ret_token_ids, required_deposit)
     }
 }
 
@@ -611,7 +2533,7 @@ impl Contract {
 mod tests {
     use std::ops::Mul;
 
-    use cost::MILI_NEAR;
+    use cost::{ISSUER_BOND, MINT_COST, STAMP_COST};
     use near_sdk::test_utils::{self, VMContextBuilder};
     use near_sdk::{testing_env, Balance, Gas, VMContext};
     use sbt::*;
@@ -665,13 +2587,15 @@ mod tests {
         AccountId::new_unchecked("sbt.near".to_string())
     }
 
+    // registry sets `issued_at` to the block timestamp (in ms) of the mint transaction.
     fn mk_metadata(class: ClassId, expires_at: Option<u64>) -> TokenMetadata {
         TokenMetadata {
             class,
-            issued_at: None,
+            issued_at: Some(START / 1_000_000),
             expires_at,
             reference: Some("abc".to_owned()),
-            reference_hash: Some(vec![61, 61].into()),
+            reference_hash: Some(vec![61; 32].into()),
+            attributes_root: None,
         }
     }
 
@@ -697,38 +2621,40 @@ mod tests {
 
     fn mk_batch_metadata(n: u64) -> Vec<TokenMetadata> {
         let mut batch_metadata: Vec<TokenMetadata> = Vec::new();
-        for i in 0..n {
+        for i in 1..=n {
             batch_metadata.push(mk_metadata(i, Some(START + i)))
         }
         batch_metadata
     }
 
+    /// max gas burnt by a function call in the mocked VM.
     fn max_gas() -> Gas {
-        return Gas::ONE_TERA.mul(300);
+        Gas::ONE_TERA.mul(200)
     }
 
     const MILI_SECOND: u64 = 1_000_000; // milisecond in ns
     const START: u64 = 10;
-    const MINT_DEPOSIT: Balance = 6 * MILI_NEAR;
+    const MINT_DEPOSIT: Balance = MINT_COST;
 
     fn setup(predecessor: &AccountId, deposit: Balance) -> (VMContext, Contract) {
         let mut ctx = VMContextBuilder::new()
             .predecessor_account_id(admin())
             .block_timestamp(START)
             .is_view(false)
+            .prepaid_gas(max_gas())
             .build();
         if deposit > 0 {
             ctx.attached_deposit = deposit
         }
         testing_env!(ctx.clone());
         let mut ctr = Contract::new(admin(), fractal_mainnet(), vec![1]);
-        ctr.admin_add_sbt_issuer(issuer1());
-        ctr.admin_add_sbt_issuer(issuer2());
-        ctr.admin_add_sbt_issuer(issuer3());
-        ctr.admin_add_sbt_issuer(fractal_mainnet());
+        for issuer in [issuer1(), issuer2(), issuer3(), fractal_mainnet()] {
+            lock_bond(&ctx, &mut ctr, &issuer);
+            ctr.admin_add_sbt_issuer(issuer, None);
+        }
         ctx.predecessor_account_id = predecessor.clone();
         testing_env!(ctx.clone());
-        return (ctx, ctr);
+        (ctx, ctr)
     }
 
     #[test]
@@ -741,19 +2667,27 @@ mod tests {
         assert_eq!(3, ctr.assert_issuer(&issuer3()));
         assert_eq!(4, ctr.assert_issuer(&fractal_mainnet()));
 
-        assert_eq!(issuer1(), ctr.issuer_by_id(1));
-        assert_eq!(issuer2(), ctr.issuer_by_id(2));
-        assert_eq!(issuer3(), ctr.issuer_by_id(3));
-        assert_eq!(fractal_mainnet(), ctr.issuer_by_id(4));
+        assert_eq!(issuer1(), ctr.issuer_account(1));
+        assert_eq!(issuer2(), ctr.issuer_account(2));
+        assert_eq!(issuer3(), ctr.issuer_account(3));
+        assert_eq!(fractal_mainnet(), ctr.issuer_account(4));
+
+        assert_eq!(4, ctr.issuers_count());
+        assert_eq!(Some(2), ctr.issuer_id(issuer2()));
+        assert_eq!(None, ctr.issuer_id(issuer4()));
+        assert_eq!(Some(issuer3()), ctr.issuer_by_id(3));
+        assert_eq!(None, ctr.issuer_by_id(5));
+        assert_eq!(None, ctr.issuer_by_id(0));
 
         ctx.predecessor_account_id = admin();
         testing_env!(ctx.clone());
-        let ok = ctr.admin_add_sbt_issuer(issuer1());
+        let ok = ctr.admin_add_sbt_issuer(issuer1(), None);
         assert!(
             !ok,
             "isser1 should be already added, so it should return false"
         );
         assert_eq!(5, ctr.next_issuer_id, "next_issuer_id should not change");
+        assert_eq!(4, ctr.issuers_count());
         assert_eq!(
             1,
             ctr.assert_issuer(&issuer1()),
@@ -771,8 +2705,8 @@ mod tests {
             (bob(), vec![m1_1.clone()]),
         ]);
         assert_eq!(minted_ids, vec![1, 2]);
-        assert_eq!(2, ctr.sbt_supply(issuer1()));
-        assert_eq!(0, ctr.sbt_supply(issuer2()));
+        assert_eq!(2, ctr.sbt_supply(issuer1(), None));
+        assert_eq!(0, ctr.sbt_supply(issuer2(), None));
 
         let sbt1_1 = ctr.sbt(issuer1(), 1).unwrap();
         assert_eq!(sbt1_1, mk_token(1, alice(), m1_1.clone()));
@@ -814,7 +2748,7 @@ mod tests {
             mk_log_str(
                 "mint",
                 &format!(
-                    r#"{{"issuer":"{}","tokens":[["{}",[1]]]}}"#,
+                    r#"{{"issuer":"{}","tokens":[["{}",[1]]],"storage_delta_bytes":928,"deposit_charged":"9280000000000000000000"}}"#,
                     issuer1(),
                     alice2()
                 )
@@ -837,7 +2771,7 @@ mod tests {
             mk_log_str(
                 "mint",
                 &format!(
-                    r#"{{"issuer":"{}","tokens":[["{}",[3]],["{}",[1,4]],["{}",[2]]]}}"#,
+                    r#"{{"issuer":"{}","tokens":[["{}",[3]],["{}",[1,4]],["{}",[2]]],"storage_delta_bytes":2409,"deposit_charged":"24090000000000000000000"}}"#,
                     issuer2(),
                     alice2(),
                     alice(),
@@ -869,11 +2803,11 @@ mod tests {
         assert_eq!(ctr.sbt_supply_by_class(issuer3(), 1), 1);
         assert_eq!(ctr.sbt_supply_by_class(issuer3(), 2), 1);
 
-        let mut supply_by_issuer = vec![1, 5, 2, 0];
-        assert_eq!(ctr.sbt_supply(issuer1()), supply_by_issuer[0]);
-        assert_eq!(ctr.sbt_supply(issuer2()), supply_by_issuer[1]);
-        assert_eq!(ctr.sbt_supply(issuer3()), supply_by_issuer[2]);
-        assert_eq!(ctr.sbt_supply(issuer4()), supply_by_issuer[3]);
+        let mut supply_by_issuer = [1, 5, 2, 0];
+        assert_eq!(ctr.sbt_supply(issuer1(), None), supply_by_issuer[0]);
+        assert_eq!(ctr.sbt_supply(issuer2(), None), supply_by_issuer[1]);
+        assert_eq!(ctr.sbt_supply(issuer3(), None), supply_by_issuer[2]);
+        assert_eq!(ctr.sbt_supply(issuer4(), None), supply_by_issuer[3]);
 
         assert_eq!(3, ctr.sbt_supply_by_owner(alice(), issuer2(), None));
         assert_eq!(2, ctr.sbt_supply_by_owner(alice(), issuer3(), None));
@@ -992,14 +2926,17 @@ mod tests {
         ctr.sbt_burn(issuer2(), vec![1, 5], Some("alice burning".to_owned()));
         assert_eq!(
             test_utils::get_logs(),
-            mk_log_str("burn", r#"{"issuer":"sbt.ne","tokens":[1,5]}"#)
+            mk_log_str(
+                "burn",
+                r#"{"issuer":"sbt.ne","tokens":[1,5],"storage_delta_bytes":-432,"deposit_charged":"0"}"#
+            )
         );
 
         supply_by_issuer[1] -= 2;
-        assert_eq!(ctr.sbt_supply(issuer1()), supply_by_issuer[0]);
-        assert_eq!(ctr.sbt_supply(issuer2()), supply_by_issuer[1]);
-        assert_eq!(ctr.sbt_supply(issuer3()), supply_by_issuer[2]);
-        assert_eq!(ctr.sbt_supply(issuer4()), supply_by_issuer[3]);
+        assert_eq!(ctr.sbt_supply(issuer1(), None), supply_by_issuer[0]);
+        assert_eq!(ctr.sbt_supply(issuer2(), None), supply_by_issuer[1]);
+        assert_eq!(ctr.sbt_supply(issuer3(), None), supply_by_issuer[2]);
+        assert_eq!(ctr.sbt_supply(issuer4(), None), supply_by_issuer[3]);
 
         assert_eq!(ctr.sbt_supply_by_owner(alice(), issuer2(), None), 1);
         assert_eq!(
@@ -1039,15 +2976,25 @@ mod tests {
         // make soul transfer
         ctx.predecessor_account_id = alice();
         testing_env!(ctx.clone());
-        let ret = ctr.sbt_soul_transfer(alice2(), None);
+        let ret = ctr.sbt_soul_transfer(alice2(), None, None);
         assert_eq!((3, true), ret);
 
         let log1 = mk_log_str("ban", &format!(r#"["{}"]"#, alice()));
-        let log2 = mk_log_str(
+        let log2 = mk_registry_log_str(
+            "soul_transfer_batch",
+            &format!(
+                r#"{{"from":"{}","to":"{}","tokens":[["{}",[1,2]],["{}",[1]]]}}"#,
+                alice(),
+                alice2(),
+                issuer1(),
+                issuer2()
+            ),
+        );
+        let log3 = mk_log_str(
             "soul_transfer",
             &format!(r#"{{"from":"{}","to":"{}"}}"#, alice(), alice2()),
         );
-        assert_eq!(test_utils::get_logs(), vec![log1, log2].concat());
+        assert_eq!(test_utils::get_logs(), [log1, log2, log3].concat());
         assert_eq!(ctr.sbt_supply_by_owner(alice(), issuer1(), None), 0);
         assert_eq!(ctr.sbt_supply_by_owner(alice2(), issuer1(), None), 2);
         assert_eq!(ctr.sbt_supply_by_owner(alice2(), issuer2(), None), 1);
@@ -1094,16 +3041,31 @@ mod tests {
         testing_env!(ctx.clone());
         let mut result = ctr._sbt_soul_transfer(alice2(), 3);
         assert_eq!((3, false), result);
-        assert!(test_utils::get_logs().len() == 1);
+        // ban and soul transfer batch
+        assert!(test_utils::get_logs().len() == 2);
+        assert_eq!(
+            test_utils::get_logs()[1],
+            mk_registry_log_str(
+                "soul_transfer_batch",
+                &format!(
+                    r#"{{"from":"{}","to":"{}","tokens":[["{}",[1,2]],["{}",[1]]]}}"#,
+                    alice(),
+                    alice2(),
+                    issuer1(),
+                    issuer2()
+                ),
+            )[0]
+        );
         result = ctr._sbt_soul_transfer(alice2(), 3);
         assert_eq!((1, true), result);
-        assert!(test_utils::get_logs().len() == 2);
+        // logs are accumulated: + soul transfer batch and the final soul transfer
+        assert!(test_utils::get_logs().len() == 4);
 
         let log_soul_transfer = mk_log_str(
             "soul_transfer",
             &format!(r#"{{"from":"{}","to":"{}"}}"#, alice(), alice2()),
         );
-        assert_eq!(test_utils::get_logs()[1], log_soul_transfer[0]);
+        assert_eq!(test_utils::get_logs()[3], log_soul_transfer[0]);
         assert_eq!(ctr.sbt_supply_by_owner(alice(), issuer1(), None), 0);
         assert_eq!(ctr.sbt_supply_by_owner(alice(), issuer2(), None), 0);
         assert_eq!(ctr.sbt_supply_by_owner(alice2(), issuer1(), None), 2);
@@ -1114,12 +3076,12 @@ mod tests {
 
     #[test]
     fn soul_transfer_no_tokens_from_caller() {
-        let (mut ctx, mut ctr) = setup(&issuer1(), 1 * MINT_DEPOSIT);
+        let (mut ctx, mut ctr) = setup(&issuer1(), MINT_DEPOSIT);
         ctx.predecessor_account_id = alice();
         testing_env!(ctx.clone());
         assert!(!ctr.is_banned(alice()));
         assert!(!ctr.is_banned(alice2()));
-        ctr.sbt_soul_transfer(alice2(), None);
+        ctr.sbt_soul_transfer(alice2(), None, None);
         assert!(ctr.is_banned(alice()));
         assert!(!ctr.is_banned(alice2()));
         // assert ban even is being emited after the caller with zero tokens has invoked the soul_transfer
@@ -1190,109 +3152,4242 @@ mod tests {
     }
 
     #[test]
-    #[should_panic(expected = "HostError(GasLimitExceeded)")]
-    fn soul_transfer_exceeded_limit() {
-        let (mut ctx, mut ctr) = setup(&issuer1(), 150 * MINT_DEPOSIT);
-        let batch_metadata = mk_batch_metadata(100);
-        assert!(batch_metadata.len() == 100);
-
-        // issuer_1
-        ctr.sbt_mint(vec![(alice(), batch_metadata[..50].to_vec())]);
-        assert_eq!(ctr.sbt_supply_by_owner(alice(), issuer1(), None), 50);
+    fn soul_transfer_gas_bounded() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 150 * MINT_DEPOSIT);
+        let batch_metadata = mk_batch_metadata(100);
+        assert!(batch_metadata.len() == 100);
+
+        // issuer_1
+        ctr.sbt_mint(vec![(alice(), batch_metadata[..50].to_vec())]);
+        assert_eq!(ctr.sbt_supply_by_owner(alice(), issuer1(), None), 50);
+
+        // issuer_2
+        ctx.predecessor_account_id = issuer2();
+        ctx.prepaid_gas = max_gas();
+        testing_env!(ctx.clone());
+        ctr.sbt_mint(vec![(alice(), batch_metadata[50..].to_vec())]);
+        assert_eq!(ctr.sbt_supply_by_owner(alice(), issuer2(), None), 50);
+
+        // add more tokens to issuer_1
+        ctx.predecessor_account_id = issuer1();
+        ctx.prepaid_gas = max_gas();
+        testing_env!(ctx.clone());
+        ctr.sbt_mint(vec![(bob(), batch_metadata[..20].to_vec())]);
+        assert_eq!(ctr.sbt_supply_by_owner(bob(), issuer1(), None), 20);
+
+        ctx.prepaid_gas = max_gas();
+        testing_env!(ctx.clone());
+        ctr.sbt_mint(vec![(alice2(), batch_metadata[..20].to_vec())]);
+        assert_eq!(ctr.sbt_supply_by_owner(alice2(), issuer1(), None), 20);
+
+        ctx.prepaid_gas = max_gas();
+        testing_env!(ctx.clone());
+        ctr.sbt_mint(vec![(carol(), batch_metadata[..20].to_vec())]);
+        assert_eq!(ctr.sbt_supply_by_owner(carol(), issuer1(), None), 20);
+
+        ctx.prepaid_gas = max_gas();
+        testing_env!(ctx.clone());
+        ctr.sbt_mint(vec![(dan(), batch_metadata[..10].to_vec())]);
+        assert_eq!(ctr.sbt_supply_by_owner(dan(), issuer1(), None), 10);
+
+        // soul transfer alice->alice2
+        ctx.predecessor_account_id = alice();
+        // balances are read by shards, so a sweep step is cheap: prepaid gas lower than
+        // max, to make the gas (rather than MAX_SWEEP_BATCH) end the sweep.
+        ctx.prepaid_gas = Gas::ONE_TERA.mul(40);
+        testing_env!(ctx.clone());
+        let mut result = ctr.sbt_soul_transfer(alice2(), None, None);
+        assert!(!result.1);
+        assert!(result.0 > 0 && result.0 < MAX_SWEEP_BATCH as u32);
+        let mut moved = result.0;
+        while !result.1 {
+            ctx.prepaid_gas = Gas::ONE_TERA.mul(40);
+            testing_env!(ctx.clone());
+            result = ctr.sbt_soul_transfer(alice2(), None, None);
+            moved += result.0;
+        }
+        assert_eq!(moved, 100);
+        assert_eq!(ctr.sbt_supply_by_owner(alice2(), issuer1(), None), 70);
+        assert_eq!(ctr.sbt_supply_by_owner(alice2(), issuer2(), None), 50);
+        assert_eq!(ctr.sbt_supply_by_owner(bob(), issuer1(), None), 20);
+    }
+
+    #[test]
+    fn soul_transfer_limit_basics() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 60 * MINT_DEPOSIT);
+        let batch_metadata = mk_batch_metadata(40);
+        assert!(batch_metadata.len() == 40);
+
+        // issuer_1
+        ctr.sbt_mint(vec![(alice(), batch_metadata[..20].to_vec())]);
+        assert_eq!(ctr.sbt_supply_by_owner(alice(), issuer1(), None), 20);
+
+        // issuer_2
+        ctx.predecessor_account_id = issuer2();
+        ctx.prepaid_gas = max_gas();
+        testing_env!(ctx.clone());
+        ctr.sbt_mint(vec![(alice(), batch_metadata[20..].to_vec())]);
+        assert_eq!(ctr.sbt_supply_by_owner(alice(), issuer2(), None), 20);
+
+        ctx.predecessor_account_id = alice();
+        ctx.prepaid_gas = max_gas();
+        testing_env!(ctx.clone());
+
+        let limit: u32 = 10;
+        let mut result = ctr._sbt_soul_transfer(alice2(), limit as usize);
+        assert_eq!((limit, false), result);
+
+        ctx.prepaid_gas = max_gas();
+        testing_env!(ctx.clone());
+        result = ctr._sbt_soul_transfer(alice2(), limit as usize);
+        assert_eq!((limit, false), result);
+
+        ctx.prepaid_gas = max_gas();
+        testing_env!(ctx.clone());
+        result = ctr._sbt_soul_transfer(alice2(), limit as usize);
+        assert_eq!((limit, false), result);
+
+        ctx.prepaid_gas = max_gas();
+        testing_env!(ctx.clone());
+        result = ctr._sbt_soul_transfer(alice2(), limit as usize);
+        assert_eq!((limit, false), result);
+
+        // resumed transfer but no more tokens to transfer
+        ctx.prepaid_gas = max_gas();
+        testing_env!(ctx.clone());
+        result = ctr._sbt_soul_transfer(alice2(), limit as usize);
+        assert_eq!((0, true), result);
+
+        assert_eq!(ctr.sbt_supply_by_owner(alice(), issuer1(), None), 0);
+        assert_eq!(ctr.sbt_supply_by_owner(alice(), issuer2(), None), 0);
+        assert_eq!(ctr.sbt_supply_by_owner(alice2(), issuer1(), None), 20);
+        assert_eq!(ctr.sbt_supply_by_owner(alice2(), issuer2(), None), 20);
+    }
+
+    /// Simulates a new transaction: sets the predecessor and refills the prepaid gas. Calling
+    /// `testing_env!` also resets the used gas counter and the logs.
+    /// locks the issuer bond and restores the `ctx`.
+    fn lock_bond(ctx: &VMContext, ctr: &mut Contract, issuer: &AccountId) {
+        let mut c = ctx.clone();
+        c.predecessor_account_id = issuer.clone();
+        c.attached_deposit = ISSUER_BOND;
+        testing_env!(c);
+        ctr.issuer_lock_bond();
+        testing_env!(ctx.clone());
+    }
+
+    fn next_tx(ctx: &mut VMContext, predecessor: &AccountId) {
+        next_tx_with_gas(ctx, predecessor, max_gas());
+    }
+
+    /// `next_tx` with the given prepaid gas, eg low enough to make the gas bounded sweeps
+    /// (`BoundedIter`) stop before `MAX_SWEEP_BATCH`.
+    fn next_tx_with_gas(ctx: &mut VMContext, predecessor: &AccountId, prepaid_gas: Gas) {
+        ctx.predecessor_account_id = predecessor.clone();
+        ctx.prepaid_gas = prepaid_gas;
+        testing_env!(ctx.clone());
+    }
+
+    /// Mints tokens of the given classes to `owner` in a new transaction made by `issuer`.
+    fn mint_classes(
+        ctx: &mut VMContext,
+        ctr: &mut Contract,
+        issuer: &AccountId,
+        owner: &AccountId,
+        classes: &[ClassId],
+    ) -> Vec<TokenId> {
+        next_tx(ctx, issuer);
+        let metadata = classes
+            .iter()
+            .map(|c| mk_metadata(*c, Some(START + 100)))
+            .collect();
+        ctr.sbt_mint(vec![(owner.clone(), metadata)])
+    }
+
+    /// Runs `_sbt_soul_transfer` from `owner` to `recipient`, each call in a new transaction,
+    /// until the transfer is completed. Returns results of all calls.
+    fn soul_transfer_all(
+        ctx: &mut VMContext,
+        ctr: &mut Contract,
+        owner: &AccountId,
+        recipient: &AccountId,
+        limit: usize,
+    ) -> Vec<(u32, bool)> {
+        let mut results = Vec::new();
+        loop {
+            next_tx(ctx, owner);
+            let r = ctr._sbt_soul_transfer(recipient.clone(), limit);
+            assert!(
+                env::used_gas() < ctx.prepaid_gas,
+                "soul transfer call must fit into the prepaid gas"
+            );
+            results.push(r);
+            if r.1 {
+                return results;
+            }
+            assert!(results.len() < 100, "soul transfer doesn't progress");
+        }
+    }
+
+    /// Prepaid gas of a sweep call ending after a few steps: the `SWEEP_GAS_RESERVE` and a
+    /// small margin.
+    fn sweep_stop_gas() -> Gas {
+        SWEEP_GAS_RESERVE + Gas::ONE_TERA.mul(3)
+    }
+
+    /// Runs `sbt_soul_transfer` from alice to alice2 with `prepaid_gas` per call, so the
+    /// gas (rather than `MAX_SWEEP_BATCH`) ends the calls, until the transfer is completed.
+    /// Checks that every interrupted call stores the cursor of the last transferred balance
+    /// of `keys` (alice balance keys in the iteration order). Returns results of all calls.
+    fn soul_transfer_all_gas_bounded(
+        ctx: &mut VMContext,
+        ctr: &mut Contract,
+        keys: &[BalanceKey],
+        prepaid_gas: Gas,
+    ) -> Vec<(u32, bool)> {
+        let mut results = Vec::new();
+        let mut moved = 0;
+        loop {
+            next_tx_with_gas(ctx, &alice(), prepaid_gas);
+            let r = ctr.sbt_soul_transfer(alice2(), None, None);
+            assert!(r.0 > 0 || r.1, "soul transfer doesn't progress");
+            moved += r.0 as usize;
+            results.push(r);
+            if r.1 {
+                assert_eq!(moved, keys.len());
+                return results;
+            }
+            let cursor = ctr.ongoing_soul_tx.get(&alice()).unwrap();
+            let last = &keys[moved - 1];
+            assert_eq!(
+                (cursor.issuer_id, cursor.token),
+                (last.issuer_id, last.class_id),
+                "cursor must point to the last transferred balance key"
+            );
+        }
+    }
+
+    /// Mints tokens for alice (from 3 issuers) and bob (as a neighbour in the balances map).
+    /// Returns alice balance keys in the iteration order.
+    fn setup_soul_transfer_tokens(ctx: &mut VMContext, ctr: &mut Contract) -> Vec<BalanceKey> {
+        mint_classes(ctx, ctr, &issuer1(), &alice(), &[1, 2, 5]);
+        mint_classes(ctx, ctr, &issuer2(), &alice(), &[3, 4]);
+        mint_classes(ctx, ctr, &issuer3(), &alice(), &[1, 2, 7]);
+        mint_classes(ctx, ctr, &issuer1(), &bob(), &[1, 2]);
+        ctr.balances
+            .iter_from(mk_balance_key(alice(), 0, 0))
+            .map(|(k, _)| k)
+            .take_while(|k| k.owner == alice())
+            .collect()
+    }
+
+    fn assert_soul_transferred(ctr: &Contract, keys: &[BalanceKey]) {
+        for k in keys {
+            assert!(!ctr.balances.contains_key(k));
+            let token = ctr
+                .balances
+                .get(&mk_balance_key(alice2(), k.issuer_id, k.class_id))
+                .expect("recipient must have the token");
+            assert_eq!(ctr.get_token(k.issuer_id, token).owner, alice2());
+        }
+        for issuer in [issuer1(), issuer2(), issuer3()] {
+            assert_eq!(ctr.sbt_supply_by_owner(alice(), issuer.clone(), None), 0);
+        }
+        assert_eq!(ctr.sbt_supply_by_owner(alice2(), issuer1(), None), 3);
+        assert_eq!(ctr.sbt_supply_by_owner(alice2(), issuer2(), None), 2);
+        assert_eq!(ctr.sbt_supply_by_owner(alice2(), issuer3(), None), 3);
+        assert_eq!(ctr.sbt_supply_by_owner(bob(), issuer1(), None), 2);
+        assert!(!ctr.ongoing_soul_tx.contains_key(&alice()));
+        assert!(ctr.is_banned(alice()));
+    }
+
+    #[test]
+    fn soul_transfer_resume_from_any_cursor() {
+        let log_soul_transfer = mk_log_str(
+            "soul_transfer",
+            &format!(r#"{{"from":"{}","to":"{}"}}"#, alice(), alice2()),
+        );
+        for first_limit in 1..=8 {
+            for limit in [1, 2, 3, 25] {
+                // testing_env! keeps the storage between calls, so we need to clear it.
+                near_sdk::mock::with_mocked_blockchain(|b| b.take_storage());
+                let (mut ctx, mut ctr) = setup(&issuer1(), 10 * MINT_DEPOSIT);
+                let keys = setup_soul_transfer_tokens(&mut ctx, &mut ctr);
+                assert_eq!(keys.len(), 8);
+
+                // the first call is interrupted after `first_limit` tokens
+                next_tx(&mut ctx, &alice());
+                let r = ctr._sbt_soul_transfer(alice2(), first_limit);
+                assert_eq!(r, (first_limit as u32, false));
+                let cursor = ctr.ongoing_soul_tx.get(&alice()).unwrap();
+                let last = &keys[first_limit - 1];
+                assert_eq!(
+                    (cursor.issuer_id, cursor.token),
+                    (last.issuer_id, last.class_id),
+                    "cursor must point to the last transferred balance key"
+                );
+
+                let results = soul_transfer_all(&mut ctx, &mut ctr, &alice(), &alice2(), limit);
+                let moved: u32 = results.iter().map(|r| r.0).sum();
+                assert_eq!(moved as usize + first_limit, keys.len());
+                assert_eq!(test_utils::get_logs().last(), log_soul_transfer.last());
+                assert_soul_transferred(&ctr, &keys);
+            }
+        }
+    }
+
+    #[test]
+    fn soul_transfer_resume_after_gas_stop() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 10 * MINT_DEPOSIT);
+        let keys = setup_soul_transfer_tokens(&mut ctx, &mut ctr);
+        let results = soul_transfer_all_gas_bounded(&mut ctx, &mut ctr, &keys, sweep_stop_gas());
+        assert!(results.len() > 2, "the gas must stop the calls");
+        assert_eq!(
+            test_utils::get_logs().last(),
+            mk_log_str(
+                "soul_transfer",
+                &format!(r#"{{"from":"{}","to":"{}"}}"#, alice(), alice2()),
+            )
+            .last()
+        );
+        assert_soul_transferred(&ctr, &keys);
+    }
+
+    #[test]
+    fn soul_transfer_resume_from_injected_cursor() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 10 * MINT_DEPOSIT);
+        let keys = setup_soul_transfer_tokens(&mut ctx, &mut ctr);
+
+        // cursor pointing in between alice's classes of issuer3: only classes > 1 of issuer3
+        // must be transferred.
+        ctr.ongoing_soul_tx.insert(
+            &alice(),
+            &IssuerTokenId {
+                issuer_id: 3,
+                token: 1,
+            },
+        );
+        let results = soul_transfer_all(&mut ctx, &mut ctr, &alice(), &alice2(), 1);
+        assert_eq!(results, vec![(1, false), (1, false), (0, true)]);
+        for k in &keys {
+            let moved = k.issuer_id == 3 && k.class_id > 1;
+            assert_eq!(ctr.balances.contains_key(k), !moved);
+        }
+        assert!(!ctr.ongoing_soul_tx.contains_key(&alice()));
+        // resumed transfer doesn't ban the owner
+        assert!(!ctr.is_banned(alice()));
+    }
+
+    #[test]
+    fn soul_transfer_cancel() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 10 * MINT_DEPOSIT);
+        let keys = setup_soul_transfer_tokens(&mut ctx, &mut ctr);
+
+        next_tx(&mut ctx, &alice());
+        assert_eq!(ctr._sbt_soul_transfer(alice2(), 3), (3, false));
+        assert_eq!(ctr.sbt_supply_by_owner(alice(), issuer1(), None), 0);
+
+        next_tx(&mut ctx, &alice());
+        ctr.sbt_soul_transfer_cancel();
+        assert_eq!(
+            test_utils::get_logs(),
+            vec![format!(
+                r#"EVENT_JSON:{{"standard":"i_am_human_registry","version":"1.0.0","event":"soul_transfer_aborted","data":{{"from":"{}"}}}}"#,
+                alice()
+            )]
+        );
+        assert!(!ctr.ongoing_soul_tx.contains_key(&alice()));
+        assert!(ctr.is_banned(alice()));
+        // moved tokens stay moved, the rest stays with alice
+        for (i, k) in keys.iter().enumerate() {
+            assert_eq!(ctr.balances.contains_key(k), i >= 3);
+        }
+        assert_eq!(ctr.sbt_supply_by_owner(alice2(), issuer1(), None), 3);
+        assert_eq!(ctr.sbt_supply_by_owner(alice(), issuer2(), None), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "no soul transfer in progress")]
+    fn soul_transfer_cancel_not_started() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 10 * MINT_DEPOSIT);
+        setup_soul_transfer_tokens(&mut ctx, &mut ctr);
+        next_tx(&mut ctx, &alice());
+        assert_eq!(ctr._sbt_soul_transfer(alice2(), 25), (8, true));
+        ctr.sbt_soul_transfer_cancel();
+    }
+
+    #[test]
+    fn soul_transfer_callback() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 10 * MINT_DEPOSIT);
+        setup_soul_transfer_tokens(&mut ctx, &mut ctr);
+        ctx.attached_deposit = MINT_DEPOSIT;
+        next_tx(&mut ctx, &carol());
+        ctr.sbt_set_notifications(true);
+
+        ctx.attached_deposit = 0;
+        next_tx(&mut ctx, &alice());
+        ctr.set_soul_transfer_callback(&alice(), carol(), "on_soul_transfer".to_owned());
+        assert_eq!(ctr._sbt_soul_transfer(alice2(), 3), (3, false));
+        assert_eq!(created_calls(), vec![]);
+
+        next_tx(&mut ctx, &alice());
+        ctr.set_soul_transfer_callback(&alice(), carol(), "on_soul_transfer".to_owned());
+        assert_eq!(ctr._sbt_soul_transfer(alice2(), 25), (5, true));
+        assert_eq!(
+            created_calls(),
+            vec![(
+                carol(),
+                "on_soul_transfer".to_owned(),
+                format!(r#"{{"from":"{}","to":"{}","tokens":8}}"#, alice(), alice2())
+            )]
+        );
+        assert!(ctr.soul_transfer_callbacks.get(&alice()).is_none());
+
+        // completed in a single call
+        next_tx(&mut ctx, &bob());
+        assert_eq!(
+            ctr.sbt_soul_transfer(dan(), None, Some((carol(), "done".to_owned()))),
+            (2, true)
+        );
+        assert_eq!(
+            created_calls(),
+            vec![(
+                carol(),
+                "done".to_owned(),
+                format!(r#"{{"from":"{}","to":"{}","tokens":2}}"#, bob(), dan())
+            )]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "callback must be the same as in the ongoing soul transfer")]
+    fn soul_transfer_callback_changed() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 10 * MINT_DEPOSIT);
+        setup_soul_transfer_tokens(&mut ctx, &mut ctr);
+        ctx.attached_deposit = MINT_DEPOSIT;
+        next_tx(&mut ctx, &carol());
+        ctr.sbt_set_notifications(true);
+        ctx.attached_deposit = 0;
+        next_tx(&mut ctx, &alice());
+        ctr.set_soul_transfer_callback(&alice(), carol(), "on_soul_transfer".to_owned());
+        assert_eq!(ctr._sbt_soul_transfer(alice2(), 3), (3, false));
+        ctr.sbt_soul_transfer(alice2(), None, Some((carol(), "other".to_owned())));
+    }
+
+    #[test]
+    #[should_panic(expected = "callback contract must be registered with sbt_set_notifications")]
+    fn soul_transfer_callback_not_registered() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 10 * MINT_DEPOSIT);
+        setup_soul_transfer_tokens(&mut ctx, &mut ctr);
+        next_tx(&mut ctx, &alice());
+        ctr.sbt_soul_transfer(
+            alice2(),
+            None,
+            Some((carol(), "on_soul_transfer".to_owned())),
+        );
+    }
+
+    /// Returns (receiver, amount) of the transfers created in the current transaction.
+    fn created_transfers() -> Vec<(AccountId, Balance)> {
+        test_utils::get_created_receipts()
+            .into_iter()
+            .flat_map(|r| {
+                r.actions.into_iter().filter_map(move |a| match a {
+                    near_sdk::mock::VmAction::Transfer { deposit } => {
+                        Some((r.receiver_id.clone(), deposit))
+                    }
+                    _ => None,
+                })
+            })
+            .collect()
+    }
+
+    /// Returns (receiver, function, args) of the created function call receipts.
+    fn created_calls() -> Vec<(AccountId, String, String)> {
+        test_utils::get_created_receipts()
+            .into_iter()
+            .flat_map(|r| {
+                r.actions.into_iter().filter_map(move |a| match a {
+                    near_sdk::mock::VmAction::FunctionCall {
+                        function_name,
+                        args,
+                        ..
+                    } => Some((
+                        r.receiver_id.clone(),
+                        function_name,
+                        String::from_utf8(args).unwrap(),
+                    )),
+                    _ => None,
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn soul_transfer_continue() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 10 * MINT_DEPOSIT);
+        let keys = setup_soul_transfer_tokens(&mut ctx, &mut ctr);
+
+        ctx.attached_deposit = SOUL_TRANSFER_BOND;
+        next_tx(&mut ctx, &alice());
+        assert_eq!(ctr._sbt_soul_transfer(alice2(), 3), (3, false));
+        assert!(ctr.soul_transfer_bonds.contains_key(&alice()));
+
+        // anyone can continue and the caller completing the transfer gets the bond
+        ctx.attached_deposit = 0;
+        next_tx(&mut ctx, &bob());
+        assert_eq!(ctr._sbt_soul_transfer_continue(alice(), 3), (3, false));
+        assert!(created_transfers().is_empty());
+        next_tx(&mut ctx, &carol());
+        assert_eq!(ctr._sbt_soul_transfer_continue(alice(), 3), (2, true));
+        assert_eq!(created_transfers(), vec![(carol(), SOUL_TRANSFER_BOND)]);
+        assert_eq!(
+            test_utils::get_logs().last(),
+            mk_log_str(
+                "soul_transfer",
+                &format!(r#"{{"from":"{}","to":"{}"}}"#, alice(), alice2()),
+            )
+            .last()
+        );
+        assert!(!ctr.soul_transfer_bonds.contains_key(&alice()));
+        assert_soul_transferred(&ctr, &keys);
+    }
+
+    #[test]
+    fn soul_transfer_cooldown() {
+        let (mut ctx, mut ctr) = setup(&admin(), 2 * MINT_DEPOSIT);
+        ctr.admin_set_soul_transfer_cooldown(100);
+        assert_eq!(ctr.soul_transfer_cooldown(), 100);
+        next_tx(&mut ctx, &issuer1());
+        ctr.sbt_mint(vec![(alice(), vec![mk_metadata(1, None)])]);
+
+        next_tx(&mut ctx, &alice());
+        assert_eq!(ctr.transfer_cooldown_until(alice()), None);
+        assert_eq!(ctr.sbt_soul_transfer(alice2(), None, None), (1, true));
+        assert_eq!(ctr.transfer_cooldown_until(alice2()), Some(100));
+        assert_eq!(ctr.transfer_cooldown_until(alice()), None);
+
+        ctx.block_timestamp = 100 * MILI_SECOND;
+        next_tx(&mut ctx, &alice2());
+        assert_eq!(ctr.transfer_cooldown_until(alice2()), None);
+        assert_eq!(ctr.sbt_soul_transfer(bob(), None, None), (1, true));
+        assert_eq!(ctr.transfer_cooldown_until(bob()), Some(200));
+    }
+
+    #[test]
+    #[should_panic(expected = "soul transfer cooldown has not passed yet")]
+    fn soul_transfer_in_cooldown() {
+        let (mut ctx, mut ctr) = setup(&admin(), 2 * MINT_DEPOSIT);
+        ctr.admin_set_soul_transfer_cooldown(100);
+        next_tx(&mut ctx, &issuer1());
+        ctr.sbt_mint(vec![(alice(), vec![mk_metadata(1, None)])]);
+        next_tx(&mut ctx, &alice());
+        ctr.sbt_soul_transfer(alice2(), None, None);
+
+        ctx.block_timestamp = 99 * MILI_SECOND;
+        next_tx(&mut ctx, &alice2());
+        ctr.sbt_soul_transfer(bob(), None, None);
+    }
+
+    #[test]
+    fn soul_transfer_key_bound_class_later_batch() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 3 * MINT_DEPOSIT);
+        ctr.sbt_set_class_transferable(3, false);
+        mint_classes(&mut ctx, &mut ctr, &issuer1(), &alice(), &[1, 2, 3]);
+
+        // the first batch (gas bounded) doesn't reach the key bound class
+        next_tx_with_gas(&mut ctx, &alice(), sweep_stop_gas());
+        let r = ctr._sbt_soul_transfer(alice2(), 2);
+        assert!(!r.1);
+        let mut moved = r.0;
+        loop {
+            next_tx_with_gas(&mut ctx, &alice(), max_gas());
+            let r = ctr._sbt_soul_transfer(alice2(), 2);
+            moved += r.0;
+            if r.1 {
+                break;
+            }
+        }
+        assert_eq!(moved, 3);
+        assert_eq!(ctr.sbt_status(issuer1(), 3), Some(TokenStatus::Burned));
+        assert_eq!(ctr.sbt_supply_by_owner(alice(), issuer1(), None), 0);
+        assert_eq!(ctr.sbt_supply_by_owner(alice2(), issuer1(), None), 2);
+        assert_eq!(ctr.sbt_supply(issuer1(), None), 2);
+        assert_eq!(ctr.check_invariants(issuer1(), None, None), vec![]);
+    }
+
+    #[test]
+    fn soul_transfer_key_bound_classes() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 3 * MINT_DEPOSIT);
+        ctr.sbt_set_class_transferable(2, false);
+        ctr.sbt_mint(vec![(
+            alice(),
+            vec![mk_metadata(1, None), mk_metadata(2, None)],
+        )]);
+        assert!(ctr.class_transferable_on_soul_transfer(issuer1(), 1));
+        assert!(!ctr.class_transferable_on_soul_transfer(issuer1(), 2));
+        next_tx(&mut ctx, &issuer2());
+        ctr.sbt_set_class_transferable(1, false);
+        ctr.sbt_mint(vec![(alice(), vec![mk_metadata(1, None)])]);
+
+        next_tx(&mut ctx, &alice());
+        assert_eq!(ctr.sbt_soul_transfer(alice2(), None, None), (3, true));
+        let logs = test_utils::get_logs();
+        assert_eq!(
+            logs[1..4],
+            [
+                mk_log_str(
+                    "burn",
+                    &format!(r#"{{"issuer":"{}","tokens":[2]}}"#, issuer1())
+                ),
+                mk_log_str(
+                    "burn",
+                    &format!(r#"{{"issuer":"{}","tokens":[1]}}"#, issuer2())
+                ),
+                mk_registry_log_str(
+                    "soul_transfer_batch",
+                    &format!(
+                        r#"{{"from":"{}","to":"{}","tokens":[["{}",[1]]]}}"#,
+                        alice(),
+                        alice2(),
+                        issuer1()
+                    ),
+                ),
+            ]
+            .concat()
+        );
+        assert_eq!(
+            ctr.sbt_tokens_by_owner(alice2(), None, None, None, None),
+            vec![(issuer1(), vec![mk_owned_token(1, mk_metadata(1, None))])]
+        );
+        assert_eq!(ctr.sbt_supply_by_owner(alice(), issuer1(), None), 0);
+        assert_eq!(ctr.sbt_supply_by_owner(alice2(), issuer1(), None), 1);
+        assert_eq!(ctr.sbt_supply_by_owner(alice2(), issuer2(), None), 0);
+        assert_eq!(ctr.sbt_supply(issuer2(), None), 0);
+        assert_eq!(ctr.sbt_status(issuer1(), 2), Some(TokenStatus::Burned));
+
+        // the flag can be reverted
+        next_tx(&mut ctx, &issuer1());
+        ctr.sbt_set_class_transferable(2, true);
+        assert!(ctr.class_transferable_on_soul_transfer(issuer1(), 2));
+    }
+
+    #[test]
+    fn soul_transfer_bond_refund() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 10 * MINT_DEPOSIT);
+        let keys = setup_soul_transfer_tokens(&mut ctx, &mut ctr);
+
+        // completed in the first call: the deposit is refunded
+        ctx.attached_deposit = SOUL_TRANSFER_BOND;
+        next_tx(&mut ctx, &alice());
+        assert_eq!(ctr._sbt_soul_transfer(alice2(), 25), (8, true));
+        assert_eq!(created_transfers(), vec![(alice(), SOUL_TRANSFER_BOND)]);
+        assert!(!ctr.soul_transfer_bonds.contains_key(&alice()));
+        assert_soul_transferred(&ctr, &keys);
+
+        // completed by the owner
+        ctx.attached_deposit = 2 * SOUL_TRANSFER_BOND;
+        next_tx(&mut ctx, &alice2());
+        assert_eq!(ctr._sbt_soul_transfer(bob(), 3), (3, false));
+        ctx.attached_deposit = 0;
+        next_tx(&mut ctx, &alice2());
+        assert_eq!(ctr._sbt_soul_transfer(bob(), 25), (5, true));
+        assert_eq!(
+            created_transfers(),
+            vec![(alice2(), 2 * SOUL_TRANSFER_BOND)]
+        );
+        assert!(!ctr.soul_transfer_bonds.contains_key(&alice2()));
+    }
+
+    #[test]
+    fn soul_transfer_deposit_refund() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 10 * MINT_DEPOSIT);
+        setup_soul_transfer_tokens(&mut ctx, &mut ctr);
+
+        // deposit below the bond
+        ctx.attached_deposit = SOUL_TRANSFER_BOND - 1;
+        next_tx(&mut ctx, &alice());
+        assert_eq!(ctr._sbt_soul_transfer(alice2(), 3), (3, false));
+        assert_eq!(created_transfers(), vec![(alice(), SOUL_TRANSFER_BOND - 1)]);
+        assert!(!ctr.soul_transfer_bonds.contains_key(&alice()));
+
+        // deposit attached to a subsequent call
+        ctx.attached_deposit = SOUL_TRANSFER_BOND;
+        next_tx(&mut ctx, &alice());
+        assert_eq!(ctr._sbt_soul_transfer(alice2(), 3), (3, false));
+        assert_eq!(created_transfers(), vec![(alice(), SOUL_TRANSFER_BOND)]);
+        assert!(!ctr.soul_transfer_bonds.contains_key(&alice()));
+
+        ctx.attached_deposit = 0;
+        next_tx(&mut ctx, &alice());
+        assert_eq!(ctr._sbt_soul_transfer(alice2(), 3), (2, true));
+        assert!(created_transfers().is_empty());
+    }
+
+    #[test]
+    fn soul_transfer_deposit_refund_bonded() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 10 * MINT_DEPOSIT);
+        setup_soul_transfer_tokens(&mut ctx, &mut ctr);
+        ctx.attached_deposit = SOUL_TRANSFER_BOND;
+        next_tx(&mut ctx, &alice());
+        assert_eq!(ctr._sbt_soul_transfer(alice2(), 3), (3, false));
+        assert!(created_transfers().is_empty());
+
+        // the bond is kept, the new deposit is refunded
+        ctx.attached_deposit = 7;
+        next_tx(&mut ctx, &alice());
+        assert_eq!(ctr._sbt_soul_transfer(alice2(), 3), (3, false));
+        assert_eq!(created_transfers(), vec![(alice(), 7)]);
+        assert_eq!(
+            ctr.soul_transfer_bonds.get(&alice()).unwrap().bond,
+            SOUL_TRANSFER_BOND
+        );
+
+        // completed: the bond and the deposit are refunded
+        ctx.attached_deposit = 3;
+        next_tx(&mut ctx, &alice());
+        assert_eq!(ctr._sbt_soul_transfer(alice2(), 3), (2, true));
+        assert_eq!(created_transfers(), vec![(alice(), SOUL_TRANSFER_BOND + 3)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "registry is paused")]
+    fn soul_transfer_continue_paused() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 10 * MINT_DEPOSIT);
+        setup_soul_transfer_tokens(&mut ctx, &mut ctr);
+        ctx.attached_deposit = SOUL_TRANSFER_BOND;
+        next_tx(&mut ctx, &alice());
+        assert_eq!(ctr._sbt_soul_transfer(alice2(), 3), (3, false));
+        ctx.attached_deposit = 0;
+        next_tx(&mut ctx, &admin());
+        ctr.admin_pause(None);
+        next_tx(&mut ctx, &bob());
+        ctr.sbt_soul_transfer_continue(alice());
+    }
+
+    #[test]
+    fn soul_transfer_cancel_refunds_bond() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 10 * MINT_DEPOSIT);
+        setup_soul_transfer_tokens(&mut ctx, &mut ctr);
+        ctx.attached_deposit = SOUL_TRANSFER_BOND;
+        next_tx(&mut ctx, &alice());
+        assert_eq!(ctr._sbt_soul_transfer(alice2(), 3), (3, false));
+        next_tx(&mut ctx, &alice());
+        ctr.sbt_soul_transfer_cancel();
+        assert_eq!(created_transfers(), vec![(alice(), SOUL_TRANSFER_BOND)]);
+        assert!(!ctr.soul_transfer_bonds.contains_key(&alice()));
+    }
+
+    #[test]
+    #[should_panic(expected = "no bonded soul transfer in progress")]
+    fn soul_transfer_continue_without_bond() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 10 * MINT_DEPOSIT);
+        setup_soul_transfer_tokens(&mut ctx, &mut ctr);
+        ctx.attached_deposit = SOUL_TRANSFER_BOND - 1;
+        next_tx(&mut ctx, &alice());
+        assert_eq!(ctr._sbt_soul_transfer(alice2(), 3), (3, false));
+        next_tx(&mut ctx, &bob());
+        ctr._sbt_soul_transfer_continue(alice(), 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "recipient must be the same as in the ongoing soul transfer")]
+    fn soul_transfer_bond_other_recipient() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 10 * MINT_DEPOSIT);
+        setup_soul_transfer_tokens(&mut ctx, &mut ctr);
+        ctx.attached_deposit = SOUL_TRANSFER_BOND;
+        next_tx(&mut ctx, &alice());
+        assert_eq!(ctr._sbt_soul_transfer(alice2(), 3), (3, false));
+        next_tx(&mut ctx, &alice());
+        ctr._sbt_soul_transfer(bob(), 3);
+    }
+
+    #[test]
+    fn soul_merge() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 10 * MINT_DEPOSIT);
+        let m1_long = mk_metadata(1, Some(START + 20));
+        let m1_short = mk_metadata(1, Some(START + 10));
+        let m2_long = mk_metadata(2, Some(START + 50));
+        let m2_short = mk_metadata(2, Some(START + 5));
+        let m3 = mk_metadata(3, Some(START + 10));
+        // primary account: alice, secondary: alice2
+        ctr.sbt_mint(vec![(alice(), vec![m1_short.clone(), m2_long.clone()])]);
+        ctr.sbt_mint(vec![(alice2(), vec![m1_long.clone(), m2_short])]);
+        next_tx(&mut ctx, &issuer2());
+        ctr.sbt_mint(vec![(alice2(), vec![m3.clone()])]);
+        // the frozen status of the burned token is replaced by the tombstone
+        next_tx(&mut ctx, &admin());
+        ctr.admin_freeze_tokens(issuer1(), vec![1], None, None);
+
+        next_tx(&mut ctx, &alice2());
+        ctr.sbt_soul_merge_approve(alice());
+        next_tx(&mut ctx, &alice());
+        assert_eq!(ctr.sbt_soul_merge(alice2()), (3, true));
+
+        let log_burn = mk_log_str(
+            "burn",
+            &format!(r#"{{"issuer":"{}","tokens":[1,4]}}"#, issuer1()),
+        );
+        let log_ban = mk_log_str("ban", &format!(r#"["{}"]"#, alice2()));
+        let log_transfer = mk_log_str(
+            "soul_transfer",
+            &format!(r#"{{"from":"{}","to":"{}"}}"#, alice2(), alice()),
+        );
+        assert_eq!(
+            test_utils::get_logs(),
+            [log_ban, log_burn, log_transfer].concat()
+        );
+
+        // alice keeps the longer expire time tokens
+        assert_eq!(
+            ctr.sbt_tokens_by_owner(alice(), None, None, None, Some(true)),
+            vec![
+                (
+                    issuer1(),
+                    vec![mk_owned_token(3, m1_long), mk_owned_token(2, m2_long)]
+                ),
+                (issuer2(), vec![mk_owned_token(1, m3)]),
+            ]
+        );
+        assert!(ctr.sbt(issuer1(), 1).is_none());
+        assert!(ctr.sbt(issuer1(), 4).is_none());
+        // merged away tokens leave the burned tombstone
+        assert_eq!(ctr.sbt_status(issuer1(), 1), Some(TokenStatus::Burned));
+        assert_eq!(ctr.sbt_status(issuer1(), 4), Some(TokenStatus::Burned));
+        assert_eq!(ctr.check_invariants(issuer1(), None, None), vec![]);
+        assert_eq!(
+            ctr.sbt_tokens_by_owner(alice2(), None, None, None, Some(true)),
+            vec![]
+        );
+        assert_eq!(ctr.sbt_supply_by_owner(alice(), issuer1(), None), 2);
+        assert_eq!(ctr.sbt_supply_by_owner(alice(), issuer2(), None), 1);
+        assert_eq!(ctr.sbt_supply_by_owner(alice2(), issuer1(), None), 0);
+        assert_eq!(ctr.sbt_supply_by_owner(alice2(), issuer2(), None), 0);
+        assert_eq!(ctr.sbt_supply(issuer1(), None), 2);
+        assert_eq!(ctr.sbt_supply_by_class(issuer1(), 1), 1);
+        assert_eq!(ctr.sbt_supply_by_class(issuer1(), 2), 1);
+        assert_eq!(ctr.sbt_supply(issuer2(), None), 1);
+        assert!(ctr.is_banned(alice2()));
+        assert!(!ctr.is_banned(alice()));
+        assert!(!ctr.merge_approvals.contains_key(&alice2()));
+    }
+
+    #[test]
+    fn soul_merge_with_continuation() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 10 * MINT_DEPOSIT);
+        let keys = setup_soul_transfer_tokens(&mut ctx, &mut ctr);
+        next_tx(&mut ctx, &alice());
+        ctr.sbt_soul_merge_approve(alice2());
+
+        next_tx(&mut ctx, &alice2());
+        let mut calls = 1;
+        while !ctr._sbt_soul_merge(alice(), 3).1 {
+            next_tx(&mut ctx, &alice2());
+            calls += 1;
+        }
+        assert_eq!(calls, 3);
+        assert_soul_transferred(&ctr, &keys);
+    }
+
+    #[test]
+    #[should_panic(expected = "merge is not approved by the secondary account")]
+    fn soul_merge_not_approved() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 10 * MINT_DEPOSIT);
+        ctr.sbt_mint(vec![(alice2(), vec![mk_metadata(1, Some(START + 10))])]);
+        // approval for a different account
+        next_tx(&mut ctx, &alice2());
+        ctr.sbt_soul_merge_approve(bob());
+        next_tx(&mut ctx, &alice());
+        ctr.sbt_soul_merge(alice2());
+    }
+
+    #[test]
+    #[should_panic(expected = "alice.near can have at most 2 tokens")]
+    fn soul_merge_max_tokens_per_account() {
+        let (mut ctx, mut ctr) = setup(&admin(), 10 * MINT_DEPOSIT);
+        ctr.admin_set_max_tokens_per_account(2);
+        next_tx(&mut ctx, &issuer1());
+        ctr.sbt_mint(vec![
+            (alice(), vec![mk_metadata(1, None), mk_metadata(3, None)]),
+            (alice2(), vec![mk_metadata(1, None), mk_metadata(2, None)]),
+        ]);
+        next_tx(&mut ctx, &alice2());
+        ctr.sbt_soul_merge_approve(alice());
+        // the class 1 token replaces the alice token, the class 2 token reaches the cap
+        next_tx(&mut ctx, &alice());
+        ctr.sbt_soul_merge(alice2());
+    }
+
+    #[test]
+    #[should_panic(expected = "soul transfer initiated by an issuer is in progress")]
+    fn soul_merge_issuer_soul_transfer_in_progress() {
+        let (mut ctx, mut ctr) = setup(&admin(), 10 * MINT_DEPOSIT);
+        ctr.admin_set_issuer_capabilities(issuer2(), CAP_DEFAULT | CAP_SOUL_TRANSFER, None);
+        setup_soul_transfer_tokens(&mut ctx, &mut ctr);
+        next_tx(&mut ctx, &alice());
+        ctr.sbt_soul_merge_approve(bob());
+        next_tx(&mut ctx, &issuer2());
+        ctr._sbt_soul_transfer_by_issuer(alice(), alice2(), 2);
+        next_tx(&mut ctx, &bob());
+        ctr.sbt_soul_merge(alice());
+    }
+
+    #[test]
+    #[should_panic(expected = "merge is not approved by the secondary account")]
+    fn soul_merge_after_soul_transfer_started() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 10 * MINT_DEPOSIT);
+        setup_soul_transfer_tokens(&mut ctx, &mut ctr);
+        next_tx(&mut ctx, &alice());
+        ctr.sbt_soul_merge_approve(carol());
+        ctr._sbt_soul_transfer(alice2(), 2);
+        next_tx(&mut ctx, &carol());
+        ctr.sbt_soul_merge(alice());
+    }
+
+    #[cfg(any(feature = "testnet", feature = "debug"))]
+    #[test]
+    fn debug_dump() {
+        use near_sdk::borsh::BorshDeserialize;
+
+        let (mut ctx, mut ctr) = setup(&issuer1(), 3 * MINT_DEPOSIT);
+        let m1 = mk_metadata(1, None);
+        ctr.sbt_mint(vec![
+            (alice(), vec![m1.clone(), mk_metadata(2, None)]),
+            (bob(), vec![m1.clone()]),
+        ]);
+        next_tx(&mut ctx, &issuer2());
+        ctr.sbt_mint(vec![(alice(), vec![m1.clone()])]);
+
+        let tokens = ctr.debug_dump_tokens(issuer1(), 0, 10);
+        assert_eq!(tokens.len(), 3);
+        let t = VerTokenData::try_from_slice(&tokens[2].value.0)
+            .unwrap()
+            .latest();
+        assert_eq!(t.owner, bob());
+        assert_eq!(t.metadata.latest(), m1);
+        assert_eq!(ctr.debug_dump_tokens(issuer1(), 2, 1).len(), 1);
+
+        let balances = ctr.debug_dump_balances(alice());
+        assert_eq!(balances.len(), 3);
+        let k = BalanceKey::try_from_slice(&balances[2].key.0).unwrap();
+        assert_eq!((k.issuer_id, k.class_id), (2, 1));
+        assert_eq!(u64::try_from_slice(&balances[2].value.0).unwrap(), 1);
+        assert_eq!(ctr.debug_dump_balances(carol()), vec![]);
+    }
+
+    #[cfg(feature = "testnet")]
+    #[test]
+    fn testnet_register_issuer_and_force_mint() {
+        let (mut ctx, mut ctr) = setup(&issuer4(), ISSUER_BOND);
+        assert!(ctr.testnet_register_issuer());
+        assert_eq!(
+            ctr.issuer_bonds.get(&issuer4()).unwrap().amount.0,
+            ISSUER_BOND
+        );
+        ctx.attached_deposit = 0;
+        testing_env!(ctx.clone());
+        assert!(!ctr.testnet_register_issuer());
+        assert_eq!(ctr.assert_issuer(&issuer4()), 5);
+
+        ctx.attached_deposit = 2 * MINT_DEPOSIT;
+        next_tx(&mut ctx, &admin());
+        let m1_1 = mk_metadata(1, Some(START + 10));
+        let ids = ctr.admin_force_mint(issuer4(), vec![(alice(), vec![m1_1.clone()])]);
+        assert_eq!(ids, vec![1]);
+        assert_eq!(ctr.sbt(issuer4(), 1).unwrap(), mk_token(1, alice(), m1_1));
+    }
+
+    #[cfg(feature = "testnet")]
+    #[test]
+    #[should_panic(expected = "issuer must lock a bond of at least")]
+    fn testnet_register_issuer_no_bond() {
+        let (_, mut ctr) = setup(&issuer4(), ISSUER_BOND - 1);
+        ctr.testnet_register_issuer();
+    }
+
+    #[cfg(feature = "testnet")]
+    #[test]
+    #[should_panic(expected = "not an admin")]
+    fn testnet_force_mint_not_admin() {
+        let (_, mut ctr) = setup(&issuer1(), 2 * MINT_DEPOSIT);
+        ctr.admin_force_mint(issuer1(), vec![(alice(), vec![mk_metadata(1, None)])]);
+    }
+
+    #[test]
+    fn event_digest() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 2 * MINT_DEPOSIT);
+        // setup emits an add_issuer event for every issuer
+        let start = ctr.event_digest();
+        assert_eq!(start.count, 4);
+
+        let tokens = ctr.sbt_mint(vec![(alice(), vec![mk_metadata(1, Some(START + 10))])]);
+        ctr.sbt_renew(tokens, START + 100);
+        let mut logs = test_utils::get_logs();
+        next_tx(&mut ctx, &alice());
+        ctr.sbt_soul_transfer(alice2(), None, None);
+        logs.extend(test_utils::get_logs());
+        // mint, renew, ban, soul_transfer_batch, soul_transfer
+        assert_eq!(logs.len(), 5);
+
+        let mut root: [u8; 32] = start.root.0.try_into().unwrap();
+        for l in &logs {
+            root = chain_event_root(&root, l);
+        }
+        assert_eq!(
+            ctr.event_digest(),
+            EventDigest {
+                root: root.to_vec().into(),
+                count: 9
+            }
+        );
+    }
+
+    #[test]
+    fn equal_events_not_deduplicated() {
+        let (_, mut ctr) = setup(&admin(), 0);
+        let start = ctr.event_digest();
+        // eg two equal revoke batches in one call
+        ctr.emit_registry_event(RegistryEvent::FinalizeGenesis);
+        ctr.emit_registry_event(RegistryEvent::FinalizeGenesis);
+        ctr.log("skipped".to_string());
+        ctr.log("skipped".to_string());
+        let log_finalize = RegistryEvent::FinalizeGenesis.to_json_event_string();
+        assert_eq!(
+            test_utils::get_logs(),
+            vec![
+                log_finalize.clone(),
+                log_finalize.clone(),
+                "skipped".to_string(),
+                "skipped".to_string()
+            ]
+        );
+        let root: [u8; 32] = start.root.0.try_into().unwrap();
+        let root = chain_event_root(&root, &log_finalize);
+        assert_eq!(
+            ctr.event_digest(),
+            EventDigest {
+                root: chain_event_root(&root, &log_finalize).to_vec().into(),
+                count: start.count + 2
+            }
+        );
+    }
+
+    #[test]
+    fn sbt_renew_batch() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 4 * MINT_DEPOSIT);
+        let m1_1 = mk_metadata(1, Some(START + 10));
+        let m2_1 = mk_metadata(2, Some(START + 10));
+        ctr.sbt_mint(vec![
+            (alice(), vec![m1_1.clone(), m2_1.clone()]),
+            (bob(), vec![m1_1.clone()]),
+        ]);
+        next_tx(&mut ctx, &issuer2());
+        ctr.sbt_mint(vec![(carol(), vec![m1_1.clone(), m2_1.clone()])]);
+        ctr.banlist.insert(&bob());
+
+        next_tx(&mut ctx, &issuer1());
+        let res = ctr.sbt_renew_batch(vec![
+            (1, START + 100),
+            (2, START + 200),
+            (3, START + 300),
+            (4, START + 400),
+        ]);
+        assert_eq!(
+            res,
+            vec![
+                RenewResult::Renewed,
+                RenewResult::Renewed,
+                RenewResult::OwnerBanned,
+                RenewResult::NotFound
+            ]
+        );
+        assert_eq!(
+            test_utils::get_logs(),
+            mk_log_str(
+                "renew",
+                &format!(r#"{{"issuer":"{}","tokens":[1,2]}}"#, issuer1())
+            )
+        );
+        assert_eq!(
+            ctr.sbt_tokens(issuer1(), None, None, Some(true)),
+            vec![
+                mk_token(1, alice(), mk_metadata(1, Some(START + 100))),
+                mk_token(2, alice(), mk_metadata(2, Some(START + 200))),
+                mk_token(3, bob(), m1_1.clone()),
+            ]
+        );
+        // issuer2 tokens are not affected
+        assert_eq!(
+            ctr.sbt_tokens(issuer2(), None, None, Some(true)),
+            vec![mk_token(1, carol(), m1_1), mk_token(2, carol(), m2_1)]
+        );
+
+        // no event when nothing was renewed
+        next_tx(&mut ctx, &issuer1());
+        assert_eq!(
+            ctr.sbt_renew_batch(vec![(10, START)]),
+            vec![RenewResult::NotFound]
+        );
+        assert!(test_utils::get_logs().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "max 2 tokens can be renewed at once")]
+    fn sbt_renew_batch_limit() {
+        let (mut ctx, mut ctr) = setup(&admin(), 4 * MINT_DEPOSIT);
+        ctr.admin_set_max_renew_batch(2);
+        next_tx(&mut ctx, &issuer1());
+        ctr.sbt_renew_batch(vec![(1, START), (2, START), (3, START)]);
+    }
+
+    #[test]
+    fn token_status_transitions() {
+        use TokenOp::*;
+        use TokenStatus::*;
+        let legal = [
+            (Active, Renew, Active),
+            (Expired, Renew, Active),
+            (Active, Revoke, Revoked),
+            (Expired, Revoke, Revoked),
+            (Frozen, Revoke, Revoked),
+            (Active, Freeze, Frozen),
+            (Expired, Freeze, Frozen),
+            (Frozen, Unfreeze, Active),
+            (Active, Burn, Burned),
+            (Expired, Burn, Burned),
+            (Revoked, Burn, Burned),
+            (Frozen, Burn, Burned),
+            (Expired, Prune, Burned),
+            (Revoked, Prune, Burned),
+        ];
+        for from in [Active, Expired, Revoked, Frozen, Burned] {
+            for op in [Renew, Revoke, Freeze, Unfreeze, Burn, Prune] {
+                let expected = legal
+                    .iter()
+                    .find(|(f, o, _)| *f == from && *o == op)
+                    .map(|(_, _, to)| *to);
+                assert_eq!(from.apply(op), expected, "{} {}", op, from);
+            }
+        }
+    }
+
+    #[test]
+    fn token_status() {
+        let (mut ctx, mut ctr) = setup(&fractal_mainnet(), 2 * MINT_DEPOSIT);
+        ctr.sbt_mint(vec![(
+            alice(),
+            vec![mk_metadata(1, Some(1000)), mk_metadata(2, Some(1000))],
+        )]);
+        assert!(ctr.is_human(alice()));
+        assert_eq!(
+            ctr.sbt_status(fractal_mainnet(), 1),
+            Some(TokenStatus::Active)
+        );
+        assert_eq!(ctr.sbt_status(fractal_mainnet(), 3), None);
+
+        // frozen tokens don't count as a proof
+        next_tx(&mut ctx, &admin());
+        ctr.admin_freeze_tokens(
+            fractal_mainnet(),
+            vec![1],
+            Some("fraud".to_owned()),
+            Some(RevocationReason::Fraud),
+        );
+        assert_eq!(
+            test_utils::get_logs(),
+            mk_registry_log_str(
+                "freeze_tokens",
+                r#"{"issuer":"fractal.i-am-human.near","tokens":[1],"memo":"fraud","reason":"fraud"}"#
+            )
+        );
+        assert_eq!(
+            ctr.sbt_status(fractal_mainnet(), 1),
+            Some(TokenStatus::Frozen)
+        );
+        assert!(!ctr.is_human(alice()));
+        assert_eq!(
+            ctr.sbt_tokens_by_owner(alice(), None, None, None, None)[0]
+                .1
+                .len(),
+            1
+        );
+        assert_eq!(
+            ctr.sbt_tokens_by_owner(alice(), None, None, None, Some(true))[0]
+                .1
+                .len(),
+            2
+        );
+        next_tx(&mut ctx, &fractal_mainnet());
+        assert_eq!(
+            ctr.sbt_renew_batch(vec![(1, 2000), (2, 2000)]),
+            vec![RenewResult::InvalidStatus, RenewResult::Renewed]
+        );
+        next_tx(&mut ctx, &admin());
+        ctr.admin_unfreeze_tokens(fractal_mainnet(), vec![1], None);
+        assert_eq!(
+            ctr.sbt_status(fractal_mainnet(), 1),
+            Some(TokenStatus::Active)
+        );
+        assert!(ctr.is_human(alice()));
+
+        // expired tokens can be renewed
+        ctx.block_timestamp = 1500 * MILI_SECOND;
+        next_tx(&mut ctx, &fractal_mainnet());
+        assert_eq!(
+            ctr.sbt_status(fractal_mainnet(), 1),
+            Some(TokenStatus::Expired)
+        );
+        ctr.sbt_renew(vec![1], 3000);
+        assert_eq!(
+            ctr.sbt_status(fractal_mainnet(), 1),
+            Some(TokenStatus::Active)
+        );
+
+        // revoked tokens can only be burned
+        ctr.sbt_revoke(vec![1], false);
+        assert_eq!(
+            ctr.sbt_status(fractal_mainnet(), 1),
+            Some(TokenStatus::Revoked)
+        );
+        assert_eq!(
+            ctr.sbt_renew_batch(vec![(1, 4000)]),
+            vec![RenewResult::InvalidStatus]
+        );
+        next_tx(&mut ctx, &fractal_mainnet());
+        assert_eq!(ctr.sbt_prune(vec![1]), 1);
+        assert_eq!(
+            test_utils::get_logs(),
+            mk_log_str(
+                "burn",
+                r#"{"issuer":"fractal.i-am-human.near","tokens":[1],"storage_delta_bytes":-189,"deposit_charged":"0"}"#
+            )
+        );
+        assert_eq!(
+            ctr.sbt_status(fractal_mainnet(), 1),
+            Some(TokenStatus::Burned)
+        );
+        assert_eq!(ctr.sbt(fractal_mainnet(), 1), None);
+        assert_eq!(ctr.sbt_supply(fractal_mainnet(), None), 1);
+        assert_eq!(ctr.sbt_supply_by_owner(alice(), fractal_mainnet(), None), 1);
+    }
+
+    /// Mints an active token (ID 1) and applies the `ops` to it: "revoke", "burn", "freeze"
+    /// or "expire".
+    fn setup_token_status(ops: &[&str]) -> (VMContext, Contract) {
+        let (mut ctx, mut ctr) = setup(&issuer1(), MINT_DEPOSIT);
+        ctr.sbt_mint(vec![(alice(), vec![mk_metadata(1, Some(1000))])]);
+        for op in ops {
+            match *op {
+                "revoke" => ctr.sbt_revoke(vec![1], false),
+                "burn" => ctr.sbt_revoke(vec![1], true),
+                "freeze" => {
+                    next_tx(&mut ctx, &admin());
+                    ctr.admin_freeze_tokens(issuer1(), vec![1], None, None);
+                }
+                "expire" => ctx.block_timestamp = 2000 * MILI_SECOND,
+                _ => panic!("unknown op"),
+            }
+            next_tx(&mut ctx, &issuer1());
+        }
+        (ctx, ctr)
+    }
+
+    #[test]
+    #[should_panic(expected = "can't renew revoked token 1")]
+    fn token_status_renew_revoked() {
+        let (_, mut ctr) = setup_token_status(&["revoke"]);
+        ctr.sbt_renew(vec![1], 3000);
+    }
+
+    #[test]
+    #[should_panic(expected = "can't revoke revoked token 1")]
+    fn token_status_revoke_revoked() {
+        let (_, mut ctr) = setup_token_status(&["revoke"]);
+        ctr.sbt_revoke(vec![1], false);
+    }
+
+    #[test]
+    #[should_panic(expected = "can't freeze revoked token 1")]
+    fn token_status_freeze_revoked() {
+        let (mut ctx, mut ctr) = setup_token_status(&["revoke"]);
+        next_tx(&mut ctx, &admin());
+        ctr.admin_freeze_tokens(issuer1(), vec![1], None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "can't unfreeze revoked token 1")]
+    fn token_status_unfreeze_revoked() {
+        let (mut ctx, mut ctr) = setup_token_status(&["revoke"]);
+        next_tx(&mut ctx, &admin());
+        ctr.admin_unfreeze_tokens(issuer1(), vec![1], None);
+    }
+
+    #[test]
+    #[should_panic(expected = "can't renew frozen token 1")]
+    fn token_status_renew_frozen() {
+        let (_, mut ctr) = setup_token_status(&["freeze"]);
+        ctr.sbt_renew(vec![1], 3000);
+    }
+
+    #[test]
+    #[should_panic(expected = "can't freeze frozen token 1")]
+    fn token_status_freeze_frozen() {
+        let (mut ctx, mut ctr) = setup_token_status(&["freeze"]);
+        next_tx(&mut ctx, &admin());
+        ctr.admin_freeze_tokens(issuer1(), vec![1], None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "can't prune frozen token 1")]
+    fn token_status_prune_frozen() {
+        let (_, mut ctr) = setup_token_status(&["freeze"]);
+        ctr.sbt_prune(vec![1]);
+    }
+
+    #[test]
+    #[should_panic(expected = "can't unfreeze active token 1")]
+    fn token_status_unfreeze_active() {
+        let (mut ctx, mut ctr) = setup_token_status(&[]);
+        next_tx(&mut ctx, &admin());
+        ctr.admin_unfreeze_tokens(issuer1(), vec![1], None);
+    }
+
+    #[test]
+    #[should_panic(expected = "can't prune active token 1")]
+    fn token_status_prune_active() {
+        let (_, mut ctr) = setup_token_status(&[]);
+        ctr.sbt_prune(vec![1]);
+    }
+
+    #[test]
+    #[should_panic(expected = "can't unfreeze expired token 1")]
+    fn token_status_unfreeze_expired() {
+        let (mut ctx, mut ctr) = setup_token_status(&["expire"]);
+        next_tx(&mut ctx, &admin());
+        ctr.admin_unfreeze_tokens(issuer1(), vec![1], None);
+    }
+
+    #[test]
+    #[should_panic(expected = "token 1 is burned")]
+    fn token_status_renew_burned() {
+        let (_, mut ctr) = setup_token_status(&["burn"]);
+        ctr.sbt_renew(vec![1], 3000);
+    }
+
+    #[test]
+    #[should_panic(expected = "token 1 is burned")]
+    fn token_status_burn_burned() {
+        let (_, mut ctr) = setup_token_status(&["expire", "burn"]);
+        ctr.sbt_prune(vec![1]);
+    }
+
+    #[test]
+    fn issuer_bond() {
+        let (mut ctx, mut ctr) = setup(&admin(), 0);
+        let bond = |amount, unbonding_since| {
+            Some(IssuerBond {
+                amount: U128(amount),
+                unbonding_since,
+            })
+        };
+        assert_eq!(ctr.issuer_bond(issuer1()), bond(ISSUER_BOND, None));
+        assert_eq!(ctr.treasury(), admin());
+        let remove = ctr.queue_action(AdminAction::RemoveIssuer { issuer: issuer1() }, None);
+
+        next_tx(&mut ctx, &admin());
+        ctr.admin_set_treasury(alice());
+        ctr.slash_issuer(issuer1(), U128(ISSUER_BOND / 4), Some("fake".to_owned()));
+        assert_eq!(
+            test_utils::get_logs(),
+            mk_registry_log_str(
+                "slash_issuer",
+                &format!(
+                    r#"{{"issuer":"sbt.n","amount":"{}","treasury":"alice.near","memo":"fake"}}"#,
+                    ISSUER_BOND / 4
+                )
+            )
+        );
+        assert_eq!(created_transfers(), vec![(alice(), ISSUER_BOND / 4)]);
+        let remaining = ISSUER_BOND - ISSUER_BOND / 4;
+        assert_eq!(ctr.issuer_bond(issuer1()), bond(remaining, None));
+
+        ctx.block_timestamp = 100 * MILI_SECOND;
+        next_tx(&mut ctx, &issuer1());
+        ctr.issuer_unbond();
+        assert_eq!(ctr.issuer_bond(issuer1()), bond(remaining, Some(100)));
+
+        // the issuer can be removed once the unbonding period passed
+        ctx.block_timestamp = (100 + ISSUER_UNBONDING_PERIOD_MS) * MILI_SECOND;
+        next_tx(&mut ctx, &admin());
+        ctr.execute_action(remove, None);
+
+        next_tx(&mut ctx, &issuer1());
+        ctr.issuer_withdraw_bond();
+        assert_eq!(created_transfers(), vec![(issuer1(), remaining)]);
+        assert_eq!(ctr.issuer_bond(issuer1()), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "issuer bond unbonding period has not passed yet")]
+    fn issuer_bond_remove_unbonding() {
+        let (mut ctx, mut ctr) = setup(&admin(), 0);
+        let remove = ctr.queue_action(AdminAction::RemoveIssuer { issuer: issuer1() }, None);
+        next_tx(&mut ctx, &issuer1());
+        ctr.issuer_unbond();
+        ctx.block_timestamp = DEFAULT_ACTION_TIMELOCK_MS * MILI_SECOND;
+        next_tx(&mut ctx, &admin());
+        ctr.execute_action(remove, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "issuer must lock a bond of at least")]
+    fn issuer_bond_missing() {
+        let (_, mut ctr) = setup(&admin(), 0);
+        ctr.admin_add_sbt_issuer(issuer4(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "registered issuer can't withdraw the bond")]
+    fn issuer_bond_withdraw_registered() {
+        let (_, mut ctr) = setup(&issuer1(), 0);
+        ctr.issuer_withdraw_bond();
+    }
+
+    #[test]
+    fn temporary_ban() {
+        let (mut ctx, mut ctr) = setup(&admin(), 3 * MINT_DEPOSIT);
+        let now = START / MILI_SECOND;
+        ctr.admin_ban(alice(), Some(now + 100), Some("spam".to_owned()));
+        assert!(ctr.is_banned(alice()));
+        assert_eq!(ctr.ban_expires_at(alice()), Some(now + 100));
+        let logs = test_utils::get_logs();
+        assert_eq!(logs[0..1], mk_log_str("ban", r#"["alice.near"]"#));
+        assert_eq!(
+            logs[1..],
+            mk_registry_log_str(
+                "admin_ban",
+                r#"{"account":"alice.near","until":100,"memo":"spam"}"#
+            )
+        );
+
+        // the ban expires
+        ctx.block_timestamp = (now + 100) * MILI_SECOND;
+        next_tx(&mut ctx, &issuer1());
+        assert!(!ctr.is_banned(alice()));
+        assert!(ctr.banlist.contains(&alice()));
+        // and is removed by the next transaction involving the account
+        ctr.sbt_mint(vec![(alice(), vec![mk_metadata(1, None)])]);
+        assert!(!ctr.banlist.contains(&alice()));
+        assert_eq!(ctr.ban_expires_at(alice()), None);
+        assert_eq!(
+            test_utils::get_logs()[0..1],
+            mk_registry_log_str("ban_lifted", r#"{"account":"alice.near"}"#)
+        );
+
+        // lifted by the authority
+        next_tx(&mut ctx, &admin());
+        ctr.admin_ban(alice(), Some(now + 200), None);
+        ctr.admin_lift_ban(alice(), None);
+        assert!(!ctr.is_banned(alice()));
+        // permanent ban
+        ctr.admin_ban(alice(), Some(now + 200), None);
+        ctr.admin_ban(alice(), None, None);
+        assert!(ctr.is_banned(alice()));
+        assert_eq!(ctr.ban_expires_at(alice()), None);
+    }
+
+    #[test]
+    fn banned_accounts() {
+        let (_, mut ctr) = setup(&admin(), 0);
+        assert_eq!(ctr.banned_count(), 0);
+        assert_eq!(ctr.banned_accounts(None, None), vec![]);
+        ctr.admin_ban(alice(), Some(100), None);
+        ctr.admin_ban(bob(), None, None);
+        ctr.admin_ban(carol(), None, None);
+        assert_eq!(ctr.banned_count(), 3);
+        assert_eq!(
+            ctr.banned_accounts(None, None),
+            vec![(alice(), Some(100)), (bob(), None), (carol(), None)]
+        );
+        assert_eq!(ctr.banned_accounts(Some(1), Some(1)), vec![(bob(), None)]);
+        assert_eq!(ctr.banned_accounts(Some(2), Some(5)), vec![(carol(), None)]);
+        assert_eq!(ctr.banned_accounts(Some(5), None), vec![]);
+
+        // the last account takes the position of the unbanned one
+        ctr.admin_lift_ban(alice(), None);
+        assert_eq!(ctr.banned_count(), 2);
+        assert_eq!(
+            ctr.banned_accounts(None, None),
+            vec![(carol(), None), (bob(), None)]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "account is already permanently banned")]
+    fn temporary_ban_after_soul_transfer() {
+        let (mut ctx, mut ctr) = setup(&alice(), 0);
+        ctr.sbt_soul_transfer(alice2(), None, None);
+        next_tx(&mut ctx, &admin());
+        ctr.admin_ban(alice(), Some(100), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "account is not temporarily banned")]
+    fn lift_permanent_ban() {
+        let (mut ctx, mut ctr) = setup(&alice(), 0);
+        ctr.sbt_soul_transfer(alice2(), None, None);
+        next_tx(&mut ctx, &admin());
+        ctr.admin_lift_ban(alice(), None);
+    }
+
+    #[test]
+    fn max_issuers() {
+        let (ctx, mut ctr) = setup(&admin(), 0);
+        assert_eq!(ctr.max_issuers(), DEFAULT_MAX_ISSUERS);
+        ctr.admin_set_max_issuers(5);
+        assert_eq!(ctr.max_issuers(), 5);
+        lock_bond(&ctx, &mut ctr, &issuer4());
+        assert!(ctr.admin_add_sbt_issuer(issuer4(), None));
+        assert_eq!(ctr.issuers_count(), 5);
+        // registered issuers are not affected
+        assert!(!ctr.admin_add_sbt_issuer(issuer4(), None));
+    }
+
+    #[test]
+    #[should_panic(expected = "at most 4 issuers can be registered")]
+    fn max_issuers_reached() {
+        let (ctx, mut ctr) = setup(&admin(), 0);
+        ctr.admin_set_max_issuers(4);
+        lock_bond(&ctx, &mut ctr, &issuer4());
+        ctr.admin_add_sbt_issuer(issuer4(), None);
+    }
+
+    #[test]
+    fn max_classes_per_issuer() {
+        let (mut ctx, mut ctr) = setup(&admin(), 4 * MINT_DEPOSIT);
+        assert_eq!(ctr.max_classes_per_issuer(), DEFAULT_MAX_CLASSES_PER_ISSUER);
+        ctr.admin_set_max_classes_per_issuer(2);
+        assert_eq!(ctr.max_classes_per_issuer(), 2);
+        next_tx(&mut ctx, &issuer1());
+        ctr.sbt_mint(vec![
+            (alice(), vec![mk_metadata(1, None), mk_metadata(2, None)]),
+            (bob(), vec![mk_metadata(1, None)]),
+        ]);
+        // burned classes stay counted, other issuers have their own limit
+        ctr.sbt_revoke(vec![2], true);
+        ctr.sbt_mint(vec![(carol(), vec![mk_metadata(2, None)])]);
+        assert_eq!(ctr.issuer_counters.get(&1).unwrap().classes, 2);
+        next_tx(&mut ctx, &issuer2());
+        ctr.sbt_mint(vec![(alice(), vec![mk_metadata(3, None)])]);
+    }
+
+    #[test]
+    #[should_panic(expected = "issuer can mint tokens of at most 2 classes")]
+    fn max_classes_per_issuer_reached() {
+        let (mut ctx, mut ctr) = setup(&admin(), 3 * MINT_DEPOSIT);
+        ctr.admin_set_max_classes_per_issuer(2);
+        next_tx(&mut ctx, &issuer1());
+        ctr.sbt_mint(vec![(alice(), vec![mk_metadata(1, None)])]);
+        ctr.sbt_mint(vec![(
+            bob(),
+            vec![mk_metadata(2, None), mk_metadata(3, None)],
+        )]);
+    }
+
+    #[test]
+    fn max_tokens_per_account() {
+        let (mut ctx, mut ctr) = setup(&admin(), 4 * MINT_DEPOSIT);
+        assert_eq!(ctr.max_tokens_per_account(), DEFAULT_MAX_TOKENS_PER_ACCOUNT);
+        ctr.admin_set_max_tokens_per_account(3);
+        assert_eq!(ctr.max_tokens_per_account(), 3);
+        next_tx(&mut ctx, &issuer1());
+        ctr.sbt_mint(vec![
+            (alice(), vec![mk_metadata(1, None), mk_metadata(2, None)]),
+            (bob(), vec![mk_metadata(1, None)]),
+        ]);
+        next_tx(&mut ctx, &issuer2());
+        ctr.sbt_mint(vec![(alice(), vec![mk_metadata(1, None)])]);
+        assert_eq!(ctr.account_supply(&alice()), 3);
+        assert_eq!(ctr.supply_by_account.get(&alice()), Some(3));
+
+        // the counter follows burns and soul transfers
+        ctr.sbt_revoke(vec![1], true);
+        assert_eq!(ctr.account_supply(&alice()), 2);
+        next_tx(&mut ctx, &alice());
+        assert_eq!(ctr._sbt_soul_transfer(carol(), 20), (2, true));
+        assert_eq!(ctr.account_supply(&alice()), 0);
+        assert!(ctr.supply_by_account.get(&alice()).is_none());
+        assert_eq!(ctr.account_supply(&carol()), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "alice.near can have at most 3 tokens")]
+    fn max_tokens_per_account_exceeded() {
+        let (mut ctx, mut ctr) = setup(&admin(), 4 * MINT_DEPOSIT);
+        ctr.admin_set_max_tokens_per_account(3);
+        next_tx(&mut ctx, &issuer1());
+        ctr.sbt_mint(vec![(
+            alice(),
+            vec![mk_metadata(1, None), mk_metadata(2, None)],
+        )]);
+        next_tx(&mut ctx, &issuer2());
+        ctr.sbt_mint(vec![
+            (alice(), vec![mk_metadata(1, None)]),
+            (alice(), vec![mk_metadata(2, None)]),
+        ]);
+    }
+
+    #[test]
+    fn soul_transfer_by_issuer() {
+        let (mut ctx, mut ctr) = setup(&admin(), 10 * MINT_DEPOSIT);
+        ctr.admin_set_issuer_capabilities(issuer2(), CAP_DEFAULT | CAP_SOUL_TRANSFER, None);
+        assert_eq!(
+            ctr.issuer_capabilities(issuer2()),
+            CAP_DEFAULT | CAP_SOUL_TRANSFER
+        );
+        assert_eq!(ctr.issuer_capabilities(issuer1()), CAP_DEFAULT);
+        let keys = setup_soul_transfer_tokens(&mut ctx, &mut ctr);
+
+        next_tx(&mut ctx, &issuer2());
+        assert_eq!(
+            ctr._sbt_soul_transfer_by_issuer(alice(), alice2(), 5),
+            (5, false)
+        );
+        assert!(ctr.is_banned(alice()));
+        assert_eq!(
+            test_utils::get_logs()[0],
+            format!(
+                "soul transfer {} -> {} executed by issuer {}",
+                alice(),
+                alice2(),
+                issuer2()
+            )
+        );
+        next_tx(&mut ctx, &issuer2());
+        assert_eq!(
+            ctr._sbt_soul_transfer_by_issuer(alice(), alice2(), 5),
+            (3, true)
+        );
+        assert!(!ctr.issuer_soul_tx.contains_key(&alice()));
+        assert_soul_transferred(&ctr, &keys);
+    }
+
+    #[test]
+    #[should_panic(expected = "issuer doesn't have the required capability")]
+    fn soul_transfer_by_issuer_no_capability() {
+        let (_, mut ctr) = setup(&issuer1(), 10 * MINT_DEPOSIT);
+        ctr.sbt_mint(vec![(alice(), vec![mk_metadata(1, Some(START + 10))])]);
+        ctr.sbt_soul_transfer_by_issuer(alice(), alice2());
+    }
+
+    #[test]
+    #[should_panic(expected = "soul transfer initiated by an issuer is in progress")]
+    fn soul_transfer_by_issuer_owner_cant_continue() {
+        let (mut ctx, mut ctr) = setup(&admin(), 10 * MINT_DEPOSIT);
+        ctr.admin_set_issuer_capabilities(issuer2(), CAP_DEFAULT | CAP_SOUL_TRANSFER, None);
+        setup_soul_transfer_tokens(&mut ctx, &mut ctr);
+        next_tx(&mut ctx, &issuer2());
+        ctr._sbt_soul_transfer_by_issuer(alice(), alice2(), 2);
+        next_tx(&mut ctx, &alice());
+        ctr.sbt_soul_transfer(bob(), None, None);
+    }
+
+    #[test]
+    fn account_summary_last_activity() {
+        let (mut ctx, mut ctr) = setup(&fractal_mainnet(), 2 * MINT_DEPOSIT);
+        let ms = 1_000_000;
+        assert_eq!(
+            ctr.account_summary(alice()),
+            AccountSummary {
+                banned: false,
+                human: false,
+                soul_transfer_in_progress: false,
+                last_activity_ms: None,
+                human_expires_in: None,
+            }
+        );
+
+        ctx.block_timestamp = 5 * ms;
+        next_tx(&mut ctx, &fractal_mainnet());
+        ctr.sbt_mint(vec![(alice(), vec![mk_metadata(1, None)])]);
+        assert_eq!(
+            ctr.account_summary(alice()),
+            AccountSummary {
+                banned: false,
+                human: true,
+                soul_transfer_in_progress: false,
+                last_activity_ms: Some(5),
+                human_expires_in: None,
+            }
+        );
+
+        ctx.block_timestamp = 8 * ms;
+        next_tx(&mut ctx, &fractal_mainnet());
+        ctr.sbt_renew(vec![1], 1000);
+        assert_eq!(ctr.account_summary(alice()).last_activity_ms, Some(8));
+        assert_eq!(ctr.account_summary(bob()).last_activity_ms, None);
+
+        ctx.block_timestamp = 12 * ms;
+        next_tx(&mut ctx, &alice());
+        ctr.sbt_soul_transfer(bob(), None, None);
+        assert_eq!(
+            ctr.account_summary(alice()),
+            AccountSummary {
+                banned: true,
+                human: false,
+                soul_transfer_in_progress: false,
+                last_activity_ms: Some(12),
+                human_expires_in: None,
+            }
+        );
+        assert_eq!(ctr.account_summary(bob()).last_activity_ms, Some(12));
+        assert!(ctr.account_summary(bob()).human);
+    }
+
+    #[test]
+    fn sbt_expires_in() {
+        let (mut ctx, mut ctr) = setup(&fractal_mainnet(), 2 * MINT_DEPOSIT);
+        ctx.block_timestamp = 1000 * MILI_SECOND;
+        next_tx(&mut ctx, &fractal_mainnet());
+        ctr.sbt_mint(vec![(
+            alice(),
+            vec![mk_metadata(1, Some(11_500)), mk_metadata(2, None)],
+        )]);
+        // 10.5s rounded down
+        assert_eq!(ctr.sbt_expires_in(fractal_mainnet(), 1), Some(10));
+        assert_eq!(ctr.account_summary(alice()).human_expires_in, Some(10));
+        assert_eq!(ctr.sbt_expires_in(fractal_mainnet(), 2), None);
+        assert_eq!(ctr.sbt_expires_in(fractal_mainnet(), 3), None);
+
+        next_tx(&mut ctx, &admin());
+        ctr.admin_freeze_tokens(fractal_mainnet(), vec![2], None, None);
+        assert_eq!(ctr.sbt_expires_in(fractal_mainnet(), 2), Some(0));
+
+        ctx.block_timestamp = 12_000 * MILI_SECOND;
+        next_tx(&mut ctx, &fractal_mainnet());
+        assert_eq!(ctr.sbt_expires_in(fractal_mainnet(), 1), Some(0));
+        let summary = ctr.account_summary(alice());
+        assert!(!summary.human);
+        assert_eq!(summary.human_expires_in, None);
+    }
+
+    #[test]
+    fn issuer_capabilities_renew_only() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 2 * MINT_DEPOSIT);
+        let m1 = mk_metadata(1, Some(START + 10));
+        ctr.sbt_mint(vec![(alice(), vec![m1.clone()])]);
+
+        next_tx(&mut ctx, &admin());
+        ctr.admin_set_issuer_capabilities(issuer1(), CAP_RENEW, None);
+        assert_eq!(ctr.issuer_capabilities(issuer1()), CAP_RENEW);
+
+        next_tx(&mut ctx, &issuer1());
+        ctr.sbt_renew(vec![1], START + 100);
+        assert_eq!(
+            ctr.sbt_renew_batch(vec![(1, START + 200)]),
+            vec![RenewResult::Renewed]
+        );
+        assert_eq!(
+            ctr.sbt(issuer1(), 1),
+            Some(mk_token(1, alice(), mk_metadata(1, Some(START + 200))))
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "issuer doesn't have the required capability")]
+    fn issuer_capabilities_no_mint() {
+        let (mut ctx, mut ctr) = setup(&admin(), 2 * MINT_DEPOSIT);
+        ctr.admin_set_issuer_capabilities(issuer1(), CAP_RENEW | CAP_RECOVER, None);
+        next_tx(&mut ctx, &issuer1());
+        ctr.sbt_mint(vec![(alice(), vec![mk_metadata(1, None)])]);
+    }
+
+    #[test]
+    #[should_panic(expected = "issuer doesn't have the required capability")]
+    fn issuer_capabilities_no_renew() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 2 * MINT_DEPOSIT);
+        ctr.sbt_mint(vec![(alice(), vec![mk_metadata(1, None)])]);
+        next_tx(&mut ctx, &admin());
+        ctr.admin_set_issuer_capabilities(issuer1(), CAP_MINT, None);
+        next_tx(&mut ctx, &issuer1());
+        ctr.sbt_renew_batch(vec![(1, START + 100)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "issuer doesn't have the required capability")]
+    fn issuer_capabilities_no_recover() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 2 * MINT_DEPOSIT);
+        ctr.sbt_mint(vec![(alice(), vec![mk_metadata(1, None)])]);
+        next_tx(&mut ctx, &admin());
+        ctr.admin_set_issuer_capabilities(issuer1(), CAP_MINT | CAP_RENEW, None);
+        next_tx(&mut ctx, &issuer1());
+        ctr.sbt_recover(alice(), bob());
+    }
+
+    #[test]
+    fn sanity_check() {
+        let (_, mut ctr) = setup(&issuer1(), 2 * MINT_DEPOSIT);
+        ctr.sbt_mint(vec![(alice(), vec![mk_metadata(1, None)])]);
+        ctr.sanity_check();
+    }
+
+    #[test]
+    fn migrate_old_state() {
+        let mut ctx = VMContextBuilder::new()
+            .predecessor_account_id(admin())
+            .block_timestamp(START)
+            .is_view(false)
+            .prepaid_gas(max_gas())
+            .build();
+        testing_env!(ctx.clone());
+        // state and records written by the previous release
+        let mut old = OldState {
+            authority: admin(),
+            sbt_issuers: UnorderedMap::new(StorageKey::SbtIssuers),
+            issuer_id_map: LookupMap::new(StorageKey::SbtIssuersRev),
+            banlist: UnorderedSet::new(StorageKey::Banlist),
+            ongoing_soul_tx: LookupMap::new(StorageKey::OngoingSoultTx),
+            supply_by_owner: LookupMap::new(StorageKey::SupplyByOwner),
+            supply_by_class: LookupMap::new(StorageKey::SupplyByClass),
+            supply_by_issuer: LookupMap::new(StorageKey::SupplyByIssuer),
+            balances: near_sdk::collections::TreeMap::new(StorageKey::Balances),
+            issuer_tokens: LookupMap::new(StorageKey::IssuerTokens),
+            next_token_ids: LookupMap::new(StorageKey::NextTokenId),
+            next_issuer_id: 2,
+            iah_classes: (fractal_mainnet(), vec![1]),
+        };
+        let m1 = mk_metadata(1, None);
+        old.sbt_issuers.insert(&issuer1(), &1);
+        old.issuer_id_map.insert(&1, &issuer1());
+        old.banlist.insert(&carol());
+        for (token, owner) in [(1, alice()), (2, bob())] {
+            old.supply_by_owner.insert(&(owner.clone(), 1), &1);
+            old.balances
+                .insert(&balance_key(owner.clone(), 1, 1), &token);
+            old.issuer_tokens.insert(
+                &IssuerTokenId {
+                    issuer_id: 1,
+                    token,
+                },
+                &TokenData {
+                    owner,
+                    metadata: m1.clone().into(),
+                },
+            );
+        }
+        old.supply_by_class.insert(&(1, 1), &2);
+        old.supply_by_issuer.insert(&1, &2);
+        old.next_token_ids.insert(&1, &2);
+        env::state_write(&old);
+
+        let mut ctr = Contract::migrate();
+        assert_eq!(ctr.sbt_contracts(), vec![issuer1()]);
+        assert_eq!(ctr.next_issuer_id, 2);
+        assert!(ctr.is_banned(carol()));
+        assert_eq!(
+            ctr.sbt(issuer1(), 1),
+            Some(mk_token(1, alice(), m1.clone()))
+        );
+        assert_eq!(
+            ctr.sbt_tokens_by_owner(alice(), None, None, None, None),
+            vec![(issuer1(), vec![mk_owned_token(1, m1.clone())])]
+        );
+        assert_eq!(ctr.sbt_supply_by_owner(alice(), issuer1(), None), 1);
+        assert_eq!(ctr.authority, admin());
+        assert!(!ctr.migration_progress().done);
+        // tokens which are not backfilled yet don't update the valid supply
+        ctr.migration.issuer_id = 1;
+        ctr.migration.token = 2;
+        assert!(ctr.is_counted(1, 1));
+        assert!(!ctr.is_counted(1, 2));
+        assert!(ctr.is_counted(1, 3));
+        ctr.migration = MigrationProgress::default();
+        next_tx(&mut ctx, &issuer1());
+        ctr.sbt_revoke(vec![2], false);
+        assert!(ctr.valid_supply.get(&1).is_none());
+        // accounts which are not counted yet sum their supply by issuer
+        assert!(ctr.supply_by_account.get(&alice()).is_none());
+        assert_eq!(ctr.account_supply(&alice()), 1);
+
+        // the legacy balance and token records are converted, and the valid tokens counted,
+        // by migrate_chunk
+        let p = ctr.migrate_chunk(10);
+        assert!(p.done);
+        // two balances and the token 1 (the token 2 record was rewritten by the revoke)
+        assert_eq!(p.migrated, 3);
+        assert!(ctr.balances.legacy.is_empty());
+        assert_eq!(ctr.sbt(issuer1(), 1), Some(mk_token(1, alice(), m1)));
+        assert_eq!(ctr.sbt_supply(issuer1(), None), 2);
+        assert_eq!(ctr.sbt_supply(issuer1(), Some(true)), 1);
+        assert_eq!(ctr.check_invariants(issuer1(), None, None), vec![]);
+        assert_eq!(ctr.supply_by_account.get(&alice()), Some(1));
+        assert_eq!(ctr.supply_by_account.get(&bob()), Some(1));
+        ctr.sanity_check();
+    }
+
+    #[test]
+    #[should_panic(expected = "valid supply backfill in progress, see migrate_chunk")]
+    fn sbt_supply_valid_backfill_in_progress() {
+        let (_, mut ctr) = setup(&issuer1(), 0);
+        ctr.migration = MigrationProgress::default();
+        ctr.sbt_supply(issuer1(), Some(true));
+    }
+
+    #[test]
+    #[should_panic(expected = "token ID counter of sbt.n is behind its tokens")]
+    fn sanity_check_token_counter() {
+        let (_, mut ctr) = setup(&issuer1(), 2 * MINT_DEPOSIT);
+        ctr.sbt_mint(vec![(alice(), vec![mk_metadata(1, None)])]);
+        ctr.next_token_ids.insert(&1, &0);
+        ctr.sanity_check();
+    }
+
+    #[test]
+    #[should_panic(expected = "storage prefixes of balances and stamps overlap")]
+    fn sanity_check_storage_prefixes() {
+        let (_, mut ctr) = setup(&issuer1(), 0);
+        ctr.stamps = LookupMap::new(StorageKey::BalanceShards);
+        ctr.sanity_check();
+    }
+
+    #[test]
+    fn check_invariants() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 4 * MINT_DEPOSIT);
+        ctr.sbt_mint(vec![
+            (alice(), vec![mk_metadata(1, None), mk_metadata(2, None)]),
+            (bob(), vec![mk_metadata(1, None)]),
+        ]);
+        next_tx(&mut ctx, &alice());
+        ctr.sbt_burn(issuer1(), vec![2], None);
+        assert_eq!(ctr.check_invariants(issuer1(), None, None), vec![]);
+        assert_eq!(ctr.check_invariants(issuer1(), Some(3), Some(1)), vec![]);
+        assert_eq!(ctr.check_invariants(issuer2(), None, None), vec![]);
+
+        // corrupt the state
+        ctr.balances.remove(&balance_key(bob(), 1, 1));
+        ctr.supply_by_class.insert(&(1, 1), &1);
+        assert_eq!(
+            ctr.check_invariants(issuer1(), None, None),
+            vec![
+                Discrepancy {
+                    token: Some(3),
+                    description: "balance of bob.near (class 1) is None, expected the token"
+                        .to_owned()
+                },
+                Discrepancy {
+                    token: None,
+                    description: "supply of bob.near is 1, found 0 balances".to_owned()
+                },
+                Discrepancy {
+                    token: None,
+                    description: "supply of class 1 is 1, found 2 tokens".to_owned()
+                },
+            ]
+        );
+        // partial range: only tokens in the range are checked
+        assert_eq!(ctr.check_invariants(issuer1(), Some(1), Some(1)), vec![]);
+
+        ctr.balances.insert(&balance_key(alice(), 1, 3), &3);
+        ctr.supply_by_owner.insert(&(alice(), 1), &2);
+        assert_eq!(
+            ctr.check_invariants(issuer1(), Some(1), Some(1)),
+            vec![Discrepancy {
+                token: Some(3),
+                description:
+                    "balance of alice.near (class 3) points to a token of bob.near (class 1)"
+                        .to_owned()
+            }]
+        );
+    }
+
+    #[test]
+    fn admin_repair_state() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 4 * MINT_DEPOSIT);
+        ctr.sbt_mint(vec![
+            (alice(), vec![mk_metadata(1, None)]),
+            (bob(), vec![mk_metadata(1, None)]),
+        ]);
+        // corrupt the state: lost balance and orphan token
+        ctr.balances.remove(&balance_key(alice(), 1, 1));
+        ctr.balances.remove(&balance_key(bob(), 1, 1));
+        ctr.supply_by_owner.insert(&(bob(), 1), &0);
+        assert_eq!(ctr.check_invariants(issuer1(), None, None).len(), 3);
+
+        next_tx(&mut ctx, &admin());
+        ctr.admin_fix_balance(alice(), issuer1(), 1, Some(1));
+        assert_eq!(
+            test_utils::get_logs(),
+            vec![format!(
+                r#"EVENT_JSON:{{"standard":"i_am_human_registry","version":"1.0.0","event":"fix_balance","data":{{"owner":"{}","issuer":"{}","class":1,"token":1}}}}"#,
+                alice(),
+                issuer1()
+            )]
+        );
+        next_tx(&mut ctx, &admin());
+        ctr.admin_remove_orphan_token(issuer1(), 2);
+        assert_eq!(
+            test_utils::get_logs(),
+            vec![format!(
+                r#"EVENT_JSON:{{"standard":"i_am_human_registry","version":"1.0.0","event":"remove_orphan_token","data":{{"issuer":"{}","token":2}}}}"#,
+                issuer1()
+            )]
+        );
+        assert_eq!(ctr.check_invariants(issuer1(), None, None), vec![]);
+        assert_eq!(ctr.sbt_supply(issuer1(), None), 1);
+        assert_eq!(ctr.sbt(issuer1(), 2), None);
+        // admin events are included in the digest (4 add_issuer events are emitted in setup)
+        assert_eq!(ctr.event_digest().count, 7);
+
+        next_tx(&mut ctx, &admin());
+        ctr.admin_fix_balance(alice(), issuer1(), 1, None);
+        assert_eq!(ctr.sbt_supply_by_owner(alice(), issuer1(), Some(1)), 0);
+    }
+
+    #[test]
+    fn admin_remove_orphan_token_supply() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 4 * MINT_DEPOSIT);
+        ctr.sbt_mint(vec![
+            (alice(), vec![mk_metadata(1, Some(START + 10))]),
+            (bob(), vec![mk_metadata(1, Some(START + 10))]),
+        ]);
+        // corrupt the state: the bob balance is lost, so token 2 is an orphan
+        ctr.balances.remove(&balance_key(bob(), 1, 1));
+        assert_eq!(ctr.sbt_supply(issuer1(), Some(true)), 2);
+
+        next_tx(&mut ctx, &admin());
+        ctr.admin_remove_orphan_token(issuer1(), 2);
+        assert_eq!(ctr.sbt_supply_by_owner(bob(), issuer1(), None), 0);
+        assert_eq!(ctr.sbt_supply_by_owner(alice(), issuer1(), None), 1);
+        assert_eq!(ctr.sbt_supply_by_class(issuer1(), 1), 1);
+        assert_eq!(ctr.sbt_supply(issuer1(), None), 1);
+        assert_eq!(ctr.sbt_supply(issuer1(), Some(true)), 1);
+        assert_eq!(
+            ctr.expirations_on(START / DAY_MS, None, None),
+            vec![ExpiringToken {
+                owner: alice(),
+                issuer: issuer1(),
+                token: 1,
+                expires_at: START + 10,
+            }]
+        );
+        assert_eq!(ctr.check_invariants(issuer1(), None, None), vec![]);
+    }
+
+    #[test]
+    #[should_panic(expected = "token is referenced by the owner balance")]
+    fn admin_remove_orphan_token_not_orphan() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 2 * MINT_DEPOSIT);
+        ctr.sbt_mint(vec![(alice(), vec![mk_metadata(1, None)])]);
+        next_tx(&mut ctx, &admin());
+        ctr.admin_remove_orphan_token(issuer1(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "token owner or class doesn't match")]
+    fn admin_fix_balance_wrong_owner() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 2 * MINT_DEPOSIT);
+        ctr.sbt_mint(vec![(alice(), vec![mk_metadata(1, None)])]);
+        next_tx(&mut ctx, &admin());
+        ctr.admin_fix_balance(bob(), issuer1(), 1, Some(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "not an admin")]
+    fn admin_fix_balance_not_admin() {
+        let (_, mut ctr) = setup(&issuer1(), 2 * MINT_DEPOSIT);
+        ctr.sbt_mint(vec![(alice(), vec![mk_metadata(1, None)])]);
+        ctr.admin_fix_balance(alice(), issuer1(), 1, None);
+    }
+
+    #[test]
+    fn expirations_on() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 4 * MINT_DEPOSIT);
+        let day1 = 100 * DAY_MS;
+        let day2 = 101 * DAY_MS;
+        ctr.sbt_mint(vec![
+            (
+                alice(),
+                vec![mk_metadata(1, Some(day1 + 5)), mk_metadata(2, None)],
+            ),
+            (bob(), vec![mk_metadata(1, Some(day1))]),
+            (carol(), vec![mk_metadata(1, Some(day2))]),
+        ]);
+        next_tx(&mut ctx, &issuer2());
+        ctr.sbt_mint(vec![(bob(), vec![mk_metadata(1, Some(day1 + 1))])]);
+
+        let mk_exp = |owner, issuer, token, expires_at| ExpiringToken {
+            owner,
+            issuer,
+            token,
+            expires_at,
+        };
+        assert_eq!(
+            ctr.expirations_on(100, None, None),
+            vec![
+                mk_exp(alice(), issuer1(), 1, day1 + 5),
+                mk_exp(bob(), issuer1(), 3, day1),
+                mk_exp(bob(), issuer2(), 1, day1 + 1),
+            ]
+        );
+        assert_eq!(
+            ctr.expirations_on(100, Some(1), Some(1)),
+            vec![mk_exp(bob(), issuer1(), 3, day1)]
+        );
+        assert_eq!(
+            ctr.expirations_on(101, None, None),
+            vec![mk_exp(carol(), issuer1(), 4, day2)]
+        );
+        assert_eq!(ctr.expirations_on(99, None, None), vec![]);
+
+        // renew moves the token to another bucket, burn and revoke remove it.
+        next_tx(&mut ctx, &issuer1());
+        ctr.sbt_renew_batch(vec![(1, day2 + 10)]);
+        ctr.sbt_revoke(vec![4], false);
+        next_tx(&mut ctx, &bob());
+        ctr.sbt_burn(issuer1(), vec![3], None);
+        assert_eq!(
+            ctr.expirations_on(100, None, None),
+            vec![mk_exp(bob(), issuer2(), 1, day1 + 1)]
+        );
+        assert_eq!(
+            ctr.expirations_on(101, None, None),
+            vec![mk_exp(alice(), issuer1(), 1, day2 + 10)]
+        );
+
+        // soul transfer changes the owner
+        next_tx(&mut ctx, &alice());
+        ctr.sbt_soul_transfer(alice2(), None, None);
+        assert_eq!(
+            ctr.expirations_on(101, None, None),
+            vec![mk_exp(alice2(), issuer1(), 1, day2 + 10)]
+        );
+    }
+
+    #[test]
+    fn expirations_on_unlink() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 5 * MINT_DEPOSIT);
+        let day = 100;
+        let exp = Some(day * DAY_MS);
+        ctr.sbt_mint(vec![
+            (alice(), vec![mk_metadata(1, exp)]),
+            (bob(), vec![mk_metadata(1, exp)]),
+            (carol(), vec![mk_metadata(1, exp)]),
+            (dan(), vec![mk_metadata(1, exp)]),
+        ]);
+        let tokens = |ctr: &Contract| -> Vec<TokenId> {
+            ctr.expirations_on(day, None, None)
+                .into_iter()
+                .map(|t| t.token)
+                .collect()
+        };
+        assert_eq!(tokens(&ctr), vec![1, 2, 3, 4]);
+        assert_eq!(ctr.expirations.get(&(day, 1)).unwrap().len, 4);
+
+        // middle, first and last token
+        ctr.sbt_revoke(vec![2], false);
+        assert_eq!(tokens(&ctr), vec![1, 3, 4]);
+        ctr.sbt_revoke(vec![1], false);
+        assert_eq!(tokens(&ctr), vec![3, 4]);
+        ctr.sbt_revoke(vec![4], false);
+        assert_eq!(tokens(&ctr), vec![3]);
+
+        // new tokens are appended
+        next_tx(&mut ctx, &issuer1());
+        ctr.sbt_mint(vec![(alice2(), vec![mk_metadata(1, exp)])]);
+        assert_eq!(tokens(&ctr), vec![3, 5]);
+        assert_eq!(ctr.expirations_on(day, Some(1), None)[0].token, 5);
+
+        ctr.sbt_revoke(vec![3, 5], false);
+        assert!(tokens(&ctr).is_empty());
+        assert!(ctr.expirations.get(&(day, 1)).is_none());
+        for token in 1..=5 {
+            assert!(ctr.expiring_tokens.get(&(day, 1, token)).is_none());
+        }
+    }
+
+    #[test]
+    fn upgrade_staged_code() {
+        let (mut ctx, mut ctr) = setup(&alice(), 0);
+        let code = vec![1, 2, 3];
+        ctx.input = code.clone();
+        ctx.attached_deposit = MINT_DEPOSIT;
+        testing_env!(ctx.clone());
+        let hash = ctr.upload_code();
+        assert_eq!(hash, env::sha256_array(&code).into());
+        assert_eq!(ctr.staged_code_info(), None);
+
+        ctx.input = vec![];
+        next_tx(&mut ctx, &admin());
+        ctr.admin_stage_code(hash);
+        let deployable_at = START / 1_000_000 + UPGRADE_TIMELOCK_MS;
+        assert_eq!(
+            ctr.staged_code_info(),
+            Some(StagedCodeInfo {
+                hash,
+                staged_at: START / 1_000_000,
+                deployable_at,
+            })
+        );
+
+        ctx.block_timestamp = deployable_at * 1_000_000;
+        next_tx(&mut ctx, &admin());
+        ctr.admin_deploy_staged_code();
+        assert_eq!(ctr.staged_code_info(), None);
+        assert!(!ctr.code_blobs.contains_key(&hash.into()));
+        assert_eq!(
+            created_calls(),
+            vec![(ctx.current_account_id, "migrate".to_owned(), String::new())]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "upgrade timelock has not passed yet")]
+    fn upgrade_staged_code_timelock() {
+        let (mut ctx, mut ctr) = setup(&admin(), MINT_DEPOSIT);
+        ctx.input = vec![1, 2, 3];
+        testing_env!(ctx.clone());
+        let hash = ctr.upload_code();
+        ctr.admin_stage_code(hash);
+        ctx.block_timestamp += (UPGRADE_TIMELOCK_MS - 1) * 1_000_000;
+        testing_env!(ctx);
+        ctr.admin_deploy_staged_code();
+    }
+
+    #[test]
+    #[should_panic(expected = "no staged code")]
+    fn upgrade_cancel_staged_code() {
+        let (mut ctx, mut ctr) = setup(&admin(), MINT_DEPOSIT);
+        ctx.input = vec![1, 2, 3];
+        testing_env!(ctx.clone());
+        let hash = ctr.upload_code();
+        ctr.admin_stage_code(hash);
+        ctr.admin_cancel_staged_code();
+        assert_eq!(ctr.staged_code_info(), None);
+        ctr.admin_deploy_staged_code();
+    }
+
+    #[test]
+    #[should_panic(expected = "not an admin")]
+    fn upgrade_stage_code_not_admin() {
+        let (mut ctx, mut ctr) = setup(&alice(), MINT_DEPOSIT);
+        ctx.input = vec![1, 2, 3];
+        testing_env!(ctx.clone());
+        let hash = ctr.upload_code();
+        ctr.admin_stage_code(hash);
+    }
+
+    #[test]
+    fn timelock_actions() {
+        let (mut ctx, mut ctr) = setup(&admin(), 2 * MINT_DEPOSIT);
+        let ms = 1_000_000;
+        let remove = AdminAction::RemoveIssuer { issuer: issuer2() };
+        let change = AdminAction::ChangeAdmin { new_admin: alice() };
+        assert_eq!(ctr.queue_action(remove.clone(), None), 1);
+        assert_eq!(ctr.queue_action(AdminAction::Unpause, None), 2);
+        ctx.block_timestamp = 100 * ms;
+        next_tx(&mut ctx, &admin());
+        assert_eq!(ctr.queue_action(change.clone(), None), 3);
+        ctr.cancel_action(2, None);
+        let timelock = DEFAULT_ACTION_TIMELOCK_MS;
+        assert_eq!(ctr.action_timelock(), timelock);
+        assert_eq!(
+            ctr.pending_actions(),
+            vec![
+                (
+                    1,
+                    PendingAction {
+                        action: remove,
+                        queued_at: 0,
+                        executable_at: timelock
+                    }
+                ),
+                (
+                    3,
+                    PendingAction {
+                        action: change,
+                        queued_at: 100,
+                        executable_at: 100 + timelock
+                    }
+                ),
+            ]
+        );
+
+        ctx.block_timestamp = timelock * ms;
+        next_tx(&mut ctx, &admin());
+        // the issuer can be removed only without a locked bond (here: fully slashed)
+        ctr.slash_issuer(issuer2(), U128(ISSUER_BOND), None);
+        ctr.execute_action(1, None);
+        assert_eq!(ctr.issuer_id(issuer2()), None);
+        assert_eq!(ctr.issuer_by_id(2), Some(issuer2()));
+        assert_eq!(ctr.issuers_count(), 3);
+
+        ctx.block_timestamp = (100 + timelock) * ms;
+        next_tx(&mut ctx, &admin());
+        ctr.execute_action(3, None);
+        assert_eq!(ctr.authority, alice());
+        assert_eq!(ctr.pending_actions(), vec![]);
+    }
+
+    #[test]
+    #[should_panic(expected = "action timelock has not passed yet")]
+    fn timelock_action_too_early() {
+        let (mut ctx, mut ctr) = setup(&admin(), 0);
+        ctr.queue_action(AdminAction::ChangeAdmin { new_admin: alice() }, None);
+        ctx.block_timestamp = (DEFAULT_ACTION_TIMELOCK_MS - 1) * 1_000_000;
+        next_tx(&mut ctx, &admin());
+        ctr.execute_action(1, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "not an admin")]
+    fn timelock_queue_not_admin() {
+        let (_, mut ctr) = setup(&alice(), 0);
+        ctr.queue_action(AdminAction::ChangeAdmin { new_admin: alice() }, None);
+    }
+
+    #[test]
+    fn pause_and_timelocked_unpause() {
+        let (mut ctx, mut ctr) = setup(&admin(), 2 * MINT_DEPOSIT);
+        ctr.queue_action(AdminAction::SetActionTimelock { timelock_ms: 0 }, None);
+        ctx.block_timestamp = DEFAULT_ACTION_TIMELOCK_MS * 1_000_000;
+        next_tx(&mut ctx, &admin());
+        ctr.execute_action(1, None);
+        assert_eq!(ctr.action_timelock(), 0);
+
+        ctr.admin_pause(None);
+        assert!(ctr.is_paused());
+        ctr.queue_action(AdminAction::Unpause, None);
+        ctr.execute_action(2, None);
+        assert!(!ctr.is_paused());
+        next_tx(&mut ctx, &issuer1());
+        ctr.sbt_mint(vec![(alice(), vec![mk_metadata(1, None)])]);
+    }
+
+    #[test]
+    fn admin_actions_memo() {
+        let (mut ctx, mut ctr) = setup(&admin(), 0);
+        ctr.admin_pause(Some("incident #12".to_owned()));
+        assert_eq!(
+            test_utils::get_logs(),
+            mk_registry_log_str("pause", r#"{"memo":"incident #12"}"#)
+        );
+
+        next_tx(&mut ctx, &admin());
+        ctr.queue_action(AdminAction::Unpause, Some("fixed".to_owned()));
+        ctr.cancel_action(1, None);
+        let logs = test_utils::get_logs();
+        assert_eq!(
+            logs[0..1],
+            mk_registry_log_str(
+                "queue_action",
+                r#"{"id":1,"action":"unpause","memo":"fixed"}"#
+            )
+        );
+        assert_eq!(
+            logs[1..],
+            mk_registry_log_str("cancel_action", r#"{"id":1}"#)
+        );
+
+        next_tx(&mut ctx, &admin());
+        assert!(!ctr.admin_add_sbt_issuer(issuer1(), Some("again".to_owned())));
+        assert!(test_utils::get_logs().is_empty());
+        ctr.admin_set_issuer_capabilities(issuer1(), CAP_RENEW, Some("legacy".to_owned()));
+        assert_eq!(
+            test_utils::get_logs(),
+            mk_registry_log_str(
+                "set_issuer_capabilities",
+                r#"{"issuer":"sbt.n","capabilities":4,"memo":"legacy"}"#
+            )
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "memo must be at most 256 bytes")]
+    fn admin_memo_too_long() {
+        let (_, mut ctr) = setup(&admin(), 0);
+        ctr.admin_pause(Some("x".repeat(MAX_MEMO_LEN + 1)));
+    }
+
+    #[test]
+    #[should_panic(expected = "registry is paused")]
+    fn pause_blocks_mint() {
+        let (mut ctx, mut ctr) = setup(&admin(), 2 * MINT_DEPOSIT);
+        ctr.admin_pause(None);
+        next_tx(&mut ctx, &issuer1());
+        ctr.sbt_mint(vec![(alice(), vec![mk_metadata(1, None)])]);
+    }
+
+    #[test]
+    #[should_panic(expected = "registry is paused")]
+    fn pause_blocks_soul_transfer() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 2 * MINT_DEPOSIT);
+        ctr.sbt_mint(vec![(alice(), vec![mk_metadata(1, None)])]);
+        next_tx(&mut ctx, &admin());
+        ctr.admin_pause(None);
+        next_tx(&mut ctx, &alice());
+        ctr.sbt_soul_transfer(bob(), None, None);
+    }
+
+    #[test]
+    fn guardian_pause() {
+        let (mut ctx, mut ctr) = setup(&admin(), 0);
+        assert_eq!(ctr.guardian(), None);
+        ctr.propose_guardian(dan(), None);
+        assert_eq!(
+            test_utils::get_logs(),
+            mk_registry_log_str(
+                "propose_guardian",
+                &format!(r#"{{"guardian":"{}"}}"#, dan())
+            )
+        );
+        assert_eq!(ctr.guardian(), None);
+        assert_eq!(ctr.proposed_guardian(), Some(dan()));
+        next_tx(&mut ctx, &dan());
+        ctr.accept_guardian();
+        assert_eq!(
+            test_utils::get_logs(),
+            mk_registry_log_str("accept_guardian", &format!(r#"{{"guardian":"{}"}}"#, dan()))
+        );
+        assert_eq!(ctr.guardian(), Some(dan()));
+        assert_eq!(ctr.proposed_guardian(), None);
+
+        next_tx(&mut ctx, &dan());
+        ctr.admin_pause(Some("incident".to_owned()));
+        assert!(ctr.is_paused());
+        assert_eq!(
+            test_utils::get_logs(),
+            mk_registry_log_str("pause", r#"{"memo":"incident"}"#)
+        );
+    }
+
+    #[test]
+    fn admin_log() {
+        // setup adds 4 issuers
+        let (mut ctx, mut ctr) = setup(&admin(), 0);
+        let log = ctr.admin_log(None, None);
+        assert_eq!(log.len(), 4);
+        assert_eq!(log[3].method, "admin_add_sbt_issuer");
+
+        let args = br#"{"memo":"incident 1"}"#.to_vec();
+        ctx.input = args.clone();
+        next_tx(&mut ctx, &admin());
+        ctr.admin_pause(Some("incident 1".to_owned()));
+        assert_eq!(
+            ctr.admin_log(Some(4), Some(10)),
+            vec![AdminLogEntry {
+                seq: 4,
+                method: "admin_pause".to_owned(),
+                args_hash: env::sha256(&args).into(),
+                timestamp: START / 1_000_000,
+                caller: admin(),
+                memo: Some("incident 1".to_owned()),
+            }]
+        );
+        assert_eq!(ctr.admin_log(Some(5), None), vec![]);
+
+        // the log keeps the last ADMIN_LOG_SIZE actions
+        ctx.input = vec![];
+        for _ in 0..ADMIN_LOG_SIZE {
+            next_tx(&mut ctx, &admin());
+            ctr.admin_set_max_issuers(10);
+        }
+        let log = ctr.admin_log(None, None);
+        assert_eq!(log.len(), ADMIN_LOG_SIZE as usize);
+        assert_eq!(log[0].seq, 5);
+        assert_eq!(log[99].seq, ADMIN_LOG_SIZE + 4);
+        assert_eq!(log[99].method, "admin_set_max_issuers");
+        let log = ctr.admin_log(Some(0), Some(2));
+        assert_eq!(log.iter().map(|e| e.seq).collect::<Vec<_>>(), vec![5, 6]);
+    }
+
+    #[test]
+    #[should_panic(expected = "not the proposed guardian")]
+    fn accept_guardian_not_proposed() {
+        let (mut ctx, mut ctr) = setup(&admin(), 0);
+        ctr.propose_guardian(dan(), None);
+        next_tx(&mut ctx, &carol());
+        ctr.accept_guardian();
+    }
+
+    #[test]
+    #[should_panic(expected = "not an admin")]
+    fn propose_guardian_not_admin() {
+        let (mut ctx, mut ctr) = setup(&admin(), 0);
+        next_tx(&mut ctx, &dan());
+        ctr.propose_guardian(dan(), None);
+    }
+
+    #[test]
+    fn remove_guardian() {
+        let (mut ctx, mut ctr) = setup(&admin(), 0);
+        ctr.guardian = Some(dan());
+        ctr.proposed_guardian = Some(carol());
+        ctr.queue_action(AdminAction::SetGuardian { guardian: None }, None);
+        ctx.block_timestamp = DEFAULT_ACTION_TIMELOCK_MS * 1_000_000;
+        next_tx(&mut ctx, &admin());
+        ctr.execute_action(1, None);
+        assert_eq!(ctr.guardian(), None);
+        assert_eq!(ctr.proposed_guardian(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "the guardian is set with propose_guardian")]
+    fn set_guardian_action() {
+        let (_, mut ctr) = setup(&admin(), 0);
+        ctr.queue_action(
+            AdminAction::SetGuardian {
+                guardian: Some(dan()),
+            },
+            None,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "not an admin nor the guardian")]
+    fn pause_not_guardian() {
+        let (mut ctx, mut ctr) = setup(&admin(), 0);
+        next_tx(&mut ctx, &dan());
+        ctr.admin_pause(None);
+    }
+
+    #[test]
+    #[should_panic(expected = "not an admin")]
+    fn guardian_cant_unpause() {
+        let (mut ctx, mut ctr) = setup(&admin(), 0);
+        ctr.guardian = Some(dan());
+        next_tx(&mut ctx, &dan());
+        ctr.admin_pause(None);
+        ctr.queue_action(AdminAction::Unpause, None);
+    }
+
+    #[test]
+    fn contract_source_metadata() {
+        let (_, ctr) = setup(&alice(), 0);
+        let m = ctr.contract_source_metadata();
+        assert_eq!(m.version.unwrap(), env!("CARGO_PKG_VERSION"));
+        assert_eq!(m.link.unwrap(), "https://github.com/alpha-fi/i-am-human");
+        assert_eq!(
+            m.standards,
+            vec![
+                Standard::new("nep330", "1.1.0"),
+                Standard::new("nep393", "1.0.0")
+            ]
+        );
+    }
+
+    #[test]
+    fn supported_extensions() {
+        let (_, ctr) = setup(&alice(), 0);
+        let exts = ctr.supported_extensions();
+        assert!(exts.contains(&EXT_SOUL_TRANSFER.to_owned()));
+        assert!(exts.contains(&EXT_IS_HUMAN_CALL.to_owned()));
+        for (i, e) in exts.iter().enumerate() {
+            assert!(!exts[i + 1..].contains(e), "duplicated extension {}", e);
+        }
+    }
+
+    #[test]
+    fn mint_backdate() {
+        let (mut ctx, mut ctr) = setup(&admin(), 2 * MINT_DEPOSIT);
+        ctr.admin_set_issuer_capabilities(issuer2(), CAP_DEFAULT | CAP_BACKDATE, None);
+        let now = 10 * MAX_BACKDATE_MS;
+        ctx.block_timestamp = now * 1_000_000;
+        let mut m1 = mk_metadata(1, None);
+        m1.issued_at = Some(now - MAX_BACKDATE_MS);
+
+        // normal issuer: issued_at is set by the registry
+        next_tx(&mut ctx, &issuer1());
+        ctr.sbt_mint(vec![(alice(), vec![m1.clone()])]);
+        assert_eq!(ctr.sbt(issuer1(), 1).unwrap().metadata.issued_at, Some(now));
+
+        next_tx(&mut ctx, &issuer2());
+        let mut m2 = mk_metadata(2, None);
+        m2.issued_at = None;
+        ctr.sbt_mint(vec![(alice(), vec![m1.clone(), m2])]);
+        assert_eq!(ctr.sbt(issuer2(), 1).unwrap().metadata, m1);
+        // issued_at is not set -> registry sets it
+        assert_eq!(ctr.sbt(issuer2(), 2).unwrap().metadata.issued_at, Some(now));
+    }
+
+    #[test]
+    #[should_panic(expected = "issued_at must be in the past")]
+    fn mint_backdate_too_old() {
+        let (mut ctx, mut ctr) = setup(&admin(), 2 * MINT_DEPOSIT);
+        ctr.admin_set_issuer_capabilities(issuer2(), CAP_DEFAULT | CAP_BACKDATE, None);
+        let now = 10 * MAX_BACKDATE_MS;
+        ctx.block_timestamp = now * 1_000_000;
+        next_tx(&mut ctx, &issuer2());
+        let mut m1 = mk_metadata(1, None);
+        m1.issued_at = Some(now - MAX_BACKDATE_MS - 1);
+        ctr.sbt_mint(vec![(alice(), vec![m1])]);
+    }
+
+    #[test]
+    #[should_panic(expected = "issued_at must be in the past")]
+    fn mint_backdate_future() {
+        let (mut ctx, mut ctr) = setup(&admin(), 2 * MINT_DEPOSIT);
+        ctr.admin_set_issuer_capabilities(issuer2(), CAP_DEFAULT | CAP_BACKDATE, None);
+        next_tx(&mut ctx, &issuer2());
+        let mut m1 = mk_metadata(1, None);
+        m1.issued_at = Some(1);
+        ctr.sbt_mint(vec![(alice(), vec![m1])]);
+    }
+
+    #[test]
+    fn vouchers() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), MINT_DEPOSIT);
+        let m1 = mk_metadata(1, Some(START + 10));
+        let ids = ctr.sbt_create_vouchers(vec![(alice(), m1.clone()), (bob(), m1.clone())]);
+        assert_eq!(ids, vec![1, 2]);
+        assert_eq!(ctr.voucher(1).unwrap().owner, alice());
+        assert_eq!(ctr.sbt_supply(issuer1(), None), 0);
+
+        // vouchers of other issuers can't be cancelled
+        next_tx(&mut ctx, &issuer2());
+        ctr.sbt_cancel_vouchers(vec![2]);
+        assert!(ctr.voucher(2).is_some());
+        next_tx(&mut ctx, &issuer1());
+        ctr.sbt_cancel_vouchers(vec![2, 10]);
+        assert_eq!(ctr.voucher(2), None);
+
+        next_tx(&mut ctx, &alice());
+        assert_eq!(ctr.redeem_voucher(1, None), 1);
+        assert_eq!(ctr.voucher(1), None);
+        assert_eq!(ctr.sbt_supply(issuer1(), None), 1);
+        let t = ctr.sbt(issuer1(), 1).unwrap();
+        assert_eq!(t.owner, alice());
+        assert_eq!(t.metadata.class, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "voucher can be redeemed only by its owner")]
+    fn redeem_voucher_not_owner() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), MINT_DEPOSIT);
+        ctr.sbt_create_vouchers(vec![(alice(), mk_metadata(1, None))]);
+        next_tx(&mut ctx, &bob());
+        ctr.redeem_voucher(1, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "min required storage deposit: 0.006 NEAR")]
+    fn redeem_voucher_no_deposit() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), MINT_DEPOSIT);
+        ctr.sbt_create_vouchers(vec![(alice(), mk_metadata(1, None))]);
+        ctx.attached_deposit = 0;
+        next_tx(&mut ctx, &alice());
+        ctr.redeem_voucher(1, None);
+    }
+
+    #[test]
+    fn redeem_voucher_relayed() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), MINT_DEPOSIT);
+        ctr.sbt_create_vouchers(vec![(alice(), mk_metadata(1, None))]);
+        next_tx(&mut ctx, &bob());
+        let escrow = ctr.sbt_escrow_deposit(issuer1()).0;
+        let balance = ctr.relayer_deposit(issuer1()).0;
+        assert_eq!(ctr.relayer_allowance(bob(), issuer1()).0, balance);
+
+        // NEP-366 meta transaction: alice delegate action signed by bob
+        ctx.signer_account_id = bob();
+        ctx.attached_deposit = 0;
+        next_tx(&mut ctx, &alice());
+        assert_eq!(ctr.redeem_voucher(1, Some(bob())), 1);
+        assert_eq!(ctr.sbt(issuer1(), 1).unwrap().owner, alice());
+        let left = ctr.relayer_allowance(bob(), issuer1()).0;
+        assert!(left < balance);
+        // the mint escrow is not used
+        assert_eq!(ctr.escrow_balance(bob(), issuer1()).0, escrow);
+        // vouchers are single use, so the relayed call can't be replayed
+        assert_eq!(ctr.voucher(1), None);
+
+        next_tx(&mut ctx, &bob());
+        ctr.relayer_withdraw(issuer1(), None);
+        assert_eq!(created_transfers(), vec![(bob(), left)]);
+        assert_eq!(ctr.relayer_allowance(bob(), issuer1()).0, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "min required storage deposit: 0.006 NEAR")]
+    fn redeem_voucher_relayed_no_allowance() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), MINT_DEPOSIT);
+        ctr.sbt_create_vouchers(vec![(alice(), mk_metadata(1, None))]);
+        // the mint escrow doesn't pay relayed redemptions
+        next_tx(&mut ctx, &bob());
+        ctr.sbt_escrow_deposit(issuer1());
+        ctx.signer_account_id = bob();
+        ctx.attached_deposit = 0;
+        next_tx(&mut ctx, &alice());
+        ctr.redeem_voucher(1, Some(bob()));
+    }
+
+    #[test]
+    #[should_panic(expected = "the relayer must sign the transaction")]
+    fn redeem_voucher_relayer_not_signer() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), MINT_DEPOSIT);
+        ctr.sbt_create_vouchers(vec![(alice(), mk_metadata(1, None))]);
+        next_tx(&mut ctx, &bob());
+        ctr.relayer_deposit(issuer1());
+        // a voucher owner (eg a contract) can't spend the allowance of another signer
+        ctx.signer_account_id = carol();
+        ctx.attached_deposit = 0;
+        next_tx(&mut ctx, &alice());
+        ctr.redeem_voucher(1, Some(bob()));
+    }
+
+    #[test]
+    #[should_panic(expected = "min required storage deposit: 0.006 NEAR")]
+    fn redeem_voucher_signed_by_other_account() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), MINT_DEPOSIT);
+        ctr.sbt_create_vouchers(vec![(alice(), mk_metadata(1, None))]);
+        next_tx(&mut ctx, &bob());
+        ctr.relayer_deposit(issuer1());
+        // without the explicit relayer, the signer funds are not used
+        ctx.signer_account_id = bob();
+        ctx.attached_deposit = 0;
+        next_tx(&mut ctx, &alice());
+        ctr.redeem_voucher(1, None);
+    }
+
+    #[test]
+    fn soul_transfer_relayed() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), MINT_DEPOSIT);
+        ctr.sbt_mint(vec![(alice(), vec![mk_metadata(1, None)])]);
+        ctx.signer_account_id = bob();
+        ctx.attached_deposit = 0;
+        next_tx(&mut ctx, &alice());
+        assert_eq!(ctr.sbt_soul_transfer(alice2(), None, None), (1, true));
+        assert_eq!(ctr.sbt(issuer1(), 1).unwrap().owner, alice2());
+    }
+
+    #[test]
+    fn mint_with_refs() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 3 * MINT_DEPOSIT);
+        let r = |s: &str| Some(s.to_owned());
+        let tokens = ctr.sbt_mint_with_refs(vec![
+            (
+                alice(),
+                vec![mk_metadata(1, None), mk_metadata(2, None)],
+                r("req-1"),
+            ),
+            (bob(), vec![mk_metadata(1, None)], None),
+        ]);
+        assert_eq!(tokens, vec![1, 2, 3]);
+        assert_eq!(ctr.sbt_mint_ref(issuer1(), "req-1".to_owned()), Some(1));
+        assert_eq!(ctr.sbt_mint_ref(issuer2(), "req-1".to_owned()), None);
+
+        // references are namespaced by the issuer
+        next_tx(&mut ctx, &issuer2());
+        let tokens =
+            ctr.sbt_mint_with_refs(vec![(carol(), vec![mk_metadata(1, None)], r("req-1"))]);
+        assert_eq!(tokens, vec![1]);
+        assert_eq!(ctr.sbt_mint_ref(issuer2(), "req-1".to_owned()), Some(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "external_ref req-1 was already used")]
+    fn mint_with_refs_retry() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 2 * MINT_DEPOSIT);
+        let spec = vec![(
+            alice(),
+            vec![mk_metadata(1, None)],
+            Some("req-1".to_owned()),
+        )];
+        ctr.sbt_mint_with_refs(spec.clone());
+        next_tx(&mut ctx, &issuer1());
+        ctr.sbt_mint_with_refs(spec);
+    }
+
+    #[test]
+    #[should_panic(expected = "external_ref req-1 was already used")]
+    fn mint_with_refs_duplicate() {
+        let (_, mut ctr) = setup(&issuer1(), 2 * MINT_DEPOSIT);
+        ctr.sbt_mint_with_refs(vec![
+            (
+                alice(),
+                vec![mk_metadata(1, None)],
+                Some("req-1".to_owned()),
+            ),
+            (bob(), vec![mk_metadata(1, None)], Some("req-1".to_owned())),
+        ]);
+    }
+
+    #[test]
+    fn reserve_token_ids() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 3 * MINT_DEPOSIT);
+        assert_eq!(ctr.reserve_token_ids(3), (1, 3));
+        let expires_at = START / 1_000_000 + TOKEN_RESERVATION_TTL;
+        assert_eq!(
+            test_utils::get_logs(),
+            mk_registry_log_str(
+                "reserve_token_ids",
+                &format!(
+                    r#"{{"issuer":"{}","first":1,"last":3,"expires_at":{}}}"#,
+                    issuer1(),
+                    expires_at
+                )
+            )
+        );
+        assert_eq!(ctr.last_token_id(issuer1()), 3);
+        assert_eq!(
+            ctr.token_reservations(issuer1()),
+            vec![TokenReservation {
+                first: 1,
+                last: 3,
+                expires_at
+            }]
+        );
+
+        // sbt_mint doesn't use the reserved IDs
+        next_tx(&mut ctx, &issuer1());
+        assert_eq!(
+            ctr.sbt_mint(vec![(alice(), vec![mk_metadata(1, None)])]),
+            vec![4]
+        );
+        next_tx(&mut ctx, &issuer1());
+        let tokens = ctr.sbt_mint_reserved(
+            2,
+            vec![(bob(), vec![mk_metadata(1, None), mk_metadata(2, None)])],
+        );
+        assert_eq!(tokens, vec![2, 3]);
+        assert_eq!(ctr.sbt(issuer1(), 3).unwrap().owner, bob());
+        assert_eq!(ctr.sbt(issuer1(), 1), None);
+
+        // expired reservation
+        ctx.block_timestamp = (expires_at + 1) * MILI_SECOND;
+        next_tx(&mut ctx, &issuer1());
+        assert_eq!(ctr.token_reservations(issuer1()), vec![]);
+    }
+
+    #[test]
+    #[should_panic(expected = "token 2 was already minted")]
+    fn mint_reserved_twice() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 2 * MINT_DEPOSIT);
+        ctr.reserve_token_ids(2);
+        ctr.sbt_mint_reserved(2, vec![(alice(), vec![mk_metadata(1, None)])]);
+        next_tx(&mut ctx, &issuer1());
+        ctr.sbt_mint_reserved(
+            1,
+            vec![(bob(), vec![mk_metadata(1, None), mk_metadata(2, None)])],
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "token IDs 1-2 are not reserved")]
+    fn mint_reserved_expired() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 2 * MINT_DEPOSIT);
+        ctr.reserve_token_ids(2);
+        ctx.block_timestamp = (START / 1_000_000 + TOKEN_RESERVATION_TTL + 1) * MILI_SECOND;
+        next_tx(&mut ctx, &issuer1());
+        ctr.sbt_mint_reserved(
+            1,
+            vec![(alice(), vec![mk_metadata(1, None), mk_metadata(2, None)])],
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "E01_ZERO_CLASS: class must be bigger than 0")]
+    fn mint_zero_class() {
+        let (_, mut ctr) = setup(&issuer1(), MINT_DEPOSIT);
+        ctr.sbt_mint(vec![(alice(), vec![mk_metadata(0, None)])]);
+    }
+
+    #[test]
+    #[should_panic(expected = "E03_REFERENCE_TOO_LONG: reference can have at most 256 bytes")]
+    fn mint_reference_too_long() {
+        let (_, mut ctr) = setup(&issuer1(), MINT_DEPOSIT);
+        let mut m = mk_metadata(1, None);
+        m.reference = Some("x".repeat(MAX_REFERENCE_LEN + 1));
+        ctr.sbt_mint(vec![(alice(), vec![m])]);
+    }
+
+    #[test]
+    #[should_panic(expected = "E04_INVALID_REFERENCE_HASH: reference_hash must have 32 bytes")]
+    fn create_voucher_invalid_reference_hash() {
+        let (_, mut ctr) = setup(&issuer1(), MINT_DEPOSIT);
+        let mut m = mk_metadata(1, None);
+        m.reference_hash = Some(vec![1; 20].into());
+        ctr.sbt_create_vouchers(vec![(alice(), m)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "E05_BATCH_TOO_LARGE: max 100 elements are allowed")]
+    fn mint_batch_too_large() {
+        let (_, mut ctr) = setup(&issuer1(), 150 * MINT_DEPOSIT);
+        ctr.sbt_mint(vec![
+            (alice(), mk_batch_metadata(60)),
+            (bob(), mk_batch_metadata(41)),
+        ]);
+    }
+
+    #[test]
+    fn verify_attribute() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 2 * MINT_DEPOSIT);
+        let leaves: Vec<[u8; 32]> = ["age=18-25", "country=CH", "kyc=true"]
+            .iter()
+            .map(|a| merkle::leaf_hash(a))
+            .collect();
+        let mut m1 = mk_metadata(1, None);
+        m1.attributes_root = merkle::merkle_root(&leaves);
+        ctr.sbt_mint(vec![(alice(), vec![m1, mk_metadata(2, None)])]);
+        next_tx(&mut ctx, &alice());
+
+        let proof = vec![leaves[0], leaves[2]];
+        assert!(ctr.verify_attribute(issuer1(), 1, proof.clone(), "country=CH".to_owned()));
+        assert!(!ctr.verify_attribute(issuer1(), 1, proof.clone(), "country=PL".to_owned()));
+        // token without attributes_root
+        assert!(!ctr.verify_attribute(issuer1(), 2, proof.clone(), "country=CH".to_owned()));
+        // token doesn't exist
+        assert!(!ctr.verify_attribute(issuer1(), 3, proof, "country=CH".to_owned()));
+    }
+
+    #[test]
+    fn recipient_policy() {
+        let (_, mut ctr) = setup(&issuer1(), 3 * MINT_DEPOSIT);
+        let p = RecipientPolicy {
+            reject_implicit: true,
+            allowed_suffix: None,
+        };
+        let implicit = AccountId::new_unchecked(
+            "061b1dd17603213b00e1a1e53ba060ad427cef4887bd34a5e0ef09010af23b0a".to_owned(),
+        );
+        assert!(!p.allows(&implicit));
+        assert!(p.allows(&alice()));
+
+        let p = RecipientPolicy {
+            reject_implicit: false,
+            allowed_suffix: Some("near".to_owned()),
+        };
+        assert!(p.allows(&alice()));
+        assert!(!p.allows(&AccountId::new_unchecked("near".to_owned())));
+        assert!(!p.allows(&AccountId::new_unchecked("alicenear".to_owned())));
+        assert!(!p.allows(&alice2()));
+        assert!(!p.allows(&implicit));
+        // a leading dot is ignored
+        let dotted = RecipientPolicy {
+            reject_implicit: false,
+            allowed_suffix: Some(".near".to_owned()),
+        };
+        assert!(dotted.allows(&alice()));
+        assert!(!dotted.allows(&AccountId::new_unchecked("near".to_owned())));
+        assert!(!dotted.allows(&alice2()));
+
+        assert_eq!(ctr.recipient_policy(issuer1()), None);
+        ctr.sbt_set_recipient_policy(Some(p.clone()));
+        assert_eq!(ctr.recipient_policy(issuer1()), Some(p));
+        assert_eq!(ctr.recipient_policy(issuer2()), None);
+        // not an issuer
+        assert_eq!(ctr.recipient_policy(alice()), None);
+        ctr.sbt_mint(vec![(alice(), vec![mk_metadata(1, None)])]);
+
+        ctr.sbt_set_recipient_policy(None);
+        assert_eq!(ctr.recipient_policy(issuer1()), None);
+        ctr.sbt_mint(vec![(alice2(), vec![mk_metadata(1, None)])]);
+    }
+
+    #[test]
+    #[should_panic(expected = "alice.nea is not allowed by the issuer recipient policy")]
+    fn recipient_policy_mint() {
+        let (_, mut ctr) = setup(&issuer1(), 2 * MINT_DEPOSIT);
+        ctr.sbt_set_recipient_policy(Some(RecipientPolicy {
+            reject_implicit: true,
+            allowed_suffix: Some("near".to_owned()),
+        }));
+        ctr.sbt_mint(vec![(alice2(), vec![mk_metadata(1, None)])]);
+    }
+
+    #[test]
+    fn is_human_call() {
+        let (mut ctx, mut ctr) = setup(&fractal_mainnet(), MINT_DEPOSIT);
+        ctr.sbt_mint(vec![(alice(), vec![mk_metadata(1, None)])]);
+        allow_human_call_target(&mut ctx, &mut ctr, 2 * MILI_NEAR, None);
+        ctx.attached_deposit = 2 * MILI_NEAR;
+        next_tx(&mut ctx, &alice());
+        ctr.is_human_call(bob(), "vote".to_owned(), r#"{"proposal": 1}"#.to_owned());
+
+        // receiver call failed: deposit is refunded
+        ctx.predecessor_account_id = ctx.current_account_id.clone();
+        testing_env!(
+            ctx.clone(),
+            near_sdk::VMConfig::test(),
+            near_sdk::RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Failed]
+        );
+        ctr.is_human_call_callback(alice(), (2 * MILI_NEAR).into());
+        assert_eq!(
+            test_utils::get_logs(),
+            vec![format!(
+                "is_human_call: receiver call failed, refunding {} yNEAR to alice.near",
+                2 * MILI_NEAR
+            )]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "caller is not a human")]
+    fn is_human_call_not_human() {
+        let (mut ctx, mut ctr) = setup(&alice(), MINT_DEPOSIT);
+        next_tx(&mut ctx, &bob());
+        ctr.is_human_call(bob(), "vote".to_owned(), "{}".to_owned());
+    }
+
+    #[test]
+    #[should_panic(expected = "payload must be a valid JSON")]
+    fn is_human_call_bad_payload() {
+        let (mut ctx, mut ctr) = setup(&fractal_mainnet(), MINT_DEPOSIT);
+        ctr.sbt_mint(vec![(alice(), vec![mk_metadata(1, None)])]);
+        next_tx(&mut ctx, &alice());
+        ctr.is_human_call(bob(), "vote".to_owned(), "{proposal: 1}".to_owned());
+    }
+
+    fn allow_human_call_target(
+        ctx: &mut VMContext,
+        ctr: &mut Contract,
+        max_deposit: Balance,
+        max_gas: Option<Gas>,
+    ) {
+        next_tx(ctx, &admin());
+        let policy = HumanCallPolicy {
+            max_deposit: max_deposit.into(),
+            max_gas,
+        };
+        ctr.admin_set_human_call_target(bob(), Some(policy.clone()));
+        assert_eq!(ctr.human_call_target(bob()), Some(policy));
+    }
+
+    #[test]
+    fn is_human_call_targets() {
+        let (mut ctx, mut ctr) = setup(&fractal_mainnet(), MINT_DEPOSIT);
+        ctr.sbt_mint(vec![(alice(), vec![mk_metadata(1, None)])]);
+        assert_eq!(ctr.human_call_target(bob()), None);
+        let max_gas = Gas(5 * Gas::ONE_TERA.0);
+        allow_human_call_target(&mut ctx, &mut ctr, MILI_NEAR, Some(max_gas));
+
+        ctx.attached_deposit = MILI_NEAR;
+        next_tx(&mut ctx, &alice());
+        ctr.is_human_call(bob(), "vote".to_owned(), "{}".to_owned());
+        let gas: Vec<Gas> = test_utils::get_created_receipts()
+            .into_iter()
+            .filter(|r| r.receiver_id == bob())
+            .flat_map(|r| r.actions)
+            .filter_map(|a| match a {
+                near_sdk::mock::VmAction::FunctionCall { gas, .. } => Some(gas),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(gas, vec![max_gas]);
+
+        next_tx(&mut ctx, &admin());
+        ctr.admin_set_human_call_target(bob(), None);
+        assert_eq!(ctr.human_call_target(bob()), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "bob.near is not an allowed is_human_call receiver")]
+    fn is_human_call_not_allowed() {
+        let (mut ctx, mut ctr) = setup(&fractal_mainnet(), MINT_DEPOSIT);
+        ctr.sbt_mint(vec![(alice(), vec![mk_metadata(1, None)])]);
+        next_tx(&mut ctx, &alice());
+        ctr.is_human_call(bob(), "vote".to_owned(), "{}".to_owned());
+    }
+
+    #[test]
+    #[should_panic(expected = "max 1000000000000000000000 yNEAR can be attached for bob.near")]
+    fn is_human_call_max_deposit() {
+        let (mut ctx, mut ctr) = setup(&fractal_mainnet(), MINT_DEPOSIT);
+        ctr.sbt_mint(vec![(alice(), vec![mk_metadata(1, None)])]);
+        allow_human_call_target(&mut ctx, &mut ctr, MILI_NEAR, None);
+        ctx.attached_deposit = MILI_NEAR + 1;
+        next_tx(&mut ctx, &alice());
+        ctr.is_human_call(bob(), "vote".to_owned(), "{}".to_owned());
+    }
+
+    #[test]
+    #[should_panic(expected = "not an admin")]
+    fn admin_set_human_call_target_not_authority() {
+        let (_, mut ctr) = setup(&alice(), MINT_DEPOSIT);
+        ctr.admin_set_human_call_target(bob(), None);
+    }
+
+    #[test]
+    fn class_cap() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 3 * MINT_DEPOSIT);
+        assert_eq!(ctr.class_cap(issuer1(), 1), None);
+        ctr.sbt_set_class_cap(1, Some(2));
+        assert_eq!(ctr.class_cap(issuer1(), 1), Some(2));
+        assert_eq!(ctr.class_cap(issuer1(), 2), None);
+        assert_eq!(ctr.class_cap(issuer2(), 1), None);
+
+        ctr.sbt_mint(vec![
+            (alice(), vec![mk_metadata(1, None), mk_metadata(2, None)]),
+            (bob(), vec![mk_metadata(1, None)]),
+        ]);
+        // burned tokens don't count into the cap
+        ctr.sbt_revoke(vec![1], true);
+        ctr.sbt_mint(vec![(carol(), vec![mk_metadata(1, None)])]);
+        assert_eq!(ctr.sbt_supply_by_class(issuer1(), 1), 2);
+
+        // other issuers are not affected
+        next_tx(&mut ctx, &issuer2());
+        ctr.sbt_mint(vec![
+            (alice(), vec![mk_metadata(1, None)]),
+            (bob(), vec![mk_metadata(1, None)]),
+            (carol(), vec![mk_metadata(1, None)]),
+        ]);
+
+        next_tx(&mut ctx, &issuer1());
+        ctr.sbt_set_class_cap(1, None);
+        ctr.sbt_mint(vec![(dan(), vec![mk_metadata(1, None)])]);
+        assert_eq!(ctr.sbt_supply_by_class(issuer1(), 1), 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "class 1 supply cap (1 tokens) exhausted")]
+    fn class_cap_exhausted() {
+        let (_, mut ctr) = setup(&issuer1(), 2 * MINT_DEPOSIT);
+        ctr.sbt_set_class_cap(1, Some(1));
+        ctr.sbt_mint(vec![
+            (alice(), vec![mk_metadata(1, None)]),
+            (bob(), vec![mk_metadata(1, None)]),
+        ]);
+    }
+
+    #[test]
+    fn pages() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 2 * MINT_DEPOSIT);
+        ctr.sbt_mint(vec![(
+            alice(),
+            vec![mk_metadata(1, None), mk_metadata(2, None)],
+        )]);
+        ctx.block_index = 7;
+        next_tx(&mut ctx, &alice());
+        let p1 = ctr.sbt_tokens_page(issuer1(), None, Some(1), None);
+        assert_eq!(p1.block_height, 7);
+        assert_eq!(p1.timestamp, START / MILI_SECOND);
+        assert_eq!(p1.items.len(), 1);
+        let p2 = ctr.sbt_tokens_by_owner_page(alice(), None, None, None, None);
+        assert_eq!(p2.event_count, p1.event_count);
+        assert_eq!(
+            p2.items,
+            ctr.sbt_tokens_by_owner(alice(), None, None, None, None)
+        );
+
+        // state changed: event_count is different
+        next_tx(&mut ctx, &issuer1());
+        ctr.sbt_revoke(vec![2], false);
+        let p3 = ctr.sbt_tokens_page(issuer1(), Some(2), Some(1), None);
+        assert!(p3.event_count > p1.event_count);
+    }
+
+    #[test]
+    fn pages_borsh() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 2 * MINT_DEPOSIT);
+        ctr.sbt_mint(vec![
+            (alice(), vec![mk_metadata(1, None), mk_metadata(2, None)]),
+            (bob(), vec![mk_metadata(1, Some(START / MILI_SECOND + 10))]),
+        ]);
+        next_tx(&mut ctx, &alice());
+        let page = ctr.sbt_tokens_page(issuer1(), None, None, None);
+        let p = ctr.sbt_tokens_page_borsh(issuer1(), None, None, None);
+        assert_eq!(Page::<Vec<Token>>::try_from_slice(&p.0).unwrap(), page);
+        // the Borsh encoding is smaller than the JSON one
+        assert!(p.0.len() < near_sdk::serde_json::to_vec(&page).unwrap().len());
+
+        let p = ctr.sbt_tokens_by_owner_page_borsh(alice(), None, None, None, None);
+        assert_eq!(
+            Page::<Vec<(AccountId, Vec<OwnedToken>)>>::try_from_slice(&p.0).unwrap(),
+            ctr.sbt_tokens_by_owner_page(alice(), None, None, None, None)
+        );
+    }
+
+    #[test]
+    fn sbt_supply_only_valid() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 4 * MINT_DEPOSIT);
+        // expire time bucket length, in miliseconds
+        let bucket = 1 << 26;
+        ctr.sbt_mint(vec![
+            (alice(), vec![mk_metadata(1, None)]),
+            (bob(), vec![mk_metadata(1, Some(100))]),
+            (carol(), vec![mk_metadata(1, Some(3 * bucket))]),
+            (dan(), vec![mk_metadata(1, Some(10 * bucket))]),
+        ]);
+        let valid = |ctr: &Contract| ctr.sbt_supply(issuer1(), Some(true));
+        assert_eq!(valid(&ctr), 4);
+        assert_eq!(ctr.sbt_supply(issuer1(), Some(false)), 4);
+        assert_eq!(ctr.sbt_supply(issuer2(), Some(true)), 0);
+        assert_eq!(ctr.sbt_supply(alice(), Some(true)), 0);
+
+        // expires_at is inclusive
+        ctx.block_timestamp = 100 * MILI_SECOND;
+        next_tx(&mut ctx, &issuer1());
+        assert_eq!(valid(&ctr), 4);
+        ctx.block_timestamp = 101 * MILI_SECOND;
+        next_tx(&mut ctx, &issuer1());
+        assert_eq!(valid(&ctr), 3);
+        ctx.block_timestamp = (4 * bucket) * MILI_SECOND;
+        next_tx(&mut ctx, &issuer1());
+        assert_eq!(valid(&ctr), 2);
+
+        // renew and revoke of tokens in the folded buckets
+        ctr.sbt_renew(vec![2], 20 * bucket);
+        assert_eq!(valid(&ctr), 3);
+        ctr.sbt_revoke(vec![3], false);
+        assert_eq!(valid(&ctr), 3);
+        assert_eq!(ctr.sbt_supply(issuer1(), None), 4);
+
+        next_tx(&mut ctx, &admin());
+        ctr.admin_freeze_tokens(issuer1(), vec![1], None, None);
+        assert_eq!(valid(&ctr), 2);
+        ctr.admin_unfreeze_tokens(issuer1(), vec![1], None);
+        assert_eq!(valid(&ctr), 3);
+
+        next_tx(&mut ctx, &issuer1());
+        ctr.sbt_revoke(vec![4], true);
+        assert_eq!(valid(&ctr), 2);
+        assert_eq!(ctr.check_invariants(issuer1(), None, None), vec![]);
+
+        ctx.block_timestamp = (21 * bucket) * MILI_SECOND;
+        next_tx(&mut ctx, &issuer1());
+        assert_eq!(valid(&ctr), 1);
+        ctr.sbt_revoke(vec![1, 2], true);
+        assert_eq!(valid(&ctr), 0);
+        assert_eq!(ctr.sbt_supply(issuer1(), None), 1);
+        // counters of the removed tokens are cleared
+        assert_eq!(ctr.valid_expirations.get(&(1, 20)), None);
+    }
+
+    #[test]
+    fn token_ids_never_reused() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 3 * MINT_DEPOSIT);
+        assert_eq!(ctr.last_token_id(issuer1()), 0);
+        assert_eq!(ctr.last_token_id(issuer4()), 0);
+
+        let ids = ctr.sbt_mint(vec![
+            (alice(), vec![mk_metadata(1, None), mk_metadata(2, None)]),
+            (bob(), vec![mk_metadata(1, None)]),
+        ]);
+        assert_eq!(ids, vec![1, 2, 3]);
+        assert_eq!(ctr.last_token_id(issuer1()), 3);
+
+        // burned IDs are not reused
+        ctr.sbt_revoke(vec![2, 3], true);
+        assert_eq!(ctr.last_token_id(issuer1()), 3);
+        let ids = ctr.sbt_mint(vec![(alice(), vec![mk_metadata(2, None)])]);
+        assert_eq!(ids, vec![4]);
+        assert_eq!(ctr.last_token_id(issuer1()), 4);
+
+        // empty mint doesn't change the counter
+        assert_eq!(ctr.sbt_mint(vec![]), Vec::<TokenId>::new());
+        assert_eq!(ctr.last_token_id(issuer1()), 4);
+
+        // IDs are per issuer and start at 1
+        next_tx(&mut ctx, &issuer2());
+        assert_eq!(
+            ctr.sbt_mint(vec![(alice(), vec![mk_metadata(1, None)])]),
+            vec![1]
+        );
+        assert_eq!(ctr.last_token_id(issuer2()), 1);
+        assert_eq!(ctr.last_token_id(issuer1()), 4);
+    }
+
+    #[test]
+    fn mint_with_payer() {
+        let (mut ctx, mut ctr) = setup(&bob(), 2 * MINT_DEPOSIT);
+        assert_eq!(ctr.escrow_balance(bob(), issuer1()).0, 0);
+        let balance = ctr.sbt_escrow_deposit(issuer1()).0;
+        assert!(balance > 0 && balance < 2 * MINT_DEPOSIT);
+        assert_eq!(ctr.escrow_balance(bob(), issuer1()).0, balance);
+        assert_eq!(ctr.escrow_balance(bob(), issuer2()).0, 0);
+
+        next_tx(&mut ctx, &issuer1());
+        ctx.attached_deposit = 0;
+        testing_env!(ctx.clone());
+        let r = ctr.sbt_mint_with_payer(vec![(alice(), vec![mk_metadata(1, None)])], bob());
+        assert_eq!(r, vec![1]);
+        assert_eq!(ctr.sbt(issuer1(), 1).unwrap().owner, alice());
+        let left = ctr.escrow_balance(bob(), issuer1()).0;
+        assert!(left < balance);
+
+        next_tx(&mut ctx, &bob());
+        ctr.sbt_escrow_withdraw(issuer1(), Some(1.into()));
+        assert_eq!(ctr.escrow_balance(bob(), issuer1()).0, left - 1);
+        ctr.sbt_escrow_withdraw(issuer1(), None);
+        assert_eq!(ctr.escrow_balance(bob(), issuer1()).0, 0);
+    }
+
+    #[test]
+    fn escrow_withdraw_removed_issuer() {
+        let (mut ctx, mut ctr) = setup(&bob(), 2 * MINT_DEPOSIT);
+        let escrow = ctr.sbt_escrow_deposit(issuer1()).0;
+        next_tx(&mut ctx, &issuer1());
+        let prepaid = ctr.issuer_storage_deposit().0;
+        ctr.issuer_unbond();
+
+        ctx.attached_deposit = 0;
+        next_tx(&mut ctx, &admin());
+        let remove = ctr.queue_action(AdminAction::RemoveIssuer { issuer: issuer1() }, None);
+        ctx.block_timestamp =
+            (ISSUER_UNBONDING_PERIOD_MS + DEFAULT_ACTION_TIMELOCK_MS) * MILI_SECOND;
+        next_tx(&mut ctx, &admin());
+        ctr.execute_action(remove, None);
+        assert_eq!(ctr.issuer_id(issuer1()), None);
+
+        // payers and the issuer can still withdraw
+        assert_eq!(ctr.escrow_balance(bob(), issuer1()).0, escrow);
+        assert_eq!(ctr.issuer_storage_balance(issuer1()).0, prepaid);
+        next_tx(&mut ctx, &bob());
+        ctr.sbt_escrow_withdraw(issuer1(), None);
+        assert_eq!(created_transfers(), vec![(bob(), escrow)]);
+        next_tx(&mut ctx, &issuer1());
+        ctr.issuer_storage_withdraw(prepaid.into());
+        assert_eq!(created_transfers(), vec![(issuer1(), prepaid)]);
+        assert_eq!(ctr.escrow_balance(bob(), issuer1()).0, 0);
+        assert_eq!(ctr.issuer_storage_balance(issuer1()).0, 0);
+
+        // the escrow for the previous ID doesn't leak to the issuer registered again
+        next_tx(&mut ctx, &admin());
+        ctr.admin_add_sbt_issuer(issuer1(), None);
+        assert_eq!(ctr.escrow_balance(bob(), issuer1()).0, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "min required storage deposit: 0.006 NEAR")]
+    fn mint_with_payer_other_issuer_escrow() {
+        let (mut ctx, mut ctr) = setup(&bob(), 2 * MINT_DEPOSIT);
+        ctr.sbt_escrow_deposit(issuer1());
+        // escrow for issuer1 can't be used by issuer2
+        next_tx(&mut ctx, &issuer2());
+        ctr.sbt_mint_with_payer(vec![(alice(), vec![mk_metadata(1, None)])], bob());
+    }
+
+    #[test]
+    fn issuer_storage_deposit() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 2 * MINT_DEPOSIT);
+        let balance = ctr.issuer_storage_deposit().0;
+        assert_eq!(ctr.issuer_storage_balance(issuer1()).0, balance);
+
+        ctx.attached_deposit = 0;
+        testing_env!(ctx.clone());
+        assert_eq!(
+            ctr.sbt_mint(vec![(alice(), vec![mk_metadata(1, None)])]),
+            vec![1]
+        );
+        let left = ctr.issuer_storage_balance(issuer1()).0;
+        assert!(left < balance);
+        // mint with an attached deposit doesn't use the prepaid balance
+        ctx.attached_deposit = MINT_DEPOSIT;
+        next_tx(&mut ctx, &issuer1());
+        ctr.sbt_mint(vec![(bob(), vec![mk_metadata(1, None)])]);
+        assert_eq!(ctr.issuer_storage_balance(issuer1()).0, left);
+
+        ctr.issuer_storage_withdraw(left.into());
+        assert_eq!(ctr.issuer_storage_balance(issuer1()).0, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "min required storage deposit: 0.006 NEAR")]
+    fn sbt_mint_no_deposit_no_prepaid_balance() {
+        let (_, mut ctr) = setup(&issuer1(), 0);
+        ctr.sbt_mint(vec![(alice(), vec![mk_metadata(1, None)])]);
+    }
+
+    #[test]
+    fn credentials() {
+        let (mut ctx, mut ctr) = setup(&admin(), 2 * MINT_DEPOSIT);
+        let fv = "face-verification".to_owned();
+        assert_eq!(ctr.resolve_credential(fv.clone()), vec![]);
+        ctr.admin_set_credential(fv.clone(), Some(vec![(issuer1(), 1), (issuer2(), 3)]));
+        assert_eq!(
+            ctr.resolve_credential(fv.clone()),
+            vec![(issuer1(), 1), (issuer2(), 3)]
+        );
+
+        next_tx(&mut ctx, &issuer2());
+        ctr.sbt_mint(vec![
+            (alice(), vec![mk_metadata(3, Some(START + 10))]),
+            (bob(), vec![mk_metadata(1, None)]),
+        ]);
+        next_tx(&mut ctx, &issuer1());
+        ctr.sbt_mint(vec![(bob(), vec![mk_metadata(2, None)])]);
+        assert!(ctr.has_credential(alice(), fv.clone()));
+        assert!(!ctr.has_credential(bob(), fv.clone()));
+        assert!(!ctr.has_credential(alice(), "kyc".to_owned()));
+
+        // expired token
+        ctx.block_timestamp = (START + 11) * MILI_SECOND;
+        testing_env!(ctx.clone());
+        assert!(!ctr.has_credential(alice(), fv.clone()));
+
+        next_tx(&mut ctx, &admin());
+        ctr.admin_set_credential(fv.clone(), None);
+        assert_eq!(ctr.resolve_credential(fv), vec![]);
+    }
+
+    #[test]
+    fn composite_credentials() {
+        let (mut ctx, mut ctr) = setup(&admin(), 3 * MINT_DEPOSIT);
+        let og = "og-human".to_owned();
+        let terms = vec![
+            CredentialTerm {
+                issuer: issuer1(),
+                class: 1,
+                issued_before: Some(100),
+            },
+            CredentialTerm {
+                issuer: issuer2(),
+                class: 2,
+                issued_before: None,
+            },
+        ];
+        ctr.admin_set_composite_credential(og.clone(), Some(terms.clone()));
+        assert_eq!(ctr.composite_credential(og.clone()), terms);
+
+        next_tx(&mut ctx, &issuer1());
+        ctr.sbt_mint(vec![(alice(), vec![mk_metadata(1, None)])]);
+        ctx.block_timestamp = 100 * MILI_SECOND;
+        next_tx(&mut ctx, &issuer1());
+        ctr.sbt_mint(vec![(bob(), vec![mk_metadata(1, None)])]);
+        assert!(!ctr.has_credential(alice(), og.clone()));
+
+        next_tx(&mut ctx, &issuer2());
+        ctr.sbt_mint(vec![
+            (alice(), vec![mk_metadata(2, None)]),
+            (bob(), vec![mk_metadata(2, None)]),
+        ]);
+        assert!(ctr.has_credential(alice(), og.clone()));
+        // bob's class 1 token is issued too late
+        assert!(!ctr.has_credential(bob(), og.clone()));
+
+        next_tx(&mut ctx, &admin());
+        ctr.admin_set_composite_credential(og.clone(), None);
+        assert_eq!(ctr.composite_credential(og.clone()), vec![]);
+        assert!(!ctr.has_credential(alice(), og));
+    }
+
+    #[test]
+    #[should_panic(expected = "name is already used by a credential alias")]
+    fn composite_credential_name_taken() {
+        let (_, mut ctr) = setup(&admin(), 0);
+        ctr.admin_set_credential("kyc".to_owned(), Some(vec![(issuer1(), 1)]));
+        ctr.admin_set_composite_credential("kyc".to_owned(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "credential must map to between 1 and 10 classes")]
+    fn credentials_empty() {
+        let (_, mut ctr) = setup(&admin(), 0);
+        ctr.admin_set_credential("kyc".to_owned(), Some(vec![]));
+    }
+
+    #[test]
+    fn policies() {
+        let (mut ctx, mut ctr) = setup(&admin(), 3 * MINT_DEPOSIT);
+        let voting = "voting".to_owned();
+        let expr = format!("{}:1 & ({}:2 | {}:1)", issuer1(), issuer2(), issuer3());
+        ctr.admin_set_policy(voting.clone(), Some(expr.clone()));
+        assert_eq!(ctr.policy(voting.clone()), Some(expr));
+        assert_eq!(
+            ctr.policies.get(&voting).unwrap().compiled,
+            vec![
+                PolicyOp::Class(1, 1),
+                PolicyOp::Class(2, 2),
+                PolicyOp::Class(3, 1),
+                PolicyOp::Any(2),
+                PolicyOp::All(2),
+            ]
+        );
+
+        next_tx(&mut ctx, &issuer1());
+        ctr.sbt_mint(vec![
+            (alice(), vec![mk_metadata(1, Some(START + 10))]),
+            (bob(), vec![mk_metadata(1, None)]),
+        ]);
+        assert!(!ctr.satisfies_policy(alice(), voting.clone()));
+        next_tx(&mut ctx, &issuer3());
+        ctr.sbt_mint(vec![(alice(), vec![mk_metadata(1, None)])]);
+        next_tx(&mut ctx, &issuer2());
+        ctr.sbt_mint(vec![(bob(), vec![mk_metadata(1, None)])]);
+        assert!(ctr.satisfies_policy(alice(), voting.clone()));
+        // bob has issuer2 class 1 token, not class 2
+        assert!(!ctr.satisfies_policy(bob(), voting.clone()));
+        assert!(!ctr.satisfies_policy(alice(), "kyc".to_owned()));
+
+        // expired token
+        ctx.block_timestamp = (START + 11) * MILI_SECOND;
+        testing_env!(ctx.clone());
+        assert!(!ctr.satisfies_policy(alice(), voting.clone()));
+
+        // the policy is updated without redeploying consumers
+        next_tx(&mut ctx, &admin());
+        ctr.admin_set_policy(voting.clone(), Some(format!("{}:1", issuer3())));
+        assert!(ctr.satisfies_policy(alice(), voting.clone()));
+        ctr.admin_set_policy(voting.clone(), None);
+        assert_eq!(ctr.policy(voting.clone()), None);
+        assert!(!ctr.satisfies_policy(alice(), voting));
+    }
+
+    #[test]
+    fn parse_policy_errors() {
+        let issuer_id = |a: &AccountId| (a.as_str() == "a.near").then_some(1);
+        let err = |expr: &str| parse_policy(expr, issuer_id).unwrap_err();
+        assert_eq!(
+            parse_policy("a.near:1|(a.near:2&a.near:3)", issuer_id),
+            Ok(vec![
+                PolicyOp::Class(1, 1),
+                PolicyOp::Class(1, 2),
+                PolicyOp::Class(1, 3),
+                PolicyOp::All(2),
+                PolicyOp::Any(2),
+            ])
+        );
+        assert_eq!(err(""), "expected <issuer>:<class> at 0");
+        assert_eq!(err("a.near"), "expected <issuer>:<class> at 0");
+        assert_eq!(err("a.near:x"), "invalid class at 0");
+        assert_eq!(err("b.near:1"), "unknown issuer b.near");
+        assert_eq!(err("a.near:1 &"), "expected <issuer>:<class> at 10");
+        assert_eq!(err("(a.near:1"), "expected ')' at 9");
+        assert_eq!(err("a.near:1 a.near:2"), "unexpected character at 9");
+        assert_eq!(
+            err("((((( a.near:1 )))))"),
+            "parentheses can be nested at most 4 times"
+        );
+        let many = vec!["a.near:1"; MAX_POLICY_TERMS + 1].join("|");
+        assert_eq!(err(&many), "expression can have at most 16 credentials");
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid policy: unknown issuer unknown.near")]
+    fn policies_unknown_issuer() {
+        let (_, mut ctr) = setup(&admin(), 0);
+        ctr.admin_set_policy("voting".to_owned(), Some("unknown.near:1".to_owned()));
+    }
+
+    #[test]
+    fn recreated_account_attest_continuity() {
+        let (mut ctx, mut ctr) = setup(&fractal_mainnet(), MINT_DEPOSIT);
+        ctr.sbt_mint(vec![(alice(), vec![mk_metadata(1, None)])]);
+        assert!(ctr.is_human(alice()));
+
+        next_tx(&mut ctx, &admin());
+        ctr.admin_flag_recreated_account(alice());
+        assert_eq!(
+            ctr.recreated_account_flagged_at(alice()),
+            Some(START / MILI_SECOND)
+        );
+        assert!(!ctr.is_human(alice()));
+
+        next_tx(&mut ctx, &fractal_mainnet());
+        ctr.sbt_attest_continuity(alice());
+        assert_eq!(ctr.recreated_account_flagged_at(alice()), None);
+        assert!(ctr.is_human(alice()));
+    }
+
+    #[test]
+    #[should_panic(expected = "must be called by the IAH issuer")]
+    fn attest_continuity_not_iah_issuer() {
+        let (mut ctx, mut ctr) = setup(&admin(), 0);
+        ctr.admin_flag_recreated_account(alice());
+        next_tx(&mut ctx, &issuer1());
+        ctr.sbt_attest_continuity(alice());
+    }
+
+    #[test]
+    fn purge_deleted_account() {
+        let (mut ctx, mut ctr) = setup(&fractal_mainnet(), 3 * MINT_DEPOSIT);
+        ctr.sbt_mint(vec![(alice(), vec![mk_metadata(1, Some(START + 100))])]);
+        next_tx(&mut ctx, &issuer1());
+        ctr.sbt_mint(vec![(
+            alice(),
+            vec![mk_metadata(1, None), mk_metadata(2, None)],
+        )]);
+        ctr.sbt_mint(vec![(bob(), vec![mk_metadata(1, None)])]);
+
+        next_tx(&mut ctx, &admin());
+        ctr.admin_freeze_tokens(issuer1(), vec![2], None, None);
+        ctr.admin_flag_recreated_account(alice());
+        next_tx(&mut ctx, &admin());
+        assert_eq!(ctr.admin_purge_deleted_account(alice(), 2), (2, false));
+        assert_eq!(
+            test_utils::get_logs(),
+            mk_log_str(
+                "burn",
+                &format!(r#"{{"issuer":"{}","tokens":[1,2]}}"#, issuer1())
+            )
+        );
+        assert!(ctr.recreated_account_flagged_at(alice()).is_some());
+        assert_eq!(ctr.admin_purge_deleted_account(alice(), 2), (1, true));
+        assert_eq!(ctr.recreated_account_flagged_at(alice()), None);
+
+        assert!(!ctr.has_any_sbt(alice()));
+        assert!(!ctr.is_human(alice()));
+        assert!(!ctr.is_banned(alice()));
+        assert_eq!(ctr.sbt_supply(issuer1(), None), 1);
+        assert_eq!(ctr.sbt_supply(fractal_mainnet(), None), 0);
+        assert_eq!(ctr.sbt_supply_by_class(issuer1(), 1), 1);
+        assert_eq!(ctr.sbt_supply_by_owner(alice(), issuer1(), None), 0);
+        assert_eq!(ctr.sbt_supply_by_owner(bob(), issuer1(), None), 1);
+        assert_eq!(ctr.sbt_supply(issuer1(), Some(true)), 1);
+        // burned tokens (also the frozen one) leave the tombstone
+        assert_eq!(ctr.sbt_status(issuer1(), 1), Some(TokenStatus::Burned));
+        assert_eq!(ctr.sbt_status(issuer1(), 2), Some(TokenStatus::Burned));
+        for issuer in [issuer1(), fractal_mainnet()] {
+            assert_eq!(ctr.check_invariants(issuer, None, None), vec![]);
+        }
+        assert_eq!(ctr.admin_purge_deleted_account(alice(), 2), (0, true));
+    }
+
+    #[test]
+    fn class_min_ttl() {
+        let (mut ctx, mut ctr) = setup(&admin(), 4 * MINT_DEPOSIT);
+        let now = START / MILI_SECOND;
+        assert_eq!(ctr.class_min_ttl(issuer1(), 1), None);
+        ctr.admin_set_class_min_ttl(issuer1(), 1, Some(100));
+        assert_eq!(ctr.class_min_ttl(issuer1(), 1), Some(100));
+        assert_eq!(ctr.class_min_ttl(issuer1(), 2), None);
+        assert_eq!(ctr.class_min_ttl(issuer4(), 1), None);
+
+        next_tx(&mut ctx, &issuer1());
+        ctr.sbt_mint(vec![(
+            alice(),
+            vec![
+                mk_metadata(1, Some(now + 100)),
+                mk_metadata(2, Some(now + 1)),
+                mk_metadata(3, None),
+            ],
+        )]);
+        ctr.sbt_mint(vec![(bob(), vec![mk_metadata(1, None)])]);
+        ctr.sbt_renew(vec![1], now + 200);
+        // other classes are not affected
+        ctr.sbt_renew(vec![2], now + 1);
+
+        next_tx(&mut ctx, &admin());
+        ctr.admin_set_class_min_ttl(issuer1(), 1, None);
+        next_tx(&mut ctx, &issuer1());
+        ctr.sbt_renew(vec![1], now + 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "class 1 tokens must be valid for at least 100ms")]
+    fn class_min_ttl_mint() {
+        let (mut ctx, mut ctr) = setup(&admin(), MINT_DEPOSIT);
+        ctr.admin_set_class_min_ttl(issuer1(), 1, Some(100));
+        next_tx(&mut ctx, &issuer1());
+        ctr.sbt_mint(vec![(
+            alice(),
+            vec![mk_metadata(1, Some(START / MILI_SECOND + 99))],
+        )]);
+    }
+
+    #[test]
+    #[should_panic(expected = "class 1 tokens must be valid for at least 100ms")]
+    fn class_min_ttl_renew_batch() {
+        let (mut ctx, mut ctr) = setup(&admin(), MINT_DEPOSIT);
+        ctr.admin_set_class_min_ttl(issuer1(), 1, Some(100));
+        next_tx(&mut ctx, &issuer1());
+        ctr.sbt_mint(vec![(alice(), vec![mk_metadata(1, None)])]);
+        ctr.sbt_renew_batch(vec![(1, START / MILI_SECOND + 10)]);
+    }
+
+    #[test]
+    fn class_max_ttl() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 5 * MINT_DEPOSIT);
+        let now = START / MILI_SECOND;
+        ctr.sbt_mint(vec![
+            (alice(), vec![mk_metadata(1, None), mk_metadata(2, None)]),
+            (bob(), vec![mk_metadata(1, Some(now + 1000))]),
+            (carol(), vec![mk_metadata(1, Some(now + 50))]),
+        ]);
+        assert_eq!(ctr.class_max_ttl(issuer1(), 1), None);
+        ctr.sbt_set_class_max_ttl(1, Some(100));
+        assert_eq!(
+            ctr.class_max_ttl(issuer1(), 1),
+            Some(ClassTtlPolicy {
+                max_ttl: 100,
+                set_at: now
+            })
+        );
+        assert_eq!(ctr.class_max_ttl(issuer2(), 1), None);
+        ctr.sbt_mint(vec![(dan(), vec![mk_metadata(1, Some(now + 100))])]);
+
+        ctx.block_timestamp = START + 10 * MILI_SECOND;
+        next_tx(&mut ctx, &issuer1());
+        assert_eq!(ctr.apply_ttl_policy(1, None, Some(3)), (2, false));
+        assert_eq!(
+            test_utils::get_logs(),
+            mk_registry_log_str(
+                "apply_ttl_policy",
+                r#"{"issuer":"sbt.n","class":1,"expires_at":100,"tokens":[1,3]}"#
+            )
+        );
+        let expires_at =
+            |ctr: &Contract, token| ctr.sbt(issuer1(), token).unwrap().metadata.expires_at;
+        // clamped to the policy set time + max TTL, not to the current time
+        assert_eq!(expires_at(&ctr, 1), Some(now + 100));
+        assert_eq!(expires_at(&ctr, 2), None);
+        assert_eq!(expires_at(&ctr, 3), Some(now + 100));
+        assert_eq!(ctr.apply_ttl_policy(1, Some(4), None), (0, true));
+        assert_eq!(expires_at(&ctr, 4), Some(now + 50));
+        assert_eq!(ctr.check_invariants(issuer1(), None, None), vec![]);
+
+        ctr.sbt_set_class_max_ttl(1, None);
+        assert_eq!(ctr.class_max_ttl(issuer1(), 1), None);
+        ctr.sbt_renew(vec![1], now + 1000);
+    }
+
+    #[test]
+    #[should_panic(expected = "class 1 tokens must expire within 100ms")]
+    fn class_max_ttl_mint() {
+        let (_, mut ctr) = setup(&issuer1(), MINT_DEPOSIT);
+        ctr.sbt_set_class_max_ttl(1, Some(100));
+        ctr.sbt_mint(vec![(alice(), vec![mk_metadata(1, None)])]);
+    }
+
+    #[test]
+    fn class_max_ttl_frozen() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 2 * MINT_DEPOSIT);
+        let now = START / MILI_SECOND;
+        ctr.sbt_mint(vec![(alice(), vec![mk_metadata(1, None)])]);
+        next_tx(&mut ctx, &admin());
+        ctr.admin_freeze_tokens(issuer1(), vec![1], None, None);
+        next_tx(&mut ctx, &issuer1());
+        ctr.sbt_set_class_max_ttl(1, Some(100));
+        assert_eq!(ctr.apply_ttl_policy(1, None, None), (1, true));
+        let t = ctr.sbt(issuer1(), 1).unwrap();
+        assert_eq!(t.metadata.expires_at, Some(now + 100));
+        assert_eq!(ctr.check_invariants(issuer1(), None, None), vec![]);
+
+        // max TTL which overflows the current time doesn't panic
+        ctr.sbt_set_class_max_ttl(1, Some(u64::MAX));
+        ctr.sbt_mint(vec![(bob(), vec![mk_metadata(1, Some(now + 1000))])]);
+    }
+
+    #[test]
+    #[should_panic(expected = "class max TTL must not be lower than the min TTL")]
+    fn class_max_ttl_below_min() {
+        let (mut ctx, mut ctr) = setup(&admin(), 0);
+        ctr.admin_set_class_min_ttl(issuer1(), 1, Some(200));
+        next_tx(&mut ctx, &issuer1());
+        ctr.sbt_set_class_max_ttl(1, Some(100));
+    }
+
+    #[test]
+    fn sbt_mint_v2() {
+        let (_, mut ctr) = setup(&issuer1(), 2 * MINT_DEPOSIT);
+        let r1 = ctr.sbt_mint_v2(vec![(alice(), vec![mk_metadata(1, None)])]);
+        assert_eq!(r1.tokens, vec![1]);
+        assert!(r1.storage_bytes > 0);
+        assert_eq!(
+            r1.deposit_used.0,
+            r1.storage_bytes as u128 * env::storage_byte_cost()
+        );
+        let r2 = ctr.sbt_mint_v2(vec![
+            (alice(), vec![mk_metadata(2, None)]),
+            (bob(), vec![mk_metadata(1, None)]),
+        ]);
+        assert_eq!(r2.tokens, vec![2, 3]);
+        assert!(r2.storage_bytes > r1.storage_bytes);
+        assert!(r2.deposit_used.0 <= 2 * MINT_DEPOSIT);
+    }
+
+    #[test]
+    fn issuer_stats() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 3 * MINT_DEPOSIT);
+        let stats = ctr.issuer_stats(issuer1(), None);
+        assert_eq!(
+            stats,
+            IssuerStats {
+                minted: 0,
+                live: 0,
+                revoked: 0,
+                recently_expired: 0,
+                last_mint_ms: None,
+                paused: false,
+            }
+        );
+        let now = DAY_MS;
+        ctx.block_timestamp = now * MILI_SECOND;
+        testing_env!(ctx.clone());
+        ctr.sbt_mint(vec![
+            (
+                alice(),
+                vec![mk_metadata(1, Some(now + 10)), mk_metadata(2, None)],
+            ),
+            (bob(), vec![mk_metadata(1, Some(now + 2 * DAY_MS))]),
+            (carol(), vec![mk_metadata(1, None)]),
+        ]);
+        ctr.sbt_revoke(vec![2], false);
+        ctr.sbt_revoke(vec![4], true);
+        ctx.block_timestamp = (now + 3 * DAY_MS) * MILI_SECOND;
+        testing_env!(ctx.clone());
+        assert_eq!(
+            ctr.issuer_stats(issuer1(), None),
+            IssuerStats {
+                minted: 4,
+                live: 3,
+                revoked: 2,
+                recently_expired: 2,
+                last_mint_ms: Some(now),
+                paused: false,
+            }
+        );
+        assert_eq!(ctr.issuer_stats(issuer1(), Some(1)).recently_expired, 1);
+
+        next_tx(&mut ctx, &admin());
+        ctr.admin_set_issuer_capabilities(issuer1(), CAP_RENEW, None);
+        assert!(ctr.issuer_stats(issuer1(), None).paused);
+    }
+
+    #[test]
+    fn legacy_token_data_record() {
+        let (_, mut ctr) = setup(&issuer1(), MINT_DEPOSIT);
+        let m1 = mk_metadata(1, None);
+        ctr.sbt_mint(vec![(alice(), vec![m1.clone()])]);
+        // overwrite the record with the unversioned TokenData layout
+        let legacy = TokenData {
+            owner: bob(),
+            metadata: m1.clone().into(),
+        };
+        let key = [
+            StorageKey::IssuerTokens.try_to_vec().unwrap(),
+            IssuerTokenId {
+                issuer_id: 1,
+                token: 1,
+            }
+            .try_to_vec()
+            .unwrap(),
+        ]
+        .concat();
+        env::storage_write(&key, &legacy.try_to_vec().unwrap());
+        assert_eq!(ctr.sbt(issuer1(), 1), Some(mk_token(1, bob(), m1)));
+    }
+
+    #[test]
+    fn migrate_chunk() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 3 * MINT_DEPOSIT);
+        let m1 = mk_metadata(1, None);
+        ctr.sbt_mint(vec![(alice(), vec![m1.clone(), mk_metadata(2, None)])]);
+        next_tx(&mut ctx, &issuer3());
+        ctr.sbt_mint(vec![(bob(), vec![m1.clone()])]);
+        let raw_key = |issuer_id, token| {
+            [
+                StorageKey::IssuerTokens.try_to_vec().unwrap(),
+                IssuerTokenId { issuer_id, token }.try_to_vec().unwrap(),
+            ]
+            .concat()
+        };
+        // unversioned TokenData record
+        let legacy = TokenData {
+            owner: alice(),
+            metadata: m1.clone().into(),
+        };
+        env::storage_write(&raw_key(1, 1), &legacy.try_to_vec().unwrap());
+        // versioned record with the old metadata layout
+        let old_metadata = TokenData {
+            owner: bob(),
+            metadata: VerTokenMetadata::V1(TokenMetadataV1 {
+                class: 1,
+                issued_at: m1.issued_at,
+                expires_at: None,
+                reference: m1.reference.clone(),
+                reference_hash: m1.reference_hash.clone(),
+            }),
+        };
+        env::storage_write(
+            &raw_key(3, 1),
+            &VerTokenData::V1(old_metadata).try_to_vec().unwrap(),
+        );
+        let key = |issuer_id, token| IssuerTokenId { issuer_id, token };
+        // migration of a registry upgraded from the previous release
+        ctr.migration = MigrationProgress::default();
+        assert!(ctr.issuer_tokens.needs_migration(&key(1, 1)));
+        assert!(!ctr.issuer_tokens.needs_migration(&key(1, 2)));
+        assert!(ctr.issuer_tokens.needs_migration(&key(3, 1)));
+
+        next_tx(&mut ctx, &carol());
+        let p = ctr.migrate_chunk(3);
+        assert_eq!(
+            p,
+            MigrationProgress {
+                issuer_id: 1,
+                token: 3,
+                migrated: 1,
+                done: false,
+            }
+        );
+        assert!(!ctr.issuer_tokens.needs_migration(&key(1, 1)));
+        assert!(test_utils::get_logs().is_empty());
+
+        next_tx(&mut ctx, &carol());
+        let p = ctr.migrate_chunk(10);
+        assert!(p.done);
+        assert_eq!(p.migrated, 2);
+        assert_eq!(
+            test_utils::get_logs(),
+            mk_registry_log_str("migration_completed", r#"{"migrated":2}"#)
+        );
+        assert!(!ctr.issuer_tokens.needs_migration(&key(3, 1)));
+        assert_eq!(
+            ctr.sbt(issuer1(), 1),
+            Some(mk_token(1, alice(), m1.clone()))
+        );
+        assert_eq!(ctr.sbt(issuer3(), 1), Some(mk_token(1, bob(), m1)));
+
+        // no more work once the migration is completed
+        next_tx(&mut ctx, &carol());
+        assert_eq!(ctr.migrate_chunk(10), p);
+        assert!(test_utils::get_logs().is_empty());
+        assert_eq!(ctr.migration_progress(), p);
+    }
+
+    #[test]
+    fn migrate_chunk_legacy_balances() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 4 * MINT_DEPOSIT);
+        let m = |class| mk_metadata(class, Some(START + 10));
+        ctr.sbt_mint(vec![
+            (alice(), vec![m(1), m(2), m(300)]),
+            (bob(), vec![m(1)]),
+        ]);
+        let tokens = |ctr: &Contract, owner| {
+            ctr.balances
+                .iter()
+                .filter(|(k, _)| k.owner == owner)
+                .map(|(k, t)| (k.class_id, t))
+                .collect::<Vec<_>>()
+        };
+        let all = |ctr: &Contract| {
+            ctr.balances
+                .iter()
+                .map(|(k, t)| (k.owner, k.class_id, t))
+                .collect::<Vec<_>>()
+        };
+        let before = all(&ctr);
+        // balances stored before the sharding
+        for (owner, class) in [(alice(), 2), (bob(), 1)] {
+            let k = balance_key(owner, 1, class);
+            let t = ctr.balances.remove(&k).unwrap();
+            ctr.balances.legacy.insert(&k, &t);
+        }
+        let legacy_key = balance_key(alice(), 1, 2);
+        assert_eq!(ctr.balances.get(&legacy_key), Some(2));
+        assert_eq!(
+            ctr.balances.state_key(&legacy_key),
+            [
+                StorageKey::Balances.try_to_vec().unwrap(),
+                b"v".to_vec(),
+                legacy_key.try_to_vec().unwrap()
+            ]
+            .concat()
+        );
+        assert_eq!(all(&ctr), before);
+        assert_eq!(tokens(&ctr, alice()), vec![(1, 1), (2, 2), (300, 3)]);
+        assert_eq!(
+            ctr.balances
+                .iter_from(balance_key(alice(), 1, 1))
+                .map(|(k, _)| k.class_id)
+                .collect::<Vec<_>>(),
+            vec![2, 300, 1]
+        );
+        assert_eq!(
+            ctr.sbt_classes_by_owner(alice()),
+            vec![
+                (issuer1(), 1, 1, Some(START + 10)),
+                (issuer1(), 2, 2, Some(START + 10)),
+                (issuer1(), 300, 3, Some(START + 10)),
+            ]
+        );
+        ctr.sanity_check();
+
+        next_tx(&mut ctx, &carol());
+        let p = ctr.migrate_chunk(1);
+        assert_eq!(p.migrated, 1);
+        assert_eq!(ctr.balances.legacy.len(), 1);
+        assert_eq!(all(&ctr), before);
+        // writes move the legacy balance
+        assert_eq!(ctr.balances.remove(&balance_key(bob(), 1, 1)), Some(4));
+        assert!(ctr.balances.legacy.is_empty());
+        assert_eq!(ctr.balances.insert(&balance_key(bob(), 1, 1), &4), None);
+        assert_eq!(all(&ctr), before);
+        assert_ne!(
+            ctr.balances.state_key(&legacy_key),
+            [
+                StorageKey::Balances.try_to_vec().unwrap(),
+                b"v".to_vec(),
+                legacy_key.try_to_vec().unwrap()
+            ]
+            .concat()
+        );
+    }
 
-        // issuer_2
-        ctx.predecessor_account_id = issuer2();
-        ctx.prepaid_gas = max_gas();
-        testing_env!(ctx.clone());
-        ctr.sbt_mint(vec![(alice(), batch_metadata[50..].to_vec())]);
-        assert_eq!(ctr.sbt_supply_by_owner(alice(), issuer2(), None), 50);
+    #[test]
+    fn mint_event_split() {
+        let (_, mut ctr) = setup(&issuer1(), 60 * MINT_DEPOSIT);
+        let owners: Vec<AccountId> = (0..30)
+            .map(|i| AccountId::new_unchecked(format!("{}{:02}.near", "a".repeat(55), i)))
+            .collect();
+        let ids = ctr.sbt_mint(
+            owners
+                .iter()
+                .map(|o| (o.clone(), vec![mk_metadata(1, None)]))
+                .collect(),
+        );
+        assert_eq!(ids.len(), 30);
+        let logs = test_utils::get_logs();
+        assert_eq!(logs.len(), 2);
+        let mut minted = 0;
+        for l in logs {
+            let e: serde_json::Value =
+                serde_json::from_str(l.strip_prefix("EVENT_JSON:").unwrap()).unwrap();
+            assert_eq!(e["event"], "mint");
+            assert_eq!(e["data"]["issuer"], issuer1().as_str());
+            minted += e["data"]["tokens"].as_array().unwrap().len();
+        }
+        assert_eq!(minted, 30);
+    }
 
-        // add more tokens to issuer_1
-        ctx.predecessor_account_id = issuer1();
-        ctx.prepaid_gas = max_gas();
-        testing_env!(ctx.clone());
-        ctr.sbt_mint(vec![(bob(), batch_metadata[..20].to_vec())]);
-        assert_eq!(ctr.sbt_supply_by_owner(bob(), issuer1(), None), 20);
+    #[test]
+    fn mint_with_msg() {
+        let (mut ctx, mut ctr) = setup(&alice(), MINT_DEPOSIT);
+        ctr.sbt_set_notifications(true);
+        assert!(ctr.sbt_notifications_enabled(alice()));
+        assert!(!ctr.sbt_notifications_enabled(bob()));
 
-        ctx.prepaid_gas = max_gas();
-        testing_env!(ctx.clone());
-        ctr.sbt_mint(vec![(alice2(), batch_metadata[..20].to_vec())]);
-        assert_eq!(ctr.sbt_supply_by_owner(alice2(), issuer1(), None), 20);
+        ctx.attached_deposit = 4 * MINT_DEPOSIT;
+        next_tx(&mut ctx, &issuer1());
+        let tokens = ctr.sbt_mint_with_msg(vec![
+            (bob(), vec![mk_metadata(1, None)], Some("hi bob".to_owned())),
+            (
+                alice(),
+                vec![mk_metadata(1, None), mk_metadata(2, None)],
+                Some("hi".to_owned()),
+            ),
+            (carol(), vec![mk_metadata(1, None)], None),
+        ]);
+        assert_eq!(tokens, vec![1, 2, 3, 4]);
+        assert_eq!(ctr.sbt(issuer1(), 2).unwrap().owner, alice());
+        // only the registered recipient is notified
+        let calls = created_calls();
+        let call = |token| {
+            (
+                alice(),
+                "on_sbt_received".to_owned(),
+                format!(
+                    r#"{{"issuer":"{}","token":{},"msg":"hi"}}"#,
+                    issuer1(),
+                    token
+                ),
+            )
+        };
+        assert_eq!(calls, vec![call(2), call(3)]);
 
-        ctx.prepaid_gas = max_gas();
-        testing_env!(ctx.clone());
-        ctr.sbt_mint(vec![(carol(), batch_metadata[..20].to_vec())]);
-        assert_eq!(ctr.sbt_supply_by_owner(carol(), issuer1(), None), 20);
+        // unregister
+        ctx.attached_deposit = 0;
+        next_tx(&mut ctx, &alice());
+        ctr.sbt_set_notifications(false);
+        assert!(!ctr.sbt_notifications_enabled(alice()));
+    }
 
-        ctx.prepaid_gas = max_gas();
-        testing_env!(ctx.clone());
-        ctr.sbt_mint(vec![(dan(), batch_metadata[..10].to_vec())]);
-        assert_eq!(ctr.sbt_supply_by_owner(dan(), issuer1(), None), 10);
+    #[test]
+    #[should_panic(expected = "msg must be at most 1024 bytes")]
+    fn mint_with_msg_too_long() {
+        let (_, mut ctr) = setup(&issuer1(), MINT_DEPOSIT);
+        ctr.sbt_mint_with_msg(vec![(
+            alice(),
+            vec![mk_metadata(1, None)],
+            Some("x".repeat(1025)),
+        )]);
+    }
 
-        // soul transfer alice->alice2
-        ctx.predecessor_account_id = alice();
-        ctx.prepaid_gas = max_gas();
+    /// Returns gas used by `f` executed in a fresh context.
+    fn view_gas(ctx: &VMContext, f: impl FnOnce()) -> Gas {
         testing_env!(ctx.clone());
-        let limit: u32 = 30; //anything above this limit will fail due to exceeding maximum gas usage per call
+        let start = env::used_gas();
+        f();
+        env::used_gas() - start
+    }
 
-        let mut result = ctr._sbt_soul_transfer(alice2(), limit as usize);
-        while !result.1 {
-            ctx.prepaid_gas = max_gas();
-            testing_env!(ctx.clone());
-            result = ctr._sbt_soul_transfer(alice2(), limit as usize);
+    #[test]
+    fn supply_by_owner_gas_is_flat() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 70 * MINT_DEPOSIT);
+        ctr.sbt_mint(vec![(alice(), mk_batch_metadata(1))]);
+        let measure = |ctx: &VMContext, ctr: &Contract| {
+            [None, Some(1)]
+                .map(|class| {
+                    view_gas(ctx, || {
+                        ctr.sbt_supply_by_owner(alice(), issuer1(), class);
+                    })
+                })
+                .to_vec()
+        };
+        let expected = measure(&ctx, &ctr);
+
+        // view gas doesn't depend on the number of tokens held by the account (tokens of
+        // other balance shards than the class 1 one)
+        for first_class in [SHARD_CLASSES, 2 * SHARD_CLASSES] {
+            let batch = (first_class..first_class + 32)
+                .map(|c| mk_metadata(c, None))
+                .collect();
+            next_tx(&mut ctx, &issuer1());
+            ctr.sbt_mint(vec![(alice(), batch)]);
+            assert_eq!(measure(&ctx, &ctr), expected);
         }
+        assert_eq!(ctr.sbt_supply_by_owner(alice(), issuer1(), None), 65);
     }
 
     #[test]
-    fn soul_transfer_limit_basics() {
-        let (mut ctx, mut ctr) = setup(&issuer1(), 60 * MINT_DEPOSIT);
-        let batch_metadata = mk_batch_metadata(40);
-        assert!(batch_metadata.len() == 40);
+    fn sbt_classes_by_owner() {
+        let (mut ctx, mut ctr) = setup(&issuer2(), 3 * MINT_DEPOSIT);
+        assert_eq!(ctr.sbt_classes_by_owner(alice()), vec![]);
+        ctr.sbt_mint(vec![
+            (alice(), vec![mk_metadata(3, Some(START + 10))]),
+            (bob(), vec![mk_metadata(1, None)]),
+        ]);
+        next_tx(&mut ctx, &issuer1());
+        ctr.sbt_mint(vec![(
+            alice(),
+            vec![mk_metadata(2, None), mk_metadata(1, None)],
+        )]);
+        assert_eq!(
+            ctr.sbt_classes_by_owner(alice()),
+            vec![
+                (issuer1(), 1, 2, None),
+                (issuer1(), 2, 1, None),
+                (issuer2(), 3, 1, Some(START + 10)),
+            ]
+        );
+        assert_eq!(
+            ctr.sbt_classes_by_owner(bob()),
+            vec![(issuer2(), 1, 2, None)]
+        );
+    }
 
-        // issuer_1
-        ctr.sbt_mint(vec![(alice(), batch_metadata[..20].to_vec())]);
-        assert_eq!(ctr.sbt_supply_by_owner(alice(), issuer1(), None), 20);
+    #[test]
+    fn sbt_exists() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 3 * MINT_DEPOSIT);
+        assert!(!ctr.sbt_exists(issuer1(), 1));
+        assert!(!ctr.has_any_sbt(alice()));
+        ctr.sbt_mint(vec![(bob(), vec![mk_metadata(1, Some(START))])]);
+        assert!(ctr.sbt_exists(issuer1(), 1));
+        assert!(!ctr.sbt_exists(issuer2(), 1));
+        assert!(!ctr.sbt_exists(issuer4(), 1));
+        assert!(!ctr.has_any_sbt(alice()));
+        assert!(ctr.has_any_sbt(bob()));
+
+        next_tx(&mut ctx, &issuer2());
+        ctr.sbt_mint(vec![(alice(), vec![mk_metadata(1, None)])]);
+        assert!(ctr.has_any_sbt(alice()));
+        ctr.sbt_revoke(vec![1], true);
+        assert!(!ctr.sbt_exists(issuer2(), 1));
+        assert!(!ctr.has_any_sbt(alice()));
+    }
 
-        // issuer_2
-        ctx.predecessor_account_id = issuer2();
-        ctx.prepaid_gas = max_gas();
-        testing_env!(ctx.clone());
-        ctr.sbt_mint(vec![(alice(), batch_metadata[20..].to_vec())]);
-        assert_eq!(ctr.sbt_supply_by_owner(alice(), issuer2(), None), 20);
+    #[test]
+    fn sbts() {
+        let (_, mut ctr) = setup(&issuer1(), 3 * MINT_DEPOSIT);
+        let m1 = mk_metadata(1, Some(START));
+        let m2 = mk_metadata(2, None);
+        ctr.sbt_mint(vec![(alice(), vec![m1.clone()]), (bob(), vec![m2])]);
+        ctr.sbt_revoke(vec![2], true);
+        let expected = Token {
+            token: 1,
+            owner: alice(),
+            metadata: m1,
+        };
+        assert_eq!(
+            ctr.sbts(issuer1(), vec![3, 1, 2, 1]),
+            vec![None, Some(expected.clone()), None, Some(expected)]
+        );
+        assert_eq!(ctr.sbts(issuer1(), vec![]), vec![]);
+    }
 
-        ctx.predecessor_account_id = alice();
-        ctx.prepaid_gas = max_gas();
-        testing_env!(ctx.clone());
+    #[test]
+    #[should_panic(expected = "at most 1000 tokens can be queried at once")]
+    fn sbts_limit() {
+        let (_, ctr) = setup(&issuer1(), 0);
+        ctr.sbts(issuer1(), (1..=1001).collect());
+    }
 
-        let limit: u32 = 10;
-        let mut result = ctr._sbt_soul_transfer(alice2(), limit as usize);
-        assert_eq!((limit, false), result);
+    #[test]
+    fn admin_invalidate_banned_tokens() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 2 * MINT_DEPOSIT);
+        let future = START / MILI_SECOND + 1000;
+        ctr.sbt_mint(vec![(
+            alice(),
+            vec![mk_metadata(1, Some(future)), mk_metadata(2, None)],
+        )]);
+        next_tx(&mut ctx, &issuer2());
+        ctr.sbt_mint(vec![(alice(), vec![mk_metadata(1, Some(future))])]);
+        ctr.sbt_mint(vec![(bob(), vec![mk_metadata(1, Some(future))])]);
+        ctr.banlist.insert(&alice());
 
-        ctx.prepaid_gas = max_gas();
-        testing_env!(ctx.clone());
-        result = ctr._sbt_soul_transfer(alice2(), limit as usize);
-        assert_eq!((limit, false), result);
+        next_tx(&mut ctx, &admin());
+        assert_eq!(ctr.admin_invalidate_banned_tokens(alice(), 2), (2, false));
+        assert_eq!(
+            test_utils::get_logs(),
+            mk_log_str(
+                "revoke",
+                &format!(r#"{{"issuer":"{}","tokens":[1,2]}}"#, issuer1())
+            )
+        );
+        let now = START / MILI_SECOND;
+        assert_eq!(
+            ctr.sbt(issuer1(), 2).unwrap().metadata.expires_at,
+            Some(now)
+        );
+        assert_eq!(
+            ctr.sbt(issuer2(), 1).unwrap().metadata.expires_at,
+            Some(future)
+        );
+        assert_eq!(
+            ctr.expirations_on(expiration_day(future), None, None).len(),
+            2
+        );
 
-        ctx.prepaid_gas = max_gas();
-        testing_env!(ctx.clone());
-        result = ctr._sbt_soul_transfer(alice2(), limit as usize);
-        assert_eq!((limit, false), result);
+        // the next call continues after the last checked balance
+        assert_eq!(ctr.invalidation_cursors.get(&alice()), Some((1, 2)));
 
-        ctx.prepaid_gas = max_gas();
-        testing_env!(ctx.clone());
-        result = ctr._sbt_soul_transfer(alice2(), limit as usize);
-        assert_eq!((limit, false), result);
+        next_tx(&mut ctx, &admin());
+        assert_eq!(ctr.admin_invalidate_banned_tokens(alice(), 2), (1, true));
+        assert_eq!(ctr.invalidation_cursors.get(&alice()), None);
+        assert_eq!(
+            ctr.sbt(issuer2(), 1).unwrap().metadata.expires_at,
+            Some(now)
+        );
+        // bob is not affected
+        assert_eq!(
+            ctr.sbt(issuer2(), 2).unwrap().metadata.expires_at,
+            Some(future)
+        );
+        assert_eq!(ctr.admin_invalidate_banned_tokens(alice(), 2), (0, true));
+    }
 
-        // resumed transfer but no more tokens to transfer
-        ctx.prepaid_gas = max_gas();
-        testing_env!(ctx.clone());
-        result = ctr._sbt_soul_transfer(alice2(), limit as usize);
-        assert_eq!((0, true), result);
+    #[test]
+    fn admin_invalidate_banned_tokens_batches() {
+        let n = MAX_SWEEP_BATCH as u64 + 5;
+        let (mut ctx, mut ctr) = setup(&issuer1(), n as Balance * MINT_DEPOSIT);
+        ctr.sbt_mint(vec![(
+            alice(),
+            (1..=n).map(|c| mk_metadata(c, None)).collect(),
+        )]);
+        ctr.banlist.insert(&alice());
 
-        assert_eq!(ctr.sbt_supply_by_owner(alice(), issuer1(), None), 0);
-        assert_eq!(ctr.sbt_supply_by_owner(alice(), issuer2(), None), 0);
-        assert_eq!(ctr.sbt_supply_by_owner(alice2(), issuer1(), None), 20);
-        assert_eq!(ctr.sbt_supply_by_owner(alice2(), issuer2(), None), 20);
+        // a call checks at most MAX_SWEEP_BATCH balances, regardless of the limit
+        next_tx(&mut ctx, &admin());
+        assert_eq!(
+            ctr.admin_invalidate_banned_tokens(alice(), 1000),
+            (MAX_SWEEP_BATCH as u32, false)
+        );
+        let cursor = (1, MAX_SWEEP_BATCH as ClassId);
+        assert_eq!(ctr.invalidation_cursors.get(&alice()), Some(cursor));
+        next_tx(&mut ctx, &admin());
+        assert_eq!(ctr.admin_invalidate_banned_tokens(alice(), 1000), (5, true));
+        assert_eq!(ctr.sbt_status(issuer1(), n), Some(TokenStatus::Revoked));
+    }
+
+    #[test]
+    #[should_panic(expected = "account is not banned")]
+    fn admin_invalidate_not_banned_tokens() {
+        let (_, mut ctr) = setup(&admin(), 0);
+        ctr.admin_invalidate_banned_tokens(alice(), 10);
     }
 
     #[test]
@@ -1313,6 +7408,13 @@ mod tests {
         )]
     }
 
+    fn mk_registry_log_str(event: &str, data: &str) -> Vec<String> {
+        vec![format!(
+            "EVENT_JSON:{{\"standard\":\"i_am_human_registry\",\"version\":\"1.0.0\",\"event\":\"{}\",\"data\":{}}}",
+            event, data
+        )]
+    }
+
     #[test]
     fn check_tree_iterator() {
         let (_, mut ctr) = setup(&issuer1(), MINT_DEPOSIT);
@@ -1441,7 +7543,7 @@ mod tests {
         let log_mint = mk_log_str(
             "mint",
             &format!(
-                r#"{{"issuer":"{}","tokens":[["{}",[1]]]}}"#,
+                r#"{{"issuer":"{}","tokens":[["{}",[1]]],"storage_delta_bytes":934,"deposit_charged":"9340000000000000000000"}}"#,
                 issuer1(),
                 alice()
             ),
@@ -1450,7 +7552,7 @@ mod tests {
             "renew",
             &format!(r#"{{"issuer":"{}","tokens":[{}]}}"#, issuer1(), tokens[0]),
         );
-        assert_eq!(test_utils::get_logs(), vec![log_mint, log_renew].concat());
+        assert_eq!(test_utils::get_logs(), [log_mint, log_renew].concat());
     }
 
     #[test]
@@ -1471,7 +7573,7 @@ mod tests {
         let recover_log = mk_log_str(
             "recover",
             &format!(
-                r#"{{"issuer":"{}","old_owner":"{}","new_owner":"{}"}}"#,
+                r#"{{"issuer":"{}","old_owner":"{}","new_owner":"{}","storage_delta_bytes":112,"deposit_charged":"1120000000000000000000"}}"#,
                 issuer1(),
                 alice(),
                 bob()
@@ -1494,8 +7596,8 @@ mod tests {
                 ]
             ),]
         );
-        assert_eq!(ctr.sbt_supply(issuer1()), 2);
-        assert_eq!(ctr.sbt_supply(issuer2()), 1);
+        assert_eq!(ctr.sbt_supply(issuer1(), None), 2);
+        assert_eq!(ctr.sbt_supply(issuer2(), None), 1);
         assert_eq!(ctr.sbt_supply_by_class(issuer2(), 1), 1);
         assert_eq!(ctr.sbt_supply_by_class(issuer1(), 1), 1);
         assert_eq!(ctr.sbt_supply_by_class(issuer1(), 2), 1);
@@ -1576,28 +7678,202 @@ mod tests {
             vec![m1_1.clone(), m2_1.clone(), m3_1.clone(), m4_1.clone()],
         )]);
 
-        // sbt_recover
-        let mut result = ctr._sbt_recover(alice(), alice2(), 3);
-        assert_eq!((3, false), result);
-        assert_eq!(ctr.sbt_supply_by_owner(alice2(), issuer1(), None), 3);
-        assert!(test_utils::get_logs().len() == 1);
-        result = ctr._sbt_recover(alice(), alice2(), 3);
-        assert_eq!((1, true), result);
-        assert!(test_utils::get_logs().len() == 2);
+        // sbt_recover
+        let mut result = ctr._sbt_recover(alice(), alice2(), 3);
+        assert_eq!((3, false), result);
+        assert_eq!(ctr.sbt_supply_by_owner(alice2(), issuer1(), None), 3);
+        assert!(test_utils::get_logs().len() == 1);
+        result = ctr._sbt_recover(alice(), alice2(), 3);
+        assert_eq!((1, true), result);
+        assert!(test_utils::get_logs().len() == 2);
+
+        assert_eq!(ctr.sbt_supply_by_owner(alice(), issuer1(), None), 0);
+        assert_eq!(ctr.sbt_supply_by_owner(alice2(), issuer1(), None), 4);
+    }
+
+    #[test]
+    fn sbt_recover_skips_other_issuers() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 3 * MINT_DEPOSIT);
+        ctr.sbt_mint(vec![(alice(), mk_batch_metadata(3))]);
+        next_tx(&mut ctx, &issuer3());
+        ctr.sbt_mint(vec![(alice(), mk_batch_metadata(2))]);
+        next_tx(&mut ctx, &issuer2());
+        ctr.sbt_mint(vec![(alice(), mk_batch_metadata(3))]);
+
+        assert_eq!(ctr._sbt_recover(alice(), alice2(), 2), (2, false));
+        assert_eq!(ctr.recoveries.get(&alice()), Some(2));
+        assert_eq!(ctr._sbt_recover(alice(), alice2(), 2), (1, true));
+        assert_eq!(ctr.recoveries.get(&alice()), None);
+        assert!(!ctr.ongoing_soul_tx.contains_key(&alice()));
+        assert_eq!(ctr.sbt_supply_by_owner(alice2(), issuer2(), None), 3);
+        assert_eq!(ctr.sbt_supply_by_owner(alice(), issuer1(), None), 3);
+        assert_eq!(ctr.sbt_supply_by_owner(alice(), issuer3(), None), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "recovery is in progress")]
+    fn sbt_recover_locks_soul_transfer() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 3 * MINT_DEPOSIT);
+        ctr.sbt_mint(vec![(alice(), mk_batch_metadata(3))]);
+        assert_eq!(ctr._sbt_recover(alice(), alice2(), 2), (2, false));
+        next_tx(&mut ctx, &alice());
+        ctr.sbt_soul_transfer(bob(), None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "recovery initiated by another issuer is in progress")]
+    fn sbt_recover_locks_other_issuers() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 3 * MINT_DEPOSIT);
+        ctr.sbt_mint(vec![(alice(), mk_batch_metadata(3))]);
+        assert_eq!(ctr._sbt_recover(alice(), alice2(), 2), (2, false));
+        next_tx(&mut ctx, &issuer2());
+        ctr._sbt_recover(alice(), bob(), 2);
+    }
+
+    #[test]
+    fn privacy_mode() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 2 * MINT_DEPOSIT);
+        let mut m = mk_metadata(1, Some(START + 10));
+        m.reference = Some("https://ref".to_owned());
+        m.reference_hash = Some(Base64VecU8(vec![1; 32]));
+        m.attributes_root = Some([2; 32]);
+        ctr.sbt_mint(vec![(alice(), vec![m.clone()]), (bob(), vec![m.clone()])]);
+        let mut redacted = m.clone();
+        redacted.reference = None;
+        redacted.reference_hash = None;
+        redacted.attributes_root = None;
+
+        ctx.attached_deposit = MINT_DEPOSIT;
+        next_tx(&mut ctx, &alice());
+        ctr.set_private(true);
+        assert!(ctr.is_private(alice()));
+        assert!(!ctr.is_private(bob()));
+
+        let private = vec![(issuer1(), vec![mk_owned_token(1, redacted.clone())])];
+        let full = vec![(issuer1(), vec![mk_owned_token(1, m.clone())])];
+        assert_eq!(
+            ctr.sbt_tokens_by_owner(alice(), None, None, None, None),
+            private
+        );
+        assert_eq!(
+            ctr.sbt_tokens_by_owner_page(alice(), None, None, None, None)
+                .items,
+            private
+        );
+        assert_eq!(
+            ctr.sbt_tokens_by_owner_at(alice(), None, None, None, START),
+            private
+        );
+        assert_eq!(ctr.sbt(issuer1(), 1).unwrap().metadata, redacted);
+        assert_eq!(
+            ctr.sbts(issuer1(), vec![1])[0].as_ref().unwrap().metadata,
+            redacted
+        );
+        let tokens = ctr.sbt_tokens(issuer1(), None, None, None);
+        assert_eq!(tokens[0].metadata, redacted);
+        assert_eq!(tokens[1].metadata, m, "bob tokens are not private");
+        // the proof of humanity doesn't depend on the privacy mode
+        assert_eq!(ctr.sbt_supply_by_owner(alice(), issuer1(), Some(1)), 1);
+
+        // the owner and the authority can query the full tokens
+        assert_eq!(
+            ctr.sbt_tokens_by_owner_full(alice(), None, None, None, None),
+            full
+        );
+        next_tx(&mut ctx, &admin());
+        assert_eq!(
+            ctr.sbt_tokens_by_owner_full(alice(), None, None, None, None),
+            full
+        );
+
+        next_tx(&mut ctx, &alice());
+        ctr.set_private(false);
+        assert_eq!(
+            ctr.sbt_tokens_by_owner(alice(), None, None, None, None),
+            full
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "only the owner or the authority can query the full tokens")]
+    fn privacy_mode_full_tokens_other_caller() {
+        let (_, ctr) = setup(&bob(), 0);
+        ctr.sbt_tokens_by_owner_full(alice(), None, None, None, None);
+    }
+
+    #[test]
+    fn sbt_revoke_with_reason() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 3 * MINT_DEPOSIT);
+        ctr.sbt_mint(vec![(
+            alice(),
+            vec![
+                mk_metadata(1, Some(START + 10)),
+                mk_metadata(2, Some(START + 10)),
+                mk_metadata(3, Some(START + 10)),
+            ],
+        )]);
+
+        next_tx(&mut ctx, &issuer1());
+        ctr.sbt_revoke_with_reason(vec![1], false, RevocationReason::UserRequest);
+        assert_eq!(
+            test_utils::get_logs(),
+            mk_log_str(
+                "revoke",
+                &format!(
+                    r#"{{"issuer":"{}","tokens":[1],"reason":"user_request"}}"#,
+                    issuer1()
+                ),
+            )
+        );
+        assert_eq!(ctr.sbt_status(issuer1(), 1), Some(TokenStatus::Revoked));
+        assert_eq!(
+            ctr.sbt_revocation_reason(issuer1(), 1),
+            Some(RevocationReason::UserRequest)
+        );
+        // the reason is kept with the tombstone
+        ctr.sbt_revoke(vec![1], true);
+        assert_eq!(ctr.sbt_status(issuer1(), 1), Some(TokenStatus::Burned));
+        assert_eq!(
+            ctr.sbt_revocation_reason(issuer1(), 1),
+            Some(RevocationReason::UserRequest)
+        );
+
+        next_tx(&mut ctx, &issuer1());
+        ctr.sbt_revoke_with_reason(vec![2], true, RevocationReason::IssuerError);
+        let logs = test_utils::get_logs();
+        assert_eq!(logs.len(), 2);
+        assert!(logs
+            .iter()
+            .all(|l| l.contains(r#""reason":"issuer_error""#)));
+        assert_eq!(
+            ctr.sbt_revocation_reason(issuer1(), 2),
+            Some(RevocationReason::IssuerError)
+        );
 
-        assert_eq!(ctr.sbt_supply_by_owner(alice(), issuer1(), None), 0);
-        assert_eq!(ctr.sbt_supply_by_owner(alice2(), issuer1(), None), 4);
+        // freeze reason is removed on unfreeze
+        next_tx(&mut ctx, &admin());
+        ctr.admin_freeze_tokens(issuer1(), vec![3], None, Some(RevocationReason::Fraud));
+        assert_eq!(
+            ctr.sbt_revocation_reason(issuer1(), 3),
+            Some(RevocationReason::Fraud)
+        );
+        ctr.admin_unfreeze_tokens(issuer1(), vec![3], None);
+        assert_eq!(ctr.sbt_revocation_reason(issuer1(), 3), None);
+        // routine revoke without a reason
+        next_tx(&mut ctx, &issuer1());
+        ctr.sbt_revoke(vec![3], false);
+        assert_eq!(ctr.sbt_revocation_reason(issuer1(), 3), None);
     }
 
     #[test]
     fn sbt_revoke() {
-        let (mut ctx, mut ctr) = setup(&issuer1(), 2 * MINT_DEPOSIT);
+        let (mut ctx, mut ctr) = setup(&issuer1(), 3 * MINT_DEPOSIT);
 
         let m1_1 = mk_metadata(1, Some(START + 10));
         let m2_1 = mk_metadata(2, Some(START + 11));
         let m3_1 = mk_metadata(3, Some(START + 21));
 
-        let current_timestamp = ctx.block_timestamp;
+        let current_timestamp = NanoSeconds(ctx.block_timestamp).to_ms().0;
 
         let m1_1_revoked = mk_metadata(1, Some(current_timestamp));
         let m2_1_revoked = mk_metadata(2, Some(current_timestamp));
@@ -1628,8 +7904,8 @@ mod tests {
         assert_eq!(test_utils::get_logs().len(), 1);
         assert_eq!(test_utils::get_logs()[0], log_revoke[0]);
 
-        assert_eq!(ctr.sbt_supply(issuer1()), 3);
-        assert_eq!(ctr.sbt_supply(issuer2()), 3);
+        assert_eq!(ctr.sbt_supply(issuer1(), None), 3);
+        assert_eq!(ctr.sbt_supply(issuer2(), None), 3);
         assert_eq!(ctr.sbt_supply_by_owner(alice(), issuer1(), None), 3);
         assert_eq!(ctr.sbt_supply_by_owner(bob(), issuer2(), None), 2);
         assert_eq!(
@@ -1663,7 +7939,7 @@ mod tests {
 
     #[test]
     fn sbt_revoke_burn() {
-        let (mut ctx, mut ctr) = setup(&issuer1(), 2 * MINT_DEPOSIT);
+        let (mut ctx, mut ctr) = setup(&issuer1(), 3 * MINT_DEPOSIT);
 
         let m1_1 = mk_metadata(1, Some(START + 10));
         let m2_1 = mk_metadata(2, Some(START + 11));
@@ -1693,12 +7969,15 @@ mod tests {
 
         let log_burn = mk_log_str(
             "burn",
-            &format!(r#"{{"issuer":"{}","tokens":[1,2,3]}}"#, issuer1()),
+            &format!(
+                r#"{{"issuer":"{}","tokens":[1,2,3],"storage_delta_bytes":-820,"deposit_charged":"0"}}"#,
+                issuer1()
+            ),
         );
         assert_eq!(test_utils::get_logs().len(), 2);
         assert_eq!(test_utils::get_logs()[0], log_burn[0]);
-        assert_eq!(ctr.sbt_supply(issuer1()), 1);
-        assert_eq!(ctr.sbt_supply(issuer2()), 3);
+        assert_eq!(ctr.sbt_supply(issuer1(), None), 1);
+        assert_eq!(ctr.sbt_supply(issuer2(), None), 3);
         assert_eq!(ctr.sbt_supply_by_owner(alice(), issuer1(), None), 1);
         assert_eq!(ctr.sbt_supply_by_owner(alice(), issuer2(), None), 1);
         assert_eq!(ctr.sbt_supply_by_owner(bob(), issuer2(), None), 2);
@@ -1733,7 +8012,7 @@ mod tests {
 
         ctx.predecessor_account_id = alice();
         testing_env!(ctx.clone());
-        ctr.sbt_soul_transfer(alice2(), None);
+        ctr.sbt_soul_transfer(alice2(), None, None);
 
         assert!(ctr.is_banned(alice()));
         assert!(!ctr.is_banned(alice2()));
@@ -1796,8 +8075,7 @@ mod tests {
     }
 
     #[test]
-    #[should_panic(expected = "HostError(GasLimitExceeded)")]
-    fn sbt_recover_limit_exceeded() {
+    fn sbt_recover_gas_bounded() {
         let (mut ctx, mut ctr) = setup(&issuer2(), 150 * MINT_DEPOSIT);
         let batch_metadata = mk_batch_metadata(100);
         assert!(batch_metadata.len() == 100);
@@ -1836,26 +8114,57 @@ mod tests {
 
         // sbt_recover alice->alice2
         ctx.predecessor_account_id = issuer2();
-        ctx.prepaid_gas = max_gas();
+        // same as in soul_transfer_gas_bounded: the gas must end the sweep
+        ctx.prepaid_gas = Gas::ONE_TERA.mul(40);
         testing_env!(ctx.clone());
-        let limit: u32 = 25; // this value exceedes the gas limit allowed per call and should fail
-
-        let mut result = ctr._sbt_recover(alice(), alice2(), limit as usize);
+        let mut result = ctr.sbt_recover(alice(), alice2());
+        assert!(!result.1);
+        assert!(result.0 > 0 && result.0 < MAX_SWEEP_BATCH as u32);
+        let mut recovered = result.0;
         while !result.1 {
-            ctx.prepaid_gas = max_gas();
+            ctx.prepaid_gas = Gas::ONE_TERA.mul(40);
             testing_env!(ctx.clone());
-            result = ctr._sbt_recover(alice(), alice2(), limit as usize);
+            result = ctr.sbt_recover(alice(), alice2());
+            recovered += result.0;
         }
+        assert_eq!(recovered, 100);
 
         // check all the balances afterwards
         assert_eq!(ctr.sbt_supply_by_owner(alice(), issuer2(), None), 0);
         assert_eq!(ctr.sbt_supply_by_owner(alice2(), issuer2(), None), 100);
     }
 
+    #[test]
+    fn bounded_iter() {
+        let (mut ctx, _) = setup(&issuer1(), 0);
+        ctx.prepaid_gas = Gas::ONE_TERA.mul(100);
+        testing_env!(ctx);
+        let reserve = Gas::ONE_TERA.mul(20);
+        let mut steps = BoundedIter::new(0_u32.., reserve);
+        let mut n = 0;
+        for i in &mut steps {
+            env::storage_write(&i.to_be_bytes(), &[1; 100]);
+            n += 1;
+        }
+        assert!(steps.stopped());
+        assert!(n > 1);
+        assert!(env::prepaid_gas() - env::used_gas() >= reserve);
+
+        // iteration is not stopped when all items are processed
+        let mut steps = BoundedIter::new(0..3, reserve);
+        assert_eq!((&mut steps).count(), 3);
+        assert!(!steps.stopped());
+
+        // the first item is always yielded
+        let mut steps = BoundedIter::new(0..3, Gas::ONE_TERA.mul(1000));
+        assert_eq!((&mut steps).collect::<Vec<_>>(), vec![0]);
+        assert!(steps.stopped());
+    }
+
     #[test]
     #[should_panic(expected = "from account is banned. Cannot start the transfer")]
     fn sbt_soul_transfer_from_banned_account() {
-        let (mut ctx, mut ctr) = setup(&issuer1(), 1 * MINT_DEPOSIT);
+        let (mut ctx, mut ctr) = setup(&issuer1(), MINT_DEPOSIT);
         let m1_1 = mk_metadata(1, Some(START + 10));
         ctr.sbt_mint(vec![(alice(), vec![m1_1.clone()])]);
         assert!(!ctr.is_banned(alice()));
@@ -1866,13 +8175,13 @@ mod tests {
 
         ctx.predecessor_account_id = alice();
         testing_env!(ctx.clone());
-        ctr.sbt_soul_transfer(alice2(), None);
+        ctr.sbt_soul_transfer(alice2(), None, None);
     }
 
     #[test]
     #[should_panic(expected = "receiver account is banned. Cannot start the transfer")]
     fn sbt_soul_transfer_to_banned_account() {
-        let (mut ctx, mut ctr) = setup(&issuer1(), 1 * MINT_DEPOSIT);
+        let (mut ctx, mut ctr) = setup(&issuer1(), MINT_DEPOSIT);
         let m1_1 = mk_metadata(1, Some(START + 10));
         ctr.sbt_mint(vec![(alice(), vec![m1_1.clone()])]);
         assert!(!ctr.is_banned(alice()));
@@ -1883,7 +8192,7 @@ mod tests {
 
         ctx.predecessor_account_id = alice();
         testing_env!(ctx.clone());
-        ctr.sbt_soul_transfer(alice2(), None);
+        ctr.sbt_soul_transfer(alice2(), None, None);
     }
 
     #[test]
@@ -1896,18 +8205,19 @@ mod tests {
         ctx.predecessor_account_id = alice();
         testing_env!(ctx.clone());
         // soul transfer
-        let result: (u32, bool) = ctr.sbt_soul_transfer(alice2(), None);
-        assert!(result.1 == false);
+        let result: (u32, bool) = ctr.sbt_soul_transfer(alice2(), None, None);
+        assert!(!result.1);
 
         // assert the from account is banned after the first soul transfer execution
         assert!(ctr.is_banned(alice()));
         assert!(!ctr.is_banned(alice2()));
 
-        ctx.prepaid_gas = max_gas();
-        testing_env!(ctx.clone());
-        ctr.sbt_soul_transfer(alice2(), None);
-        let result: (u32, bool) = ctr.sbt_soul_transfer(alice2(), None);
-        assert!(result.1 == true);
+        let mut result = result;
+        while !result.1 {
+            ctx.prepaid_gas = max_gas();
+            testing_env!(ctx.clone());
+            result = ctr.sbt_soul_transfer(alice2(), None, None);
+        }
 
         // assert it stays banned after the soul transfer has been completed
         assert!(ctr.is_banned(alice()));
@@ -2007,6 +8317,297 @@ mod tests {
         assert!(!ctr.is_human(bob()));
     }
 
+    #[test]
+    fn negative_classes() {
+        let (mut ctx, mut ctr) = setup(&fractal_mainnet(), 150 * MINT_DEPOSIT);
+        ctr.sbt_mint(vec![
+            (alice(), vec![mk_metadata(1, Some(START + 100))]),
+            (bob(), vec![mk_metadata(1, Some(START + 100))]),
+        ]);
+        // confirmed bot class of issuer1, alice token is issued at 50ms
+        ctx.block_timestamp = 50 * MILI_SECOND;
+        next_tx(&mut ctx, &issuer1());
+        ctr.sbt_mint(vec![(alice(), vec![mk_metadata(7, Some(80))])]);
+        next_tx(&mut ctx, &admin());
+        assert!(ctr.is_human(alice()));
+
+        ctr.admin_set_negative_classes(vec![(issuer1(), 7), (issuer2(), 7)]);
+        assert_eq!(ctr.negative_classes(), vec![(issuer1(), 7), (issuer2(), 7)]);
+        // the negative token takes precedence over the proof of humanity
+        assert!(!ctr.is_human(alice()));
+        assert!(!ctr.account_summary(alice()).human);
+        assert!(ctr.humanity_claim_payload(alice()).is_none());
+        assert!(ctr.is_human(bob()));
+        // the negative token is evaluated at the timestamp
+        assert!(ctr.is_human_at(alice(), 40));
+        assert!(!ctr.is_human_at(alice(), 80));
+        assert!(ctr.is_human_at(alice(), 81));
+
+        // removing the negative class restores the proof of humanity
+        ctr.admin_set_negative_classes(vec![]);
+        assert_eq!(ctr.negative_classes(), vec![]);
+        assert!(ctr.is_human(alice()));
+
+        // expired negative token doesn't count, a ban still does
+        ctr.admin_set_negative_classes(vec![(issuer1(), 7)]);
+        ctx.block_timestamp = 90 * MILI_SECOND;
+        next_tx(&mut ctx, &admin());
+        assert!(ctr.is_human(alice()));
+        ctr.admin_ban(alice(), None, None);
+        assert!(!ctr.is_human(alice()));
+    }
+
+    #[test]
+    #[should_panic(expected = "proof of humanity class can't be negative")]
+    fn negative_classes_iah_class() {
+        let (_, mut ctr) = setup(&admin(), 0);
+        ctr.admin_set_negative_classes(vec![(issuer1(), 1), (fractal_mainnet(), 1)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "duplicated negative class")]
+    fn negative_classes_duplicated() {
+        let (_, mut ctr) = setup(&admin(), 0);
+        ctr.admin_set_negative_classes(vec![(issuer1(), 2), (issuer1(), 2)]);
+    }
+
+    #[test]
+    fn is_human_at() {
+        let (mut ctx, mut ctr) = setup(&fractal_mainnet(), 150 * MINT_DEPOSIT);
+        ctr.sbt_mint(vec![(alice(), vec![mk_metadata(1, Some(100))])]);
+        // bob token is issued at 50ms
+        ctx.block_timestamp = 50 * MILI_SECOND;
+        next_tx(&mut ctx, &fractal_mainnet());
+        ctr.sbt_mint(vec![(bob(), vec![mk_metadata(1, None)])]);
+
+        ctx.block_timestamp = 200 * MILI_SECOND;
+        next_tx(&mut ctx, &admin());
+        assert!(!ctr.is_human(alice()));
+        assert!(ctr.is_human_at(alice(), 99));
+        assert!(!ctr.is_human_at(alice(), 101));
+        // all views consider the token valid at its `expires_at`
+        ctr.admin_set_credential("kyc".to_owned(), Some(vec![(fractal_mainnet(), 1)]));
+        assert!(ctr.is_human_at(alice(), 100));
+        assert!(ctr.has_credential_at(alice(), "kyc".to_owned(), 100));
+        assert!(!ctr.has_credential_at(alice(), "kyc".to_owned(), 101));
+        assert_eq!(
+            ctr.sbt_tokens_by_owner_at(alice(), None, None, None, 99)[0]
+                .1
+                .len(),
+            1
+        );
+        assert_eq!(
+            ctr.sbt_tokens_by_owner_at(alice(), None, None, None, 101),
+            vec![]
+        );
+        assert!(ctr.is_human(bob()));
+        assert!(!ctr.is_human_at(bob(), 40));
+        assert!(ctr.is_human_at(bob(), 60));
+
+        // temporary ban is evaluated at the timestamp
+        ctr.admin_ban(bob(), Some(300), None);
+        assert!(!ctr.is_human(bob()));
+        assert!(!ctr.is_human_at(bob(), 250));
+        assert!(ctr.is_human_at(bob(), 300));
+
+        ctr.admin_set_credential("fv".to_owned(), Some(vec![(fractal_mainnet(), 1)]));
+        assert!(ctr.has_credential_at(alice(), "fv".to_owned(), 99));
+        assert!(!ctr.has_credential_at(alice(), "fv".to_owned(), 101));
+        assert!(!ctr.has_credential_at(bob(), "fv".to_owned(), 250));
+        assert!(ctr.has_credential_at(bob(), "fv".to_owned(), 300));
+    }
+
+    #[test]
+    fn mark_used() {
+        let (mut ctx, mut ctr) = setup(&fractal_mainnet(), 2 * MINT_DEPOSIT);
+        let ubi = AccountId::new_unchecked("ubi.near".to_string());
+        let e1 = "epoch-1".to_owned();
+        ctr.sbt_mint(vec![(alice(), vec![mk_metadata(1, None)])]);
+
+        next_tx(&mut ctx, &ubi);
+        assert!(!ctr.is_used(ubi.clone(), alice(), e1.clone()));
+        assert!(ctr.mark_used(alice(), e1.clone()));
+        assert!(created_transfers().is_empty());
+        assert!(!ctr.mark_used(alice(), e1.clone()));
+        assert_eq!(created_transfers(), vec![(ubi.clone(), 2 * MINT_DEPOSIT)]);
+        assert!(ctr.is_used(ubi.clone(), alice(), e1.clone()));
+        assert!(ctr.mark_used(alice(), "epoch-2".to_owned()));
+        // markers are namespaced by the app
+        assert!(!ctr.is_used(issuer1(), alice(), e1.clone()));
+
+        // the marker follows the human proof token
+        next_tx(&mut ctx, &alice());
+        assert_eq!(ctr.sbt_soul_transfer(alice2(), None, None), (1, true));
+        next_tx(&mut ctx, &ubi);
+        assert!(ctr.is_used(ubi.clone(), alice2(), e1.clone()));
+        assert!(!ctr.mark_used(alice2(), e1.clone()));
+        next_tx(&mut ctx, &issuer1());
+        assert!(ctr.mark_used(alice2(), e1));
+    }
+
+    #[test]
+    #[should_panic(expected = "account is not a human")]
+    fn mark_used_not_human() {
+        let (_, mut ctr) = setup(&issuer1(), 2 * MINT_DEPOSIT);
+        ctr.mark_used(alice(), "epoch-1".to_owned());
+    }
+
+    #[test]
+    fn social_badge() {
+        let (mut ctx, mut ctr) = setup(&admin(), 3 * MINT_DEPOSIT);
+        let social = AccountId::new_unchecked("social.near".to_owned());
+        let config = SocialBadgeConfig {
+            social_db: social.clone(),
+            issuer: issuer1(),
+            class: 2,
+            badge: "iah-verified".to_owned(),
+        };
+        ctr.admin_set_social_badge(Some(config.clone()));
+        assert_eq!(ctr.social_badge(), Some(config));
+
+        next_tx(&mut ctx, &issuer1());
+        ctr.sbt_mint(vec![
+            (alice(), vec![mk_metadata(1, None), mk_metadata(2, None)]),
+            (bob(), vec![mk_metadata(1, None)]),
+            (carol(), vec![mk_metadata(2, None)]),
+        ]);
+        let data = format!(
+            r#"{{"data":{{"{}":{{"badge":{{"iah-verified":{{"holder":{{"{}":"","{}":""}}}}}}}}}}}}"#,
+            ctx.current_account_id,
+            alice(),
+            carol()
+        );
+        assert_eq!(created_calls(), vec![(social, "set".to_owned(), data)]);
+
+        // other issuers don't set the badge
+        next_tx(&mut ctx, &issuer2());
+        ctr.sbt_mint(vec![(alice(), vec![mk_metadata(2, None)])]);
+        assert_eq!(created_calls(), vec![]);
+
+        // the badge is skipped when there is not enough gas
+        next_tx(&mut ctx, &issuer1());
+        ctx.prepaid_gas = SOCIAL_SET_GAS;
+        testing_env!(ctx.clone());
+        ctr.sbt_mint(vec![(bob(), vec![mk_metadata(2, None)])]);
+        assert_eq!(created_calls(), vec![]);
+        assert_eq!(
+            test_utils::get_logs().last().unwrap(),
+            "social badge skipped: not enough gas"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "badge must have 1 to 64 alphanumeric, '-' or '_' characters")]
+    fn social_badge_invalid_name() {
+        let (_, mut ctr) = setup(&admin(), 3 * MINT_DEPOSIT);
+        ctr.admin_set_social_badge(Some(SocialBadgeConfig {
+            social_db: AccountId::new_unchecked("social.near".to_owned()),
+            issuer: issuer1(),
+            class: 2,
+            badge: "iah/verified".to_owned(),
+        }));
+    }
+
+    #[test]
+    fn export_state() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 150 * MINT_DEPOSIT);
+        ctr.sbt_mint(vec![
+            (alice(), vec![mk_metadata(1, None), mk_metadata(2, None)]),
+            (bob(), vec![mk_metadata(1, None)]),
+        ]);
+        next_tx(&mut ctx, &issuer2());
+        ctr.sbt_mint(vec![(carol(), vec![mk_metadata(1, None)])]);
+        next_tx(&mut ctx, &alice());
+        ctr.sbt_burn(issuer1(), vec![1], None);
+
+        let export = |section, from: Option<Vec<u8>>| {
+            let chunk = ctr.export_state(section, from.map(Into::into), Some(2));
+            decode_export_chunk(&chunk.0).unwrap()
+        };
+        let issuer = |issuer_id, account| ExportedIssuer {
+            issuer_id,
+            account,
+            registered: true,
+        };
+        let c1 = export(ExportSection::Issuers, None);
+        assert_eq!(
+            c1.records,
+            ExportRecords::Issuers(vec![issuer(1, issuer1()), issuer(2, issuer2())])
+        );
+        let c2 = export(ExportSection::Issuers, c1.next);
+        assert_eq!(
+            c2.records,
+            ExportRecords::Issuers(vec![issuer(3, issuer3()), issuer(4, fractal_mainnet())])
+        );
+        assert_eq!(c2.next, None);
+
+        let token = |issuer_id, token, owner, class| ExportedToken {
+            issuer_id,
+            token,
+            owner,
+            metadata: mk_metadata(class, None),
+        };
+        // the burned token ID is scanned, but not exported
+        let c1 = export(ExportSection::Tokens, None);
+        assert_eq!(
+            c1.records,
+            ExportRecords::Tokens(vec![token(1, 2, alice(), 2)])
+        );
+        let c2 = export(ExportSection::Tokens, c1.next);
+        assert_eq!(
+            c2.records,
+            ExportRecords::Tokens(vec![token(1, 3, bob(), 1), token(2, 1, carol(), 1)])
+        );
+        assert_eq!(c2.next, None);
+
+        let balance = |owner, issuer_id, class_id, token| ExportedBalance {
+            owner,
+            issuer_id,
+            class_id,
+            token,
+        };
+        let c1 = export(ExportSection::Balances, None);
+        assert_eq!(
+            c1.records,
+            ExportRecords::Balances(vec![balance(alice(), 1, 2, 2), balance(bob(), 1, 1, 3)])
+        );
+        let c2 = export(ExportSection::Balances, c1.next);
+        assert_eq!(
+            c2.records,
+            ExportRecords::Balances(vec![balance(carol(), 2, 1, 1)])
+        );
+        assert_eq!(c2.next, None);
+    }
+
+    #[test]
+    fn export_state_private() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 2 * MINT_DEPOSIT);
+        ctr.sbt_mint(vec![(alice(), vec![mk_metadata(1, None)])]);
+        next_tx(&mut ctx, &alice());
+        ctr.set_private(true);
+        let chunk = ctr.export_state(ExportSection::Tokens, None, None);
+        let mut metadata = mk_metadata(1, None);
+        metadata.reference = None;
+        metadata.reference_hash = None;
+        metadata.attributes_root = None;
+        assert_eq!(
+            decode_export_chunk(&chunk.0).unwrap().records,
+            ExportRecords::Tokens(vec![ExportedToken {
+                issuer_id: 1,
+                token: 1,
+                owner: alice(),
+                metadata,
+            }])
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "invalid export cursor")]
+    fn export_state_invalid_cursor() {
+        let (_, ctr) = setup(&issuer1(), 2 * MINT_DEPOSIT);
+        ctr.export_state(ExportSection::Tokens, Some(vec![1].into()), None);
+    }
+
     #[test]
     fn is_human_multiple_classes() {
         let (mut ctx, mut ctr) = setup(&fractal_mainnet(), 150 * MINT_DEPOSIT);
@@ -2064,7 +8665,10 @@ mod tests {
         );
         let log_burn = mk_log_str(
             "burn",
-            &format!(r#"{{"issuer":"{}","tokens":[1]}}"#, fractal_mainnet()),
+            &format!(
+                r#"{{"issuer":"{}","tokens":[1],"storage_delta_bytes":-418,"deposit_charged":"0"}}"#,
+                fractal_mainnet()
+            ),
         );
 
         // check only revoke event is emitted
@@ -2079,7 +8683,403 @@ mod tests {
 
         // check both burn and revoke events are emitted
         assert_eq!(test_utils::get_logs().len(), 2); // -> only 1 event is emmited
-        assert_eq!(test_utils::get_logs(), vec![log_burn, log_revoke].concat());
+        assert_eq!(test_utils::get_logs(), [log_burn, log_revoke].concat());
         // -> missing revoke event
     }
+
+    #[test]
+    fn genesis_import() {
+        let (mut ctx, mut ctr) = setup(&admin(), 20 * MINT_DEPOSIT);
+        ctr.admin_set_issuer_capabilities(issuer2(), CAP_DEFAULT | CAP_IMPORT, None);
+        ctx.block_timestamp = 1000 * MILI_SECOND;
+        next_tx(&mut ctx, &admin());
+        let mut m1 = mk_metadata(1, Some(START + 500));
+        m1.issued_at = Some(1);
+        let m2 = mk_metadata(2, None);
+        let mut m3 = mk_metadata(1, None);
+        m3.issued_at = None;
+        ctr.admin_import_tokens(
+            issuer2(),
+            vec![
+                (alice(), m1.clone(), 5),
+                (alice(), m2.clone(), 7),
+                (bob(), m3.clone(), 12),
+            ],
+        );
+        assert_eq!(
+            test_utils::get_logs(),
+            mk_log_str(
+                "mint",
+                &format!(
+                    r#"{{"issuer":"{}","tokens":[["{}",[5,7]],["{}",[12]]],"storage_delta_bytes":1588,"deposit_charged":"15880000000000000000000"}}"#,
+                    issuer2(),
+                    alice(),
+                    bob()
+                )
+            )
+        );
+        assert_eq!(ctr.sbt(issuer2(), 5), Some(mk_token(5, alice(), m1)));
+        assert_eq!(ctr.sbt(issuer2(), 7), Some(mk_token(7, alice(), m2)));
+        m3.issued_at = Some(1000);
+        assert_eq!(ctr.sbt(issuer2(), 12), Some(mk_token(12, bob(), m3)));
+        assert_eq!(ctr.sbt(issuer2(), 6), None);
+        assert_eq!(ctr.last_token_id(issuer2()), 12);
+        assert_eq!(ctr.sbt_supply(issuer2(), None), 3);
+        assert_eq!(ctr.sbt_supply_by_class(issuer2(), 1), 2);
+        assert_eq!(ctr.sbt_supply_by_owner(alice(), issuer2(), None), 2);
+
+        // minted tokens continue after the imported IDs
+        next_tx(&mut ctx, &issuer2());
+        let ids = ctr.sbt_mint(vec![(carol(), vec![mk_metadata(1, None)])]);
+        assert_eq!(ids, vec![13]);
+
+        next_tx(&mut ctx, &admin());
+        assert!(!ctr.is_genesis_finalized());
+        ctr.finalize_genesis();
+        assert!(ctr.is_genesis_finalized());
+        assert_eq!(
+            test_utils::get_logs(),
+            vec![
+                r#"EVENT_JSON:{"standard":"i_am_human_registry","version":"1.0.0","event":"finalize_genesis"}"#
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "genesis is finalized")]
+    fn genesis_import_finalized() {
+        let (_, mut ctr) = setup(&admin(), 20 * MINT_DEPOSIT);
+        ctr.admin_set_issuer_capabilities(issuer2(), CAP_IMPORT, None);
+        ctr.finalize_genesis();
+        ctr.admin_import_tokens(issuer2(), vec![(alice(), mk_metadata(1, None), 1)]);
+    }
+
+    #[test]
+    #[should_panic(expected = "token ID 3 must be bigger than 4")]
+    fn genesis_import_token_order() {
+        let (_, mut ctr) = setup(&admin(), 20 * MINT_DEPOSIT);
+        ctr.admin_set_issuer_capabilities(issuer2(), CAP_IMPORT, None);
+        ctr.admin_import_tokens(
+            issuer2(),
+            vec![
+                (alice(), mk_metadata(1, None), 4),
+                (bob(), mk_metadata(1, None), 3),
+            ],
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "issuer doesn't have the required capability")]
+    fn genesis_import_capability() {
+        let (_, mut ctr) = setup(&admin(), 20 * MINT_DEPOSIT);
+        ctr.admin_import_tokens(issuer2(), vec![(alice(), mk_metadata(1, None), 1)]);
+    }
+
+    #[test]
+    fn stamps() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 3 * STAMP_COST);
+        let long = AccountId::new_unchecked(format!("{}.near", "a".repeat(59)));
+        let storage_start = env::storage_usage();
+        ctr.sbt_mint_stamps(1, vec![alice(), bob(), long.clone()]);
+        let stamp_bytes = (env::storage_usage() - storage_start) / 3;
+        assert!((stamp_bytes as u128) * env::storage_byte_cost() <= STAMP_COST);
+        assert_eq!(
+            test_utils::get_logs(),
+            mk_registry_log_str(
+                "mint_stamps",
+                &format!(
+                    r#"{{"issuer":"{}","class":1,"recipients":["alice.near","bob.near","{}"]}}"#,
+                    issuer1(),
+                    long
+                )
+            )
+        );
+        assert_eq!(ctr.stamp_issued_at(alice(), issuer1(), 1), Some(0));
+        assert_eq!(ctr.stamp_issued_at(alice(), issuer1(), 2), None);
+        assert_eq!(ctr.stamp_issued_at(alice(), issuer2(), 1), None);
+        assert_eq!(ctr.stamp_issued_at(carol(), issuer1(), 1), None);
+        assert_eq!(ctr.stamp_supply(issuer1(), 1), 3);
+        // stamps are not tokens
+        assert_eq!(ctr.sbt_supply(issuer1(), None), 0);
+        assert_eq!(
+            ctr.sbt_tokens_by_owner(alice(), None, None, None, None),
+            vec![]
+        );
+
+        ctx.block_timestamp = 1000 * MILI_SECOND;
+        next_tx(&mut ctx, &issuer1());
+        ctr.sbt_mint_stamps(2, vec![alice()]);
+        assert_eq!(ctr.stamp_issued_at(alice(), issuer1(), 2), Some(1000));
+
+        next_tx(&mut ctx, &issuer1());
+        ctr.sbt_revoke_stamps(1, vec![alice(), carol()]);
+        assert_eq!(
+            test_utils::get_logs(),
+            mk_registry_log_str(
+                "revoke_stamps",
+                &format!(
+                    r#"{{"issuer":"{}","class":1,"accounts":["alice.near"]}}"#,
+                    issuer1()
+                )
+            )
+        );
+        assert_eq!(ctr.stamp_issued_at(alice(), issuer1(), 1), None);
+        assert_eq!(ctr.stamp_supply(issuer1(), 1), 2);
+        // nothing to revoke
+        next_tx(&mut ctx, &issuer1());
+        ctr.sbt_revoke_stamps(1, vec![alice()]);
+        assert_eq!(test_utils::get_logs().len(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "alice.near already has a stamp of class 1")]
+    fn stamps_duplicate() {
+        let (_, mut ctr) = setup(&issuer1(), 3 * STAMP_COST);
+        ctr.sbt_mint_stamps(1, vec![alice(), bob(), alice()]);
+    }
+
+    #[test]
+    #[should_panic(expected = "account alice.near is banned")]
+    fn stamps_banned() {
+        let (mut ctx, mut ctr) = setup(&admin(), 3 * STAMP_COST);
+        ctr.admin_ban(alice(), None, None);
+        next_tx(&mut ctx, &issuer1());
+        ctr.sbt_mint_stamps(1, vec![alice()]);
+    }
+
+    #[test]
+    fn whale_account() {
+        let (mut ctx, mut ctr) = setup(&admin(), 100 * MINT_DEPOSIT);
+        ctr.admin_set_max_tokens_per_account(MAX_TOKENS_PER_ACCOUNT);
+        ctr.admin_set_max_classes_per_issuer(MAX_TOKENS_PER_ACCOUNT);
+        let n = MAX_TOKENS_PER_ACCOUNT as u64;
+        for batch in 0..n / 100 {
+            next_tx(&mut ctx, &issuer1());
+            let metadata = (1..=100)
+                .map(|c| mk_metadata(batch * 100 + c, None))
+                .collect();
+            ctr.sbt_mint(vec![(alice(), metadata)]);
+        }
+        next_tx(&mut ctx, &issuer2());
+        ctr.sbt_mint(vec![(bob(), vec![mk_metadata(1, None)])]);
+
+        // views of the whale account and its neighbour
+        next_tx(&mut ctx, &admin());
+        assert_eq!(ctr.sbt_supply_by_owner(alice(), issuer1(), None), n);
+        assert_eq!(ctr.sbt_supply_by_owner(alice(), issuer1(), Some(n)), 1);
+        let page = ctr.sbt_tokens_by_owner(alice(), Some(issuer1()), Some(n - 1), None, None);
+        assert_eq!(page[0].1.len(), 2);
+        assert_eq!(page[0].1[1].token, n);
+        let page = ctr.sbt_tokens_by_owner(alice(), None, None, None, None);
+        assert_eq!(page[0].1.len(), registry::MAX_LIMIT as usize);
+        assert_eq!(
+            ctr.sbt_tokens_by_owner(bob(), None, None, None, None)[0]
+                .1
+                .len(),
+            1
+        );
+
+        // soul transfer completes in gas bounded calls
+        let mut moved = 0;
+        let mut calls = 0;
+        loop {
+            next_tx(&mut ctx, &alice());
+            let (num, completed) = ctr.sbt_soul_transfer(alice2(), None, None);
+            moved += num as u64;
+            calls += 1;
+            if completed {
+                break;
+            }
+        }
+        assert_eq!(moved, n);
+        assert!(calls <= n / MAX_SWEEP_BATCH as u64 + 1);
+        assert_eq!(ctr.sbt_supply_by_owner(alice2(), issuer1(), None), n);
+        assert_eq!(ctr.sbt_supply_by_owner(alice(), issuer1(), None), 0);
+        assert_eq!(ctr.sbt_supply_by_owner(bob(), issuer2(), None), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "limit must be between 1 and 10000")]
+    fn max_tokens_per_account_cap() {
+        let (_, mut ctr) = setup(&admin(), 0);
+        ctr.admin_set_max_tokens_per_account(MAX_TOKENS_PER_ACCOUNT + 1);
+    }
+
+    #[test]
+    fn attestation() {
+        let (mut ctx, mut ctr) = setup(&issuer1(), 2 * MINT_DEPOSIT);
+        ctr.sbt_mint(vec![
+            (alice(), vec![mk_metadata(1, None)]),
+            (bob(), vec![mk_metadata(2, Some(START + 10))]),
+        ]);
+        assert!(ctr.sbt_attestation(issuer1(), 3).is_none());
+        assert!(ctr.sbt_attestation(issuer2(), 1).is_none());
+
+        let p =
+            sbt::decode_attestation_proof(&ctr.sbt_attestation(issuer1(), 1).unwrap().0).unwrap();
+        let a = &p.attestation;
+        assert_eq!((a.issuer_id, a.token, a.class), (1, 1, 1));
+        assert_eq!(a.owner, alice());
+        assert_eq!(a.issuer, issuer1());
+        assert!(a.valid);
+        assert_eq!(sbt::Attestation::decode(&a.encode()).unwrap(), *a);
+        // the keys point to the registry records backing the attestation
+        let read = |k: &Vec<u8>| env::storage_read(k).unwrap();
+        assert!(a.verify_state(
+            &read(&p.keys.issuer),
+            &read(&p.keys.token),
+            &read(&p.keys.balance)
+        ));
+        assert!(env::storage_read(&p.keys.status).is_none());
+
+        ctr.sbt_revoke(vec![1], false);
+        let p =
+            sbt::decode_attestation_proof(&ctr.sbt_attestation(issuer1(), 1).unwrap().0).unwrap();
+        assert!(!p.attestation.valid);
+        assert!(env::storage_read(&p.keys.status).is_some());
+
+        // the bob token doesn't match the alice balance
+        let pb =
+            sbt::decode_attestation_proof(&ctr.sbt_attestation(issuer1(), 2).unwrap().0).unwrap();
+        assert!(pb.attestation.valid);
+        assert!(!pb.attestation.verify_state(
+            &read(&pb.keys.issuer),
+            &read(&pb.keys.token),
+            &read(&p.keys.balance)
+        ));
+        ctx.block_timestamp = (START + 11) * MILI_SECOND;
+        testing_env!(ctx);
+        let pb =
+            sbt::decode_attestation_proof(&ctr.sbt_attestation(issuer1(), 2).unwrap().0).unwrap();
+        assert!(!pb.attestation.valid);
+    }
+
+    #[test]
+    fn sbt_metadata_hash() {
+        let (_, mut ctr) = setup(&issuer1(), 2 * MINT_DEPOSIT);
+        ctr.sbt_mint(vec![
+            (alice(), vec![mk_metadata(1, Some(START + 10))]),
+            (bob(), vec![mk_metadata(1, Some(START + 10))]),
+        ]);
+        assert_eq!(ctr.sbt_metadata_hash(issuer1(), 3), None);
+        assert_eq!(ctr.sbt_metadata_hash(issuer2(), 1), None);
+        let h1 = ctr.sbt_metadata_hash(issuer1(), 1).unwrap();
+        let m = ctr.sbt(issuer1(), 1).unwrap().metadata;
+        assert_eq!(h1.0, token_metadata_hash(&m).to_vec());
+        // equal metadata have equal hashes
+        assert_eq!(ctr.sbt_metadata_hash(issuer1(), 2), Some(h1.clone()));
+
+        // the hash follows metadata updates
+        ctr.sbt_renew(vec![1], START + 20);
+        let h2 = ctr.sbt_metadata_hash(issuer1(), 1).unwrap();
+        assert_ne!(h2, h1);
+        let m = ctr.sbt(issuer1(), 1).unwrap().metadata;
+        assert_eq!(h2.0, token_metadata_hash(&m).to_vec());
+    }
+
+    #[test]
+    fn humanity_claim() {
+        use ed25519_dalek::{Keypair, SecretKey, Signer};
+
+        let (mut ctx, mut ctr) = setup(&fractal_mainnet(), 2 * MINT_DEPOSIT);
+        let now = ctx.block_timestamp / MILI_SECOND;
+        let long = now + HUMANITY_CLAIM_TTL * 2;
+        ctr.sbt_mint(vec![
+            (alice(), vec![mk_metadata(1, Some(long))]),
+            (bob(), vec![mk_metadata(1, Some(START + 10))]),
+        ]);
+        assert!(ctr.humanity_claim_payload(carol()).is_none());
+
+        let msg = ctr.humanity_claim_payload(alice()).unwrap().0;
+        let claim = sbt::HumanityClaim::decode(&msg).unwrap();
+        assert_eq!(claim.account, alice());
+        assert_eq!(claim.registry, ctx.current_account_id);
+        assert_eq!(claim.block_height, ctx.block_index);
+        assert_eq!(claim.timestamp, now);
+        assert_eq!(claim.expires_at, now + HUMANITY_CLAIM_TTL);
+        // the claim doesn't outlive the proof of humanity tokens
+        let bob_claim = sbt::HumanityClaim::decode(&ctr.humanity_claim_payload(bob()).unwrap().0);
+        assert_eq!(bob_claim.unwrap().expires_at, START + 10);
+
+        let secret = SecretKey::from_bytes(&[3; 32]).unwrap();
+        let kp = Keypair {
+            public: (&secret).into(),
+            secret,
+        };
+        let sig = kp.sign(&msg).to_bytes();
+        let pk = kp.public.to_bytes();
+        assert_eq!(
+            sbt::verify_humanity_claim(&msg, &pk, &sig, now + 1),
+            Ok(claim)
+        );
+        assert_eq!(
+            sbt::verify_humanity_claim(&msg, &pk, &sig, now + HUMANITY_CLAIM_TTL + 1),
+            Err(sbt::ClaimError::Expired)
+        );
+
+        ctx.predecessor_account_id = admin();
+        testing_env!(ctx);
+        ctr.admin_ban(alice(), None, None);
+        assert!(ctr.humanity_claim_payload(alice()).is_none());
+    }
+
+    #[test]
+    fn add_sbt_issuer_verified() {
+        let (mut ctx, mut ctr) = setup(&admin(), 0);
+        let metadata = ContractMetadata {
+            spec: "sbt-1.0.0".to_owned(),
+            name: "Issuer 4".to_owned(),
+            symbol: "I4".to_owned(),
+            icon: None,
+            base_uri: None,
+            reference: None,
+            reference_hash: None,
+        };
+        let callback = |ctx: &mut VMContext, ctr: &mut Contract, res: PromiseResult| {
+            ctx.predecessor_account_id = ctx.current_account_id.clone();
+            testing_env!(
+                ctx.clone(),
+                near_sdk::VMConfig::test(),
+                near_sdk::RuntimeFeesConfig::test(),
+                Default::default(),
+                vec![res]
+            );
+            ctr.on_issuer_metadata(issuer4(), None)
+        };
+        lock_bond(&ctx, &mut ctr, &issuer4());
+        ctr.admin_add_sbt_issuer_verified(issuer4(), None);
+
+        // the issuer call failed or returned invalid metadata
+        assert!(!callback(&mut ctx, &mut ctr, PromiseResult::Failed));
+        let ok = |m: &ContractMetadata| {
+            PromiseResult::Successful(near_sdk::serde_json::to_vec(m).unwrap())
+        };
+        let mut invalid = metadata.clone();
+        invalid.symbol = "".to_owned();
+        assert!(!callback(&mut ctx, &mut ctr, ok(&invalid)));
+        invalid.symbol = "I4".to_owned();
+        invalid.icon = Some("x".repeat(MAX_ISSUER_METADATA_LEN));
+        assert!(!callback(&mut ctx, &mut ctr, ok(&invalid)));
+        let other = PromiseResult::Successful(br#"{"name": "x"}"#.to_vec());
+        assert!(!callback(&mut ctx, &mut ctr, other));
+        assert_eq!(ctr.issuer_id(issuer4()), None);
+
+        assert!(callback(&mut ctx, &mut ctr, ok(&metadata)));
+        assert_eq!(ctr.issuer_id(issuer4()), Some(5));
+        assert_eq!(ctr.sbt_issuer_metadata(issuer4()), Some(metadata.clone()));
+        assert_eq!(
+            test_utils::get_logs(),
+            mk_registry_log_str("add_issuer", &format!(r#"{{"issuer":"{}"}}"#, issuer4()))
+        );
+        // already registered
+        assert!(!callback(&mut ctx, &mut ctr, ok(&metadata)));
+        assert_eq!(ctr.sbt_issuer_metadata(issuer1()), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "issuer is already registered")]
+    fn add_sbt_issuer_verified_registered() {
+        let (_, mut ctr) = setup(&admin(), 0);
+        ctr.admin_add_sbt_issuer_verified(issuer1(), None);
+    }
 }
@@ -0,0 +1,117 @@
+//! Lazy state migration. Storage redesigns can't be migrated in a single call once the
+//! registry state is large, so records are converted in chunks by anyone calling
+//! `migrate_chunk`, until the migration is completed.
+//! The current migration moves balances stored before the balances sharding to the shards
+//! (see `Balances`), then rewrites tokens stored before the token records were versioned, and
+//! tokens with the old metadata layout, in the latest format, and counts the valid tokens of
+//! the previous release in the valid supply (see `valid_supply.rs`).
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::{LookupMap, TreeMap, UnorderedMap, UnorderedSet};
+use near_sdk::serde::Serialize;
+use near_sdk::{near_bindgen, require, AccountId};
+use sbt::{ClassId, TokenData, TokenId};
+
+use crate::*;
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Default, Clone, Copy)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq))]
+pub struct MigrationProgress {
+    /// issuer of the next token to check. 0 at the beginning of the migration.
+    pub issuer_id: IssuerId,
+    /// next token to check
+    pub token: TokenId,
+    /// number of converted records
+    pub migrated: u64,
+    pub done: bool,
+}
+
+/// Contract state of the previous registry release, read by `Contract::migrate`. Must stay
+/// frozen: it describes the layout of the deployed state, not the current `Contract`.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub(crate) struct OldState {
+    pub authority: AccountId,
+    pub sbt_issuers: UnorderedMap<AccountId, IssuerId>,
+    pub issuer_id_map: LookupMap<IssuerId, AccountId>,
+    pub banlist: UnorderedSet<AccountId>,
+    pub ongoing_soul_tx: LookupMap<AccountId, IssuerTokenId>,
+    pub supply_by_owner: LookupMap<(AccountId, IssuerId), u64>,
+    pub supply_by_class: LookupMap<(IssuerId, ClassId), u64>,
+    pub supply_by_issuer: LookupMap<IssuerId, u64>,
+    pub balances: TreeMap<BalanceKey, TokenId>,
+    pub issuer_tokens: LookupMap<IssuerTokenId, TokenData>,
+    pub next_token_ids: LookupMap<IssuerId, TokenId>,
+    pub next_issuer_id: IssuerId,
+    pub iah_classes: (AccountId, Vec<ClassId>),
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Moves up to `limit` legacy balances to the shards, then checks up to the rest of
+    /// `limit` tokens (in the issuer, token order) and converts records which are not in the
+    /// latest format. Valid tokens of the previous release are added to the valid supply
+    /// counters. Can be called by anyone, until the returned progress is
+    /// `done`. Emits `MigrationCompleted` registry event when the last chunk is processed.
+    pub fn migrate_chunk(&mut self, limit: u32) -> MigrationProgress {
+        require!(limit > 0, "limit must be bigger than 0");
+        let mut p = self.migration;
+        let mut checked = self.balances.migrate_legacy(limit);
+        p.migrated += checked as u64;
+        while !p.done && checked < limit {
+            checked += 1;
+            let last_token = self.next_token_ids.get(&p.issuer_id).unwrap_or(0);
+            if p.token == 0 || p.token > last_token {
+                if p.issuer_id + 1 >= self.next_issuer_id {
+                    p.done = true;
+                    self.emit_registry_event(RegistryEvent::MigrationCompleted {
+                        migrated: p.migrated,
+                    });
+                } else {
+                    p.issuer_id += 1;
+                    p.token = 1;
+                }
+                continue;
+            }
+            let key = IssuerTokenId {
+                issuer_id: p.issuer_id,
+                token: p.token,
+            };
+            if self.issuer_tokens.needs_migration(&key) {
+                let mut t = self.issuer_tokens.get(&key).unwrap();
+                t.metadata = t.metadata.latest().into();
+                self.issuer_tokens.insert(&key, t);
+                p.migrated += 1;
+            }
+            if p.token <= self.legacy_token_ids.get(&p.issuer_id).unwrap_or(0) {
+                if let Some(t) = self.issuer_tokens.get(&key) {
+                    if !self.supply_by_account.contains_key(&t.owner) {
+                        let supply = self.account_supply(&t.owner);
+                        self.supply_by_account.insert(&t.owner, &supply);
+                    }
+                    if is_valid_status(self.status_of(key.issuer_id, key.token, &t)) {
+                        self.add_valid(key.issuer_id, t.metadata.expires_at(), 1);
+                    }
+                }
+            }
+            p.token += 1;
+        }
+        self.migration = p;
+        p
+    }
+
+    pub fn migration_progress(&self) -> MigrationProgress {
+        self.migration
+    }
+}
+
+impl Contract {
+    /// Returns true if the token is counted in the valid supply: it was minted after the
+    /// upgrade from the previous release, or it was already checked by `migrate_chunk`.
+    pub(crate) fn is_counted(&self, issuer_id: IssuerId, token: TokenId) -> bool {
+        let p = &self.migration;
+        p.done
+            || (issuer_id, token) < (p.issuer_id, p.token)
+            || token > self.legacy_token_ids.get(&issuer_id).unwrap_or(0)
+    }
+}
@@ -0,0 +1,77 @@
+//! Mint idempotency keys: an issuer tags mint entries with an external reference (eg the
+//! oracle request ID), and the registry rejects a second mint with the same reference, so a
+//! retry after a timeout can't mint the same credential twice.
+
+use near_sdk::borsh::BorshSerialize;
+use near_sdk::{env, near_bindgen, require, AccountId, CryptoHash};
+use sbt::*;
+
+use crate::*;
+
+/// Max length (in bytes) of a mint external reference.
+pub const MAX_EXTERNAL_REF_LEN: usize = 256;
+
+#[near_bindgen]
+impl Contract {
+    /// Same as `sbt_mint`, but each entry has an optional `external_ref`: the registry stores
+    /// a hash of the reference for the issuer and panics if a reference was already used by
+    /// the issuer (in an earlier mint or in the same call). Returns the minted token IDs.
+    /// Requires attaching enough NEAR to cover the tokens and the references storage.
+    #[payable]
+    pub fn sbt_mint_with_refs(
+        &mut self,
+        token_spec: Vec<(AccountId, Vec<TokenMetadata>, Option<String>)>,
+    ) -> Vec<TokenId> {
+        let storage_start = env::storage_usage();
+        let issuer = env::predecessor_account_id();
+        let issuer_id = self.assert_issuer(&issuer);
+        let mut refs = Vec::with_capacity(token_spec.len());
+        let mut seen = Vec::new();
+        let mut spec = Vec::with_capacity(token_spec.len());
+        for (owner, metadatas, external_ref) in token_spec {
+            if let Some(r) = &external_ref {
+                require!(
+                    r.len() <= MAX_EXTERNAL_REF_LEN,
+                    format!(
+                        "external_ref must be at most {} bytes",
+                        MAX_EXTERNAL_REF_LEN
+                    )
+                );
+                let key = mint_ref_key(issuer_id, r);
+                require!(
+                    !self.mint_refs.contains_key(&key) && !seen.contains(&key),
+                    format!("external_ref {} was already used", r)
+                );
+                seen.push(key);
+            }
+            refs.push((metadatas.len(), external_ref));
+            spec.push((owner, metadatas));
+        }
+        let tokens = self
+            .mint_with_deposit(&issuer, spec, env::attached_deposit(), None)
+            .0;
+        let mut first = 0;
+        for (num, external_ref) in refs {
+            if let Some(r) = external_ref {
+                if num > 0 {
+                    self.mint_refs
+                        .insert(&mint_ref_key(issuer_id, &r), &tokens[first]);
+                }
+            }
+            first += num;
+        }
+        assert_storage_deposit(storage_start, env::attached_deposit());
+        tokens
+    }
+
+    /// Returns the first token minted by the `issuer` with the `external_ref` (see
+    /// `sbt_mint_with_refs`), or None if the reference was not used.
+    pub fn sbt_mint_ref(&self, issuer: AccountId, external_ref: String) -> Option<TokenId> {
+        let issuer_id = self.sbt_issuers.get(&issuer)?;
+        self.mint_refs.get(&mint_ref_key(issuer_id, &external_ref))
+    }
+}
+
+fn mint_ref_key(issuer_id: IssuerId, external_ref: &str) -> CryptoHash {
+    env::sha256_array(&(issuer_id, external_ref).try_to_vec().unwrap())
+}
@@ -0,0 +1,53 @@
+//! Negative classes: tokens attesting that the owner is a bot or an automation account (eg
+//! the "confirmed bot" class of a bot detection issuer). A valid token of a negative class
+//! makes `is_human` (and the checks based on it) return false, regardless of the other
+//! proofs of the account.
+
+use near_sdk::{near_bindgen, require, AccountId};
+
+use crate::*;
+
+/// Max number of negative `(issuer, class)` pairs.
+pub const MAX_NEGATIVE_CLASSES: usize = 10;
+
+#[near_bindgen]
+impl Contract {
+    /// Sets the negative `(issuer, class)` pairs, replacing the current list (an empty list
+    /// removes all of them). The proof of humanity classes can't be negative.
+    /// Must be called by the authority.
+    pub fn admin_set_negative_classes(&mut self, classes: Vec<(AccountId, ClassId)>) {
+        self.assert_admin_action("admin_set_negative_classes", None);
+        require!(
+            classes.len() <= MAX_NEGATIVE_CLASSES,
+            format!("max {} negative classes are allowed", MAX_NEGATIVE_CLASSES)
+        );
+        let mut negative: Vec<(IssuerId, ClassId)> = Vec::with_capacity(classes.len());
+        for (issuer, class) in classes {
+            require!(
+                issuer != self.iah_classes.0 || !self.iah_classes.1.contains(&class),
+                "proof of humanity class can't be negative"
+            );
+            let key = (self.assert_issuer(&issuer), class);
+            require!(!negative.contains(&key), "duplicated negative class");
+            negative.push(key);
+        }
+        self.negative_classes = negative;
+    }
+
+    /// Returns the negative `(issuer, class)` pairs.
+    pub fn negative_classes(&self) -> Vec<(AccountId, ClassId)> {
+        self.negative_classes
+            .iter()
+            .map(|(issuer_id, class)| (self.issuer_account(*issuer_id), *class))
+            .collect()
+    }
+}
+
+impl Contract {
+    /// Returns true if the account has a token of a negative class valid at `now`.
+    pub(crate) fn has_negative_token(&self, account: &AccountId, now: MilliSeconds) -> bool {
+        self.negative_classes
+            .iter()
+            .any(|(issuer_id, class)| self.has_valid_token(account, *issuer_id, *class, now))
+    }
+}
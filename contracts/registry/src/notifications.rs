@@ -0,0 +1,169 @@
+//! Opt-in notifications of minted tokens: recipients registered with `sbt_set_notifications`
+//! get an `on_sbt_received` call for tokens minted with `sbt_mint_with_msg`. Registered
+//! contracts can also be set as soul transfer completion callbacks (see `sbt_soul_transfer`).
+
+use near_sdk::serde::Serialize;
+use near_sdk::{env, near_bindgen, require, serde_json, AccountId, Gas, Promise};
+use sbt::*;
+
+use crate::*;
+
+/// Max length (in bytes) of the mint message.
+pub const MAX_MINT_MSG_LEN: usize = 1024;
+/// Gas attached to the `on_sbt_received` call.
+pub const ON_SBT_RECEIVED_GAS: Gas = Gas(5 * Gas::ONE_TERA.0);
+/// Gas attached to the soul transfer completion callback. Covered by the soul transfer
+/// sweep gas reserve.
+pub const SOUL_TRANSFER_CALLBACK_GAS: Gas = Gas(5 * Gas::ONE_TERA.0);
+/// Max length (in bytes) of the soul transfer callback method name.
+pub const MAX_CALLBACK_METHOD_LEN: usize = 64;
+
+/// Arguments of the soul transfer completion callback.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq))]
+pub struct SoulTransferSummary<'a> {
+    pub from: &'a AccountId,
+    pub to: &'a AccountId,
+    /// number of tokens processed by all calls of the soul transfer.
+    pub tokens: u32,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Registers (or unregisters when `enabled` is false) the caller to receive the
+    /// `on_sbt_received` calls for tokens minted with `sbt_mint_with_msg`. The caller must be
+    /// a contract implementing `sbt::SBTReceiver`. Requires attaching enough NEAR to cover
+    /// the registration storage.
+    #[payable]
+    pub fn sbt_set_notifications(&mut self, enabled: bool) {
+        let storage_start = env::storage_usage();
+        let account = env::predecessor_account_id();
+        if enabled {
+            self.notification_receivers.insert(&account);
+        } else {
+            self.notification_receivers.remove(&account);
+        }
+        assert_storage_deposit(storage_start, env::attached_deposit());
+    }
+
+    /// Returns true if the `account` registered to receive mint notifications.
+    pub fn sbt_notifications_enabled(&self, account: AccountId) -> bool {
+        self.notification_receivers.contains(&account)
+    }
+
+    /// Same as `sbt_mint`, but each entry has an optional `msg`: if set and the recipient
+    /// registered for notifications (see `sbt_set_notifications`), the registry calls
+    /// `on_sbt_received(issuer, token, msg)` on the recipient for each minted token.
+    /// Notifications are not awaited: a failed call doesn't revert the mint.
+    /// Each notification requires `ON_SBT_RECEIVED_GAS` of the attached gas.
+    #[payable]
+    pub fn sbt_mint_with_msg(
+        &mut self,
+        token_spec: Vec<(AccountId, Vec<TokenMetadata>, Option<String>)>,
+    ) -> Vec<TokenId> {
+        let issuer = env::predecessor_account_id();
+        let mut msgs = Vec::with_capacity(token_spec.len());
+        let mut spec = Vec::with_capacity(token_spec.len());
+        for (owner, metadatas, msg) in token_spec {
+            if let Some(m) = &msg {
+                require!(
+                    m.len() <= MAX_MINT_MSG_LEN,
+                    format!("msg must be at most {} bytes", MAX_MINT_MSG_LEN)
+                );
+            }
+            msgs.push((owner.clone(), metadatas.len(), msg));
+            spec.push((owner, metadatas));
+        }
+        let tokens = self._sbt_mint(&issuer, spec);
+        let mut ids = tokens.iter();
+        for (owner, num, msg) in msgs {
+            let ids: Vec<TokenId> = ids.by_ref().take(num).copied().collect();
+            let msg = match msg {
+                Some(m) if self.notification_receivers.contains(&owner) => m,
+                _ => continue,
+            };
+            for token in ids {
+                ext_sbt_receiver::ext(owner.clone())
+                    .with_static_gas(ON_SBT_RECEIVED_GAS)
+                    .on_sbt_received(issuer.clone(), token, msg.clone());
+            }
+        }
+        tokens
+    }
+}
+
+impl Contract {
+    /// Sets the completion callback of the `owner` soul transfer. The callback can only be
+    /// set when the transfer starts, subsequent calls must pass the same callback.
+    pub(crate) fn set_soul_transfer_callback(
+        &mut self,
+        owner: &AccountId,
+        contract: AccountId,
+        method: String,
+    ) {
+        match self.soul_transfer_callbacks.get(owner) {
+            Some(c) => require!(
+                c.contract == contract && c.method == method,
+                "callback must be the same as in the ongoing soul transfer"
+            ),
+            None => {
+                require!(
+                    !self.ongoing_soul_tx.contains_key(owner),
+                    "callback can only be set when the soul transfer starts"
+                );
+                require!(
+                    !method.is_empty() && method.len() <= MAX_CALLBACK_METHOD_LEN,
+                    format!(
+                        "callback method must be between 1 and {} bytes",
+                        MAX_CALLBACK_METHOD_LEN
+                    )
+                );
+                require!(
+                    self.notification_receivers.contains(&contract),
+                    "callback contract must be registered with sbt_set_notifications"
+                );
+                self.soul_transfer_callbacks.insert(
+                    owner,
+                    &SoulTransferCallback {
+                        contract,
+                        method,
+                        tokens: 0,
+                    },
+                );
+            }
+        }
+    }
+
+    /// Records the soul transfer call result `(tokens, completed)` and calls the `owner`
+    /// callback (if set) when the transfer completed. The call is not awaited.
+    pub(crate) fn soul_transfer_callback_progress(
+        &mut self,
+        owner: &AccountId,
+        recipient: &AccountId,
+        (tokens, completed): (u32, bool),
+    ) {
+        let mut callback = match self.soul_transfer_callbacks.get(owner) {
+            None => return,
+            Some(c) => c,
+        };
+        callback.tokens += tokens;
+        if !completed {
+            self.soul_transfer_callbacks.insert(owner, &callback);
+            return;
+        }
+        self.soul_transfer_callbacks.remove(owner);
+        let args = serde_json::to_vec(&SoulTransferSummary {
+            from: owner,
+            to: recipient,
+            tokens: callback.tokens,
+        })
+        .unwrap();
+        Promise::new(callback.contract).function_call(
+            callback.method,
+            args,
+            0,
+            SOUL_TRANSFER_CALLBACK_GAS,
+        );
+    }
+}
@@ -0,0 +1,224 @@
+//! Eligibility policies: named boolean expressions over `(issuer, class)` credentials, set by
+//! the authority and checked by consumer contracts with `satisfies_policy`, so eligibility
+//! rules (eg for voting) can change without redeploying the consumers.
+//!
+//! Expression format: `<issuer>:<class>` is satisfied by a valid token of the issuer class,
+//! `&` (AND) binds stronger than `|` (OR) and parentheses group sub expressions, eg:
+//! `fractal.i-am-human.near:1 & (issuer-b.near:2 | issuer-c.near:1)`.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::{near_bindgen, require, AccountId};
+use sbt::ClassId;
+
+use crate::*;
+
+/// Max length (in bytes) of a policy expression.
+pub const MAX_POLICY_LEN: usize = 512;
+/// Max number of credentials (`<issuer>:<class>` terms) in a policy expression.
+pub const MAX_POLICY_TERMS: usize = 16;
+/// Max nesting depth of parentheses in a policy expression.
+pub const MAX_POLICY_DEPTH: usize = 4;
+
+/// Operation of a compiled policy expression. The expression is compiled to postfix
+/// notation, evaluated with a stack of booleans.
+#[derive(BorshSerialize, BorshDeserialize)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq))]
+pub enum PolicyOp {
+    /// pushes true if the account has a valid token of the issuer class.
+    Class(IssuerId, ClassId),
+    /// pops the `n` last values and pushes true if all of them are true.
+    All(u8),
+    /// pops the `n` last values and pushes true if any of them is true.
+    Any(u8),
+}
+
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct Policy {
+    /// the expression as set by the authority.
+    pub expr: String,
+    pub compiled: Vec<PolicyOp>,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets (or removes, when `None`) the eligibility policy `name`, see the module
+    /// documentation for the `expr` format. Panics if the expression is invalid or refers
+    /// to an unknown issuer. Must be called by the authority.
+    pub fn admin_set_policy(&mut self, name: String, expr: Option<String>) {
+        self.assert_admin_action("admin_set_policy", None);
+        require!(!name.is_empty(), "name must be a non empty string");
+        match expr {
+            None => {
+                self.policies.remove(&name);
+            }
+            Some(expr) => {
+                let compiled = parse_policy(&expr, |issuer| self.sbt_issuers.get(issuer))
+                    .unwrap_or_else(|e| panic!("invalid policy: {}", e));
+                self.policies.insert(&name, &Policy { expr, compiled });
+            }
+        }
+    }
+
+    /// Returns the expression of the policy `name`, or None if the policy is not defined.
+    pub fn policy(&self, name: String) -> Option<String> {
+        self.policies.get(&name).map(|p| p.expr)
+    }
+
+    /// Returns true if the account satisfies the policy `name`: it's not banned, not in a
+    /// soul transfer and its valid (not expired) tokens satisfy the policy expression.
+    /// Returns false if the policy is not defined.
+    pub fn satisfies_policy(&self, account: AccountId, policy_name: String) -> bool {
+        let now = MilliSeconds::now();
+        if self.is_banned_at(&account, now) || self.ongoing_soul_tx.contains_key(&account) {
+            return false;
+        }
+        self.policies
+            .get(&policy_name)
+            .map_or(false, |p| self.eval_policy(&p.compiled, &account, now))
+    }
+}
+
+impl Contract {
+    fn eval_policy(&self, ops: &[PolicyOp], account: &AccountId, now: MilliSeconds) -> bool {
+        let mut stack: Vec<bool> = Vec::new();
+        for op in ops {
+            let v = match op {
+                PolicyOp::Class(issuer_id, class) => {
+                    self.has_valid_token(account, *issuer_id, *class, now)
+                }
+                PolicyOp::All(n) => {
+                    let args = stack.split_off(stack.len() - *n as usize);
+                    args.into_iter().all(|v| v)
+                }
+                PolicyOp::Any(n) => {
+                    let args = stack.split_off(stack.len() - *n as usize);
+                    args.into_iter().any(|v| v)
+                }
+            };
+            stack.push(v);
+        }
+        stack.pop().unwrap_or(false)
+    }
+}
+
+/// Parses the policy expression, resolving issuer accounts to issuer IDs with `issuer_id`.
+pub(crate) fn parse_policy(
+    expr: &str,
+    issuer_id: impl Fn(&AccountId) -> Option<IssuerId>,
+) -> Result<Vec<PolicyOp>, String> {
+    if expr.len() > MAX_POLICY_LEN {
+        return Err(format!(
+            "expression must be at most {} bytes",
+            MAX_POLICY_LEN
+        ));
+    }
+    let mut p = Parser {
+        s: expr.as_bytes(),
+        pos: 0,
+        terms: 0,
+        ops: Vec::new(),
+        issuer_id: &issuer_id,
+    };
+    p.parse_or(0)?;
+    p.skip_spaces();
+    if p.pos < p.s.len() {
+        return Err(format!("unexpected character at {}", p.pos));
+    }
+    Ok(p.ops)
+}
+
+struct Parser<'a, F> {
+    s: &'a [u8],
+    pos: usize,
+    terms: usize,
+    ops: Vec<PolicyOp>,
+    issuer_id: &'a F,
+}
+
+impl<'a, F: Fn(&AccountId) -> Option<IssuerId>> Parser<'a, F> {
+    fn skip_spaces(&mut self) {
+        while self.pos < self.s.len() && self.s[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+    }
+
+    /// Consumes the `op` character (after spaces) if it's the next one.
+    fn eat(&mut self, op: u8) -> bool {
+        self.skip_spaces();
+        if self.s.get(self.pos) == Some(&op) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn parse_or(&mut self, depth: usize) -> Result<(), String> {
+        self.parse_and(depth)?;
+        let mut n = 1;
+        while self.eat(b'|') {
+            self.parse_and(depth)?;
+            n += 1;
+        }
+        if n > 1 {
+            self.ops.push(PolicyOp::Any(n));
+        }
+        Ok(())
+    }
+
+    fn parse_and(&mut self, depth: usize) -> Result<(), String> {
+        self.parse_term(depth)?;
+        let mut n = 1;
+        while self.eat(b'&') {
+            self.parse_term(depth)?;
+            n += 1;
+        }
+        if n > 1 {
+            self.ops.push(PolicyOp::All(n));
+        }
+        Ok(())
+    }
+
+    fn parse_term(&mut self, depth: usize) -> Result<(), String> {
+        if self.eat(b'(') {
+            if depth == MAX_POLICY_DEPTH {
+                return Err(format!(
+                    "parentheses can be nested at most {} times",
+                    MAX_POLICY_DEPTH
+                ));
+            }
+            self.parse_or(depth + 1)?;
+            if !self.eat(b')') {
+                return Err(format!("expected ')' at {}", self.pos));
+            }
+            return Ok(());
+        }
+        let start = self.pos;
+        while self.pos < self.s.len()
+            && (self.s[self.pos].is_ascii_alphanumeric() || b".-_:".contains(&self.s[self.pos]))
+        {
+            self.pos += 1;
+        }
+        // the scanned bytes are ASCII
+        let term = std::str::from_utf8(&self.s[start..self.pos]).unwrap();
+        let (issuer, class) = term
+            .rsplit_once(':')
+            .ok_or_else(|| format!("expected <issuer>:<class> at {}", start))?;
+        let issuer: AccountId = issuer
+            .parse()
+            .map_err(|_| format!("invalid issuer account at {}", start))?;
+        let class: ClassId = class
+            .parse()
+            .map_err(|_| format!("invalid class at {}", start))?;
+        let issuer_id =
+            (self.issuer_id)(&issuer).ok_or_else(|| format!("unknown issuer {}", issuer))?;
+        self.terms += 1;
+        if self.terms > MAX_POLICY_TERMS {
+            return Err(format!(
+                "expression can have at most {} credentials",
+                MAX_POLICY_TERMS
+            ));
+        }
+        self.ops.push(PolicyOp::Class(issuer_id, class));
+        Ok(())
+    }
+}
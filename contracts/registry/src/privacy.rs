@@ -0,0 +1,91 @@
+//! Owner privacy mode: a holder can hide the details of its credentials (`reference`,
+//! `reference_hash` and `attributes_root` of the token metadata) from the token views, while
+//! the class level existence (class, issue and expire time) stays public for auditability.
+//! The caller of a view call is not known, so the views always return redacted tokens of
+//! private accounts; the owner and the authority get the full tokens with
+//! `sbt_tokens_by_owner_full` in a transaction (or a cross-contract call).
+//! This is not confidentiality: the registry state (and the mint arguments) can still be
+//! read from the chain.
+
+use near_sdk::{env, near_bindgen, require, AccountId};
+use sbt::{OwnedToken, Token, TokenMetadata};
+
+use crate::*;
+
+#[near_bindgen]
+impl Contract {
+    /// Enables (or disables when `private` is false) the privacy mode of the caller tokens.
+    /// Requires attaching enough NEAR to cover the storage. The mode is not moved by a soul
+    /// transfer.
+    #[payable]
+    pub fn set_private(&mut self, private: bool) {
+        let storage_start = env::storage_usage();
+        let account = env::predecessor_account_id();
+        if private {
+            self.private_accounts.insert(&account);
+        } else {
+            self.private_accounts.remove(&account);
+        }
+        assert_storage_deposit(storage_start, env::attached_deposit());
+    }
+
+    /// Returns true if the `account` enabled the privacy mode.
+    pub fn is_private(&self, account: AccountId) -> bool {
+        self.private_accounts.contains(&account)
+    }
+
+    /// `sbt_tokens_by_owner` without the privacy mode redaction. Must be called by the
+    /// `account` or the authority, not in a view call.
+    pub fn sbt_tokens_by_owner_full(
+        &self,
+        account: AccountId,
+        issuer: Option<AccountId>,
+        from_class: Option<u64>,
+        limit: Option<u32>,
+        with_expired: Option<bool>,
+    ) -> Vec<(AccountId, Vec<OwnedToken>)> {
+        let caller = env::predecessor_account_id();
+        require!(
+            caller == account || caller == self.authority,
+            "only the owner or the authority can query the full tokens"
+        );
+        self.tokens_by_owner_at(
+            account,
+            issuer,
+            from_class,
+            limit,
+            with_expired,
+            MilliSeconds::now(),
+        )
+    }
+}
+
+impl Contract {
+    /// Redacts the `owner` tokens if the owner enabled the privacy mode.
+    pub(crate) fn redact_owned(
+        &self,
+        owner: &AccountId,
+        mut tokens: Vec<(AccountId, Vec<OwnedToken>)>,
+    ) -> Vec<(AccountId, Vec<OwnedToken>)> {
+        if self.private_accounts.contains(owner) {
+            for t in tokens.iter_mut().flat_map(|(_, ts)| ts.iter_mut()) {
+                redact(&mut t.metadata);
+            }
+        }
+        tokens
+    }
+
+    /// Redacts the token if its owner enabled the privacy mode.
+    pub(crate) fn redact_token(&self, mut t: Token) -> Token {
+        if self.private_accounts.contains(&t.owner) {
+            redact(&mut t.metadata);
+        }
+        t
+    }
+}
+
+pub(crate) fn redact(m: &mut TokenMetadata) {
+    m.reference = None;
+    m.reference_hash = None;
+    m.attributes_root = None;
+}
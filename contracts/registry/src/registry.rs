@@ -1,10 +1,8 @@
-use std::collections::HashMap;
-
-use near_sdk::{near_bindgen, AccountId};
+use near_sdk::{near_bindgen, require, AccountId};
 
 use crate::*;
 
-const MAX_LIMIT: u32 = 1000;
+pub(crate) const MAX_LIMIT: u32 = 1000;
 
 #[near_bindgen]
 impl SBTRegistry for Contract {
@@ -17,15 +15,25 @@ impl SBTRegistry for Contract {
         let issuer_id = self.assert_issuer(&issuer);
         self.issuer_tokens
             .get(&IssuerTokenId { issuer_id, token })
-            .map(|td| td.to_token(token))
+            .map(|td| self.redact_token(td.to_token(token)))
     }
 
-    /// returns total amount of tokens minted by the given issuer
-    fn sbt_supply(&self, issuer: AccountId) -> u64 {
+    /// returns total amount of tokens minted by the given issuer.
+    /// If `only_valid` is true, revoked, frozen and expired tokens are not counted. Reads the
+    /// valid supply counters (see `valid_supply.rs`) rather than the tokens. Panics until the
+    /// tokens of the previous release are counted by `migrate_chunk`.
+    fn sbt_supply(&self, issuer: AccountId, only_valid: Option<bool>) -> u64 {
         let issuer_id = match self.sbt_issuers.get(&issuer) {
             None => return 0,
             Some(id) => id,
         };
+        if only_valid == Some(true) {
+            require!(
+                self.migration.done,
+                "valid supply backfill in progress, see migrate_chunk"
+            );
+            return self.valid_supply_of(issuer_id);
+        }
         self.supply_by_issuer.get(&issuer_id).unwrap_or(0)
     }
 
@@ -40,6 +48,8 @@ impl SBTRegistry for Contract {
 
     /// returns total supply of SBTs for a given owner.
     /// If class is specified, returns only owner supply of the given class -- must be 0 or 1.
+    /// Constant cost: reads the `supply_by_owner` counter (updated on mint, burn and soul
+    /// transfer) or a single balance, regardless of the number of tokens the owner holds.
     fn sbt_supply_by_owner(
         &self,
         account: AccountId,
@@ -76,6 +86,7 @@ impl SBTRegistry for Contract {
     /// should continue querying the contract by setting `from_token = previous from_token + limit`
     /// until the `from_token > sbt_supply(issuer)`.
     /// If limit is not specified, default is used: 1000.
+    /// Frozen tokens (see `admin_freeze_tokens`) are treated as expired.
     fn sbt_tokens(
         &self,
         issuer: AccountId,
@@ -97,15 +108,17 @@ impl SBTRegistry for Contract {
         }
         max_id = std::cmp::min(max_id + 1, from_token + limit as u64);
 
-        let now = env::block_timestamp_ms();
+        let now = MilliSeconds::now();
         let non_expired = !with_expired.unwrap_or(false);
         let mut resp = Vec::new();
         for token in from_token..max_id {
             if let Some(t) = self.issuer_tokens.get(&IssuerTokenId { issuer_id, token }) {
-                if non_expired && t.metadata.expires_at().unwrap_or(now) < now {
+                if non_expired
+                    && (t.metadata.is_expired_at(now) || self.is_frozen(issuer_id, token))
+                {
                     continue;
                 }
-                resp.push(t.to_token(token))
+                resp.push(self.redact_token(t.to_token(token)))
             }
         }
         resp
@@ -117,6 +130,8 @@ impl SBTRegistry for Contract {
     /// If limit is not specified, default is used: 100.
     /// Returns list of pairs: `(Issuer address, list of token IDs)`.
     /// if `with_expired` is set to `true` then only non-expired tokens are returned, otherwise all tokens are returned.
+    /// Frozen tokens (see `admin_freeze_tokens`) are treated as expired.
+    /// Tokens of an account in the privacy mode (see `set_private`) are redacted.
     fn sbt_tokens_by_owner(
         &self,
         account: AccountId,
@@ -125,72 +140,15 @@ impl SBTRegistry for Contract {
         limit: Option<u32>,
         with_expired: Option<bool>,
     ) -> Vec<(AccountId, Vec<OwnedToken>)> {
-        if from_class.is_some() {
-            require!(
-                issuer.is_some(),
-                "issuer must be defined if from_class is defined"
-            );
-        }
-        // we don't check banlist because we should still enable banned accounts to query their tokens
-        if self.ongoing_soul_tx.contains_key(&account) {
-            return vec![];
-        }
-
-        let issuer_id = match issuer {
-            None => 0,
-            // use self.sbt_contracts.get when changing to query by issuer_start
-            Some(addr) => self.assert_issuer(&addr),
-        };
-        let mut from_class = from_class.unwrap_or(0);
-        // iter_from starts from exclusive "left end"
-        from_class = from_class.saturating_sub(1);
-        let mut limit = limit.unwrap_or(MAX_LIMIT);
-        require!(limit > 0, "limit must be bigger than 0");
-
-        let mut resp = Vec::new();
-        let mut tokens = Vec::new();
-        let mut prev_issuer = issuer_id;
-
-        let now = env::block_timestamp_ms();
-        let with_expired = with_expired.unwrap_or(false);
-
-        for (key, token_id) in
-            self.balances
-                .iter_from(balance_key(account.clone(), issuer_id, from_class))
-        {
-            // TODO: maybe we should continue the scan?
-            if key.owner != account {
-                break;
-            }
-            if prev_issuer != key.issuer_id {
-                if issuer_id != 0 {
-                    break;
-                }
-                if !tokens.is_empty() {
-                    let issuer = self.issuer_by_id(prev_issuer);
-                    resp.push((issuer, tokens));
-                    tokens = Vec::new();
-                }
-                prev_issuer = key.issuer_id;
-            }
-            let t: TokenData = self.get_token(key.issuer_id, token_id);
-            if !with_expired && t.metadata.expires_at().unwrap_or(now) < now {
-                continue;
-            }
-            tokens.push(OwnedToken {
-                token: token_id,
-                metadata: t.metadata.v1(),
-            });
-            limit -= 1;
-            if limit == 0 {
-                break;
-            }
-        }
-        if prev_issuer != 0 && !tokens.is_empty() {
-            let issuer = self.issuer_by_id(prev_issuer);
-            resp.push((issuer, tokens));
-        }
-        resp
+        let tokens = self.tokens_by_owner_at(
+            account.clone(),
+            issuer,
+            from_class,
+            limit,
+            with_expired,
+            MilliSeconds::now(),
+        );
+        self.redact_owned(&account, tokens)
     }
 
     /// checks if an `account` was banned by the registry.
@@ -207,12 +165,16 @@ impl SBTRegistry for Contract {
     /// Each TokenMetadata must have non zero `class`.
     /// Must be called by an SBT contract.
     /// Must emit `Mint` event.
-    /// Must provide enough NEAR to cover registry storage cost.
+    /// Must provide enough NEAR to cover registry storage cost. If no deposit is attached,
+    /// the storage is paid from the issuer prepaid balance (see `issuer_storage_deposit`).
     /// Panics with "out of gas" if token_spec vector is too long and not enough gas was
     /// provided.
     #[payable]
     fn sbt_mint(&mut self, token_spec: Vec<(AccountId, Vec<TokenMetadata>)>) -> Vec<TokenId> {
         let issuer = &env::predecessor_account_id();
+        if env::attached_deposit() == 0 {
+            return self.mint_from_escrow(issuer, issuer.clone(), token_spec);
+        }
         self._sbt_mint(issuer, token_spec)
     }
 
@@ -223,10 +185,12 @@ impl SBTRegistry for Contract {
     /// + Returns the amount of tokens recovered and a boolean: `true` if the whole
     ///   process has finished, `false` when the process has not finished and should be
     ///   continued by a subsequent call.
-    /// + User must keep calling the `sbt_recover` until `true` is returned.
+    /// + The issuer must keep calling the `sbt_recover` until `true` is returned. Until then
+    ///   the old account is locked: its tokens are not returned by queries, it can't start a
+    ///   soul transfer and only the same issuer can continue the recovery.
     #[payable]
     fn sbt_recover(&mut self, from: AccountId, to: AccountId) -> (u32, bool) {
-        self._sbt_recover(from, to, 20)
+        self._sbt_recover(from, to, MAX_SWEEP_BATCH)
     }
 
     /// sbt_renew will update the expire time of provided tokens.
@@ -246,80 +210,6 @@ impl SBTRegistry for Contract {
     /// Must also emit `Burn` event if the SBT tokens are burned (removed).
     fn sbt_revoke(&mut self, tokens: Vec<TokenId>, burn: bool) {
         let issuer = env::predecessor_account_id();
-        let issuer_id = self.assert_issuer(&issuer);
-        if burn == true {
-            let mut revoked_per_class: HashMap<u64, u64> = HashMap::new();
-            let mut revoked_per_owner: HashMap<AccountId, u64> = HashMap::new();
-            let tokens_burned: u64 = tokens.len().try_into().unwrap();
-            for token in tokens.clone() {
-                // update balances
-                let token_object = self.get_token(issuer_id, token);
-                let owner = token_object.owner;
-                let class_id = token_object.metadata.class_id();
-                let balance_key = &BalanceKey {
-                    issuer_id,
-                    owner: owner.clone(),
-                    class_id,
-                };
-                self.balances.remove(balance_key);
-
-                // collect the info about the tokens revoked per owner and per class
-                // to update the balances accordingly
-                revoked_per_class
-                    .entry(class_id)
-                    .and_modify(|key_value| *key_value += 1)
-                    .or_insert(1);
-                revoked_per_owner
-                    .entry(owner)
-                    .and_modify(|key_value| *key_value += 1)
-                    .or_insert(1);
-
-                // remove from issuer_tokens
-                self.issuer_tokens
-                    .remove(&IssuerTokenId { issuer_id, token });
-            }
-
-            // update supply by owner
-            for (owner_id, tokens_revoked) in revoked_per_owner {
-                let old_supply = self
-                    .supply_by_owner
-                    .get(&(owner_id.clone(), issuer_id))
-                    .unwrap();
-                self.supply_by_owner
-                    .insert(&(owner_id, issuer_id), &(old_supply - &tokens_revoked));
-            }
-
-            // update supply by class
-            for (class_id, tokens_revoked) in revoked_per_class {
-                let old_supply = self.supply_by_class.get(&(issuer_id, class_id)).unwrap();
-                self.supply_by_class
-                    .insert(&(issuer_id, class_id), &(&old_supply - &tokens_revoked));
-            }
-
-            // update supply by issuer
-            let supply_by_issuer = self.supply_by_issuer.get(&(issuer_id)).unwrap_or(0);
-            self.supply_by_issuer
-                .insert(&(issuer_id), &(supply_by_issuer - tokens_burned));
-
-            // emit event
-            SbtTokensEvent {
-                issuer: issuer.clone(),
-                tokens: tokens.clone(),
-            }
-            .emit_burn();
-        } else {
-            let current_timestamp = env::block_timestamp();
-            // revoke
-            for token in tokens.clone() {
-                // update expire date for all tokens to current_timestamp
-                let mut t = self.get_token(issuer_id, token);
-                let mut m = t.metadata.v1();
-                m.expires_at = Some(current_timestamp);
-                t.metadata = m.into();
-                self.issuer_tokens
-                    .insert(&IssuerTokenId { issuer_id, token }, &t);
-            }
-        }
-        SbtTokensEvent { issuer, tokens }.emit_revoke();
+        self.revoke_tokens(issuer, tokens, burn, None);
     }
 }
@@ -0,0 +1,131 @@
+//! Token ID reservations: an issuer which prepares the token metadata off-chain (eg a high
+//! throughput oracle pipelining the mint preparation) reserves a range of token IDs with
+//! `reserve_token_ids`, and later mints tokens with exactly these IDs with
+//! `sbt_mint_reserved`. Reservations expire after `TOKEN_RESERVATION_TTL`: the IDs not minted
+//! before are never assigned, because token IDs are not reused.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::serde::Serialize;
+use near_sdk::{env, near_bindgen, require, AccountId};
+use sbt::*;
+
+use crate::*;
+
+/// Max number of token IDs in a single reservation.
+pub const MAX_RESERVED_IDS: u64 = 1000;
+/// Max number of active (not expired) reservations of an issuer.
+pub const MAX_TOKEN_RESERVATIONS: usize = 10;
+/// Time to live of a reservation, in miliseconds (24 hours).
+pub const TOKEN_RESERVATION_TTL: u64 = 24 * 3600 * 1000;
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq, Clone))]
+pub struct TokenReservation {
+    /// first reserved token ID.
+    pub first: TokenId,
+    /// last reserved token ID (inclusive).
+    pub last: TokenId,
+    /// unix timestamp in miliseconds after which the IDs can't be minted.
+    pub expires_at: u64,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Reserves `num` consecutive token IDs for the calling issuer, to be minted with
+    /// `sbt_mint_reserved` within `TOKEN_RESERVATION_TTL`. Returns the first and the last
+    /// (inclusive) reserved ID. Reserved IDs count as assigned (see `last_token_id`).
+    /// Requires attaching enough NEAR to cover the reservation storage.
+    #[payable]
+    pub fn reserve_token_ids(&mut self, num: u64) -> (TokenId, TokenId) {
+        self.assert_not_paused();
+        let storage_start = env::storage_usage();
+        let issuer = env::predecessor_account_id();
+        let issuer_id = self.assert_issuer(&issuer);
+        self.assert_capability(issuer_id, CAP_MINT);
+        require!(
+            num > 0 && num <= MAX_RESERVED_IDS,
+            format!("can reserve between 1 and {} token IDs", MAX_RESERVED_IDS)
+        );
+        let now = env::block_timestamp_ms();
+        let mut reservations = self.active_reservations(issuer_id, now);
+        require!(
+            reservations.len() < MAX_TOKEN_RESERVATIONS,
+            format!(
+                "issuer can have at most {} active token ID reservations",
+                MAX_TOKEN_RESERVATIONS
+            )
+        );
+        let first = self.next_token_id(issuer_id, num);
+        let r = TokenReservation {
+            first,
+            last: first + num - 1,
+            expires_at: now + TOKEN_RESERVATION_TTL,
+        };
+        self.emit_registry_event(RegistryEvent::ReserveTokenIds {
+            issuer: &issuer,
+            first: r.first,
+            last: r.last,
+            expires_at: r.expires_at,
+        });
+        let ret = (r.first, r.last);
+        reservations.push(r);
+        self.token_reservations.insert(&issuer_id, &reservations);
+        assert_storage_deposit(storage_start, env::attached_deposit());
+        ret
+    }
+
+    /// Same as `sbt_mint`, but the tokens get consecutive IDs starting from `first_token`,
+    /// instead of new IDs. The IDs must be reserved by the issuer (see `reserve_token_ids`)
+    /// in a single not expired reservation, and not minted yet. Returns the minted token IDs.
+    #[payable]
+    pub fn sbt_mint_reserved(
+        &mut self,
+        first_token: TokenId,
+        token_spec: Vec<(AccountId, Vec<TokenMetadata>)>,
+    ) -> Vec<TokenId> {
+        let issuer = env::predecessor_account_id();
+        let issuer_id = self.assert_issuer(&issuer);
+        let num: u64 = token_spec.iter().map(|(_, m)| m.len() as u64).sum();
+        require!(num > 0, "token_spec must not be empty");
+        let last = first_token.saturating_add(num - 1);
+        require!(
+            self.active_reservations(issuer_id, env::block_timestamp_ms())
+                .iter()
+                .any(|r| r.first <= first_token && last <= r.last),
+            format!("token IDs {}-{} are not reserved", first_token, last)
+        );
+        for token in first_token..=last {
+            require!(
+                !self
+                    .issuer_tokens
+                    .contains_key(&IssuerTokenId { issuer_id, token })
+                    && !self.is_burned(issuer_id, token),
+                format!("token {} was already minted", token)
+            );
+        }
+        self.mint_with_deposit(
+            &issuer,
+            token_spec,
+            env::attached_deposit(),
+            Some(first_token),
+        )
+        .0
+    }
+
+    /// Returns the active (not expired) token ID reservations of the issuer.
+    pub fn token_reservations(&self, issuer: AccountId) -> Vec<TokenReservation> {
+        match self.sbt_issuers.get(&issuer) {
+            None => vec![],
+            Some(issuer_id) => self.active_reservations(issuer_id, env::block_timestamp_ms()),
+        }
+    }
+}
+
+impl Contract {
+    fn active_reservations(&self, issuer_id: IssuerId, now: u64) -> Vec<TokenReservation> {
+        let mut reservations = self.token_reservations.get(&issuer_id).unwrap_or_default();
+        reservations.retain(|r| r.expires_at > now);
+        reservations
+    }
+}
@@ -0,0 +1,142 @@
+//! Registry self-check, executed by `new` and `migrate`: a deployment with an inconsistent
+//! state layout (eg two collections sharing a storage prefix after a refactor), counters
+//! behind the stored records or an invalid admin configuration panics, which reverts the
+//! deployment, rather than corrupting the state at the first use.
+
+use near_sdk::{env, near_bindgen, require};
+
+use crate::*;
+
+/// Raw storage key of the contract state struct.
+const STATE_KEY: &[u8] = b"STATE";
+
+macro_rules! storage_prefixes {
+    ($ctr:expr; $($field:ident),* $(,)?) => {
+        vec![$((stringify!($field), $ctr.$field.storage_prefix())),*]
+    };
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Reads the state written by the previous contract version (`OldState`), builds the
+    /// current state from it and runs `sanity_check`. Records of the old layout are converted
+    /// later, by `migrate_chunk`. Called by `admin_deploy_staged_code` in the deployment
+    /// batch, so a failed check reverts the deployment.
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate() -> Self {
+        let old: OldState = env::state_read().expect("registry state not found");
+        let mut ctr = Self::empty(old.authority, old.iah_classes.0, old.iah_classes.1);
+        // lookup maps keep their storage prefixes, only the collections with a state (length,
+        // tree root) and the counters are taken from the old state.
+        ctr.sbt_issuers = old.sbt_issuers;
+        ctr.banlist = old.banlist;
+        ctr.balances = Balances::with_legacy(old.balances);
+        ctr.next_issuer_id = old.next_issuer_id;
+        for (_, issuer_id) in ctr.sbt_issuers.iter() {
+            if let Some(last) = old.next_token_ids.get(&issuer_id) {
+                ctr.legacy_token_ids.insert(&issuer_id, &last);
+            }
+        }
+        ctr.migration = MigrationProgress::default();
+        ctr.sanity_check();
+        ctr
+    }
+}
+
+impl Contract {
+    /// Checks that collection storage prefixes don't overlap, that the ID counters are
+    /// ahead of the stored records and that the admin settings are valid. Panics with the
+    /// first problem found. Reads a few records per issuer.
+    pub(crate) fn sanity_check(&self) {
+        self.check_storage_prefixes();
+        self.check_counters();
+        self.check_settings();
+    }
+
+    fn check_storage_prefixes(&self) {
+        let mut prefixes = storage_prefixes![self;
+            sbt_issuers, issuer_id_map, banlist, ban_expirations, ongoing_soul_tx,
+            merge_approvals, issuer_soul_tx, recoveries, issuer_counters, issuer_capabilities,
+            supply_by_owner, supply_by_class, supply_by_issuer, balances, issuer_tokens,
+            next_token_ids, last_activity, expirations, code_blobs, pending_actions,
+            class_caps, class_min_ttls, recipient_policies, recreated_accounts, credentials,
+            mint_escrow, vouchers, token_statuses, soul_transfer_bonds,
+            notification_receivers, issuer_bonds, used_contexts, transfer_cooldowns,
+            key_bound_classes, policies, stamps, stamp_supply, issuer_metadata,
+            soul_transfer_callbacks, mint_refs, revocation_reasons, private_accounts,
+            human_call_targets, composite_credentials, token_reservations, admin_log,
+            class_max_ttls, valid_supply, valid_expirations, invalidation_cursors,
+            expiring_tokens, removed_issuers, relayer_allowances, legacy_token_ids,
+            supply_by_account,
+        ];
+        prefixes.push(("legacy_balances", self.balances.legacy.storage_prefix()));
+        for (i, (name, p)) in prefixes.iter().enumerate() {
+            require!(
+                !STATE_KEY.starts_with(p) && !p.starts_with(STATE_KEY),
+                format!("storage prefix of {} overlaps the contract state key", name)
+            );
+            for (other, q) in &prefixes[i + 1..] {
+                require!(
+                    !p.starts_with(q) && !q.starts_with(p),
+                    format!("storage prefixes of {} and {} overlap", name, other)
+                );
+            }
+        }
+    }
+
+    fn check_counters(&self) {
+        require!(
+            self.issuer_id_map.get(&self.next_issuer_id).is_none(),
+            "next issuer ID is already assigned"
+        );
+        for (issuer, issuer_id) in self.sbt_issuers.iter() {
+            require!(
+                issuer_id < self.next_issuer_id
+                    && self.issuer_id_map.get(&issuer_id).as_ref() == Some(&issuer),
+                format!("inconsistent issuer ID of {}", issuer)
+            );
+            let next_token = self.next_token_ids.get(&issuer_id).unwrap_or(0) + 1;
+            require!(
+                !self.issuer_tokens.contains_key(&IssuerTokenId {
+                    issuer_id,
+                    token: next_token
+                }),
+                format!("token ID counter of {} is behind its tokens", issuer)
+            );
+        }
+        require!(
+            self.vouchers.get(&self.next_voucher_id).is_none(),
+            "voucher ID counter is behind the vouchers"
+        );
+        require!(
+            self.pending_actions
+                .keys()
+                .all(|id| id < self.next_action_id),
+            "admin action ID counter is behind the pending actions"
+        );
+        require!(
+            (self.event_count == 0) == (self.event_root == [0; 32]),
+            "event digest is inconsistent"
+        );
+    }
+
+    fn check_settings(&self) {
+        require!(
+            self.authority != env::current_account_id(),
+            "authority must not be the registry"
+        );
+        require!(
+            !self.iah_classes.1.is_empty(),
+            "iah_classes must be a non empty list"
+        );
+        require!(
+            self.max_renew_batch > 0
+                && self.max_issuers > 0
+                && self.max_classes_per_issuer > 0
+                && self.max_tokens_per_account > 0
+                && self.max_tokens_per_account <= MAX_TOKENS_PER_ACCOUNT,
+            "invalid registry limits"
+        );
+    }
+}
@@ -0,0 +1,97 @@
+//! Optional NEAR Social (SocialDB) profile attestation: on mint of the configured class, the
+//! registry sets a verification badge of the token holders in SocialDB. SocialDB accounts can
+//! only write their own data, so badges are stored under the registry account, following the
+//! NEAR Social badge layout: `<registry>/badge/<badge>/holder/<holder>`.
+//! The SocialDB call is not awaited, and it's skipped when the mint doesn't have enough gas
+//! left, so a SocialDB failure never reverts the mint.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen, require, AccountId, Gas, Promise};
+use sbt::ClassId;
+
+use crate::*;
+
+/// Gas attached to the SocialDB `set` call.
+pub const SOCIAL_SET_GAS: Gas = Gas(10 * Gas::ONE_TERA.0);
+/// Gas left for the mint after scheduling the SocialDB call.
+const SOCIAL_GAS_RESERVE: Gas = Gas(5 * Gas::ONE_TERA.0);
+/// Max length (in bytes) of the badge name.
+pub const MAX_BADGE_LEN: usize = 64;
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq))]
+pub struct SocialBadgeConfig {
+    /// SocialDB contract, eg `social.near`.
+    pub social_db: AccountId,
+    pub issuer: AccountId,
+    pub class: ClassId,
+    /// badge name, eg `iah-verified`.
+    pub badge: String,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets (or removes, when `None`) the SocialDB badge set on mint of the `issuer` `class`
+    /// tokens. The registry account must have a SocialDB storage balance (see the SocialDB
+    /// `storage_deposit`) covering the badges. Must be called by the authority.
+    pub fn admin_set_social_badge(&mut self, config: Option<SocialBadgeConfig>) {
+        self.assert_admin_action("admin_set_social_badge", None);
+        if let Some(c) = &config {
+            require!(
+                !c.badge.is_empty()
+                    && c.badge.len() <= MAX_BADGE_LEN
+                    && c.badge
+                        .bytes()
+                        .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_'),
+                format!(
+                    "badge must have 1 to {} alphanumeric, '-' or '_' characters",
+                    MAX_BADGE_LEN
+                )
+            );
+        }
+        self.social_badge = config;
+    }
+
+    pub fn social_badge(&self) -> Option<SocialBadgeConfig> {
+        self.social_badge.clone()
+    }
+}
+
+impl Contract {
+    /// Returns the class which sets the SocialDB badge on mint by the `issuer`, if configured.
+    pub(crate) fn social_badge_class(&self, issuer: &AccountId) -> Option<ClassId> {
+        self.social_badge
+            .as_ref()
+            .filter(|c| c.issuer == *issuer)
+            .map(|c| c.class)
+    }
+
+    /// Sets the badge of the `holders` in SocialDB. Skipped if there is not enough gas left.
+    pub(crate) fn set_social_badges(&mut self, holders: Vec<AccountId>) {
+        let config = match &self.social_badge {
+            Some(c) if !holders.is_empty() => c,
+            _ => return,
+        };
+        let gas_left = env::prepaid_gas().0.saturating_sub(env::used_gas().0);
+        if gas_left < SOCIAL_SET_GAS.0 + SOCIAL_GAS_RESERVE.0 {
+            self.log("social badge skipped: not enough gas".to_string());
+            return;
+        }
+        // account IDs and the badge name don't need JSON escaping.
+        let holders: Vec<String> = holders.iter().map(|h| format!(r#""{}":"""#, h)).collect();
+        let args = format!(
+            r#"{{"data":{{"{}":{{"badge":{{"{}":{{"holder":{{{}}}}}}}}}}}}}"#,
+            env::current_account_id(),
+            config.badge,
+            holders.join(",")
+        );
+        Promise::new(config.social_db.clone()).function_call(
+            "set".to_owned(),
+            args.into_bytes(),
+            0,
+            SOCIAL_SET_GAS,
+        );
+    }
+}
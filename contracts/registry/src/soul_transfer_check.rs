@@ -0,0 +1,370 @@
+//! Exhaustive soul transfer check: starting from a small state, explores every interleaving
+//! of soul transfer calls (by the owner, or by a relayer continuing the bonded transfer, with
+//! batch limits of 1 to 3 tokens) and mints to the source account, and checks after every
+//! step that no token is lost or duplicated. Calls which panic are reverted, as on chain.
+//! The contract storage and state are snapshotted between the branches, so every reachable
+//! storage state is expanded once.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use cost::{ISSUER_BOND, SOUL_TRANSFER_BOND};
+use near_sdk::borsh::{BorshDeserialize, BorshSerialize};
+use near_sdk::test_utils::VMContextBuilder;
+use near_sdk::{
+    env, AccountId, Balance, Gas, MockedBlockchain, RuntimeFeesConfig, VMConfig, VMContext,
+};
+use sbt::*;
+
+use crate::storage::BalanceKey;
+use crate::{Contract, IssuerId};
+
+/// block timestamp of all calls, in miliseconds.
+const START_MS: u64 = 1_000_000;
+/// batch limits of the soul transfer calls.
+const LIMITS: [usize; 3] = [1, 2, 3];
+/// class minted to the source account during the exploration.
+const MINTED_CLASS: ClassId = 3;
+
+type Storage = HashMap<Vec<u8>, Vec<u8>>;
+
+fn acc(name: &str) -> AccountId {
+    AccountId::new_unchecked(format!("{}.near", name))
+}
+
+fn issuers() -> Vec<AccountId> {
+    vec![acc("sbt1"), acc("sbt2")]
+}
+
+fn source() -> AccountId {
+    acc("alice")
+}
+
+fn recipient() -> AccountId {
+    acc("bob")
+}
+
+fn relayer() -> AccountId {
+    acc("carol")
+}
+
+fn mk_metadata(class: ClassId) -> TokenMetadata {
+    TokenMetadata {
+        class,
+        issued_at: None,
+        expires_at: None,
+        reference: None,
+        reference_hash: None,
+        attributes_root: None,
+    }
+}
+
+fn context(predecessor: &AccountId, deposit: Balance) -> VMContext {
+    VMContextBuilder::new()
+        .current_account_id(acc("registry"))
+        .predecessor_account_id(predecessor.clone())
+        .attached_deposit(deposit)
+        .prepaid_gas(Gas::ONE_TERA * 300)
+        .block_timestamp(START_MS * 1_000_000)
+        .is_view(false)
+        .build()
+}
+
+fn set_env(ctx: VMContext, storage: Storage) {
+    env::set_blockchain_interface(MockedBlockchain::new(
+        ctx,
+        VMConfig::test(),
+        RuntimeFeesConfig::test(),
+        vec![],
+        storage,
+        Default::default(),
+        None,
+    ));
+}
+
+fn take_storage() -> Storage {
+    near_sdk::mock::with_mocked_blockchain(|b| b.take_storage())
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Action {
+    /// `sbt_soul_transfer` by the source account, with the batch limit.
+    Transfer(usize),
+    /// `sbt_soul_transfer_continue` by the relayer, with the batch limit.
+    Continue(usize),
+    /// mint of `MINTED_CLASS` to the source account by the issuer (index).
+    Mint(usize),
+}
+
+/// Contract storage and the serialized contract struct.
+struct Snapshot {
+    storage: Storage,
+    state: Vec<u8>,
+}
+
+#[derive(Clone, Default)]
+struct Model {
+    /// (issuer index, token) -> class, of all tokens of the source and the recipient.
+    tokens: BTreeMap<(usize, TokenId), ClassId>,
+    /// tokens owned by the recipient, they must stay with the recipient.
+    received: BTreeSet<(usize, TokenId)>,
+    started: bool,
+    completed: bool,
+    /// actions applied from the initial state, for failure messages.
+    path: Vec<Action>,
+}
+
+impl Model {
+    fn actions(&self) -> Vec<Action> {
+        let mut actions: Vec<Action> = LIMITS
+            .iter()
+            .flat_map(|&l| [Action::Transfer(l), Action::Continue(l)])
+            .collect();
+        for i in 0..issuers().len() {
+            if !self
+                .tokens
+                .iter()
+                .any(|((ti, _), c)| *ti == i && *c == MINTED_CLASS)
+            {
+                actions.push(Action::Mint(i));
+            }
+        }
+        actions
+    }
+
+    /// Returns true if the action must panic: the source is banned once the transfer starts
+    /// and the bond is returned when it completes.
+    fn must_fail(&self, action: Action) -> bool {
+        match action {
+            Action::Transfer(_) => self.completed,
+            Action::Continue(_) => !self.started || self.completed,
+            Action::Mint(_) => self.started,
+        }
+    }
+}
+
+/// Applies the action to the snapshot. Returns the new snapshot (and model), or None if
+/// the call panicked, which reverts it.
+fn apply(snap: &Snapshot, m: &Model, action: Action) -> Option<(Snapshot, Model)> {
+    let (caller, deposit) = match action {
+        Action::Transfer(_) if !m.started => (source(), SOUL_TRANSFER_BOND),
+        Action::Transfer(_) => (source(), 0),
+        Action::Continue(_) => (relayer(), 0),
+        Action::Mint(i) => (issuers()[i].clone(), near_sdk::ONE_NEAR),
+    };
+    let mut m = m.clone();
+    m.path.push(action);
+    set_env(context(&caller, deposit), snap.storage.clone());
+    let mut ctr = Contract::try_from_slice(&snap.state).unwrap();
+    let res = catch_unwind(AssertUnwindSafe(|| match action {
+        Action::Transfer(limit) => (ctr._sbt_soul_transfer(recipient(), limit), vec![]),
+        Action::Continue(limit) => (ctr._sbt_soul_transfer_continue(source(), limit), vec![]),
+        Action::Mint(_) => (
+            (0, false),
+            ctr.sbt_mint(vec![(source(), vec![mk_metadata(MINTED_CLASS)])]),
+        ),
+    }));
+    let ((moved, completed), minted) = match res {
+        Err(_) => {
+            assert!(m.must_fail(action), "unexpected panic, path: {:?}", m.path);
+            return None;
+        }
+        Ok(r) => r,
+    };
+    assert!(!m.must_fail(action), "expected panic, path: {:?}", m.path);
+    match action {
+        Action::Mint(i) => {
+            m.tokens.insert((i, minted[0]), MINTED_CLASS);
+        }
+        Action::Transfer(limit) | Action::Continue(limit) => {
+            assert!(moved as usize <= limit, "path: {:?}", m.path);
+            m.started = true;
+            m.completed = completed;
+        }
+    }
+    check(&ctr, &mut m);
+    let state = ctr.try_to_vec().unwrap();
+    Some((
+        Snapshot {
+            storage: take_storage(),
+            state,
+        },
+        m,
+    ))
+}
+
+/// Checks that every token is owned by either the source or the recipient (exactly one
+/// balance points to it), that received tokens stay with the recipient and that the supply
+/// counters match. Once the transfer completes, all tokens must be owned by the recipient.
+fn check(ctr: &Contract, m: &mut Model) {
+    let issuers = issuers();
+    let issuer_ids: Vec<IssuerId> = issuers
+        .iter()
+        .map(|i| ctr.sbt_issuers.get(i).unwrap())
+        .collect();
+    let path = m.path.clone();
+    for (&(i, token), &class) in &m.tokens {
+        let t = ctr
+            .sbt(issuers[i].clone(), token)
+            .unwrap_or_else(|| panic!("token {} lost, path: {:?}", token, path));
+        let (owner, other) = if t.owner == source() {
+            (source(), recipient())
+        } else {
+            assert_eq!(t.owner, recipient(), "path: {:?}", path);
+            (recipient(), source())
+        };
+        if owner == recipient() {
+            m.received.insert((i, token));
+        } else {
+            assert!(
+                !m.received.contains(&(i, token)),
+                "token {} moved back, path: {:?}",
+                token,
+                path
+            );
+        }
+        let key = |owner| BalanceKey {
+            owner,
+            issuer_id: issuer_ids[i],
+            class_id: class,
+        };
+        assert_eq!(
+            ctr.balances.get(&key(owner)),
+            Some(token),
+            "path: {:?}",
+            path
+        );
+        assert_ne!(
+            ctr.balances.get(&key(other)),
+            Some(token),
+            "path: {:?}",
+            path
+        );
+    }
+    let balances = |owner: AccountId| {
+        ctr.balances
+            .iter()
+            .filter(|(k, _)| k.owner == owner)
+            .count()
+    };
+    assert_eq!(
+        balances(source()) + balances(recipient()),
+        m.tokens.len(),
+        "path: {:?}",
+        path
+    );
+
+    for (i, issuer) in issuers.iter().enumerate() {
+        let supply = |owner: AccountId| {
+            ctr.supply_by_owner
+                .get(&(owner, issuer_ids[i]))
+                .unwrap_or(0)
+        };
+        let owned = |owner: &AccountId| {
+            m.tokens
+                .keys()
+                .filter(|k| k.0 == i && m.received.contains(k) == (*owner == recipient()))
+                .count() as u64
+        };
+        let total = m.tokens.keys().filter(|k| k.0 == i).count() as u64;
+        assert_eq!(
+            ctr.sbt_supply(issuer.clone(), None),
+            total,
+            "path: {:?}",
+            path
+        );
+        // the owner supply is moved with the first transferred token of the issuer.
+        assert_eq!(
+            supply(source()) + supply(recipient()),
+            total,
+            "path: {:?}",
+            path
+        );
+        if !m.started || m.completed {
+            assert_eq!(supply(source()), owned(&source()), "path: {:?}", path);
+            assert_eq!(supply(recipient()), owned(&recipient()), "path: {:?}", path);
+        }
+    }
+
+    if m.completed {
+        assert_eq!(m.received.len(), m.tokens.len(), "path: {:?}", path);
+        assert!(!ctr.ongoing_soul_tx.contains_key(&source()));
+        assert!(!ctr.soul_transfer_bonds.contains_key(&source()));
+        for issuer in issuers {
+            assert_eq!(ctr.check_invariants(issuer, None, None), vec![]);
+        }
+    }
+}
+
+/// Registry with two issuers. The source has 3 tokens of both issuers, the recipient has a
+/// token of a class the source doesn't have.
+fn initial() -> (Snapshot, Model) {
+    let authority = acc("admin");
+    set_env(context(&authority, 0), Storage::new());
+    let mut ctr = Contract::new(authority.clone(), acc("fractal"), vec![1]);
+    for issuer in issuers() {
+        set_env(context(&issuer, ISSUER_BOND), take_storage());
+        ctr.issuer_lock_bond();
+        set_env(context(&authority, 0), take_storage());
+        ctr.admin_add_sbt_issuer(issuer, None);
+    }
+    let mut m = Model::default();
+    let specs = [
+        vec![(source(), vec![mk_metadata(1), mk_metadata(2)])],
+        vec![
+            (source(), vec![mk_metadata(1)]),
+            (recipient(), vec![mk_metadata(2)]),
+        ],
+    ];
+    for (i, spec) in specs.into_iter().enumerate() {
+        set_env(context(&issuers()[i], near_sdk::ONE_NEAR), take_storage());
+        let classes: Vec<ClassId> = spec
+            .iter()
+            .flat_map(|(_, ms)| ms.iter().map(|md| md.class))
+            .collect();
+        let ids = ctr.sbt_mint(spec);
+        for (token, class) in ids.into_iter().zip(classes) {
+            m.tokens.insert((i, token), class);
+        }
+    }
+    check(&ctr, &mut m);
+    let state = ctr.try_to_vec().unwrap();
+    (
+        Snapshot {
+            storage: take_storage(),
+            state,
+        },
+        m,
+    )
+}
+
+/// Depth first search of the reachable states. Returns the number of distinct states with
+/// the completed transfer.
+fn explore(snap: &Snapshot, m: &Model, visited: &mut HashSet<BTreeMap<Vec<u8>, Vec<u8>>>) -> usize {
+    let mut completed = 0;
+    for action in m.actions() {
+        let (next, next_m) = match apply(snap, m, action) {
+            Some(n) => n,
+            None => continue,
+        };
+        if !visited.insert(next.storage.clone().into_iter().collect()) {
+            continue;
+        }
+        if next_m.completed {
+            completed += 1;
+        }
+        completed += explore(&next, &next_m, visited);
+    }
+    completed
+}
+
+#[test]
+fn soul_transfer_interleavings() {
+    let (snap, m) = initial();
+    let mut visited = HashSet::new();
+    let completed = explore(&snap, &m, &mut visited);
+    // the completed transfer doesn't depend on the batches: there is one final state per
+    // set of tokens minted before the transfer started.
+    assert_eq!(completed, 4);
+    assert!(visited.len() > 20, "visited states: {}", visited.len());
+}
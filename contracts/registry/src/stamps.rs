@@ -0,0 +1,112 @@
+//! Stamps: lightweight attestations (eg event check-ins) for high volume, low value use cases.
+//! A stamp only stores its `issued_at`, keyed by `(owner, issuer, class)`, in a separate map:
+//! there is no token ID, metadata, owner balance or expiration. Stamp classes are a separate
+//! namespace from the token classes of the issuer. Stamps are not NEP-393 tokens: they are
+//! not returned by the token views, don't count in `is_human` and are not moved by soul
+//! transfers or recoveries.
+
+use near_sdk::{env, near_bindgen, require, AccountId};
+use sbt::{ClassId, InputError};
+
+use crate::*;
+
+#[near_bindgen]
+impl Contract {
+    /// Mints a stamp of the `class` to each of the `recipients`. Panics if a recipient already
+    /// has a stamp of the class, or is banned. Emits the `mint_stamps` registry event.
+    /// Must be called by an issuer with the `CAP_MINT` capability, with a deposit covering
+    /// the storage (`cost::STAMP_COST` per stamp).
+    #[payable]
+    pub fn sbt_mint_stamps(&mut self, class: ClassId, recipients: Vec<AccountId>) {
+        self.assert_not_paused();
+        let storage_start = env::storage_usage();
+        let issuer = env::predecessor_account_id();
+        let issuer_id = self.assert_issuer(&issuer);
+        self.assert_capability(issuer_id, CAP_MINT);
+        if class == 0 {
+            InputError::ZeroClass.panic();
+        }
+        if recipients.len() > MAX_MINT_BATCH {
+            InputError::BatchTooLarge {
+                max: MAX_MINT_BATCH,
+            }
+            .panic();
+        }
+        let recipient_policy = self.recipient_policies.get(&issuer_id);
+        let now = MilliSeconds::now().0;
+        for owner in &recipients {
+            self.lift_expired_ban(owner);
+            self.assert_not_banned(owner);
+            if let Some(p) = &recipient_policy {
+                require!(
+                    p.allows(owner),
+                    format!("{} is not allowed by the issuer recipient policy", owner)
+                );
+            }
+            let prev = self
+                .stamps
+                .insert(&balance_key(owner.clone(), issuer_id, class), &now);
+            require!(
+                prev.is_none(),
+                format!("{} already has a stamp of class {}", owner, class)
+            );
+        }
+        let key = (issuer_id, class);
+        let supply = self.stamp_supply.get(&key).unwrap_or(0) + recipients.len() as u64;
+        self.stamp_supply.insert(&key, &supply);
+        self.emit_registry_event(RegistryEvent::MintStamps {
+            issuer: &issuer,
+            class,
+            recipients: &recipients,
+        });
+        assert_storage_deposit(storage_start, env::attached_deposit());
+    }
+
+    /// Removes the stamps of the `class` of the `accounts`. Accounts without the stamp are
+    /// skipped. Emits the `revoke_stamps` registry event with the accounts which had the
+    /// stamp. Must be called by an issuer.
+    pub fn sbt_revoke_stamps(&mut self, class: ClassId, accounts: Vec<AccountId>) {
+        self.assert_not_paused();
+        let issuer = env::predecessor_account_id();
+        let issuer_id = self.assert_issuer(&issuer);
+        let revoked: Vec<AccountId> = accounts
+            .into_iter()
+            .filter(|a| {
+                self.stamps
+                    .remove(&balance_key(a.clone(), issuer_id, class))
+                    .is_some()
+            })
+            .collect();
+        if revoked.is_empty() {
+            return;
+        }
+        let key = (issuer_id, class);
+        let supply = self.stamp_supply.get(&key).unwrap_or(0) - revoked.len() as u64;
+        self.stamp_supply.insert(&key, &supply);
+        self.emit_registry_event(RegistryEvent::RevokeStamps {
+            issuer: &issuer,
+            class,
+            accounts: &revoked,
+        });
+    }
+
+    /// Returns the time (unix timestamp in miliseconds) the `account` got the stamp of the
+    /// `issuer` `class`, or None if the account doesn't have the stamp.
+    pub fn stamp_issued_at(
+        &self,
+        account: AccountId,
+        issuer: AccountId,
+        class: ClassId,
+    ) -> Option<u64> {
+        let issuer_id = self.sbt_issuers.get(&issuer)?;
+        self.stamps.get(&balance_key(account, issuer_id, class))
+    }
+
+    /// Returns the number of stamps of the `issuer` `class`.
+    pub fn stamp_supply(&self, issuer: AccountId, class: ClassId) -> u64 {
+        match self.sbt_issuers.get(&issuer) {
+            None => 0,
+            Some(issuer_id) => self.stamp_supply.get(&(issuer_id, class)).unwrap_or(0),
+        }
+    }
+}
@@ -0,0 +1,371 @@
+//! Token status state machine. Every token status change (renew, revoke, freeze, unfreeze,
+//! burn and prune) goes through `Contract::transition_token`, which panics on transitions
+//! not allowed by `TokenStatus::apply`.
+//! Only `Revoked`, `Frozen` and `Burned` statuses are stored. `Active` and `Expired` are
+//! derived from the token `expires_at`.
+
+use std::fmt;
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::serde::Serialize;
+use near_sdk::{env, near_bindgen, AccountId};
+use sbt::{RevocationReason, TokenData, TokenId};
+
+use crate::*;
+
+/// Token status, see `sbt_status`.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub enum TokenStatus {
+    Active,
+    /// `expires_at` is in the past. The token can be renewed.
+    Expired,
+    /// revoked by the issuer. Can only be burned.
+    Revoked,
+    /// frozen by the authority: the token doesn't count as a valid proof and can't be
+    /// renewed until it's unfrozen.
+    Frozen,
+    /// tombstone of a burned token. Final status.
+    Burned,
+}
+
+/// Operation changing the token status.
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub enum TokenOp {
+    Renew,
+    Revoke,
+    Freeze,
+    Unfreeze,
+    Burn,
+    /// burn of an expired or revoked token.
+    Prune,
+}
+
+impl TokenStatus {
+    /// Returns the status after applying the `op`, or None if the transition is not allowed.
+    pub fn apply(self, op: TokenOp) -> Option<TokenStatus> {
+        use TokenStatus::*;
+        match (self, op) {
+            (Active | Expired, TokenOp::Renew) => Some(Active),
+            (Active | Expired | Frozen, TokenOp::Revoke) => Some(Revoked),
+            (Active | Expired, TokenOp::Freeze) => Some(Frozen),
+            (Frozen, TokenOp::Unfreeze) => Some(Active),
+            (Active | Expired | Revoked | Frozen, TokenOp::Burn) => Some(Burned),
+            (Expired | Revoked, TokenOp::Prune) => Some(Burned),
+            _ => None,
+        }
+    }
+
+    /// Returns true if the status is stored, rather than derived from the token data.
+    fn is_stored(self) -> bool {
+        matches!(
+            self,
+            TokenStatus::Revoked | TokenStatus::Frozen | TokenStatus::Burned
+        )
+    }
+}
+
+impl fmt::Display for TokenStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            TokenStatus::Active => "active",
+            TokenStatus::Expired => "expired",
+            TokenStatus::Revoked => "revoked",
+            TokenStatus::Frozen => "frozen",
+            TokenStatus::Burned => "burned",
+        };
+        f.write_str(s)
+    }
+}
+
+impl fmt::Display for TokenOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            TokenOp::Renew => "renew",
+            TokenOp::Revoke => "revoke",
+            TokenOp::Freeze => "freeze",
+            TokenOp::Unfreeze => "unfreeze",
+            TokenOp::Burn => "burn",
+            TokenOp::Prune => "prune",
+        };
+        f.write_str(s)
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Returns the token status, or None if the token was never minted.
+    pub fn sbt_status(&self, issuer: AccountId, token: TokenId) -> Option<TokenStatus> {
+        let issuer_id = self.assert_issuer(&issuer);
+        let key = IssuerTokenId { issuer_id, token };
+        if let Some(s) = self.token_statuses.get(&key) {
+            return Some(s);
+        }
+        self.issuer_tokens
+            .get(&key)
+            .map(|t| self.status_of(issuer_id, token, &t))
+    }
+
+    /// Returns the reason given when the token was revoked or frozen (also kept after the
+    /// token is burned), or None.
+    pub fn sbt_revocation_reason(
+        &self,
+        issuer: AccountId,
+        token: TokenId,
+    ) -> Option<RevocationReason> {
+        let issuer_id = self.assert_issuer(&issuer);
+        self.revocation_reasons
+            .get(&IssuerTokenId { issuer_id, token })
+    }
+
+    /// `sbt_revoke` with the `reason`, which is stored with the token status (see
+    /// `sbt_revocation_reason`) and included in the `revoke` and `burn` events.
+    /// Must be called by an SBT contract.
+    pub fn sbt_revoke_with_reason(
+        &mut self,
+        tokens: Vec<TokenId>,
+        burn: bool,
+        reason: RevocationReason,
+    ) {
+        let issuer = env::predecessor_account_id();
+        self.revoke_tokens(issuer, tokens, burn, Some(reason));
+    }
+
+    /// Burns expired or revoked tokens of the caller to free the registry storage.
+    /// Stops when the transaction gas is running out and returns the number `n` of burned
+    /// tokens: the remaining tokens (`tokens[n..]`) must be pruned in a subsequent call.
+    /// Panics if any of the burned tokens is active, frozen or already burned.
+    /// Must be called by an SBT issuer. Emits `Burn` event.
+    pub fn sbt_prune(&mut self, tokens: Vec<TokenId>) -> u32 {
+        self.assert_not_paused();
+        let issuer = env::predecessor_account_id();
+        let issuer_id = self.assert_issuer(&issuer);
+        let storage_start = env::storage_usage();
+        let mut pruned = Vec::new();
+        for token in BoundedIter::new(tokens.into_iter(), SWEEP_GAS_RESERVE) {
+            self.burn_tokens(issuer_id, &[token], TokenOp::Prune);
+            pruned.push(token);
+        }
+        let num_pruned = pruned.len() as u32;
+        self.emit_event(Nep393Event::Burn(SbtTokensEvent {
+            issuer,
+            tokens: pruned,
+            storage: Some(StorageCharge::since(storage_start, 0)),
+            reason: None,
+        }));
+        num_pruned
+    }
+
+    /// Freezes the `issuer` tokens: frozen tokens are not returned by queries of valid
+    /// tokens (so they don't count in `is_human`) and can't be renewed until unfrozen.
+    /// Only active and expired tokens can be frozen. The optional `reason` is stored until
+    /// the tokens are unfrozen. Emits `FreezeTokens` registry event with the optional `memo`
+    /// and `reason`. Must be called by the authority.
+    pub fn admin_freeze_tokens(
+        &mut self,
+        issuer: AccountId,
+        tokens: Vec<TokenId>,
+        memo: Option<String>,
+        reason: Option<RevocationReason>,
+    ) {
+        self.assert_admin_action("admin_freeze_tokens", memo.as_deref());
+        self.assert_memo(&memo);
+        let issuer_id = self.assert_issuer(&issuer);
+        for token in &tokens {
+            let t = self.get_token(issuer_id, *token);
+            self.transition_token(issuer_id, *token, &t, TokenOp::Freeze);
+            self.set_revocation_reason(issuer_id, *token, reason);
+        }
+        self.emit_registry_event(RegistryEvent::FreezeTokens {
+            issuer: &issuer,
+            tokens: &tokens,
+            memo: memo.as_deref(),
+            reason,
+        });
+    }
+
+    /// Unfreezes frozen `issuer` tokens. Emits `UnfreezeTokens` registry event with the
+    /// optional `memo`. Must be called by the authority.
+    pub fn admin_unfreeze_tokens(
+        &mut self,
+        issuer: AccountId,
+        tokens: Vec<TokenId>,
+        memo: Option<String>,
+    ) {
+        self.assert_admin_action("admin_unfreeze_tokens", memo.as_deref());
+        self.assert_memo(&memo);
+        let issuer_id = self.assert_issuer(&issuer);
+        for token in &tokens {
+            let t = self.get_token(issuer_id, *token);
+            self.transition_token(issuer_id, *token, &t, TokenOp::Unfreeze);
+        }
+        self.emit_registry_event(RegistryEvent::UnfreezeTokens {
+            issuer: &issuer,
+            tokens: &tokens,
+            memo: memo.as_deref(),
+        });
+    }
+}
+
+impl Contract {
+    /// Revokes (or burns, when `burn`) the `issuer` tokens, see `sbt_revoke`. Panics if the
+    /// `issuer` is not registered.
+    pub(crate) fn revoke_tokens(
+        &mut self,
+        issuer: AccountId,
+        tokens: Vec<TokenId>,
+        burn: bool,
+        reason: Option<RevocationReason>,
+    ) {
+        self.assert_not_paused();
+        let issuer_id = self.assert_issuer(&issuer);
+        if burn {
+            let storage_start = env::storage_usage();
+            self.burn_tokens(issuer_id, &tokens, TokenOp::Burn);
+            for token in &tokens {
+                self.set_revocation_reason(issuer_id, *token, reason);
+            }
+            self.emit_event(Nep393Event::Burn(SbtTokensEvent {
+                issuer: issuer.clone(),
+                tokens: tokens.clone(),
+                storage: Some(StorageCharge::since(storage_start, 0)),
+                reason,
+            }));
+        } else {
+            let current_timestamp = MilliSeconds::now().0;
+            // revoke
+            for token in tokens.clone() {
+                // update expire date for all tokens to current_timestamp
+                let mut t = self.get_token(issuer_id, token);
+                self.transition_token(issuer_id, token, &t, TokenOp::Revoke);
+                self.set_revocation_reason(issuer_id, token, reason);
+                let mut m = t.metadata.latest();
+                // revoked tokens are removed from the expirations index
+                self.index_expiration(issuer_id, token, m.expires_at, None);
+                m.expires_at = Some(current_timestamp);
+                t.metadata = m.into();
+                self.issuer_tokens
+                    .insert(&IssuerTokenId { issuer_id, token }, t);
+            }
+        }
+        self.count_revoked(issuer_id, tokens.len() as u64);
+        self.emit_event(Nep393Event::Revoke(SbtTokensEvent {
+            issuer,
+            tokens,
+            storage: None,
+            reason,
+        }));
+    }
+
+    /// Stores the revocation `reason` of the token. Without a reason, the reason given by an
+    /// earlier revoke or freeze is kept.
+    fn set_revocation_reason(
+        &mut self,
+        issuer_id: IssuerId,
+        token: TokenId,
+        reason: Option<RevocationReason>,
+    ) {
+        if let Some(r) = reason {
+            self.revocation_reasons
+                .insert(&IssuerTokenId { issuer_id, token }, &r);
+        }
+    }
+
+    /// Returns the status of the existing token `t`.
+    pub(crate) fn status_of(
+        &self,
+        issuer_id: IssuerId,
+        token: TokenId,
+        t: &TokenData,
+    ) -> TokenStatus {
+        if let Some(s) = self.token_statuses.get(&IssuerTokenId { issuer_id, token }) {
+            return s;
+        }
+        if t.metadata.is_expired_at(MilliSeconds::now()) {
+            TokenStatus::Expired
+        } else {
+            TokenStatus::Active
+        }
+    }
+
+    /// Returns true if the token is frozen.
+    pub(crate) fn is_frozen(&self, issuer_id: IssuerId, token: TokenId) -> bool {
+        self.token_statuses.get(&IssuerTokenId { issuer_id, token }) == Some(TokenStatus::Frozen)
+    }
+
+    /// Returns true if the burned token tombstone exists.
+    pub(crate) fn is_burned(&self, issuer_id: IssuerId, token: TokenId) -> bool {
+        self.token_statuses.get(&IssuerTokenId { issuer_id, token }) == Some(TokenStatus::Burned)
+    }
+
+    /// Applies the `op` to the status of the existing token `t` and returns the new status.
+    /// Panics if the transition is not allowed. The caller is responsible for updating the
+    /// token data (eg removing burned tokens).
+    pub(crate) fn transition_token(
+        &mut self,
+        issuer_id: IssuerId,
+        token: TokenId,
+        t: &TokenData,
+        op: TokenOp,
+    ) -> TokenStatus {
+        let from = self.status_of(issuer_id, token, t);
+        let to = from
+            .apply(op)
+            .unwrap_or_else(|| panic!("can't {} {} token {}", op, from, token));
+        match (is_valid_status(from), is_valid_status(to)) {
+            _ if !self.is_counted(issuer_id, token) => (),
+            (true, false) => self.remove_valid(issuer_id, t.metadata.expires_at()),
+            (false, true) => self.add_valid(issuer_id, t.metadata.expires_at(), 1),
+            _ => (),
+        }
+        let key = IssuerTokenId { issuer_id, token };
+        if op == TokenOp::Unfreeze {
+            self.revocation_reasons.remove(&key);
+        }
+        if to.is_stored() {
+            self.token_statuses.insert(&key, &to);
+        } else if from.is_stored() {
+            self.token_statuses.remove(&key);
+        }
+        to
+    }
+
+    /// Burns the `tokens` of the issuer (`op` must be `Burn` or `Prune`): removes them from
+    /// the owner balances, supplies and the expirations index, and leaves the `Burned`
+    /// tombstone.
+    pub(crate) fn burn_tokens(&mut self, issuer_id: IssuerId, tokens: &[TokenId], op: TokenOp) {
+        let mut burned_per_class: HashMap<ClassId, u64> = HashMap::new();
+        let mut burned_per_owner: HashMap<AccountId, u64> = HashMap::new();
+        for token in tokens {
+            let t = self.get_token(issuer_id, *token);
+            self.transition_token(issuer_id, *token, &t, op);
+            let class_id = t.metadata.class_id();
+            self.balances
+                .remove(&balance_key(t.owner.clone(), issuer_id, class_id));
+            *burned_per_class.entry(class_id).or_default() += 1;
+            *burned_per_owner.entry(t.owner).or_default() += 1;
+            self.issuer_tokens.remove(&IssuerTokenId {
+                issuer_id,
+                token: *token,
+            });
+            self.index_expiration(issuer_id, *token, t.metadata.expires_at(), None);
+        }
+        for (owner, n) in burned_per_owner {
+            self.update_account_supply(&owner, n, false);
+            let key = (owner, issuer_id);
+            let supply = self.supply_by_owner.get(&key).unwrap();
+            self.supply_by_owner.insert(&key, &(supply - n));
+        }
+        for (class_id, n) in burned_per_class {
+            let key = (issuer_id, class_id);
+            let supply = self.supply_by_class.get(&key).unwrap();
+            self.supply_by_class.insert(&key, &(supply - n));
+        }
+        let supply = self.supply_by_issuer.get(&issuer_id).unwrap_or(0);
+        self.supply_by_issuer
+            .insert(&issuer_id, &(supply - tokens.len() as u64));
+    }
+}
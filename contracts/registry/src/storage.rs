@@ -1,10 +1,57 @@
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::{AccountId, BorshStorageKey};
-use sbt::{ClassId, TokenId};
+use std::ops::Bound;
+
+use near_sdk::collections::{LookupMap, LookupSet, TreeMap, UnorderedMap, UnorderedSet};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, AccountId, Balance, BorshStorageKey, CryptoHash};
+use sbt::{ClassId, TokenData, TokenId, VerTokenData};
 
 /// Issuer contract ID based on the SBT Contract address -> u16 map.
 pub type IssuerId = u32;
 
+/// Bitmask of issuer capabilities granted by the registry authority.
+pub type Capabilities = u32;
+
+/// Issuer can make a soul transfer on behalf of a user (`sbt_soul_transfer_by_issuer`).
+pub const CAP_SOUL_TRANSFER: Capabilities = 1;
+/// Issuer can mint new tokens.
+pub const CAP_MINT: Capabilities = 1 << 1;
+/// Issuer can renew its tokens.
+pub const CAP_RENEW: Capabilities = 1 << 2;
+/// Issuer can recover its tokens (`sbt_recover`).
+pub const CAP_RECOVER: Capabilities = 1 << 3;
+/// Issuer can set `issued_at` of minted tokens in the past (bounded by `MAX_BACKDATE_MS`),
+/// used by migration issuers importing historical credentials. Tokens minted by other
+/// issuers always get `issued_at` set by the registry.
+pub const CAP_BACKDATE: Capabilities = 1 << 4;
+/// Tokens of the issuer can be imported by the authority, preserving the token IDs and
+/// `issued_at`, until the genesis is finalized (see `admin_import_tokens`).
+pub const CAP_IMPORT: Capabilities = 1 << 5;
+
+/// Max time (in miliseconds) `issued_at` can be set in the past by an issuer with the
+/// `CAP_BACKDATE` capability.
+pub const MAX_BACKDATE_MS: u64 = 5 * 365 * 24 * 3600 * 1000;
+
+/// Capabilities of an issuer which were not explicitly set by the authority.
+pub const CAP_DEFAULT: Capabilities = CAP_MINT | CAP_RENEW | CAP_RECOVER;
+
+/// Max number of tokens which can be minted (or vouchers created) in a single call.
+pub const MAX_MINT_BATCH: usize = 100;
+
+/// Default max number of tokens (from all issuers) an account can own.
+pub const DEFAULT_MAX_TOKENS_PER_ACCOUNT: u32 = 1000;
+/// Default max number of registered issuers.
+pub const DEFAULT_MAX_ISSUERS: u32 = 500;
+/// Default max number of classes an issuer can mint tokens of.
+pub const DEFAULT_MAX_CLASSES_PER_ISSUER: u32 = 1000;
+/// Hard cap of `max_tokens_per_account`. Views of an account read its balances by shards
+/// (see `Balances`), but soul transfers and recoveries move every token of the account: at
+/// the cap they need a few hundred gas bounded calls.
+pub const MAX_TOKENS_PER_ACCOUNT: u32 = 10_000;
+
+/// Number of classes in a balances shard, see `Balances`.
+pub(crate) const SHARD_CLASSES: ClassId = 128;
+
 /// Helper structure for keys of the persistent collections.
 #[derive(BorshSerialize, BorshStorageKey)]
 pub enum StorageKey {
@@ -18,6 +65,79 @@ pub enum StorageKey {
     IssuerTokens,
     NextTokenId,
     OngoingSoultTx,
+    MergeApprovals,
+    IssuerSoulTx,
+    IssuerCapabilities,
+    LastActivity,
+    Expirations,
+    CodeBlobs,
+    PendingActions,
+    Vouchers,
+    RecipientPolicies,
+    ClassCaps,
+    ClassMinTtls,
+    RecreatedAccounts,
+    Credentials,
+    MintEscrow,
+    Recoveries,
+    IssuerCounters,
+    BanExpirations,
+    TokenStatuses,
+    SoulTransferBonds,
+    NotificationReceivers,
+    IssuerBonds,
+    UsedContexts,
+    TransferCooldowns,
+    KeyBoundClasses,
+    Policies,
+    Stamps,
+    StampSupply,
+    IssuerMetadata,
+    SoulTransferCallbacks,
+    MintRefs,
+    RevocationReasons,
+    PrivateAccounts,
+    HumanCallTargets,
+    CompositeCredentials,
+    TokenReservations,
+    AdminLog,
+    ClassMaxTtls,
+    ValidSupply,
+    ValidExpirations,
+    InvalidationCursors,
+    ExpiringTokens,
+    RemovedIssuers,
+    RelayerAllowances,
+    BalanceShards,
+    LegacyTokenIds,
+    SupplyByAccount,
+}
+
+/// Issuer policy of accounts which can receive its tokens on mint.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq, Clone))]
+pub struct RecipientPolicy {
+    /// reject implicit (64 character hex) accounts.
+    pub reject_implicit: bool,
+    /// if set, only sub-accounts of the given account (eg "near") are allowed. A leading
+    /// dot (eg ".near") is ignored.
+    pub allowed_suffix: Option<String>,
+}
+
+impl RecipientPolicy {
+    pub fn allows(&self, account: &AccountId) -> bool {
+        let a = account.as_str();
+        if self.reject_implicit && a.len() == 64 && a.bytes().all(|c| c.is_ascii_hexdigit()) {
+            return false;
+        }
+        match &self.allowed_suffix {
+            None => true,
+            Some(suffix) => a
+                .strip_suffix(suffix.trim_start_matches('.'))
+                .map_or(false, |prefix| prefix.len() > 1 && prefix.ends_with('.')),
+        }
+    }
 }
 
 /// Composition of issuer address and token id used for indexing
@@ -27,6 +147,151 @@ pub(crate) struct IssuerTokenId {
     pub token: TokenId,
 }
 
+/// Issuer tokens storage. Tokens are stored as `VerTokenData`, so new token data versions
+/// don't require rewriting the stored records. Accessors convert to the latest version.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub(crate) struct TokenStore(LookupMap<IssuerTokenId, VerTokenData>);
+
+impl TokenStore {
+    /// Creates the store with the `StorageKey::IssuerTokens` prefix.
+    pub fn new() -> Self {
+        Self(LookupMap::new(StorageKey::IssuerTokens))
+    }
+
+    /// Returns true if the token record is not stored in the latest format: it's a legacy
+    /// (not versioned) record or a V1 record (without the metadata hash, possibly with the
+    /// old metadata layout).
+    pub fn needs_migration(&self, key: &IssuerTokenId) -> bool {
+        let raw = match env::storage_read(&Self::state_key(key)) {
+            Some(raw) => raw,
+            None => return false,
+        };
+        // versioned records start with the enum tag (0 for V1, 1 for V2), legacy records
+        // start with the owner account ID length, which is never 0 nor 1. V2 records always
+        // have the metadata hash and the latest metadata layout.
+        raw[0] != 1
+    }
+
+    /// Returns the raw storage key of the token record.
+    pub fn state_key(key: &IssuerTokenId) -> Vec<u8> {
+        lookup_state_key(StorageKey::IssuerTokens, key)
+    }
+
+    pub fn get(&self, key: &IssuerTokenId) -> Option<TokenData> {
+        self.0.get(key).map(VerTokenData::latest)
+    }
+
+    /// Returns the token and its `sbt::token_metadata_hash`.
+    pub fn get_with_hash(&self, key: &IssuerTokenId) -> Option<(TokenData, CryptoHash)> {
+        self.0.get(key).map(VerTokenData::latest_with_hash)
+    }
+
+    /// Returns the token as stored, without the version conversion.
+    #[cfg(any(feature = "testnet", feature = "debug"))]
+    pub fn get_stored(&self, key: &IssuerTokenId) -> Option<VerTokenData> {
+        self.0.get(key)
+    }
+
+    pub fn contains_key(&self, key: &IssuerTokenId) -> bool {
+        self.0.contains_key(key)
+    }
+
+    /// Stores the token in the latest version. Returns the previous token.
+    pub fn insert(&mut self, key: &IssuerTokenId, t: TokenData) -> Option<TokenData> {
+        self.0.insert(key, &t.into()).map(VerTokenData::latest)
+    }
+
+    pub fn remove(&mut self, key: &IssuerTokenId) -> Option<TokenData> {
+        self.0.remove(key).map(VerTokenData::latest)
+    }
+}
+
+/// Persistent collection with a storage prefix. near-sdk doesn't expose the prefix, it's
+/// read from the collection Borsh encoding: `HEADER` bytes followed by the prefix (extended
+/// by the collection with `SUFFIX` bytes).
+pub(crate) trait StoragePrefix: BorshSerialize {
+    const HEADER: usize = 0;
+    const SUFFIX: usize = 0;
+
+    fn storage_prefix(&self) -> Vec<u8> {
+        let data = self.try_to_vec().unwrap();
+        let mut prefix =
+            <Vec<u8> as BorshDeserialize>::deserialize(&mut &data[Self::HEADER..]).unwrap();
+        prefix.truncate(prefix.len() - Self::SUFFIX);
+        prefix
+    }
+}
+
+impl<K: BorshSerialize, V: BorshSerialize> StoragePrefix for LookupMap<K, V> {}
+impl<T: BorshSerialize> StoragePrefix for LookupSet<T> {}
+impl StoragePrefix for TokenStore {}
+
+impl<K: BorshSerialize, V: BorshSerialize> StoragePrefix for UnorderedMap<K, V> {
+    /// the key index prefix: prefix + 'i'
+    const SUFFIX: usize = 1;
+}
+
+impl<T: BorshSerialize> StoragePrefix for UnorderedSet<T> {
+    /// the element index prefix: prefix + 'i'
+    const SUFFIX: usize = 1;
+}
+
+impl<K: BorshSerialize, V: BorshSerialize> StoragePrefix for TreeMap<K, V> {
+    /// TreeMap root (u64), followed by the values map prefix: prefix + 'v'
+    const HEADER: usize = 8;
+    const SUFFIX: usize = 1;
+}
+
+impl StoragePrefix for Balances {
+    /// the shards TreeMap (see `TreeMap` above)
+    const HEADER: usize = 8;
+    const SUFFIX: usize = 1;
+}
+
+/// Returns the raw storage key of the `key` record of a `LookupMap` with the `prefix`.
+pub(crate) fn lookup_state_key(prefix: StorageKey, key: &impl BorshSerialize) -> Vec<u8> {
+    [prefix.try_to_vec().unwrap(), key.try_to_vec().unwrap()].concat()
+}
+
+/// Issuer activity counters, see `issuer_stats`.
+#[derive(BorshSerialize, BorshDeserialize, Default)]
+pub(crate) struct IssuerCounters {
+    /// number of revoked tokens (including burned with `sbt_revoke`).
+    pub revoked: u64,
+    /// unix timestamp in miliseconds of the last mint, 0 if the issuer didn't mint yet.
+    /// Fixed size, so updating it on mint doesn't require additional storage deposit.
+    pub last_mint_ms: u64,
+    /// number of classes the issuer minted tokens of (including classes with all tokens
+    /// burned).
+    pub classes: u32,
+}
+
+/// Bond of a soul transfer which can be continued by anyone, see
+/// `sbt_soul_transfer_continue`.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub(crate) struct SoulTransferBond {
+    pub recipient: AccountId,
+    pub bond: Balance,
+}
+
+/// Completion callback of an ongoing soul transfer, see `sbt_soul_transfer`.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub(crate) struct SoulTransferCallback {
+    pub contract: AccountId,
+    pub method: String,
+    /// number of tokens processed by the soul transfer calls so far.
+    pub tokens: u32,
+}
+
+/// Contract code staged for deployment.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub(crate) struct StagedCode {
+    /// sha256 hash of the code
+    pub hash: CryptoHash,
+    /// unix timestamp in miliseconds
+    pub staged_at: u64,
+}
+
 #[derive(BorshSerialize, BorshDeserialize, Eq, Ord, PartialEq, PartialOrd, Clone)]
 pub(crate) struct BalanceKey {
     pub owner: AccountId,
@@ -43,6 +308,189 @@ pub(crate) fn balance_key(owner: AccountId, issuer_id: IssuerId, class_id: Class
     }
 }
 
+/// Key of a balances shard: the owner classes of the issuer in the
+/// `[bucket * SHARD_CLASSES, (bucket + 1) * SHARD_CLASSES)` range. Ordered as `BalanceKey`.
+#[derive(BorshSerialize, BorshDeserialize, Eq, Ord, PartialEq, PartialOrd, Clone)]
+pub(crate) struct ShardKey {
+    pub owner: AccountId,
+    pub issuer_id: IssuerId,
+    pub bucket: u64,
+}
+
+fn shard_key(key: &BalanceKey) -> ShardKey {
+    ShardKey {
+        owner: key.owner.clone(),
+        issuer_id: key.issuer_id,
+        bucket: key.class_id / SHARD_CLASSES,
+    }
+}
+
+/// Owner balances: `BalanceKey` -> token ID. The (owner, issuer) class map is sharded by
+/// `SHARD_CLASSES` classes: shards are sorted `(class, token)` lists, stored in a tree ordered
+/// by `ShardKey`. A balance lookup reads a single shard, and iteration reads a shard (one
+/// O(log n) tree step) per `SHARD_CLASSES` classes rather than per token, so accounts with
+/// thousands of tokens stay cheap to query. Only creating or emptying a shard changes the tree.
+/// Balances stored before the sharding (one `BalanceKey` -> token ID record per balance, under
+/// the `StorageKey::Balances` prefix) are read from the `legacy` tree, and moved to the shards
+/// when written or by `migrate_chunk`.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub(crate) struct Balances {
+    shards: TreeMap<ShardKey, Vec<(ClassId, TokenId)>>,
+    pub(crate) legacy: TreeMap<BalanceKey, TokenId>,
+}
+
+impl Balances {
+    /// Creates the balances with the `StorageKey::BalanceShards` prefix.
+    pub fn new() -> Self {
+        Self::with_legacy(TreeMap::new(StorageKey::Balances))
+    }
+
+    /// Creates the balances on top of the not sharded balances, see `Contract::migrate`.
+    pub fn with_legacy(legacy: TreeMap<BalanceKey, TokenId>) -> Self {
+        Self {
+            shards: TreeMap::new(StorageKey::BalanceShards),
+            legacy,
+        }
+    }
+
+    pub fn get(&self, key: &BalanceKey) -> Option<TokenId> {
+        let shard = self.shards.get(&shard_key(key));
+        match shard.and_then(|s| {
+            s.binary_search_by_key(&key.class_id, |e| e.0)
+                .ok()
+                .map(|i| s[i].1)
+        }) {
+            Some(token) => Some(token),
+            None if !self.legacy.is_empty() => self.legacy.get(key),
+            None => None,
+        }
+    }
+
+    pub fn contains_key(&self, key: &BalanceKey) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Returns the raw storage key of the record holding the `key` balance: its shard, or the
+    /// legacy record if the balance was not moved yet.
+    pub fn state_key(&self, key: &BalanceKey) -> Vec<u8> {
+        // TreeMap stores values in a LookupMap with the `v` suffixed prefix.
+        let (prefix, record) = if !self.legacy.is_empty() && self.legacy.contains_key(key) {
+            (StorageKey::Balances, key.try_to_vec().unwrap())
+        } else {
+            (
+                StorageKey::BalanceShards,
+                shard_key(key).try_to_vec().unwrap(),
+            )
+        };
+        [prefix.try_to_vec().unwrap(), b"v".to_vec(), record].concat()
+    }
+
+    /// Sets the owner token of the class. Returns the previous token.
+    pub fn insert(&mut self, key: &BalanceKey, token: &TokenId) -> Option<TokenId> {
+        let legacy = self.remove_legacy(key);
+        let skey = shard_key(key);
+        let mut shard = self.shards.get(&skey).unwrap_or_default();
+        let prev = match shard.binary_search_by_key(&key.class_id, |e| e.0) {
+            Ok(i) => Some(std::mem::replace(&mut shard[i].1, *token)),
+            Err(i) => {
+                shard.insert(i, (key.class_id, *token));
+                None
+            }
+        };
+        self.shards.insert(&skey, &shard);
+        prev.or(legacy)
+    }
+
+    pub fn remove(&mut self, key: &BalanceKey) -> Option<TokenId> {
+        if let Some(token) = self.remove_legacy(key) {
+            return Some(token);
+        }
+        let skey = shard_key(key);
+        let mut shard = self.shards.get(&skey)?;
+        let i = shard.binary_search_by_key(&key.class_id, |e| e.0).ok()?;
+        let (_, token) = shard.remove(i);
+        if shard.is_empty() {
+            self.shards.remove(&skey);
+        } else {
+            self.shards.insert(&skey, &shard);
+        }
+        Some(token)
+    }
+
+    fn remove_legacy(&mut self, key: &BalanceKey) -> Option<TokenId> {
+        if self.legacy.is_empty() {
+            return None;
+        }
+        self.legacy.remove(key)
+    }
+
+    /// Moves up to `limit` legacy balances to the shards. Returns the number of moved balances.
+    pub fn migrate_legacy(&mut self, limit: u32) -> u32 {
+        let mut moved = 0;
+        while moved < limit {
+            let key = match self.legacy.min() {
+                Some(key) => key,
+                None => break,
+            };
+            let token = self.legacy.get(&key).unwrap();
+            self.insert(&key, &token);
+            moved += 1;
+        }
+        moved
+    }
+
+    /// Iterates all balances in the `BalanceKey` order.
+    pub fn iter(&self) -> impl Iterator<Item = (BalanceKey, TokenId)> + '_ {
+        merge_sorted(shard_entries(self.shards.iter()), self.legacy.iter())
+    }
+
+    /// Iterates balances bigger than `from` (exclusive) in the `BalanceKey` order.
+    pub fn iter_from(&self, from: BalanceKey) -> impl Iterator<Item = (BalanceKey, TokenId)> + '_ {
+        let start = shard_key(&from);
+        let shards = shard_entries(
+            self.shards
+                .range((Bound::Included(start), Bound::Unbounded)),
+        )
+        .skip_while({
+            let from = from.clone();
+            move |(key, _)| *key <= from
+        });
+        merge_sorted(shards, self.legacy.iter_from(from))
+    }
+
+    /// Returns the smallest balance key bigger than `key`.
+    pub fn higher(&self, key: &BalanceKey) -> Option<BalanceKey> {
+        self.iter_from(key.clone()).next().map(|(k, _)| k)
+    }
+}
+
+/// Merges two iterators sorted by the balance key. A balance is stored either in a shard or
+/// in the legacy tree, so the iterators are disjoint.
+fn merge_sorted<'a>(
+    a: impl Iterator<Item = (BalanceKey, TokenId)> + 'a,
+    b: impl Iterator<Item = (BalanceKey, TokenId)> + 'a,
+) -> impl Iterator<Item = (BalanceKey, TokenId)> + 'a {
+    let (mut a, mut b) = (a.peekable(), b.peekable());
+    std::iter::from_fn(move || match (a.peek(), b.peek()) {
+        (Some(x), Some(y)) if y.0 < x.0 => b.next(),
+        (Some(_), _) => a.next(),
+        (None, _) => b.next(),
+    })
+}
+
+fn shard_entries(
+    shards: impl Iterator<Item = (ShardKey, Vec<(ClassId, TokenId)>)>,
+) -> impl Iterator<Item = (BalanceKey, TokenId)> {
+    shards.flat_map(|(skey, classes)| {
+        classes.into_iter().map(move |(class_id, token)| {
+            (
+                balance_key(skey.owner.clone(), skey.issuer_id, class_id),
+                token,
+            )
+        })
+    })
+}
+
 // macro_rules! borsh_be_integer {
 //     ($type: ident) => {
 //         impl BorshSerialize for $type {
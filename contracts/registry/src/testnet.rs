@@ -0,0 +1,36 @@
+//! Extra methods only available in the `testnet` build (`--features testnet`). They make
+//! testing and staging easier, but must never be deployed on mainnet.
+
+use near_sdk::{env, near_bindgen, AccountId};
+use sbt::*;
+
+use crate::*;
+
+#[near_bindgen]
+impl Contract {
+    /// Registers the caller as an SBT issuer. On testnet, anyone can become an issuer
+    /// without the authority approval, but the issuer bond is still required: the attached
+    /// deposit is added to the caller bond, which must be at least `cost::ISSUER_BOND`.
+    /// Returns false if the caller was already registered.
+    #[payable]
+    pub fn testnet_register_issuer(&mut self) -> bool {
+        let issuer = env::predecessor_account_id();
+        let deposit = env::attached_deposit();
+        if deposit > 0 {
+            self.lock_issuer_bond(&issuer, deposit);
+        }
+        self.assert_issuer_bond(&issuer);
+        self._add_sbt_issuer(&issuer)
+    }
+
+    /// Mints tokens on behalf of the `issuer`. Must be called by the authority.
+    #[payable]
+    pub fn admin_force_mint(
+        &mut self,
+        issuer: AccountId,
+        token_spec: Vec<(AccountId, Vec<TokenMetadata>)>,
+    ) -> Vec<TokenId> {
+        self.assert_admin_action("admin_force_mint", None);
+        self._sbt_mint(&issuer, token_spec)
+    }
+}
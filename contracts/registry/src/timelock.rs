@@ -0,0 +1,145 @@
+//! Timelock queue for destructive authority actions. An action must be queued first and can
+//! be executed only after the configured delay, so the community can react before the change
+//! lands.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen, require, AccountId};
+
+use crate::*;
+
+/// Default delay between queuing and executing an admin action.
+pub const DEFAULT_ACTION_TIMELOCK_MS: u64 = 2 * 24 * 3600 * 1000;
+
+/// Authority actions which must go through the timelock queue.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq, Clone))]
+pub enum AdminAction {
+    /// Removes the issuer from the registry. Tokens of the removed issuer are kept (and
+    /// can be queried and burned), but the issuer can't make any new transactions.
+    /// Can't be executed until the issuer bond unbonding period passes.
+    RemoveIssuer {
+        issuer: AccountId,
+    },
+    /// Unpauses the registry, see `admin_pause`.
+    Unpause,
+    ChangeAdmin {
+        new_admin: AccountId,
+    },
+    /// Sets the delay between queuing and executing an action.
+    SetActionTimelock {
+        timelock_ms: u64,
+    },
+    /// Removes the emergency guardian (and the proposed one), see `admin_pause`. `guardian`
+    /// must be `None`: the guardian is set with `propose_guardian` and `accept_guardian`.
+    SetGuardian {
+        guardian: Option<AccountId>,
+    },
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq, Clone))]
+pub struct PendingAction {
+    pub action: AdminAction,
+    /// unix timestamp in miliseconds
+    pub queued_at: u64,
+    /// time (unix timestamp in miliseconds) since when the action can be executed.
+    pub executable_at: u64,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Queues the admin action and returns its ID. The action can be executed with
+    /// `execute_action` once `action_timelock()` passed. Must be called by the authority.
+    /// Emits `QueueAction` registry event with the optional `memo`.
+    pub fn queue_action(&mut self, action: AdminAction, memo: Option<String>) -> u64 {
+        self.assert_admin_action("queue_action", memo.as_deref());
+        self.assert_memo(&memo);
+        require!(
+            !matches!(action, AdminAction::SetGuardian { guardian: Some(_) }),
+            "the guardian is set with propose_guardian"
+        );
+        let now = env::block_timestamp_ms();
+        let id = self.next_action_id;
+        self.next_action_id += 1;
+        self.emit_registry_event(RegistryEvent::QueueAction {
+            id,
+            action: &action,
+            memo: memo.as_deref(),
+        });
+        self.pending_actions.insert(
+            &id,
+            &PendingAction {
+                action,
+                queued_at: now,
+                executable_at: now + self.action_timelock_ms,
+            },
+        );
+        id
+    }
+
+    /// Executes the queued action once the timelock passed. Must be called by the authority.
+    /// Emits `ExecuteAction` registry event with the optional `memo`.
+    pub fn execute_action(&mut self, id: u64, memo: Option<String>) {
+        self.assert_admin_action("execute_action", memo.as_deref());
+        self.assert_memo(&memo);
+        let pa = self.pending_actions.get(&id).expect("action not found");
+        require!(
+            env::block_timestamp_ms() >= pa.executable_at,
+            "action timelock has not passed yet"
+        );
+        self.pending_actions.remove(&id);
+        self.emit_registry_event(RegistryEvent::ExecuteAction {
+            id,
+            action: &pa.action,
+            memo: memo.as_deref(),
+        });
+        match pa.action {
+            AdminAction::RemoveIssuer { issuer } => {
+                self.assert_issuer_unbonded(&issuer);
+                let issuer_id = self
+                    .sbt_issuers
+                    .remove(&issuer)
+                    .expect("issuer not registered");
+                let mut ids = self.removed_issuers.get(&issuer).unwrap_or_default();
+                ids.push(issuer_id);
+                self.removed_issuers.insert(&issuer, &ids);
+            }
+            AdminAction::Unpause => self.paused = false,
+            AdminAction::ChangeAdmin { new_admin } => self.authority = new_admin,
+            AdminAction::SetActionTimelock { timelock_ms } => self.action_timelock_ms = timelock_ms,
+            AdminAction::SetGuardian { guardian } => {
+                self.guardian = guardian;
+                self.proposed_guardian = None;
+            }
+        }
+    }
+
+    /// Removes the action from the queue. Must be called by the authority.
+    /// Emits `CancelAction` registry event with the optional `memo`.
+    pub fn cancel_action(&mut self, id: u64, memo: Option<String>) {
+        self.assert_admin_action("cancel_action", memo.as_deref());
+        self.assert_memo(&memo);
+        require!(
+            self.pending_actions.remove(&id).is_some(),
+            "action not found"
+        );
+        self.emit_registry_event(RegistryEvent::CancelAction {
+            id,
+            memo: memo.as_deref(),
+        });
+    }
+
+    /// Returns all queued actions with their IDs.
+    pub fn pending_actions(&self) -> Vec<(u64, PendingAction)> {
+        self.pending_actions.to_vec()
+    }
+
+    /// Returns the delay (in miliseconds) between queuing and executing an admin action.
+    pub fn action_timelock(&self) -> u64 {
+        self.action_timelock_ms
+    }
+}
@@ -0,0 +1,158 @@
+//! Class max TTL policies: an issuer caps the time to live of its class tokens with
+//! `sbt_set_class_max_ttl`. New mints and renewals must expire within the max TTL, and
+//! tokens minted before the policy (or before it was shortened) are clamped in batches with
+//! `apply_ttl_policy`, so old over-long proofs don't outlive the new policy.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::serde::Serialize;
+use near_sdk::{env, near_bindgen, require, AccountId};
+use sbt::*;
+
+use crate::*;
+
+/// Max number of token IDs checked by a single `apply_ttl_policy` call.
+pub const MAX_TTL_POLICY_BATCH: u32 = 100;
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq, Clone))]
+pub struct ClassTtlPolicy {
+    /// max time to live of the class tokens, in miliseconds.
+    pub max_ttl: u64,
+    /// unix timestamp in miliseconds when the policy was set. Existing tokens are clamped
+    /// to expire at most at `set_at + max_ttl`.
+    pub set_at: u64,
+}
+
+impl ClassTtlPolicy {
+    /// Max `expires_at` of the tokens minted before the policy was set.
+    pub fn max_expires_at(&self) -> u64 {
+        self.set_at.saturating_add(self.max_ttl)
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets (or removes, when `None`) the max time to live of the caller `class` tokens:
+    /// mint and renew panic if the token doesn't expire within now + `max_ttl_ms`. Tokens
+    /// minted before are not changed until `apply_ttl_policy` clamps them. The max TTL can't
+    /// be lower than the class min TTL (see `admin_set_class_min_ttl`).
+    /// Must be called by an SBT issuer.
+    pub fn sbt_set_class_max_ttl(&mut self, class: ClassId, max_ttl_ms: Option<u64>) {
+        let issuer_id = self.assert_issuer(&env::predecessor_account_id());
+        let key = (issuer_id, class);
+        match max_ttl_ms {
+            None => {
+                self.class_max_ttls.remove(&key);
+            }
+            Some(max_ttl) => {
+                require!(
+                    self.class_min_ttls
+                        .get(&key)
+                        .map_or(true, |min| min <= max_ttl),
+                    "class max TTL must not be lower than the min TTL"
+                );
+                self.class_max_ttls.insert(
+                    &key,
+                    &ClassTtlPolicy {
+                        max_ttl,
+                        set_at: env::block_timestamp_ms(),
+                    },
+                );
+            }
+        }
+    }
+
+    /// Returns the max TTL policy of the issuer class, see `sbt_set_class_max_ttl`.
+    pub fn class_max_ttl(&self, issuer: AccountId, class: ClassId) -> Option<ClassTtlPolicy> {
+        let issuer_id = self.sbt_issuers.get(&issuer)?;
+        self.class_max_ttls.get(&(issuer_id, class))
+    }
+
+    /// Clamps the `expires_at` of the caller `class` tokens with IDs in the
+    /// `[from_token, from_token + limit)` range to the max TTL policy: tokens expiring after
+    /// `set_at + max_ttl` (or never) expire at that time. Burned, revoked, expired and
+    /// already compliant tokens are skipped. Frozen tokens are clamped too, so they don't
+    /// outlive the policy once unfrozen. If `from_token` is not specified, 1 is used. If limit
+    /// is not specified, `MAX_TTL_POLICY_BATCH` is used.
+    /// Returns the number of clamped tokens and `true` when the range covers the last
+    /// issuer token, otherwise the function should be called again with `from_token + limit`.
+    /// Emits `apply_ttl_policy` registry event if any token was clamped.
+    /// Must be called by an SBT issuer.
+    pub fn apply_ttl_policy(
+        &mut self,
+        class: ClassId,
+        from_token: Option<TokenId>,
+        limit: Option<u32>,
+    ) -> (u32, bool) {
+        self.assert_not_paused();
+        let issuer = env::predecessor_account_id();
+        let issuer_id = self.assert_issuer(&issuer);
+        let policy = self
+            .class_max_ttls
+            .get(&(issuer_id, class))
+            .unwrap_or_else(|| env::panic_str(&format!("class {} has no max TTL", class)));
+        let limit = limit.unwrap_or(MAX_TTL_POLICY_BATCH);
+        require!(
+            limit > 0 && limit <= MAX_TTL_POLICY_BATCH,
+            format!("limit must be between 1 and {}", MAX_TTL_POLICY_BATCH)
+        );
+        let from_token = from_token.unwrap_or(1).max(1);
+        let last_token = self.next_token_ids.get(&issuer_id).unwrap_or(0);
+        let to_token = from_token.saturating_add(limit as u64).min(last_token + 1);
+        let expires_at = policy.max_expires_at();
+        let now = env::block_timestamp_ms();
+        let mut tokens = Vec::new();
+        for token in from_token..to_token {
+            let i_key = IssuerTokenId { issuer_id, token };
+            let mut t = match self.issuer_tokens.get(&i_key) {
+                None => continue,
+                Some(t) => t,
+            };
+            let status = self.status_of(issuer_id, token, &t);
+            let mut m = t.metadata.latest();
+            if status == TokenStatus::Revoked
+                || m.class != class
+                || m.expires_at.map_or(false, |e| e <= expires_at || e <= now)
+            {
+                continue;
+            }
+            self.index_expiration(issuer_id, token, m.expires_at, Some(expires_at));
+            if is_valid_status(status) {
+                self.move_valid(issuer_id, token, m.expires_at, Some(expires_at));
+            }
+            m.expires_at = Some(expires_at);
+            t.metadata = m.into();
+            self.issuer_tokens.insert(&i_key, t);
+            tokens.push(token);
+        }
+        let clamped = tokens.len() as u32;
+        if !tokens.is_empty() {
+            self.emit_registry_event(RegistryEvent::ApplyTtlPolicy {
+                issuer: &issuer,
+                class,
+                expires_at,
+                tokens,
+            });
+        }
+        (clamped, to_token > last_token)
+    }
+}
+
+impl Contract {
+    /// Panics if the class has a max TTL and the token doesn't expire within now + max TTL.
+    pub(crate) fn assert_max_ttl(
+        &self,
+        issuer_id: IssuerId,
+        class: ClassId,
+        expires_at: Option<u64>,
+    ) {
+        if let Some(p) = self.class_max_ttls.get(&(issuer_id, class)) {
+            require!(
+                expires_at.map_or(false, |e| e
+                    <= env::block_timestamp_ms().saturating_add(p.max_ttl)),
+                format!("class {} tokens must expire within {}ms", class, p.max_ttl)
+            );
+        }
+    }
+}
@@ -0,0 +1,88 @@
+//! Two phase contract upgrade controlled by the authority (expected to be a DAO): new code is
+//! staged first and can be deployed only after a timelock, giving the community time to
+//! review (and the authority to cancel) the upgrade.
+
+use near_sdk::json_types::Base58CryptoHash;
+use near_sdk::serde::Serialize;
+use near_sdk::{env, near_bindgen, require, CryptoHash, Gas, GasWeight, Promise};
+
+use crate::*;
+
+/// Minimum time between staging and deploying a new contract code.
+pub const UPGRADE_TIMELOCK_MS: u64 = 7 * 24 * 3600 * 1000;
+/// Min gas attached to the `migrate` call of the deployed code. The call also gets the
+/// unused gas of the deployment.
+pub const MIGRATE_GAS: Gas = Gas(20 * Gas::ONE_TERA.0);
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq))]
+pub struct StagedCodeInfo {
+    /// sha256 hash of the staged code
+    pub hash: Base58CryptoHash,
+    /// unix timestamp in miliseconds
+    pub staged_at: u64,
+    /// time (unix timestamp in miliseconds) since when the code can be deployed.
+    pub deployable_at: u64,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Uploads a new contract code. The code is passed as the raw (not JSON encoded) function
+    /// input. Returns sha256 hash of the code, which must be used in `admin_stage_code`.
+    /// Requires attaching enough NEAR to cover the code storage.
+    #[payable]
+    pub fn upload_code(&mut self) -> Base58CryptoHash {
+        let storage_start = env::storage_usage();
+        let code = env::input().expect("code must be provided as the function input");
+        let hash = env::sha256_array(&code);
+        self.code_blobs.insert(&hash, &code);
+        assert_storage_deposit(storage_start, env::attached_deposit());
+        hash.into()
+    }
+
+    /// Stages previously uploaded code for deployment. The code can be deployed with
+    /// `admin_deploy_staged_code` after `UPGRADE_TIMELOCK_MS`. Overwrites previously staged
+    /// code (and restarts the timelock). Must be called by the authority.
+    pub fn admin_stage_code(&mut self, blob_hash: Base58CryptoHash) {
+        self.assert_admin_action("admin_stage_code", None);
+        let hash: CryptoHash = blob_hash.into();
+        require!(self.code_blobs.contains_key(&hash), "code not uploaded");
+        self.staged_code = Some(StagedCode {
+            hash,
+            staged_at: env::block_timestamp_ms(),
+        });
+    }
+
+    /// Cancels the staged upgrade and removes the staged code. Must be called by the authority.
+    pub fn admin_cancel_staged_code(&mut self) {
+        self.assert_admin_action("admin_cancel_staged_code", None);
+        let staged = self.staged_code.take().expect("no staged code");
+        self.code_blobs.remove(&staged.hash);
+    }
+
+    /// Deploys the staged code once the timelock passed, and calls `migrate` of the new code
+    /// in the same batch: the deployment is reverted if the state check fails. Must be
+    /// called by the authority.
+    pub fn admin_deploy_staged_code(&mut self) -> Promise {
+        self.assert_admin_action("admin_deploy_staged_code", None);
+        let staged = self.staged_code.take().expect("no staged code");
+        require!(
+            env::block_timestamp_ms() >= staged.staged_at + UPGRADE_TIMELOCK_MS,
+            "upgrade timelock has not passed yet"
+        );
+        let code = self.code_blobs.remove(&staged.hash).unwrap();
+        Promise::new(env::current_account_id())
+            .deploy_contract(code)
+            .function_call_weight("migrate".to_owned(), vec![], 0, MIGRATE_GAS, GasWeight(1))
+    }
+
+    /// Returns information about the staged code, if any.
+    pub fn staged_code_info(&self) -> Option<StagedCodeInfo> {
+        self.staged_code.as_ref().map(|s| StagedCodeInfo {
+            hash: s.hash.into(),
+            staged_at: s.staged_at,
+            deployable_at: s.staged_at + UPGRADE_TIMELOCK_MS,
+        })
+    }
+}
@@ -0,0 +1,155 @@
+//! Valid supply counters, used by `sbt_supply(issuer, Some(true))`: the number of the issuer
+//! tokens which are neither revoked, frozen nor burned is stored with the number of those
+//! tokens per expire time bucket (~18.6 hours), and every bucket node counts its tokens per
+//! exact expire time. The view reads two records, regardless of the number of tokens. An
+//! update rewrites the issuer record (with the list of its pending buckets) and the bucket
+//! node (with the list of its expire times), so its cost grows with the number of distinct
+//! expire buckets and expire times of the issuer valid tokens, not with the number of
+//! tokens. Buckets in the past are folded into a single expired counter by the next update,
+//! which keeps the per issuer record small.
+//! Tokens of the previous release (minted before the counters were introduced) are counted by
+//! `migrate_chunk` (see `Contract::is_counted`): their status changes don't update the
+//! counters until then, and `only_valid` queries panic until the backfill is completed.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::env;
+
+use crate::*;
+
+/// An expire time bucket covers `2^BUCKET_SHIFT` miliseconds (~18.6 hours).
+const BUCKET_SHIFT: u32 = 26;
+
+#[derive(BorshSerialize, BorshDeserialize, Default)]
+pub(crate) struct ValidSupply {
+    /// number of tokens which are not revoked, frozen nor burned.
+    pub tokens: u64,
+    /// number of those tokens expiring in the buckets before `folded_bucket`.
+    pub expired: u64,
+    pub folded_bucket: u32,
+    /// sorted (bucket, number of tokens) list of the buckets from `folded_bucket`.
+    pub buckets: Vec<(u32, u32)>,
+}
+
+impl ValidSupply {
+    /// Moves the counts of the buckets before `bucket` to `expired`.
+    fn fold(&mut self, bucket: u32) {
+        let n = self.buckets.iter().take_while(|(b, _)| *b < bucket).count();
+        self.expired += self.buckets.drain(..n).map(|(_, c)| c as u64).sum::<u64>();
+        self.folded_bucket = self.folded_bucket.max(bucket);
+    }
+}
+
+impl Contract {
+    /// Returns the number of the issuer tokens which are not revoked, frozen, burned nor
+    /// expired.
+    pub(crate) fn valid_supply_of(&self, issuer_id: IssuerId) -> u64 {
+        let s = match self.valid_supply.get(&issuer_id) {
+            None => return 0,
+            Some(s) => s,
+        };
+        let (bucket, offset) = split(env::block_timestamp_ms()).unwrap_or((u32::MAX, 0));
+        let mut expired = s.expired;
+        for (b, n) in &s.buckets {
+            if *b >= bucket {
+                break;
+            }
+            expired += *n as u64;
+        }
+        if let Some(node) = self.valid_expirations.get(&(issuer_id, bucket)) {
+            for (o, n) in node {
+                if o >= offset {
+                    break;
+                }
+                expired += n as u64;
+            }
+        }
+        s.tokens.saturating_sub(expired)
+    }
+
+    /// Counts `n` new valid tokens of the issuer expiring at `expires_at`.
+    pub(crate) fn add_valid(&mut self, issuer_id: IssuerId, expires_at: Option<u64>, n: u64) {
+        if n > 0 {
+            self.count_valid(issuer_id, expires_at, n as u32, true);
+        }
+    }
+
+    /// Removes a valid token of the issuer expiring at `expires_at` from the counters.
+    pub(crate) fn remove_valid(&mut self, issuer_id: IssuerId, expires_at: Option<u64>) {
+        self.count_valid(issuer_id, expires_at, 1, false);
+    }
+
+    /// Updates the expire time of a valid token (eg on renew).
+    pub(crate) fn move_valid(
+        &mut self,
+        issuer_id: IssuerId,
+        token: TokenId,
+        old: Option<u64>,
+        new: Option<u64>,
+    ) {
+        if old != new && self.is_counted(issuer_id, token) {
+            self.remove_valid(issuer_id, old);
+            self.add_valid(issuer_id, new, 1);
+        }
+    }
+
+    fn count_valid(&mut self, issuer_id: IssuerId, expires_at: Option<u64>, n: u32, add: bool) {
+        let mut s = self.valid_supply.get(&issuer_id).unwrap_or_default();
+        if let Some((now_bucket, _)) = split(env::block_timestamp_ms()) {
+            s.fold(now_bucket);
+        }
+        s.tokens = if add {
+            s.tokens + n as u64
+        } else {
+            s.tokens.saturating_sub(n as u64)
+        };
+        // tokens expiring in millions of years are counted as never expiring.
+        if let Some((bucket, offset)) = expires_at.and_then(split) {
+            if bucket < s.folded_bucket {
+                s.expired = if add {
+                    s.expired + n as u64
+                } else {
+                    s.expired.saturating_sub(n as u64)
+                };
+            } else {
+                update_count(&mut s.buckets, bucket, n, add);
+            }
+            let key = (issuer_id, bucket);
+            let mut node = self.valid_expirations.get(&key).unwrap_or_default();
+            update_count(&mut node, offset, n, add);
+            if node.is_empty() {
+                self.valid_expirations.remove(&key);
+            } else {
+                self.valid_expirations.insert(&key, &node);
+            }
+        }
+        self.valid_supply.insert(&issuer_id, &s);
+    }
+}
+
+/// Returns true if tokens with the `status` are counted in the valid supply (expired tokens
+/// are counted until they are revoked or burned).
+pub(crate) fn is_valid_status(status: TokenStatus) -> bool {
+    matches!(status, TokenStatus::Active | TokenStatus::Expired)
+}
+
+/// Splits the unix timestamp in miliseconds into the bucket and the offset in the bucket.
+fn split(ms: u64) -> Option<(u32, u32)> {
+    let bucket = u32::try_from(ms >> BUCKET_SHIFT).ok()?;
+    Some((bucket, (ms & ((1 << BUCKET_SHIFT) - 1)) as u32))
+}
+
+/// Adds (or subtracts) `n` to the count of the `key` in the sorted (key, count) list.
+/// Entries with zero count are removed. Keys which are not in the list are not subtracted.
+fn update_count(list: &mut Vec<(u32, u32)>, key: u32, n: u32, add: bool) {
+    match list.binary_search_by_key(&key, |(k, _)| *k) {
+        Ok(i) if add => list[i].1 += n,
+        Ok(i) => {
+            list[i].1 = list[i].1.saturating_sub(n);
+            if list[i].1 == 0 {
+                list.remove(i);
+            }
+        }
+        Err(i) if add => list.insert(i, (key, n)),
+        Err(_) => (),
+    }
+}
@@ -0,0 +1,66 @@
+use near_sdk::json_types::U128;
+use near_sdk::serde::Serialize;
+use sbt::TokenId;
+
+/// Registry status of an account.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq))]
+pub struct AccountSummary {
+    pub banned: bool,
+    pub human: bool,
+    /// true if the account started a soul transfer (or is being recovered) and the process
+    /// has not finished yet.
+    pub soul_transfer_in_progress: bool,
+    /// last time (unix timestamp in miliseconds) the account was involved in a mint, renew
+    /// or transfer. Apps can use it to discount long dormant accounts.
+    pub last_activity_ms: Option<u64>,
+    /// remaining validity of the proof of humanity in seconds: until the first of the
+    /// required tokens expires. None if the account is not a human or the proof never
+    /// expires.
+    pub human_expires_in: Option<u64>,
+}
+
+/// Issuer health overview, see `issuer_stats`.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq))]
+pub struct IssuerStats {
+    /// number of minted tokens (including burned).
+    pub minted: u64,
+    /// number of not burned tokens (including expired and revoked).
+    pub live: u64,
+    /// number of revoked tokens (including burned with `sbt_revoke`).
+    pub revoked: u64,
+    /// estimated number of tokens which expired in the last `lookback_days`, based on the
+    /// expirations index (renewed, revoked and burned tokens are not counted).
+    pub recently_expired: u64,
+    /// last mint time (unix timestamp in miliseconds).
+    pub last_mint_ms: Option<u64>,
+    /// true if the issuer can't mint: the registry is paused or the issuer doesn't have
+    /// the mint capability.
+    pub paused: bool,
+}
+
+/// Result of `sbt_mint_v2`.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq))]
+pub struct MintResult {
+    /// minted token IDs, in the same order as in `sbt_mint`.
+    pub tokens: Vec<TokenId>,
+    /// registry storage consumed by the mint, in bytes.
+    pub storage_bytes: u64,
+    /// part of the attached deposit used to cover the storage, in yoctoNEAR.
+    pub deposit_used: U128,
+}
+
+/// State inconsistency found by `check_invariants`.
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq))]
+pub struct Discrepancy {
+    /// token related to the discrepancy, `None` for supply counters of accounts or classes.
+    pub token: Option<TokenId>,
+    pub description: String,
+}
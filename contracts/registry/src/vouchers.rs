@@ -0,0 +1,156 @@
+//! Deferred mint vouchers: an issuer creates a voucher for an account and token metadata, and
+//! the account redeems it later, paying the token storage. This shifts the mint cost from
+//! issuers to claimants (eg for airdrop style credential distribution).
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::Serialize;
+use near_sdk::{env, near_bindgen, require, AccountId, Balance, Promise};
+
+use crate::*;
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq))]
+pub struct Voucher {
+    pub issuer: AccountId,
+    /// account allowed to redeem the voucher
+    pub owner: AccountId,
+    pub metadata: TokenMetadata,
+    /// storage deposit paid by the issuer for the voucher, refunded when the voucher is
+    /// redeemed or cancelled.
+    #[serde(skip)]
+    pub(crate) deposit: Balance,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Creates mint vouchers and returns their IDs (in the same order as `vouchers`).
+    /// A voucher can be redeemed only by its owner with `redeem_voucher`.
+    /// Must be called by an issuer with the mint capability.
+    /// Requires attaching enough NEAR to cover the vouchers storage. The deposit is refunded
+    /// to the issuer when a voucher is redeemed or cancelled.
+    #[payable]
+    pub fn sbt_create_vouchers(&mut self, vouchers: Vec<(AccountId, TokenMetadata)>) -> Vec<u64> {
+        self.assert_not_paused();
+        let issuer = env::predecessor_account_id();
+        let issuer_id = self.assert_issuer(&issuer);
+        self.assert_capability(issuer_id, CAP_MINT);
+        if vouchers.len() > MAX_MINT_BATCH {
+            InputError::BatchTooLarge {
+                max: MAX_MINT_BATCH,
+            }
+            .panic();
+        }
+        let storage_start = env::storage_usage();
+        let mut ids = Vec::with_capacity(vouchers.len());
+        for (owner, metadata) in vouchers {
+            metadata.assert_valid();
+            let voucher_start = env::storage_usage();
+            let id = self.next_voucher_id;
+            self.next_voucher_id += 1;
+            let mut v = Voucher {
+                issuer: issuer.clone(),
+                owner,
+                metadata,
+                deposit: 0,
+            };
+            self.vouchers.insert(&id, &v);
+            // the deposit is a fixed size field, so we can update it in place.
+            v.deposit = required_storage_deposit(voucher_start);
+            self.vouchers.insert(&id, &v);
+            ids.push(id);
+        }
+        assert_storage_deposit(storage_start, env::attached_deposit());
+        ids
+    }
+
+    /// Mints the SBT from the voucher to the caller, who must be the voucher owner.
+    /// Requires attaching enough NEAR to cover the token storage (same as `sbt_mint`).
+    /// When `relayer` is set, the call must be relayed by it (NEP-366 meta transaction signed
+    /// by the `relayer`) and the storage is paid from the relayer allowance for the voucher
+    /// issuer (see `relayer_deposit`), so owners without NEAR can redeem vouchers.
+    /// Returns the minted token ID.
+    #[payable]
+    pub fn redeem_voucher(&mut self, voucher_id: u64, relayer: Option<AccountId>) -> TokenId {
+        let v = self.vouchers.get(&voucher_id).expect("voucher not found");
+        require!(
+            v.owner == env::predecessor_account_id(),
+            "voucher can be redeemed only by its owner"
+        );
+        let spec = vec![(v.owner.clone(), vec![v.metadata])];
+        let token = match relayer {
+            None => self._sbt_mint(&v.issuer, spec)[0],
+            Some(relayer) => {
+                require!(
+                    relayer != v.owner && env::signer_account_id() == relayer,
+                    "the relayer must sign the transaction"
+                );
+                let key = (relayer, self.assert_issuer(&v.issuer));
+                let allowance = self.relayer_allowances.get(&key).unwrap_or(0);
+                let (tokens, used) = self.mint_with_deposit(&v.issuer, spec, allowance, None);
+                self.relayer_allowances.insert(&key, &(allowance - used));
+                tokens[0]
+            }
+        };
+        self.vouchers.remove(&voucher_id);
+        Promise::new(v.issuer).transfer(v.deposit);
+        token
+    }
+
+    /// Deposits the attached NEAR into the caller (relayer) allowance for the `issuer`. The
+    /// allowance pays the storage of the issuer vouchers redeemed with the caller as the
+    /// `relayer`. It's separate from the mint escrow (`sbt_escrow_deposit`). The storage of a
+    /// new allowance entry is paid from the deposit. Returns the allowance balance.
+    #[payable]
+    pub fn relayer_deposit(&mut self, issuer: AccountId) -> U128 {
+        let issuer_id = self.assert_issuer(&issuer);
+        deposit_balance(
+            &mut self.relayer_allowances,
+            (env::predecessor_account_id(), issuer_id),
+        )
+    }
+
+    /// Withdraws `amount` (all, if not specified) from the caller allowance for the `issuer`.
+    pub fn relayer_withdraw(&mut self, issuer: AccountId, amount: Option<U128>) -> Promise {
+        let key = self
+            .issuer_balance_key(
+                &self.relayer_allowances,
+                env::predecessor_account_id(),
+                &issuer,
+            )
+            .expect("issuer not found");
+        withdraw_balance(&mut self.relayer_allowances, key, amount)
+    }
+
+    /// Returns the `relayer` allowance balance for the `issuer`.
+    pub fn relayer_allowance(&self, relayer: AccountId, issuer: AccountId) -> U128 {
+        let balance = match self.issuer_balance_key(&self.relayer_allowances, relayer, &issuer) {
+            None => 0,
+            Some(key) => self.relayer_allowances.get(&key).unwrap_or(0),
+        };
+        balance.into()
+    }
+
+    /// Cancels vouchers created by the caller. Unknown IDs and vouchers created by other
+    /// issuers are ignored.
+    pub fn sbt_cancel_vouchers(&mut self, voucher_ids: Vec<u64>) {
+        let issuer = env::predecessor_account_id();
+        let mut refund = 0;
+        for id in voucher_ids {
+            if let Some(v) = self.vouchers.get(&id) {
+                if v.issuer == issuer {
+                    self.vouchers.remove(&id);
+                    refund += v.deposit;
+                }
+            }
+        }
+        if refund > 0 {
+            Promise::new(issuer).transfer(refund);
+        }
+    }
+
+    pub fn voucher(&self, voucher_id: u64) -> Option<Voucher> {
+        self.vouchers.get(&voucher_id)
+    }
+}
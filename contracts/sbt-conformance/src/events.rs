@@ -0,0 +1,151 @@
+//! Checks of the NEP-393 event shapes emitted by the registry. The checks work on raw log
+//! lines, so they can also be used outside of the sandbox (eg on indexed logs).
+
+use serde_json::Value;
+
+use sbt::{TokenId, SPEC_VERSION, STANDARD_NAME};
+
+/// Parses a NEP-393 event log (`EVENT_JSON:{...}`) and returns the event name and data.
+/// Fails if the log is not an event, or the standard or the version is not the one
+/// expected by the registry.
+pub fn parse_nep393_event(log: &str) -> Result<(String, Value), String> {
+    let json = log
+        .strip_prefix("EVENT_JSON:")
+        .ok_or_else(|| format!("not an event log: {}", log))?;
+    let mut e: Value = serde_json::from_str(json).map_err(|err| format!("{}: {}", err, log))?;
+    if e["standard"] != STANDARD_NAME {
+        return Err(format!("expected {} standard: {}", STANDARD_NAME, log));
+    }
+    if e["version"] != SPEC_VERSION {
+        return Err(format!("expected {} version: {}", SPEC_VERSION, log));
+    }
+    match e["event"].take() {
+        Value::String(name) => Ok((name, e["data"].take())),
+        _ => Err(format!("event name missing: {}", log)),
+    }
+}
+
+/// Checks the `mint` event of the `issuer` and returns the token IDs minted to the `owner`.
+/// Data shape: `{"issuer": "<issuer>", "tokens": [["<owner>", [<token>, ...]], ...]}`.
+pub fn check_mint_event(log: &str, issuer: &str, owner: &str) -> Result<Vec<TokenId>, String> {
+    let data = event_data(log, "mint", issuer)?;
+    let tokens = data["tokens"]
+        .as_array()
+        .ok_or_else(|| format!("`tokens` must be a list: {}", log))?;
+    for el in tokens {
+        let pair = match el.as_array() {
+            Some(p) if p.len() == 2 && p[0].is_string() => p,
+            _ => {
+                return Err(format!(
+                    "`tokens` must be (owner, token IDs) pairs: {}",
+                    log
+                ))
+            }
+        };
+        if pair[0] == owner {
+            let ids = token_ids(&pair[1]).ok_or_else(|| format!("invalid token IDs: {}", log))?;
+            if ids.is_empty() {
+                return Err(format!("no tokens minted to {}: {}", owner, log));
+            }
+            return Ok(ids);
+        }
+    }
+    Err(format!("no tokens minted to {}: {}", owner, log))
+}
+
+/// Checks the `renew`, `revoke` or `burn` event of the `issuer` `tokens`.
+/// Data shape: `{"issuer": "<issuer>", "tokens": [<token>, ...]}`.
+pub fn check_tokens_event(
+    log: &str,
+    event: &str,
+    issuer: &str,
+    tokens: &[TokenId],
+) -> Result<(), String> {
+    let data = event_data(log, event, issuer)?;
+    match token_ids(&data["tokens"]) {
+        Some(ids) if ids == tokens => Ok(()),
+        _ => Err(format!("expected tokens {:?}: {}", tokens, log)),
+    }
+}
+
+/// Returns the result of the first log passing the `check`, or the error of the last log.
+pub fn find_event<T>(
+    logs: &[&str],
+    check: impl Fn(&str) -> Result<T, String>,
+) -> Result<T, String> {
+    let mut err = "no event logs".to_owned();
+    for log in logs {
+        match check(log) {
+            Ok(t) => return Ok(t),
+            Err(e) => err = e,
+        }
+    }
+    Err(err)
+}
+
+fn event_data(log: &str, event: &str, issuer: &str) -> Result<Value, String> {
+    let (name, data) = parse_nep393_event(log)?;
+    if name != event {
+        return Err(format!("expected {} event: {}", event, log));
+    }
+    if data["issuer"] != issuer {
+        return Err(format!("expected {} issuer: {}", issuer, log));
+    }
+    Ok(data)
+}
+
+/// Token IDs must be a list of positive integers.
+fn token_ids(v: &Value) -> Option<Vec<TokenId>> {
+    v.as_array()?
+        .iter()
+        .map(|t| t.as_u64().filter(|t| *t > 0))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINT: &str = r#"EVENT_JSON:{"standard":"nep393","version":"1.0.0","event":"mint","data":{"issuer":"issuer.near","tokens":[["bob.near",[1]],["alice.near",[2,3]]]}}"#;
+
+    #[test]
+    fn mint_event() {
+        assert_eq!(
+            check_mint_event(MINT, "issuer.near", "alice.near"),
+            Ok(vec![2, 3])
+        );
+        assert!(check_mint_event(MINT, "issuer.near", "carol.near").is_err());
+        assert!(check_mint_event(MINT, "other.near", "alice.near").is_err());
+        let v2 = MINT.replace("1.0.0", "2.0.0");
+        assert_eq!(
+            check_mint_event(&v2, "issuer.near", "alice.near"),
+            Err(format!("expected 1.0.0 version: {}", v2))
+        );
+        let zero = MINT.replace("[2,3]", "[0]");
+        assert!(check_mint_event(&zero, "issuer.near", "alice.near").is_err());
+        assert!(check_mint_event("mint", "issuer.near", "alice.near").is_err());
+    }
+
+    #[test]
+    fn tokens_event() {
+        let log = r#"EVENT_JSON:{"standard":"nep393","version":"1.0.0","event":"revoke","data":{"issuer":"issuer.near","tokens":[2,3]}}"#;
+        assert_eq!(
+            check_tokens_event(log, "revoke", "issuer.near", &[2, 3]),
+            Ok(())
+        );
+        assert!(check_tokens_event(log, "renew", "issuer.near", &[2, 3]).is_err());
+        assert!(check_tokens_event(log, "revoke", "issuer.near", &[2]).is_err());
+
+        let logs = ["SBT mint memo: hi", log];
+        assert_eq!(
+            find_event(&logs, |l| check_tokens_event(
+                l,
+                "revoke",
+                "issuer.near",
+                &[2, 3]
+            )),
+            Ok(())
+        );
+        assert!(find_event(&logs[..1], parse_nep393_event).is_err());
+    }
+}
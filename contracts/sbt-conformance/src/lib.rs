@@ -0,0 +1,327 @@
+//! SBT issuer conformance suite. Third party issuers run it against their wasm to prove that
+//! the issuer mints, renews and revokes tokens through the registry the way the registry
+//! expects: calls succeed, the registry emits NEP-393 events of the expected shape and the
+//! registry state is updated.
+//!
+//! The suite deploys the registry and the issuer wasm in a near-workspaces sandbox (see
+//! `run`). The issuer specific calls are provided by an `IssuerAdapter`.
+
+use std::fmt;
+
+use serde_json::{json, Value};
+use workspaces::result::ExecutionFinalResult;
+use workspaces::{Account, AccountId, Contract};
+
+use sbt::TokenId;
+
+pub mod events;
+
+use crate::events::*;
+
+const ONE_NEAR: u128 = 1_000_000_000_000_000_000_000_000;
+/// registry issuer bond, see `cost::ISSUER_BOND`.
+const ISSUER_BOND: u128 = ONE_NEAR;
+
+/// Function call of the issuer contract.
+pub struct IssuerCall {
+    pub method: String,
+    pub args: Value,
+    /// attached deposit in yoctoNEAR.
+    pub deposit: u128,
+}
+
+impl IssuerCall {
+    pub fn new(method: &str, args: Value, deposit: u128) -> Self {
+        Self {
+            method: method.to_owned(),
+            args,
+            deposit,
+        }
+    }
+}
+
+/// Issuer specific calls of the suite. `renew` and `revoke` are optional: the related checks
+/// are skipped if the issuer doesn't support them.
+pub trait IssuerAdapter {
+    /// Initialization call of the issuer contract. The `admin` account makes the issuer
+    /// calls (`mint`, `renew` and `revoke`).
+    fn init(&self, registry: &AccountId, admin: &AccountId) -> IssuerCall;
+
+    /// Mints a single token to the `recipient`.
+    fn mint(&self, recipient: &AccountId) -> IssuerCall;
+
+    /// Renews the `tokens`, setting `expires_at` (unix timestamp in miliseconds).
+    fn renew(&self, _tokens: &[TokenId], _expires_at: u64) -> Option<IssuerCall> {
+        None
+    }
+
+    /// Revokes (or burns) the `tokens`.
+    fn revoke(&self, _tokens: &[TokenId]) -> Option<IssuerCall> {
+        None
+    }
+}
+
+pub struct Check {
+    pub name: &'static str,
+    /// None when the check was skipped.
+    pub result: Option<Result<(), String>>,
+}
+
+/// Results of all conformance checks.
+#[derive(Default)]
+pub struct Report {
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    /// Returns true if no check failed.
+    pub fn is_ok(&self) -> bool {
+        self.checks
+            .iter()
+            .all(|c| !matches!(c.result, Some(Err(_))))
+    }
+
+    /// Panics with the report if any check failed.
+    pub fn assert_ok(&self) {
+        assert!(self.is_ok(), "SBT conformance checks failed:\n{}", self);
+    }
+
+    fn add(&mut self, name: &'static str, result: Result<(), String>) -> bool {
+        let ok = result.is_ok();
+        self.checks.push(Check {
+            name,
+            result: Some(result),
+        });
+        ok
+    }
+
+    fn skip(&mut self, name: &'static str) {
+        self.checks.push(Check { name, result: None });
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for c in &self.checks {
+            match &c.result {
+                None => writeln!(f, "SKIP {}", c.name)?,
+                Some(Ok(())) => writeln!(f, "OK   {}", c.name)?,
+                Some(Err(e)) => writeln!(f, "FAIL {}: {}", c.name, e)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Deploys the registry and the issuer in a new sandbox, registers the issuer (locking the
+/// issuer bond) and runs the conformance checks:
+/// * `mint`: the mint call succeeds, the registry emits the `mint` event for the
+///   recipient and the registry returns the minted token.
+/// * `renew`: the renew call succeeds, the registry emits the `renew` event and the token
+///   `expires_at` is updated.
+/// * `revoke`: the revoke call succeeds, the registry emits the `revoke` (or `burn`) event
+///   and the token is revoked (or burned).
+///
+/// Returns an error only if the sandbox setup fails; failed checks are in the report.
+pub async fn run(
+    registry_wasm: &[u8],
+    issuer_wasm: &[u8],
+    adapter: &impl IssuerAdapter,
+) -> anyhow::Result<Report> {
+    let worker = workspaces::sandbox().await?;
+    let root = worker.root_account()?;
+    let authority = create_account(&root, "authority").await?;
+    let admin = create_account(&root, "admin").await?;
+    let alice = create_account(&root, "alice").await?;
+    let registry = deploy(&root, "registry", registry_wasm).await?;
+    let issuer = deploy(&root, "issuer", issuer_wasm).await?;
+
+    registry
+        .call("new")
+        .args_json(json!({
+            "authority": authority.id(),
+            "iah_issuer": issuer.id(),
+            "iah_classes": [1],
+        }))
+        .transact()
+        .await?
+        .into_result()?;
+    let init = adapter.init(registry.id(), admin.id());
+    issuer
+        .call(&init.method)
+        .args_json(init.args)
+        .deposit(init.deposit)
+        .transact()
+        .await?
+        .into_result()?;
+    issuer
+        .as_account()
+        .call(registry.id(), "issuer_lock_bond")
+        .deposit(ISSUER_BOND)
+        .transact()
+        .await?
+        .into_result()?;
+    authority
+        .call(registry.id(), "admin_add_sbt_issuer")
+        .args_json(json!({"issuer": issuer.id(), "memo": "sbt conformance"}))
+        .transact()
+        .await?
+        .into_result()?;
+
+    let mut report = Report::default();
+    let issuer_id = issuer.id().as_str();
+    let registry_logs = |res: &ExecutionFinalResult| -> Vec<String> {
+        res.receipt_outcomes()
+            .iter()
+            .filter(|o| o.executor_id == *registry.id())
+            .flat_map(|o| o.logs.clone())
+            .collect()
+    };
+
+    // mint
+    let res = call(&admin, issuer.id(), adapter.mint(alice.id())).await?;
+    let mut tokens = None;
+    if report.add("mint call succeeds", success(&res)) {
+        let logs = registry_logs(&res);
+        let logs: Vec<&str> = logs.iter().map(String::as_str).collect();
+        let minted = find_event(&logs, |l| check_mint_event(l, issuer_id, alice.id()));
+        tokens = minted.as_ref().ok().cloned();
+        report.add("mint event", minted.map(|_| ()));
+    } else {
+        report.skip("mint event");
+    }
+    let tokens = match tokens {
+        Some(t) => t,
+        None => {
+            for name in ["minted token in the registry", "renew", "revoke"] {
+                report.skip(name);
+            }
+            return Ok(report);
+        }
+    };
+    let owned: Value = registry
+        .view("sbt_tokens_by_owner")
+        .args_json(json!({"account": alice.id(), "issuer": issuer.id()}))
+        .await?
+        .json()?;
+    let owned_ids: Vec<u64> = owned[0][1]
+        .as_array()
+        .map(|ts| ts.iter().filter_map(|t| t["token"].as_u64()).collect())
+        .unwrap_or_default();
+    report.add(
+        "minted token in the registry",
+        check(
+            owned_ids == tokens,
+            format!("expected tokens {:?}, registry: {}", tokens, owned),
+        ),
+    );
+
+    // renew
+    let token: Value = registry
+        .view("sbt")
+        .args_json(json!({"issuer": issuer.id(), "token": tokens[0]}))
+        .await?
+        .json()?;
+    let expires_at = token["metadata"]["expires_at"].as_u64().unwrap_or(0) + 24 * 3600 * 1000;
+    match adapter.renew(&tokens, expires_at) {
+        None => report.skip("renew"),
+        Some(c) => {
+            let res = call(&admin, issuer.id(), c).await?;
+            let mut result = success(&res);
+            if result.is_ok() {
+                let logs = registry_logs(&res);
+                let logs: Vec<&str> = logs.iter().map(String::as_str).collect();
+                result = find_event(&logs, |l| {
+                    check_tokens_event(l, "renew", issuer_id, &tokens)
+                });
+            }
+            if result.is_ok() {
+                let token: Value = registry
+                    .view("sbt")
+                    .args_json(json!({"issuer": issuer.id(), "token": tokens[0]}))
+                    .await?
+                    .json()?;
+                result = check(
+                    token["metadata"]["expires_at"] == expires_at,
+                    format!("expected expires_at {}: {}", expires_at, token),
+                );
+            }
+            report.add("renew", result);
+        }
+    }
+
+    // revoke
+    match adapter.revoke(&tokens) {
+        None => report.skip("revoke"),
+        Some(c) => {
+            let res = call(&admin, issuer.id(), c).await?;
+            let mut result = success(&res);
+            if result.is_ok() {
+                let logs = registry_logs(&res);
+                let logs: Vec<&str> = logs.iter().map(String::as_str).collect();
+                result = find_event(&logs, |l| {
+                    check_tokens_event(l, "revoke", issuer_id, &tokens)
+                        .or_else(|_| check_tokens_event(l, "burn", issuer_id, &tokens))
+                });
+            }
+            if result.is_ok() {
+                let status: Value = registry
+                    .view("sbt_status")
+                    .args_json(json!({"issuer": issuer.id(), "token": tokens[0]}))
+                    .await?
+                    .json()?;
+                result = check(
+                    status == "revoked" || status == "burned",
+                    format!("expected revoked or burned token, status: {}", status),
+                );
+            }
+            report.add("revoke", result);
+        }
+    }
+    Ok(report)
+}
+
+async fn create_account(root: &Account, name: &str) -> anyhow::Result<Account> {
+    Ok(root
+        .create_subaccount(name)
+        .initial_balance(20 * ONE_NEAR)
+        .transact()
+        .await?
+        .into_result()?)
+}
+
+async fn deploy(root: &Account, name: &str, wasm: &[u8]) -> anyhow::Result<Contract> {
+    let account = create_account(root, name).await?;
+    Ok(account.deploy(wasm).await?.into_result()?)
+}
+
+async fn call(
+    caller: &Account,
+    issuer: &AccountId,
+    c: IssuerCall,
+) -> anyhow::Result<ExecutionFinalResult> {
+    Ok(caller
+        .call(issuer, &c.method)
+        .args_json(c.args)
+        .deposit(c.deposit)
+        .max_gas()
+        .transact()
+        .await?)
+}
+
+/// The transaction and all its receipts must succeed.
+fn success(res: &ExecutionFinalResult) -> Result<(), String> {
+    let failures = res.failures();
+    check(
+        res.is_success() && failures.is_empty(),
+        format!("call failed: {:?}", failures),
+    )
+}
+
+fn check(ok: bool, err: String) -> Result<(), String> {
+    if ok {
+        Ok(())
+    } else {
+        Err(err)
+    }
+}
@@ -0,0 +1,43 @@
+//! Runs the conformance suite against the demo issuer. Requires the release wasm files
+//! (`cargo xtask build`) and the near-sandbox binary (downloaded on the first run):
+//! `cargo test -p sbt-conformance -- --ignored`.
+
+use serde_json::json;
+use workspaces::AccountId;
+
+use sbt_conformance::*;
+
+/// `cost::MINT_COST`
+const MINT_COST: u128 = 10_000_000_000_000_000_000_000;
+
+struct DemoIssuer;
+
+impl IssuerAdapter for DemoIssuer {
+    fn init(&self, registry: &AccountId, admin: &AccountId) -> IssuerCall {
+        IssuerCall::new(
+            "new",
+            json!({
+                "metadata": {"spec": "sbt-1.0.0", "name": "Demo", "symbol": "DEMO"},
+                "registry": registry,
+                "claim_ttl": 0,
+                "admin": admin,
+            }),
+            0,
+        )
+    }
+
+    fn mint(&self, recipient: &AccountId) -> IssuerCall {
+        IssuerCall::new("sbt_mint", json!({ "receiver": recipient }), MINT_COST)
+    }
+}
+
+#[tokio::test]
+#[ignore]
+async fn demo_issuer_conformance() -> anyhow::Result<()> {
+    let registry = std::fs::read("../res/registry.wasm")?;
+    let issuer = std::fs::read("../res/demo_issuer.wasm")?;
+    let report = run(&registry, &issuer, &DemoIssuer).await?;
+    println!("{}", report);
+    report.assert_ok();
+    Ok(())
+}
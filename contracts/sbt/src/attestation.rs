@@ -0,0 +1,210 @@
+//! Cross-chain attestation of SBT ownership (see the registry `sbt_attestation` view).
+//!
+//! `sbt_attestation` returns a Borsh encoded `AttestationProof`:
+//! * `attestation: Attestation` -- canonical statement that `owner` holds the issuer `token`
+//!   at `block_height`. `Attestation::encode` returns its canonical bytes.
+//! * `keys: StateKeys` -- storage keys of the registry records backing the attestation.
+//!
+//! A light client bridge (eg the Rainbow Bridge verifier) proves the registry state to
+//! another chain: the relayer gets a state proof (NEAR `view_state` query with
+//! `include_proof`) of the `keys` values at the attestation block, and the verifier checks
+//! with `Attestation::verify_state` that the proven values match the attestation.
+//!
+//! Use `decode_attestation_proof` to decode the view result.
+
+use std::io;
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::AccountId;
+
+use crate::{ClassId, TokenId, VerTokenData};
+
+/// Version of the `Attestation` layout.
+pub const ATTESTATION_FORMAT_VERSION: u8 = 1;
+
+#[derive(BorshSerialize, BorshDeserialize)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq, Clone))]
+pub struct Attestation {
+    pub version: u8,
+    /// registry account.
+    pub registry: AccountId,
+    /// block of the attested state.
+    pub block_height: u64,
+    /// block timestamp in miliseconds.
+    pub timestamp: u64,
+    pub issuer: AccountId,
+    /// registry internal issuer ID.
+    pub issuer_id: u32,
+    pub token: TokenId,
+    pub owner: AccountId,
+    pub class: ClassId,
+    pub issued_at: Option<u64>,
+    pub expires_at: Option<u64>,
+    /// true if the token was valid at the block: not expired, revoked nor frozen, and the
+    /// owner was not banned. Evaluated by the registry, the state proof covers only expiry
+    /// and the `status` key.
+    pub valid: bool,
+}
+
+/// Registry storage keys (raw trie keys of the registry account) of the attested records.
+#[derive(BorshSerialize, BorshDeserialize)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq, Clone))]
+pub struct StateKeys {
+    /// issuer ID -> issuer account record, value: Borsh `AccountId`.
+    pub issuer: Vec<u8>,
+    /// token record, value: Borsh `VerTokenData`.
+    pub token: Vec<u8>,
+    /// owner balance shard with the token class, value: Borsh `Vec<(ClassId, TokenId)>`
+    /// sorted by class.
+    pub balance: Vec<u8>,
+    /// token status record. Not set for active tokens: revoked and frozen tokens have it.
+    pub status: Vec<u8>,
+}
+
+#[derive(BorshSerialize, BorshDeserialize)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq, Clone))]
+pub struct AttestationProof {
+    pub attestation: Attestation,
+    pub keys: StateKeys,
+}
+
+impl Attestation {
+    /// Canonical Borsh encoding of the attestation.
+    pub fn encode(&self) -> Vec<u8> {
+        self.try_to_vec().unwrap()
+    }
+
+    /// Decodes the canonical encoding. Fails if the data is malformed or uses an unsupported
+    /// format version.
+    pub fn decode(data: &[u8]) -> io::Result<Self> {
+        let a = Self::try_from_slice(data)?;
+        check_version(a.version)?;
+        Ok(a)
+    }
+
+    /// Returns true if the proven registry values (under the `StateKeys` issuer, token and
+    /// balance keys) match the attestation: the issuer ID maps to the issuer, the token
+    /// record has the attested owner and metadata, and the owner balance of the class
+    /// references the token.
+    pub fn verify_state(&self, issuer: &[u8], token: &[u8], balance: &[u8]) -> bool {
+        if AccountId::try_from_slice(issuer).ok().as_ref() != Some(&self.issuer) {
+            return false;
+        }
+        let t = match VerTokenData::try_from_slice(token) {
+            Ok(t) => t.latest(),
+            Err(_) => return false,
+        };
+        if t.owner != self.owner
+            || t.metadata.class_id() != self.class
+            || t.metadata.issued_at() != self.issued_at
+            || t.metadata.expires_at() != self.expires_at
+        {
+            return false;
+        }
+        match Vec::<(ClassId, TokenId)>::try_from_slice(balance) {
+            Ok(shard) => shard
+                .binary_search_by_key(&self.class, |e| e.0)
+                .map_or(false, |i| shard[i].1 == self.token),
+            Err(_) => false,
+        }
+    }
+}
+
+/// Decodes the registry `sbt_attestation` result. Fails if the data is malformed or uses an
+/// unsupported format version.
+pub fn decode_attestation_proof(data: &[u8]) -> io::Result<AttestationProof> {
+    let p = AttestationProof::try_from_slice(data)?;
+    check_version(p.attestation.version)?;
+    Ok(p)
+}
+
+fn check_version(version: u8) -> io::Result<()> {
+    if version != ATTESTATION_FORMAT_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unsupported attestation format version {}", version),
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{TokenData, TokenMetadata};
+
+    fn acc(a: &str) -> AccountId {
+        AccountId::new_unchecked(a.to_string())
+    }
+
+    fn mk_attestation() -> Attestation {
+        Attestation {
+            version: ATTESTATION_FORMAT_VERSION,
+            registry: acc("registry.near"),
+            block_height: 10,
+            timestamp: 1_000,
+            issuer: acc("issuer.near"),
+            issuer_id: 1,
+            token: 7,
+            owner: acc("alice.near"),
+            class: 2,
+            issued_at: Some(500),
+            expires_at: None,
+            valid: true,
+        }
+    }
+
+    #[test]
+    fn encode_decode() {
+        let mut a = mk_attestation();
+        assert_eq!(Attestation::decode(&a.encode()).unwrap(), a);
+        let p = AttestationProof {
+            attestation: a.clone(),
+            keys: StateKeys {
+                issuer: vec![1],
+                token: vec![2],
+                balance: vec![3],
+                status: vec![4],
+            },
+        };
+        assert_eq!(
+            decode_attestation_proof(&p.try_to_vec().unwrap()).unwrap(),
+            p
+        );
+
+        a.version = 2;
+        let err = Attestation::decode(&a.encode()).unwrap_err();
+        assert_eq!(err.to_string(), "unsupported attestation format version 2");
+    }
+
+    #[test]
+    fn verify_state() {
+        let a = mk_attestation();
+        let issuer = a.issuer.try_to_vec().unwrap();
+        let token = |owner: &str| {
+            VerTokenData::from(TokenData {
+                owner: acc(owner),
+                metadata: TokenMetadata {
+                    class: 2,
+                    issued_at: Some(500),
+                    expires_at: None,
+                    reference: None,
+                    reference_hash: None,
+                    attributes_root: None,
+                }
+                .into(),
+            })
+            .try_to_vec()
+            .unwrap()
+        };
+        let balance = vec![(1u64, 3u64), (2, 7)].try_to_vec().unwrap();
+        assert!(a.verify_state(&issuer, &token("alice.near"), &balance));
+
+        assert!(!a.verify_state(&issuer, &token("bob.near"), &balance));
+        let other = acc("other.near").try_to_vec().unwrap();
+        assert!(!a.verify_state(&other, &token("alice.near"), &balance));
+        let moved = vec![(2u64, 8u64)].try_to_vec().unwrap();
+        assert!(!a.verify_state(&issuer, &token("alice.near"), &moved));
+        assert!(!a.verify_state(&issuer, &[1, 2], &balance));
+    }
+}
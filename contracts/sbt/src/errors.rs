@@ -0,0 +1,61 @@
+use std::fmt;
+
+use near_sdk::env;
+
+/// Input validation errors. The panic message starts with the error code followed by a
+/// description, eg: `E03_REFERENCE_TOO_LONG: reference can have at most 256 bytes`, so
+/// clients can match on the code rather than on the message.
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq, Clone))]
+pub enum InputError {
+    /// class must be bigger than 0.
+    ZeroClass,
+    /// `reference` and `reference_hash` must be both set or both empty.
+    ReferenceHashMissing,
+    /// `reference` is longer than the max length.
+    ReferenceTooLong { max: usize },
+    /// `reference_hash` is not a 32 bytes hash.
+    InvalidReferenceHash,
+    /// too many elements in a single call.
+    BatchTooLarge { max: usize },
+}
+
+impl InputError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            InputError::ZeroClass => "E01_ZERO_CLASS",
+            InputError::ReferenceHashMissing => "E02_REFERENCE_HASH_MISSING",
+            InputError::ReferenceTooLong { .. } => "E03_REFERENCE_TOO_LONG",
+            InputError::InvalidReferenceHash => "E04_INVALID_REFERENCE_HASH",
+            InputError::BatchTooLarge { .. } => "E05_BATCH_TOO_LARGE",
+        }
+    }
+
+    /// Panics with the error code and description (same as `require!`).
+    pub fn panic(&self) -> ! {
+        if cfg!(target_arch = "wasm32") {
+            env::panic_str(&self.to_string())
+        } else {
+            panic!("{}", self)
+        }
+    }
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: ", self.code())?;
+        match self {
+            InputError::ZeroClass => write!(f, "class must be bigger than 0"),
+            InputError::ReferenceHashMissing => {
+                write!(
+                    f,
+                    "reference and reference_hash must be both set or both empty"
+                )
+            }
+            InputError::ReferenceTooLong { max } => {
+                write!(f, "reference can have at most {} bytes", max)
+            }
+            InputError::InvalidReferenceHash => write!(f, "reference_hash must have 32 bytes"),
+            InputError::BatchTooLarge { max } => write!(f, "max {} elements are allowed", max),
+        }
+    }
+}
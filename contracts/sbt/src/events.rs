@@ -1,8 +1,10 @@
+use near_sdk::json_types::U128;
 use near_sdk::serde::Serialize;
-use near_sdk::{env, AccountId};
+use near_sdk::serde_json;
+use near_sdk::{env, AccountId, Balance};
 
 use crate::SPEC_VERSION;
-use crate::{TokenId, STANDARD_NAME};
+use crate::{RevocationReason, TokenId, STANDARD_NAME};
 
 /// Helper struct to create Standard NEAR Event JSON.
 /// Arguments:
@@ -58,21 +60,88 @@ impl Nep393Event<'_> {
     }
 }
 
+/// Storage usage change of the registry call emitting the event, so issuer operators can
+/// reconcile their NEAR spend with the registry activity. Serialized as the
+/// `storage_delta_bytes` and `deposit_charged` fields of the event data.
+#[derive(Serialize, Clone, Copy)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq))]
+#[serde(crate = "near_sdk::serde")]
+pub struct StorageCharge {
+    /// change of the contract storage usage in bytes, negative when storage is released.
+    pub storage_delta_bytes: i64,
+    /// storage deposit (in yoctoNEAR) charged by the call.
+    pub deposit_charged: U128,
+}
+
+impl StorageCharge {
+    /// Storage change since `storage_start` (`env::storage_usage()` at the beginning of the
+    /// call).
+    pub fn since(storage_start: u64, deposit_charged: Balance) -> Self {
+        Self {
+            storage_delta_bytes: env::storage_usage() as i64 - storage_start as i64,
+            deposit_charged: U128(deposit_charged),
+        }
+    }
+}
+
 /// An event emitted when an SBT token issuance succeeded.
 /// Arguments:
 /// * `issuer`: SBT smart contract initiating the token issuance.
 /// * `tokens`: list of pairs (token owner, TokenId[])
-/// * `memo`: optional message
+/// * `storage`: optional storage change of the call, set by the registry.
 #[derive(Serialize)]
 #[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq, Clone))]
 #[serde(crate = "near_sdk::serde")]
 pub struct SbtMint<'a> {
     pub issuer: &'a AccountId,
     pub tokens: Vec<(&'a AccountId, &'a Vec<TokenId>)>,
+    #[serde(flatten)]
+    pub storage: Option<StorageCharge>,
 }
-impl SbtMint<'_> {
+/// Max length (in bytes) of the JSON `tokens` data of a single Mint event, see
+/// `SbtMint::split`.
+pub const MAX_MINT_EVENT_DATA_LEN: usize = 2048;
+
+impl<'a> SbtMint<'a> {
+    /// Emits the event, split into chunks (see `split`).
     pub fn emit(self) {
-        Nep393Event::Mint(self).emit();
+        for e in self.split() {
+            Nep393Event::Mint(e).emit();
+        }
+    }
+
+    /// Splits the event into events of the same issuer with at most
+    /// `MAX_MINT_EVENT_DATA_LEN` bytes of the JSON `tokens` data each, to stay within the
+    /// log size limits. Tokens of one owner are never split between events. Only the first
+    /// event has the `storage` change, so it's not counted multiple times.
+    pub fn split(self) -> Vec<SbtMint<'a>> {
+        let mut storage = self.storage;
+        let mut events = Vec::new();
+        let mut tokens = Vec::new();
+        // JSON list brackets, minus the missing comma after the last element.
+        let mut len = 1;
+        for (owner, ids) in self.tokens {
+            // `["owner",[1,2]],`
+            let el_len = owner.as_str().len()
+                + 7
+                + ids.iter().map(|t| t.to_string().len() + 1).sum::<usize>();
+            if !tokens.is_empty() && len + el_len > MAX_MINT_EVENT_DATA_LEN {
+                events.push(SbtMint {
+                    issuer: self.issuer,
+                    tokens: std::mem::take(&mut tokens),
+                    storage: storage.take(),
+                });
+                len = 1;
+            }
+            len += el_len;
+            tokens.push((owner, ids));
+        }
+        events.push(SbtMint {
+            issuer: self.issuer,
+            tokens,
+            storage,
+        });
+        events
     }
 }
 
@@ -84,6 +153,8 @@ impl SbtMint<'_> {
 /// * `issuer`: SBT smart contract initiating the token recovery.
 /// * `old_owner`: source account from which we recover the tokens.
 /// * `new_owner`: destination account for recevered tokens.
+/// * `storage`: optional storage change of the call completing the recovery, set by the
+///   registry.
 #[derive(Serialize)]
 #[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq, Clone))]
 #[serde(crate = "near_sdk::serde")]
@@ -91,6 +162,8 @@ pub struct SbtRecover<'a> {
     pub issuer: &'a AccountId,
     pub old_owner: &'a AccountId,
     pub new_owner: &'a AccountId,
+    #[serde(flatten)]
+    pub storage: Option<StorageCharge>,
 }
 
 impl SbtRecover<'_> {
@@ -104,12 +177,18 @@ impl SbtRecover<'_> {
 /// Arguments:
 /// * `issuer`: SBT smart contract initiating the SBT state change.
 /// * `tokens`: list of tokens concering the transaction emitting the event.
+/// * `storage`: optional storage change of the call, set by the registry in burn events.
+/// * `reason`: optional reason of the revoke (or burn), see `sbt_revoke_with_reason`.
 #[derive(Serialize)]
 #[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq, Clone))]
 #[serde(crate = "near_sdk::serde")]
 pub struct SbtTokensEvent {
     pub issuer: AccountId, // SBT Contract account address
     pub tokens: Vec<TokenId>,
+    #[serde(flatten)]
+    pub storage: Option<StorageCharge>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<RevocationReason>,
 }
 
 impl SbtTokensEvent {
@@ -223,11 +302,46 @@ mod tests {
     fn from_nftmint<'a>(n: &NftMint<'a>) -> Nep171Mint<'a> {
         Nep171Mint {
             owner_id: n.owner_id,
-            token_ids: n.token_ids.iter().map(|s| s.clone().to_owned()).collect(),
+            token_ids: n.token_ids.iter().map(|s| (*s).to_owned()).collect(),
             memo: n.memo.map(|s| s.to_owned()),
         }
     }
 
+    #[test]
+    fn sbt_mint_split() {
+        let issuer = sbt_issuer();
+        let owners: Vec<AccountId> = (0..100)
+            .map(|i| AccountId::new_unchecked(format!("{}{:02}.near", "a".repeat(50), i)))
+            .collect();
+        let ids: Vec<Vec<TokenId>> = (0..100).map(|i| vec![i, i + 100]).collect();
+        let event = SbtMint {
+            issuer: &issuer,
+            tokens: owners.iter().zip(ids.iter()).collect(),
+            storage: Some(StorageCharge {
+                storage_delta_bytes: 1000,
+                deposit_charged: U128(10),
+            }),
+        };
+        let events = event.clone().split();
+        assert_eq!(events.len(), 4);
+        for e in &events {
+            assert_eq!(e.issuer, &issuer);
+            let data = serde_json::to_string(&e.tokens).unwrap();
+            assert!(data.len() <= MAX_MINT_EVENT_DATA_LEN);
+        }
+        assert_eq!(events[0].storage, event.storage);
+        assert!(events[1..].iter().all(|e| e.storage.is_none()));
+        let tokens: Vec<_> = events.into_iter().flat_map(|e| e.tokens).collect();
+        assert_eq!(tokens, event.tokens);
+
+        let small = SbtMint {
+            issuer: &issuer,
+            tokens: vec![(&owners[0], &ids[0])],
+            storage: None,
+        };
+        assert_eq!(small.clone().split(), vec![small]);
+    }
+
     #[test]
     fn log_format_nep171_mint() {
         let alice = alice();
@@ -288,10 +402,22 @@ mod tests {
         let event = Nep393Event::Mint(SbtMint {
             issuer: &issuer,
             tokens: vec![(&bob, &bob1_tokens), (&bob, &bob2_tokens)],
+            storage: None,
         });
         assert_eq!(expected, event.clone().to_json_event_string());
         event.emit();
         assert_eq!(expected, test_utils::get_logs()[0]);
+
+        let expected = r#"EVENT_JSON:{"standard":"nep393","version":"1.0.0","event":"mint","data":{"issuer":"sbt.near","tokens":[["bob.near",[1]]],"storage_delta_bytes":220,"deposit_charged":"2200000000000000000000"}}"#;
+        let event = Nep393Event::Mint(SbtMint {
+            issuer: &issuer,
+            tokens: vec![(&bob, &bob2_tokens)],
+            storage: Some(StorageCharge {
+                storage_delta_bytes: 220,
+                deposit_charged: U128(2_200_000_000_000_000_000_000),
+            }),
+        });
+        assert_eq!(expected, event.to_json_event_string());
     }
 
     #[test]
@@ -304,6 +430,7 @@ mod tests {
             issuer: &issuer,
             old_owner: &bob,
             new_owner: &charlie,
+            storage: None,
         });
         assert_eq!(expected, event.clone().to_json_event_string());
         event.emit();
@@ -316,6 +443,8 @@ mod tests {
         let e = SbtTokensEvent {
             issuer: sbt_issuer(),
             tokens: vec![21, 10, 888],
+            storage: None,
+            reason: None,
         };
         let event = Nep393Event::Renew(e.clone());
         assert_eq!(expected, event.clone().to_json_event_string());
@@ -331,6 +460,8 @@ mod tests {
         let e = SbtTokensEvent {
             issuer: sbt_issuer(),
             tokens: vec![19853, 1],
+            storage: None,
+            reason: None,
         };
         let event = Nep393Event::Revoke(e.clone());
         assert_eq!(expected, event.clone().to_json_event_string());
@@ -338,6 +469,15 @@ mod tests {
         assert_eq!(expected, test_utils::get_logs()[0]);
         e.emit_revoke();
         assert_eq!(expected, test_utils::get_logs()[1]);
+
+        let expected = r#"EVENT_JSON:{"standard":"nep393","version":"1.0.0","event":"revoke","data":{"issuer":"sbt.near","tokens":[1],"reason":"fraud"}}"#;
+        let e = SbtTokensEvent {
+            issuer: sbt_issuer(),
+            tokens: vec![1],
+            storage: None,
+            reason: Some(RevocationReason::Fraud),
+        };
+        assert_eq!(expected, Nep393Event::Revoke(e).to_json_event_string());
     }
 
     #[test]
@@ -346,6 +486,8 @@ mod tests {
         let e = SbtTokensEvent {
             issuer: sbt_issuer(),
             tokens: vec![19853, 12],
+            storage: None,
+            reason: None,
         };
         let event = Nep393Event::Burn(e.clone());
         assert_eq!(expected, event.clone().to_json_event_string());
@@ -353,6 +495,18 @@ mod tests {
         assert_eq!(expected, test_utils::get_logs()[0]);
         e.emit_burn();
         assert_eq!(expected, test_utils::get_logs()[1]);
+
+        let expected = r#"EVENT_JSON:{"standard":"nep393","version":"1.0.0","event":"burn","data":{"issuer":"sbt.near","tokens":[12],"storage_delta_bytes":-180,"deposit_charged":"0"}}"#;
+        let e = SbtTokensEvent {
+            issuer: sbt_issuer(),
+            tokens: vec![12],
+            storage: Some(StorageCharge {
+                storage_delta_bytes: -180,
+                deposit_charged: U128(0),
+            }),
+            reason: None,
+        };
+        assert_eq!(expected, Nep393Event::Burn(e).to_json_event_string());
     }
 
     #[test]
@@ -0,0 +1,123 @@
+//! Registry state export format (see the registry `export_state` view), used to build read
+//! replicas or data availability backups of the registry.
+//!
+//! `export_state` returns a Borsh encoded `ExportChunk`:
+//! * `version: u8` -- `EXPORT_FORMAT_VERSION`.
+//! * `records: ExportRecords` -- records of the requested section.
+//! * `next: Option<Vec<u8>>` -- opaque cursor of the next chunk (pass it as `from` to
+//!   `export_state`), or None if the section is complete.
+//!
+//! Use `decode_export_chunk` to decode a chunk.
+
+use std::io;
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+#[cfg(feature = "json")]
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::AccountId;
+
+use crate::{ClassId, TokenId, TokenMetadata};
+
+/// Version of the `ExportChunk` layout.
+pub const EXPORT_FORMAT_VERSION: u8 = 1;
+
+/// Exported part of the registry state.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "json",
+    derive(Serialize, Deserialize),
+    serde(crate = "near_sdk::serde", rename_all = "snake_case")
+)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub enum ExportSection {
+    /// all issuers ever registered, ordered by issuer ID.
+    Issuers,
+    /// all not burned tokens, ordered by issuer ID and token ID.
+    Tokens,
+    /// owner balances (owner, issuer, class) -> token, ordered by the balance key.
+    Balances,
+}
+
+#[derive(BorshSerialize, BorshDeserialize)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq, Clone))]
+pub struct ExportedIssuer {
+    /// registry internal issuer ID.
+    pub issuer_id: u32,
+    pub account: AccountId,
+    /// false if the issuer was removed.
+    pub registered: bool,
+}
+
+#[derive(BorshSerialize, BorshDeserialize)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq, Clone))]
+pub struct ExportedToken {
+    pub issuer_id: u32,
+    pub token: TokenId,
+    pub owner: AccountId,
+    pub metadata: TokenMetadata,
+}
+
+#[derive(BorshSerialize, BorshDeserialize)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq, Clone))]
+pub struct ExportedBalance {
+    pub owner: AccountId,
+    pub issuer_id: u32,
+    pub class_id: ClassId,
+    pub token: TokenId,
+}
+
+#[derive(BorshSerialize, BorshDeserialize)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq, Clone))]
+pub enum ExportRecords {
+    Issuers(Vec<ExportedIssuer>),
+    Tokens(Vec<ExportedToken>),
+    Balances(Vec<ExportedBalance>),
+}
+
+/// Chunk of the exported registry state, see the module documentation.
+#[derive(BorshSerialize, BorshDeserialize)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq, Clone))]
+pub struct ExportChunk {
+    pub version: u8,
+    pub records: ExportRecords,
+    pub next: Option<Vec<u8>>,
+}
+
+/// Decodes a chunk returned by the registry `export_state`. Fails if the chunk is malformed
+/// or uses an unsupported format version.
+pub fn decode_export_chunk(data: &[u8]) -> io::Result<ExportChunk> {
+    let chunk = ExportChunk::try_from_slice(data)?;
+    if chunk.version != EXPORT_FORMAT_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unsupported export format version {}", chunk.version),
+        ));
+    }
+    Ok(chunk)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_export_chunk_version() {
+        let mut chunk = ExportChunk {
+            version: EXPORT_FORMAT_VERSION,
+            records: ExportRecords::Balances(vec![ExportedBalance {
+                owner: AccountId::new_unchecked("alice.near".to_string()),
+                issuer_id: 1,
+                class_id: 2,
+                token: 3,
+            }]),
+            next: Some(vec![1, 2]),
+        };
+        let data = chunk.try_to_vec().unwrap();
+        assert_eq!(decode_export_chunk(&data).unwrap(), chunk);
+
+        chunk.version = 2;
+        let err = decode_export_chunk(&chunk.try_to_vec().unwrap()).unwrap_err();
+        assert_eq!(err.to_string(), "unsupported export format version 2");
+        assert!(decode_export_chunk(&data[..data.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,56 @@
+//! Names of the optional registry features, returned by the registry `supported_extensions`
+//! view. A contract talking to registry deployments of different versions (eg on other
+//! networks) can check the list before using a feature: deployments older than the view
+//! don't implement it, so a failed `supported_extensions` call means that only the NEP-393
+//! core methods are available.
+
+/// `sbt_soul_transfer`, `sbt_soul_transfer_continue` and `sbt_soul_transfer_cancel`.
+pub const EXT_SOUL_TRANSFER: &str = "soul_transfer";
+/// `sbt_soul_merge` and `sbt_soul_merge_approve`.
+pub const EXT_SOUL_MERGE: &str = "soul_merge";
+/// issuer driven account recovery: `sbt_recover` and `sbt_soul_transfer_by_issuer`.
+pub const EXT_RECOVERY: &str = "recovery";
+/// `is_human_call`: calls the receiver with the proof of humanity.
+pub const EXT_IS_HUMAN_CALL: &str = "is_human_call";
+/// `is_human_at`: proof of humanity at a past timestamp.
+pub const EXT_IS_HUMAN_AT: &str = "is_human_at";
+/// `sbt_classes_by_owner`, per class caps and the issuer metadata (`sbt_issuer_metadata`).
+pub const EXT_CLASSES_METADATA: &str = "classes_metadata";
+/// `sbt_mint_with_msg` and the `SBTReceiver` notifications.
+pub const EXT_MINT_WITH_MSG: &str = "mint_with_msg";
+/// `sbt_mint_with_refs`: mint idempotency keys.
+pub const EXT_MINT_REFS: &str = "mint_refs";
+/// `sbt_renew_batch`.
+pub const EXT_RENEW_BATCH: &str = "renew_batch";
+/// issuer storage escrow and mint vouchers.
+pub const EXT_VOUCHERS: &str = "vouchers";
+/// `sbt_mint_stamps`: lightweight stamps.
+pub const EXT_STAMPS: &str = "stamps";
+/// `sbt_attestation`: cross-chain attestations.
+pub const EXT_ATTESTATION: &str = "attestation";
+/// `humanity_claim_payload`: off-chain humanity claims.
+pub const EXT_HUMANITY_CLAIM: &str = "humanity_claim";
+/// `sbt_metadata_hash`: canonical token metadata hashes.
+pub const EXT_METADATA_HASH: &str = "metadata_hash";
+/// named humanity policies: `satisfies_policy`.
+pub const EXT_POLICIES: &str = "policies";
+/// one-time human actions: `mark_used` and `is_used`.
+pub const EXT_CONTEXTS: &str = "contexts";
+/// negative (eg confirmed bot) classes: `negative_classes`.
+pub const EXT_NEGATIVE_CLASSES: &str = "negative_classes";
+/// `sbt_revoke_with_reason` and `sbt_revocation_reason`.
+pub const EXT_REVOCATION_REASONS: &str = "revocation_reasons";
+/// owner privacy mode: `set_private` and `sbt_tokens_by_owner_full`.
+pub const EXT_PRIVACY_MODE: &str = "privacy_mode";
+/// allowlist of the `is_human_call` receivers: `human_call_target`.
+pub const EXT_HUMAN_CALL_ALLOWLIST: &str = "human_call_allowlist";
+/// `admin_set_composite_credential` and `composite_credential`.
+pub const EXT_COMPOSITE_CREDENTIALS: &str = "composite_credentials";
+/// `reserve_token_ids` and `sbt_mint_reserved`.
+pub const EXT_TOKEN_RESERVATIONS: &str = "token_reservations";
+/// audit log of the admin actions: `admin_log`.
+pub const EXT_ADMIN_LOG: &str = "admin_log";
+/// class max TTL policies: `sbt_set_class_max_ttl` and `apply_ttl_policy`.
+pub const EXT_CLASS_TTL_POLICY: &str = "class_ttl_policy";
+/// `sbt_supply` count of the valid tokens (`only_valid` argument).
+pub const EXT_VALID_SUPPLY: &str = "valid_supply";
@@ -0,0 +1,197 @@
+//! Off-chain humanity claims (see the registry `humanity_claim_payload` view).
+//!
+//! A Web2 service checks the IAH status of a NEAR account without a transaction:
+//! 1. The service (or the user wallet) calls the registry `humanity_claim_payload(account)`
+//!    view. It returns the canonical `HumanityClaim` message: the account is a human at the
+//!    returned block, and the claim expires at `expires_at`.
+//! 2. The user signs the message off-chain with an ed25519 access key of the account.
+//! 3. The service verifies the signature with `verify_humanity_claim` (requires the
+//!    `verifier` feature) and checks that the public key is an access key of the `account`
+//!    (eg with the `view_access_key` RPC query, or the key of an implicit account).
+//!
+//! The claim is signed by the user, not by the registry: a service which doesn't fetch the
+//! payload itself must not trust the claimed status blindly. The view result is deterministic
+//! for a block, so the service can query `humanity_claim_payload` at `block_height` (an
+//! archival RPC node is needed for old blocks) and compare the bytes.
+//!
+//! The message starts with `HUMANITY_CLAIM_TAG`, so a signed claim can't be a valid NEAR
+//! transaction or a claim of another format.
+
+use std::fmt;
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::AccountId;
+
+/// Version of the `HumanityClaim` layout.
+pub const HUMANITY_CLAIM_FORMAT_VERSION: u8 = 1;
+
+/// Prefix of the canonical claim message.
+pub const HUMANITY_CLAIM_TAG: &[u8] = b"NEAR i-am-human claim:";
+
+#[derive(BorshSerialize, BorshDeserialize)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq, Clone))]
+pub struct HumanityClaim {
+    pub version: u8,
+    /// registry account.
+    pub registry: AccountId,
+    /// the claimed human.
+    pub account: AccountId,
+    /// block of the registry state the claim was created at.
+    pub block_height: u64,
+    /// block timestamp in miliseconds.
+    pub timestamp: u64,
+    /// unix timestamp in miliseconds after which the claim must be rejected. Not later than
+    /// the expiry of the account proof of humanity tokens.
+    pub expires_at: u64,
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq, Clone))]
+pub enum ClaimError {
+    /// the message is not a `HumanityClaim` canonical encoding.
+    Malformed,
+    UnsupportedVersion(u8),
+    /// the public key or signature is malformed, or the signature doesn't match.
+    InvalidSignature,
+    Expired,
+}
+
+impl fmt::Display for ClaimError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClaimError::Malformed => write!(f, "malformed humanity claim"),
+            ClaimError::UnsupportedVersion(v) => {
+                write!(f, "unsupported humanity claim format version {}", v)
+            }
+            ClaimError::InvalidSignature => write!(f, "invalid humanity claim signature"),
+            ClaimError::Expired => write!(f, "humanity claim is expired"),
+        }
+    }
+}
+
+impl HumanityClaim {
+    /// Canonical message: `HUMANITY_CLAIM_TAG` followed by the Borsh encoding of the claim.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut msg = HUMANITY_CLAIM_TAG.to_vec();
+        msg.extend(self.try_to_vec().unwrap());
+        msg
+    }
+
+    /// Decodes the canonical message. Fails if the message is malformed or uses an
+    /// unsupported format version.
+    pub fn decode(msg: &[u8]) -> Result<Self, ClaimError> {
+        let data = msg
+            .strip_prefix(HUMANITY_CLAIM_TAG)
+            .ok_or(ClaimError::Malformed)?;
+        let c = Self::try_from_slice(data).map_err(|_| ClaimError::Malformed)?;
+        if c.version != HUMANITY_CLAIM_FORMAT_VERSION {
+            return Err(ClaimError::UnsupportedVersion(c.version));
+        }
+        Ok(c)
+    }
+
+    /// Returns true if the claim is expired at `now` (unix timestamp in miliseconds).
+    pub fn is_expired(&self, now: u64) -> bool {
+        now > self.expires_at
+    }
+}
+
+/// Decodes the claim `msg` and verifies the ed25519 `signature` (64 bytes) of the message
+/// with the `public_key` (32 bytes) and the claim expiry at `now` (unix timestamp in
+/// miliseconds). Returns the claim. The caller must check that the public key is an access
+/// key of the claim `account`, and the `registry`.
+#[cfg(feature = "verifier")]
+pub fn verify_humanity_claim(
+    msg: &[u8],
+    public_key: &[u8],
+    signature: &[u8],
+    now: u64,
+) -> Result<HumanityClaim, ClaimError> {
+    use ed25519_dalek::{PublicKey, Signature, Verifier};
+
+    let claim = HumanityClaim::decode(msg)?;
+    let pk = PublicKey::from_bytes(public_key).map_err(|_| ClaimError::InvalidSignature)?;
+    let sig = Signature::from_bytes(signature).map_err(|_| ClaimError::InvalidSignature)?;
+    pk.verify(msg, &sig)
+        .map_err(|_| ClaimError::InvalidSignature)?;
+    if claim.is_expired(now) {
+        return Err(ClaimError::Expired);
+    }
+    Ok(claim)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mk_claim() -> HumanityClaim {
+        HumanityClaim {
+            version: HUMANITY_CLAIM_FORMAT_VERSION,
+            registry: AccountId::new_unchecked("registry.near".to_string()),
+            account: AccountId::new_unchecked("alice.near".to_string()),
+            block_height: 10,
+            timestamp: 1_000,
+            expires_at: 5_000,
+        }
+    }
+
+    #[test]
+    fn encode_decode() {
+        let mut c = mk_claim();
+        let msg = c.encode();
+        assert!(msg.starts_with(HUMANITY_CLAIM_TAG));
+        assert_eq!(HumanityClaim::decode(&msg).unwrap(), c);
+        assert_eq!(
+            HumanityClaim::decode(&msg[HUMANITY_CLAIM_TAG.len()..]),
+            Err(ClaimError::Malformed)
+        );
+        assert_eq!(
+            HumanityClaim::decode(&msg[..msg.len() - 1]),
+            Err(ClaimError::Malformed)
+        );
+
+        c.version = 2;
+        let err = HumanityClaim::decode(&c.encode()).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "unsupported humanity claim format version 2"
+        );
+    }
+
+    #[cfg(feature = "verifier")]
+    #[test]
+    fn verify() {
+        use ed25519_dalek::{Keypair, SecretKey, Signer};
+
+        let secret = SecretKey::from_bytes(&[7; 32]).unwrap();
+        let kp = Keypair {
+            public: (&secret).into(),
+            secret,
+        };
+        let pk = kp.public.to_bytes();
+        let msg = mk_claim().encode();
+        let sig = kp.sign(&msg).to_bytes();
+        assert_eq!(
+            verify_humanity_claim(&msg, &pk, &sig, 5_000),
+            Ok(mk_claim())
+        );
+        assert_eq!(
+            verify_humanity_claim(&msg, &pk, &sig, 5_001),
+            Err(ClaimError::Expired)
+        );
+
+        let mut other = mk_claim();
+        other.expires_at = 9_000;
+        assert_eq!(
+            verify_humanity_claim(&other.encode(), &pk, &sig, 5_000),
+            Err(ClaimError::InvalidSignature)
+        );
+        assert_eq!(
+            verify_humanity_claim(&msg, &pk[1..], &sig, 5_000),
+            Err(ClaimError::InvalidSignature)
+        );
+        assert_eq!(
+            verify_humanity_claim(&msg[1..], &pk, &sig, 5_000),
+            Err(ClaimError::Malformed)
+        );
+    }
+}
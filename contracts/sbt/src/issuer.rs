@@ -0,0 +1,86 @@
+use near_sdk::{env, require, Balance};
+#[cfg(feature = "json")]
+use near_sdk::{AccountId, Gas, Promise};
+
+#[cfg(feature = "json")]
+use crate::{ext_registry, TokenId, TokenMetadata};
+
+/// SBTIssuer implements the registry protocol for issuer contracts: minting, renewing and
+/// revoking tokens is done by calling the registry. An issuer contract only has to provide
+/// the registry address, and can use the default methods instead of making the cross
+/// contract calls itself.
+#[cfg(feature = "json")]
+pub trait SBTIssuer {
+    /// Registry where the issuer tokens are minted.
+    fn registry(&self) -> AccountId;
+
+    /// Calls the registry `sbt_mint`. `deposit` must cover the registry storage cost.
+    /// The promise resolves to the minted token IDs.
+    fn registry_mint(
+        &self,
+        token_spec: Vec<(AccountId, Vec<TokenMetadata>)>,
+        deposit: Balance,
+        gas: Gas,
+    ) -> Promise {
+        ext_registry::ext(self.registry())
+            .with_attached_deposit(deposit)
+            .with_static_gas(gas)
+            .sbt_mint(token_spec)
+    }
+
+    /// Calls the registry `sbt_renew`. `expires_at` is a unix timestamp in miliseconds.
+    fn registry_renew(&self, tokens: Vec<TokenId>, expires_at: u64) -> Promise {
+        ext_registry::ext(self.registry()).sbt_renew(tokens, expires_at)
+    }
+
+    /// Calls the registry `sbt_revoke`.
+    fn registry_revoke(&self, tokens: Vec<TokenId>, burn: bool) -> Promise {
+        ext_registry::ext(self.registry()).sbt_revoke(tokens, burn)
+    }
+}
+
+/// Returns the NEAR amount required to cover the storage used since `storage_start`
+/// (value of `env::storage_usage()` at the beginning of the transaction).
+pub fn required_storage_deposit(storage_start: u64) -> Balance {
+    let used = env::storage_usage().saturating_sub(storage_start);
+    used as Balance * env::storage_byte_cost()
+}
+
+/// Panics if `deposit` doesn't cover the storage used since `storage_start`. Returns the
+/// required deposit.
+pub fn assert_storage_deposit(storage_start: u64, deposit: Balance) -> Balance {
+    let required = required_storage_deposit(storage_start);
+    require!(
+        deposit >= required,
+        format!("not enough NEAR storage depost, required: {}", required)
+    );
+    required
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::VMContextBuilder;
+    use near_sdk::testing_env;
+
+    use super::*;
+
+    #[test]
+    fn storage_deposit() {
+        testing_env!(VMContextBuilder::new().build());
+        let start = env::storage_usage();
+        assert_eq!(assert_storage_deposit(start, 0), 0);
+        env::storage_write(b"key", &[1; 90]);
+        let required = required_storage_deposit(start);
+        assert!(required > 0);
+        assert_eq!(assert_storage_deposit(start, required), required);
+    }
+
+    #[test]
+    #[should_panic(expected = "not enough NEAR storage depost")]
+    fn storage_deposit_not_enough() {
+        testing_env!(VMContextBuilder::new().build());
+        let start = env::storage_usage();
+        env::storage_write(b"key", &[1; 90]);
+        assert_storage_deposit(start, 1);
+    }
+}
@@ -1,10 +1,38 @@
+mod attestation;
+mod errors;
+#[cfg(feature = "json")]
 mod events;
+mod export;
+mod extensions;
+mod humanity_claim;
+mod issuer;
+pub mod merkle;
 mod metadata;
-
-use near_sdk::{ext_contract, AccountId};
-
+#[cfg(feature = "json")]
+pub mod registry_calls;
+#[cfg(feature = "json")]
+mod source_metadata;
+mod time;
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+#[cfg(feature = "json")]
+use near_sdk::ext_contract;
+#[cfg(feature = "json")]
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::AccountId;
+
+pub use crate::attestation::*;
+pub use crate::errors::*;
+#[cfg(feature = "json")]
 pub use crate::events::*;
+pub use crate::export::*;
+pub use crate::extensions::*;
+pub use crate::humanity_claim::*;
+pub use crate::issuer::*;
 pub use crate::metadata::*;
+#[cfg(feature = "json")]
+pub use crate::source_metadata::*;
+pub use crate::time::*;
 
 /// This spec can be treated like a version of the standard.
 pub const SPEC_VERSION: &str = "1.0.0";
@@ -32,6 +60,44 @@ pub type TokenId = u64;
 /// Minimum valid `ClassId` must be 1.
 pub type ClassId = u64;
 
+/// Result of renewing a single token in the registry `sbt_renew_batch`.
+#[cfg_attr(
+    feature = "json",
+    derive(Serialize, Deserialize),
+    serde(crate = "near_sdk::serde", rename_all = "snake_case")
+)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq, Clone))]
+pub enum RenewResult {
+    Renewed,
+    /// token doesn't exist or was not issued by the caller.
+    NotFound,
+    /// token owner is banned.
+    OwnerBanned,
+    /// token status doesn't allow renew (eg the token is revoked or frozen).
+    InvalidStatus,
+}
+
+/// Reason of a token revoke or freeze, see the registry `sbt_revoke_with_reason`. Stored
+/// with the token status and included in the `revoke` and `burn` events, so analytics can
+/// tell routine churn from abuse driven revocations.
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "json",
+    derive(Serialize, Deserialize),
+    serde(crate = "near_sdk::serde", rename_all = "snake_case")
+)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub enum RevocationReason {
+    /// routine revoke of a token the owner doesn't qualify for anymore.
+    Expired,
+    /// the owner cheated, eg a fake or duplicated identity.
+    Fraud,
+    /// requested by the owner.
+    UserRequest,
+    /// the token was issued by mistake.
+    IssuerError,
+}
+
 /// SBTContract is the minimum required interface to be implemented by issuer.
 /// Other methods, such as a mint function, which requests the registry to proceed with token
 /// minting, is specific to an Issuer implementation (similarly, mint is not part of the FT
@@ -52,7 +118,9 @@ pub trait SBTRegistry {
     /// Returns total amount of tokens issued by `issuer` SBT contract, including expired
     /// tokens. Depending on the implementation, if a revoke removes a token, it then is should
     /// not be included in the supply.
-    fn sbt_supply(&self, issuer: AccountId) -> u64;
+    /// If `only_valid` is true, returns only the number of valid (not expired nor revoked)
+    /// tokens.
+    fn sbt_supply(&self, issuer: AccountId, only_valid: Option<bool>) -> u64;
 
     /// Returns total amount of tokens of given class minted by `issuer`. See `sbt_supply` for
     /// information about revoked tokens.
@@ -134,9 +202,37 @@ pub trait SBTRegistry {
 }
 
 // ext_registry is a helper to make cross contract registry calls
+#[cfg(feature = "json")]
 #[ext_contract(ext_registry)]
-trait ExtRegistry {
+pub trait ExtRegistry {
     fn sbt_mint(&mut self, token_spec: Vec<(AccountId, Vec<TokenMetadata>)>) -> Vec<TokenId>;
+    fn sbt_mint_with_msg(
+        &mut self,
+        token_spec: Vec<(AccountId, Vec<TokenMetadata>, Option<String>)>,
+    ) -> Vec<TokenId>;
+    fn sbt_mint_with_refs(
+        &mut self,
+        token_spec: Vec<(AccountId, Vec<TokenMetadata>, Option<String>)>,
+    ) -> Vec<TokenId>;
     fn sbt_renew(&mut self, tokens: Vec<TokenId>, expires_at: u64);
+    fn sbt_renew_batch(&mut self, tokens: Vec<(TokenId, u64)>) -> Vec<RenewResult>;
     fn sbt_revoke(&mut self, tokens: Vec<TokenId>, burn: bool);
+    fn sbt_revoke_with_reason(
+        &mut self,
+        tokens: Vec<TokenId>,
+        burn: bool,
+        reason: RevocationReason,
+    );
+}
+
+/// SBTReceiver is implemented by contracts (eg smart wallets) which want to be notified about
+/// tokens minted to them, see the registry `sbt_mint_with_msg`. The recipient must opt in
+/// with the registry `sbt_set_notifications`.
+#[cfg(feature = "json")]
+#[ext_contract(ext_sbt_receiver)]
+pub trait SBTReceiver {
+    /// Called by the registry after the `token` of the `issuer` was minted to the contract.
+    /// `msg` is set by the issuer. The result is ignored and a failure doesn't revert the
+    /// mint.
+    fn on_sbt_received(&mut self, issuer: AccountId, token: TokenId, msg: String);
 }
@@ -0,0 +1,76 @@
+//! Merkle commitments to token attributes. Leaves are sha256 hashes of the attribute strings
+//! (eg "country=CH"), and each parent is the sha256 hash of its two children sorted
+//! lexicographically, so proofs don't need to encode the sibling positions.
+
+use near_sdk::env;
+
+pub type Hash = [u8; 32];
+
+/// Hashes an attribute into a Merkle leaf.
+pub fn leaf_hash(attribute: &str) -> Hash {
+    env::sha256_array(attribute.as_bytes())
+}
+
+fn hash_pair(a: &Hash, b: &Hash) -> Hash {
+    let (first, second) = if a <= b { (a, b) } else { (b, a) };
+    let mut buf = [0u8; 64];
+    buf[..32].copy_from_slice(first);
+    buf[32..].copy_from_slice(second);
+    env::sha256_array(&buf)
+}
+
+/// Returns true if `proof` (list of sibling hashes, from the leaf up to the root) proves that
+/// `leaf` is committed in `root`.
+pub fn verify_proof(root: &Hash, proof: &[Hash], leaf: Hash) -> bool {
+    let computed = proof
+        .iter()
+        .fold(leaf, |acc, sibling| hash_pair(&acc, sibling));
+    &computed == root
+}
+
+/// Computes the Merkle root of the leaves. A node without a sibling is promoted to the next
+/// level unchanged. Returns `None` for an empty list.
+pub fn merkle_root(leaves: &[Hash]) -> Option<Hash> {
+    if leaves.is_empty() {
+        return None;
+    }
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|c| {
+                if c.len() == 2 {
+                    hash_pair(&c[0], &c[1])
+                } else {
+                    c[0]
+                }
+            })
+            .collect();
+    }
+    Some(level[0])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn proofs() {
+        let leaves: Vec<Hash> = ["age=18-25", "country=CH", "kyc=true"]
+            .iter()
+            .map(|a| leaf_hash(a))
+            .collect();
+        assert_eq!(merkle_root(&[]), None);
+        assert_eq!(merkle_root(&leaves[..1]), Some(leaves[0]));
+
+        let root = merkle_root(&leaves).unwrap();
+        let h01 = hash_pair(&leaves[0], &leaves[1]);
+        assert_eq!(root, hash_pair(&h01, &leaves[2]));
+
+        assert!(verify_proof(&root, &[leaves[1], leaves[2]], leaves[0]));
+        assert!(verify_proof(&root, &[leaves[0], leaves[2]], leaves[1]));
+        assert!(verify_proof(&root, &[h01], leaves[2]));
+        assert!(!verify_proof(&root, &[h01], leaf_hash("kyc=false")));
+        assert!(!verify_proof(&root, &[leaves[2]], leaves[0]));
+    }
+}
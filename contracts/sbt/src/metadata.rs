@@ -1,13 +1,20 @@
+use std::collections::BTreeMap;
+
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
 use near_sdk::json_types::Base64VecU8;
+#[cfg(feature = "json")]
 use near_sdk::serde::{Deserialize, Serialize};
-use near_sdk::{require, AccountId};
+use near_sdk::{env, require, AccountId, CryptoHash};
 
 use crate::*;
 
 /// ContractMetadata defines contract wide attributes, which describes the whole contract.
-#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
-#[serde(crate = "near_sdk::serde")]
+#[derive(BorshDeserialize, BorshSerialize)]
+#[cfg_attr(
+    feature = "json",
+    derive(Serialize, Deserialize),
+    serde(crate = "near_sdk::serde")
+)]
 #[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq, Clone))]
 pub struct ContractMetadata {
     pub spec: String,              // required, essentially a version like "sbt-1.0.0"
@@ -19,17 +26,62 @@ pub struct ContractMetadata {
     pub reference_hash: Option<Base64VecU8>, // Base64-encoded sha256 hash of JSON from reference field. Required if `reference` is included.
 }
 
+/// Maximum length (in bytes) of the token and class `reference`.
+pub const MAX_REFERENCE_LEN: usize = 256;
+
+/// Maximum number of `ClassMetadata` attributes.
+pub const MAX_CLASS_ATTRIBUTES: usize = 16;
+/// Maximum length (in bytes) of a `ClassMetadata` attribute key.
+pub const MAX_CLASS_ATTRIBUTE_KEY_LEN: usize = 32;
+/// Maximum length (in bytes) of a `ClassMetadata` attribute value.
+pub const MAX_CLASS_ATTRIBUTE_VALUE_LEN: usize = 128;
+
+/// ClassMetadata describes a token class of an issuer.
+#[derive(BorshDeserialize, BorshSerialize)]
+#[cfg_attr(
+    feature = "json",
+    derive(Serialize, Deserialize),
+    serde(crate = "near_sdk::serde")
+)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq, Clone))]
+pub struct ClassMetadata {
+    pub name: String,                        // required, ex. "Community Member"
+    pub symbol: Option<String>,              // ex. "MEMBER"
+    pub icon: Option<String>,                // Data URL
+    pub reference: Option<String>,           // URL to a JSON file with more info
+    pub reference_hash: Option<Base64VecU8>, // Base64-encoded sha256 hash of JSON from reference field. Required if `reference` is included.
+    /// Small app specific key/value config, ex. `"vote_weight": "2"`. See `assert_valid`
+    /// for size limits.
+    #[cfg_attr(feature = "json", serde(default))]
+    pub attributes: BTreeMap<String, String>,
+}
+
 /// Versioned token metadata
-#[derive(BorshDeserialize, BorshSerialize, Serialize)]
+#[derive(BorshDeserialize, BorshSerialize)]
 #[cfg_attr(test, derive(Debug, Clone))]
-#[serde(crate = "near_sdk::serde")]
 pub enum VerTokenMetadata {
-    V1(TokenMetadata),
+    V1(TokenMetadataV1),
+    V2(TokenMetadata),
+}
+
+/// TokenMetadata layout before `attributes_root` was added. Only used to read old tokens.
+#[derive(BorshDeserialize, BorshSerialize)]
+#[cfg_attr(test, derive(Debug, Clone))]
+pub struct TokenMetadataV1 {
+    pub class: ClassId,
+    pub issued_at: Option<u64>,
+    pub expires_at: Option<u64>,
+    pub reference: Option<String>,
+    pub reference_hash: Option<Base64VecU8>,
 }
 
 /// TokenMetadata defines attributes for each SBT token.
-#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
-#[serde(crate = "near_sdk::serde")]
+#[derive(BorshDeserialize, BorshSerialize)]
+#[cfg_attr(
+    feature = "json",
+    derive(Serialize, Deserialize),
+    serde(crate = "near_sdk::serde")
+)]
 #[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq, Clone))]
 pub struct TokenMetadata {
     pub class: ClassId,                      // token class
@@ -37,45 +89,149 @@ pub struct TokenMetadata {
     pub expires_at: Option<u64>, // When token expires, Unix epoch in milliseconds
     pub reference: Option<String>, // URL to an off-chain JSON file with more info.
     pub reference_hash: Option<Base64VecU8>, // Base64-encoded sha256 hash of JSON from reference field. Required if `reference` is included.
+    /// Merkle root of hashed token attributes (see `merkle::verify_proof`). Allows the holder
+    /// to prove a single attribute without revealing the others.
+    pub attributes_root: Option<[u8; 32]>,
 }
 
 impl VerTokenMetadata {
-    pub fn v1(self) -> TokenMetadata {
+    /// Returns metadata in the latest version.
+    pub fn latest(self) -> TokenMetadata {
         match self {
-            VerTokenMetadata::V1(x) => x,
+            VerTokenMetadata::V1(x) => x.into(),
+            VerTokenMetadata::V2(x) => x,
         }
     }
 
     pub fn class_id(&self) -> ClassId {
         match self {
             VerTokenMetadata::V1(x) => x.class,
+            VerTokenMetadata::V2(x) => x.class,
         }
     }
 
     pub fn expires_at(&self) -> Option<u64> {
         match self {
             VerTokenMetadata::V1(x) => x.expires_at,
+            VerTokenMetadata::V2(x) => x.expires_at,
         }
     }
+
+    pub fn issued_at(&self) -> Option<u64> {
+        match self {
+            VerTokenMetadata::V1(x) => x.issued_at,
+            VerTokenMetadata::V2(x) => x.issued_at,
+        }
+    }
+
+    /// Returns true if the token expired before `now`. A token is still valid at its
+    /// `expires_at`.
+    pub fn is_expired_at(&self, now: MilliSeconds) -> bool {
+        self.expires_at().map_or(false, |e| MilliSeconds(e) < now)
+    }
+
+    /// Returns true if the token is valid at `now`: it's not expired and it's not issued
+    /// after `now`.
+    pub fn is_valid_at(&self, now: MilliSeconds) -> bool {
+        !self.is_expired_at(now) && self.issued_at().map_or(true, |i| MilliSeconds(i) <= now)
+    }
 }
 
 impl From<TokenMetadata> for VerTokenMetadata {
     fn from(m: TokenMetadata) -> Self {
-        VerTokenMetadata::V1(m)
+        VerTokenMetadata::V2(m)
+    }
+}
+
+impl From<TokenMetadataV1> for TokenMetadata {
+    fn from(m: TokenMetadataV1) -> Self {
+        TokenMetadata {
+            class: m.class,
+            issued_at: m.issued_at,
+            expires_at: m.expires_at,
+            reference: m.reference,
+            reference_hash: m.reference_hash,
+            attributes_root: None,
+        }
     }
 }
 
 /// Full information about the token
-#[derive(BorshDeserialize, BorshSerialize, Serialize)]
-#[serde(crate = "near_sdk::serde")]
+#[derive(BorshDeserialize, BorshSerialize)]
 pub struct TokenData {
     pub owner: AccountId,
     pub metadata: VerTokenMetadata,
 }
 
+/// Versioned token data, as stored by the registry. New versions can extend the token data
+/// without rewriting the already stored records.
+#[derive(BorshSerialize)]
+pub enum VerTokenData {
+    V1(TokenData),
+    /// token data with the `token_metadata_hash` of its metadata (in the latest layout).
+    V2(TokenData, CryptoHash),
+}
+
+impl VerTokenData {
+    /// Returns token data in the latest version.
+    pub fn latest(self) -> TokenData {
+        match self {
+            VerTokenData::V1(x) | VerTokenData::V2(x, _) => x,
+        }
+    }
+
+    /// Returns token data in the latest version and the metadata hash. The hash of records
+    /// stored without it is computed.
+    pub fn latest_with_hash(self) -> (TokenData, CryptoHash) {
+        match self {
+            VerTokenData::V1(x) => {
+                let metadata = x.metadata.latest();
+                let hash = token_metadata_hash(&metadata);
+                let t = TokenData {
+                    owner: x.owner,
+                    metadata: metadata.into(),
+                };
+                (t, hash)
+            }
+            VerTokenData::V2(x, hash) => (x, hash),
+        }
+    }
+}
+
+impl From<TokenData> for VerTokenData {
+    /// Converts the metadata to the latest layout and computes its hash.
+    fn from(t: TokenData) -> Self {
+        let (t, hash) = VerTokenData::V1(t).latest_with_hash();
+        VerTokenData::V2(t, hash)
+    }
+}
+
+impl BorshDeserialize for VerTokenData {
+    fn deserialize(buf: &mut &[u8]) -> std::io::Result<Self> {
+        // Records stored before the versioning are plain `TokenData`, which starts with the
+        // owner account ID length (u32 LE). Account IDs have at least 2 characters, so the
+        // first byte of a legacy record is never 0 (V1 tag) nor 1 (V2 tag).
+        match buf.first() {
+            Some(0) => {
+                *buf = &buf[1..];
+                Ok(VerTokenData::V1(TokenData::deserialize(buf)?))
+            }
+            Some(1) => {
+                *buf = &buf[1..];
+                let t = TokenData::deserialize(buf)?;
+                Ok(VerTokenData::V2(
+                    t,
+                    <CryptoHash as BorshDeserialize>::deserialize(buf)?,
+                ))
+            }
+            _ => Ok(VerTokenData::V1(TokenData::deserialize(buf)?)),
+        }
+    }
+}
+
 impl TokenData {
     pub fn to_token(self, token: TokenId) -> Token {
-        let metadata: TokenMetadata = self.metadata.v1();
+        let metadata: TokenMetadata = self.metadata.latest();
         Token {
             token,
             metadata,
@@ -85,8 +241,8 @@ impl TokenData {
 }
 
 /// token data for sbt_tokens_by_owner response
-#[derive(Serialize)]
-#[serde(crate = "near_sdk::serde")]
+#[derive(BorshSerialize, BorshDeserialize)]
+#[cfg_attr(feature = "json", derive(Serialize), serde(crate = "near_sdk::serde"))]
 #[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq, Clone))]
 pub struct OwnedToken {
     pub token: TokenId,
@@ -94,8 +250,8 @@ pub struct OwnedToken {
 }
 
 /// Full information about the token
-#[derive(Serialize)]
-#[serde(crate = "near_sdk::serde")]
+#[derive(BorshSerialize, BorshDeserialize)]
+#[cfg_attr(feature = "json", derive(Serialize), serde(crate = "near_sdk::serde"))]
 #[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq, Clone))]
 pub struct Token {
     pub token: TokenId,
@@ -103,6 +259,23 @@ pub struct Token {
     pub metadata: TokenMetadata,
 }
 
+/// Paginated registry view result with the state snapshot it was read from. Clients
+/// assembling multiple pages should restart the pagination when `event_count` changes
+/// between pages: the registry state changed and the pages may be inconsistent.
+/// The `*_page_borsh` registry views return it Borsh encoded, decode it with
+/// `Page::<T>::try_from_slice`.
+#[derive(BorshSerialize, BorshDeserialize)]
+#[cfg_attr(feature = "json", derive(Serialize), serde(crate = "near_sdk::serde"))]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq, Clone))]
+pub struct Page<T> {
+    pub block_height: u64,
+    /// block timestamp in miliseconds
+    pub timestamp: u64,
+    /// number of events emitted by the registry so far, see the registry `event_digest`.
+    pub event_count: u64,
+    pub items: T,
+}
+
 impl ContractMetadata {
     pub fn assert_valid(&self) {
         require!(self.spec == crate::SPEC_VERSION, "Spec is not NFT metadata");
@@ -120,19 +293,195 @@ impl ContractMetadata {
     }
 }
 
-impl TokenMetadata {
+/// Validates `reference` and `reference_hash`: both must be set or both empty, the
+/// reference can have at most `MAX_REFERENCE_LEN` bytes and the hash must have 32 bytes.
+pub fn validate_reference(
+    reference: &Option<String>,
+    reference_hash: &Option<Base64VecU8>,
+) -> Result<(), InputError> {
+    if reference.is_some() != reference_hash.is_some() {
+        return Err(InputError::ReferenceHashMissing);
+    }
+    if reference
+        .as_ref()
+        .map_or(false, |r| r.len() > MAX_REFERENCE_LEN)
+    {
+        return Err(InputError::ReferenceTooLong {
+            max: MAX_REFERENCE_LEN,
+        });
+    }
+    if reference_hash.as_ref().map_or(false, |h| h.0.len() != 32) {
+        return Err(InputError::InvalidReferenceHash);
+    }
+    Ok(())
+}
+
+impl ClassMetadata {
     pub fn assert_valid(&self) {
+        require!(!self.name.is_empty(), "name must be a non empty string");
+        if let Err(e) = validate_reference(&self.reference, &self.reference_hash) {
+            e.panic();
+        }
+        require!(
+            self.attributes.len() <= MAX_CLASS_ATTRIBUTES,
+            format!("max {} attributes are allowed", MAX_CLASS_ATTRIBUTES)
+        );
+        for (k, v) in &self.attributes {
+            require!(
+                !k.is_empty() && k.len() <= MAX_CLASS_ATTRIBUTE_KEY_LEN,
+                format!(
+                    "attribute key must have between 1 and {} bytes",
+                    MAX_CLASS_ATTRIBUTE_KEY_LEN
+                )
+            );
+            require!(
+                v.len() <= MAX_CLASS_ATTRIBUTE_VALUE_LEN,
+                format!(
+                    "attribute value can have at most {} bytes",
+                    MAX_CLASS_ATTRIBUTE_VALUE_LEN
+                )
+            );
+        }
+    }
+}
+
+/// Canonical hash of the token metadata: sha256 of its Borsh encoding. Equal metadata have
+/// equal hashes, so it can be used to dedup tokens and audit the metadata off-chain.
+pub fn token_metadata_hash(metadata: &TokenMetadata) -> CryptoHash {
+    env::sha256_array(&metadata.try_to_vec().unwrap())
+}
+
+impl TokenMetadata {
+    /// Checks that the class is not zero and the reference fields are valid (see
+    /// `validate_reference`).
+    pub fn validate(&self) -> Result<(), InputError> {
         // require!(self.media.is_some() == self.media_hash.is_some());
         // if let Some(media_hash) = &self.media_hash {
         //     require!(media_hash.0.len() == 32, "Media hash has to be 32 bytes");
         // }
 
-        require!(self.reference.is_some() == self.reference_hash.is_some());
-        if let Some(reference_hash) = &self.reference_hash {
-            require!(
-                reference_hash.0.len() == 32,
-                "Reference hash has to be 32 bytes"
-            );
+        if self.class == 0 {
+            return Err(InputError::ZeroClass);
         }
+        validate_reference(&self.reference, &self.reference_hash)
+    }
+
+    /// Panics with the `InputError` code if the metadata is not valid.
+    pub fn assert_valid(&self) {
+        if let Err(e) = self.validate() {
+            e.panic();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::borsh::{BorshDeserialize, BorshSerialize};
+    use near_sdk::AccountId;
+
+    use super::*;
+
+    fn token_data() -> TokenData {
+        TokenData {
+            owner: AccountId::new_unchecked("alice.near".to_string()),
+            metadata: TokenMetadata {
+                class: 2,
+                issued_at: Some(1),
+                expires_at: None,
+                reference: None,
+                reference_hash: None,
+                attributes_root: None,
+            }
+            .into(),
+        }
+    }
+
+    #[test]
+    fn valid_at() {
+        let mut m = token_data().metadata.latest();
+        m.issued_at = Some(10);
+        m.expires_at = Some(20);
+        let m = VerTokenMetadata::from(m);
+        assert!(!m.is_valid_at(MilliSeconds(9)));
+        assert!(m.is_valid_at(MilliSeconds(10)));
+        assert!(m.is_valid_at(MilliSeconds(20)));
+        assert!(!m.is_expired_at(MilliSeconds(20)));
+        assert!(m.is_expired_at(MilliSeconds(21)));
+        assert!(!m.is_valid_at(MilliSeconds(21)));
+        assert!(token_data().metadata.is_valid_at(MilliSeconds(u64::MAX)));
+    }
+
+    #[test]
+    fn ver_token_data_borsh() {
+        let bytes = VerTokenData::V1(token_data()).try_to_vec().unwrap();
+        assert_eq!(bytes[0], 0);
+        let t = VerTokenData::try_from_slice(&bytes).unwrap().latest();
+        assert_eq!(t.owner.as_str(), "alice.near");
+        assert_eq!(t.metadata.class_id(), 2);
+    }
+
+    #[test]
+    fn ver_token_data_metadata_hash() {
+        let m = token_data().metadata.latest();
+        let hash = token_metadata_hash(&m);
+        assert_eq!(hash, env::sha256_array(&m.try_to_vec().unwrap()));
+        let mut other = m.clone();
+        other.expires_at = Some(5);
+        assert_ne!(token_metadata_hash(&other), hash);
+
+        let bytes = VerTokenData::from(token_data()).try_to_vec().unwrap();
+        assert_eq!(bytes[0], 1);
+        let (t, h) = VerTokenData::try_from_slice(&bytes)
+            .unwrap()
+            .latest_with_hash();
+        assert_eq!((t.metadata.latest(), h), (m.clone(), hash));
+
+        // records without the hash and with the old metadata layout
+        let v1 = VerTokenData::V1(TokenData {
+            owner: AccountId::new_unchecked("alice.near".to_string()),
+            metadata: VerTokenMetadata::V1(TokenMetadataV1 {
+                class: 2,
+                issued_at: Some(1),
+                expires_at: None,
+                reference: None,
+                reference_hash: None,
+            }),
+        });
+        let v1 = VerTokenData::try_from_slice(&v1.try_to_vec().unwrap()).unwrap();
+        assert_eq!(v1.latest_with_hash().1, hash);
+    }
+
+    #[test]
+    fn ver_token_data_legacy_record() {
+        let legacy = token_data().try_to_vec().unwrap();
+        let t = VerTokenData::try_from_slice(&legacy).unwrap().latest();
+        assert_eq!(t.owner.as_str(), "alice.near");
+        assert_eq!(t.metadata.class_id(), 2);
+        assert_eq!(t.metadata.latest().issued_at, Some(1));
+    }
+
+    #[test]
+    fn token_metadata_validate() {
+        let mut m = token_data().metadata.latest();
+        assert_eq!(m.validate(), Ok(()));
+        m.reference = Some("a".repeat(MAX_REFERENCE_LEN));
+        assert_eq!(m.validate(), Err(InputError::ReferenceHashMissing));
+        m.reference_hash = Some(vec![1; 31].into());
+        assert_eq!(m.validate(), Err(InputError::InvalidReferenceHash));
+        m.reference_hash = Some(vec![1; 32].into());
+        assert_eq!(m.validate(), Ok(()));
+        m.reference = Some("a".repeat(MAX_REFERENCE_LEN + 1));
+        assert_eq!(
+            m.validate(),
+            Err(InputError::ReferenceTooLong {
+                max: MAX_REFERENCE_LEN
+            })
+        );
+        m.class = 0;
+        assert_eq!(m.validate(), Err(InputError::ZeroClass));
+        assert_eq!(
+            InputError::ZeroClass.to_string(),
+            "E01_ZERO_CLASS: class must be bigger than 0"
+        );
     }
 }
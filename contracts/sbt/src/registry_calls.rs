@@ -0,0 +1,226 @@
+//! Typed registry calls for issuer contracts: promise builders attaching the default gas and
+//! deposit of the registry methods, and parsers of the call results in callbacks, so issuers
+//! don't need to build the calls and decode the JSON results by hand.
+//!
+//! ```ignore
+//! registry_calls::sbt_mint(registry, vec![(recipient, vec![metadata])]).then(
+//!     Self::ext(env::current_account_id())
+//!         .with_static_gas(CALLBACK_GAS)
+//!         .on_mint(recipient),
+//! )
+//!
+//! #[private]
+//! pub fn on_mint(&mut self, recipient: AccountId) -> Option<TokenId> {
+//!     registry_calls::minted_tokens().ok().map(|t| t[0])
+//! }
+//! ```
+//!
+//! Use `ext_registry` directly to attach custom gas or deposit.
+
+use std::fmt;
+
+use cost::{renew_gas, MINT_COST, MINT_GAS};
+use near_sdk::serde::de::DeserializeOwned;
+use near_sdk::serde_json;
+use near_sdk::{env, AccountId, Balance, Gas, Promise, PromiseResult};
+
+use crate::{ext_registry, RevocationReason, TokenId, TokenMetadata};
+
+/// Default gas of the registry `sbt_mint` call minting `num_tokens` tokens.
+#[inline]
+pub fn mint_gas(num_tokens: usize) -> Gas {
+    Gas(MINT_GAS.0 * num_tokens.max(1) as u64)
+}
+
+/// Default deposit of the registry `sbt_mint` call minting `num_tokens` tokens. Covers the
+/// registry storage of tokens with a short (or without) `reference`, the unused part is
+/// refunded to the issuer.
+#[inline]
+pub fn mint_deposit(num_tokens: usize) -> Balance {
+    MINT_COST * num_tokens as Balance
+}
+
+/// Default gas of the registry `sbt_revoke` call revoking `num_tokens` tokens.
+#[inline]
+pub fn revoke_gas(num_tokens: usize) -> Gas {
+    mint_gas(num_tokens)
+}
+
+/// Calls the registry `sbt_mint` with the default gas and deposit (see `mint_gas` and
+/// `mint_deposit`). The promise resolves to the minted token IDs, see `minted_tokens`.
+#[inline]
+pub fn sbt_mint(registry: AccountId, token_spec: Vec<(AccountId, Vec<TokenMetadata>)>) -> Promise {
+    let num: usize = token_spec.iter().map(|(_, m)| m.len()).sum();
+    ext_registry::ext(registry)
+        .with_attached_deposit(mint_deposit(num))
+        .with_static_gas(mint_gas(num))
+        .sbt_mint(token_spec)
+}
+
+/// Calls the registry `sbt_renew` with the default gas (`cost::renew_gas`). `expires_at` is
+/// a unix timestamp in miliseconds.
+#[inline]
+pub fn sbt_renew(registry: AccountId, tokens: Vec<TokenId>, expires_at: u64) -> Promise {
+    ext_registry::ext(registry)
+        .with_static_gas(renew_gas(tokens.len()))
+        .sbt_renew(tokens, expires_at)
+}
+
+/// Calls the registry `sbt_revoke` with the default gas (see `revoke_gas`).
+#[inline]
+pub fn sbt_revoke(registry: AccountId, tokens: Vec<TokenId>, burn: bool) -> Promise {
+    ext_registry::ext(registry)
+        .with_static_gas(revoke_gas(tokens.len()))
+        .sbt_revoke(tokens, burn)
+}
+
+/// Calls the registry `sbt_revoke_with_reason` with the default gas (see `revoke_gas`).
+#[inline]
+pub fn sbt_revoke_with_reason(
+    registry: AccountId,
+    tokens: Vec<TokenId>,
+    burn: bool,
+    reason: RevocationReason,
+) -> Promise {
+    ext_registry::ext(registry)
+        .with_static_gas(revoke_gas(tokens.len()))
+        .sbt_revoke_with_reason(tokens, burn, reason)
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq, Clone))]
+pub enum RegistryCallError {
+    /// the callback doesn't have a single promise result.
+    NoResult,
+    /// the registry call failed (eg panicked or run out of gas).
+    Failed,
+    /// the call result is not a valid JSON of the expected type.
+    InvalidResult,
+}
+
+impl fmt::Display for RegistryCallError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegistryCallError::NoResult => write!(f, "registry call result not found"),
+            RegistryCallError::Failed => write!(f, "registry call failed"),
+            RegistryCallError::InvalidResult => write!(f, "invalid registry call result"),
+        }
+    }
+}
+
+/// Returns the result of the registry call in a callback. Must be called in a callback of a
+/// single promise.
+#[inline]
+pub fn call_result<T: DeserializeOwned>() -> Result<T, RegistryCallError> {
+    if env::promise_results_count() != 1 {
+        return Err(RegistryCallError::NoResult);
+    }
+    match env::promise_result(0) {
+        PromiseResult::Successful(v) => {
+            serde_json::from_slice(&v).map_err(|_| RegistryCallError::InvalidResult)
+        }
+        _ => Err(RegistryCallError::Failed),
+    }
+}
+
+/// Returns the token IDs minted by the registry `sbt_mint` call, in a callback.
+#[inline]
+pub fn minted_tokens() -> Result<Vec<TokenId>, RegistryCallError> {
+    call_result()
+}
+
+/// Returns `Ok` if the registry call without a result (eg `sbt_renew` or `sbt_revoke`)
+/// succeeded, in a callback.
+#[inline]
+pub fn call_succeeded() -> Result<(), RegistryCallError> {
+    if env::promise_results_count() != 1 {
+        return Err(RegistryCallError::NoResult);
+    }
+    match env::promise_result(0) {
+        PromiseResult::Successful(_) => Ok(()),
+        _ => Err(RegistryCallError::Failed),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::mock::VmAction;
+    use near_sdk::test_utils::{get_created_receipts, VMContextBuilder};
+    use near_sdk::{testing_env, RuntimeFeesConfig, VMConfig};
+
+    use super::*;
+
+    fn acc(name: &str) -> AccountId {
+        AccountId::new_unchecked(format!("{}.near", name))
+    }
+
+    fn mk_metadata(class: u64) -> TokenMetadata {
+        TokenMetadata {
+            class,
+            issued_at: None,
+            expires_at: None,
+            reference: None,
+            reference_hash: None,
+            attributes_root: None,
+        }
+    }
+
+    fn with_result(result: PromiseResult) {
+        testing_env!(
+            VMContextBuilder::new().build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![result]
+        );
+    }
+
+    #[test]
+    fn mint_defaults() {
+        testing_env!(VMContextBuilder::new().build());
+        sbt_mint(
+            acc("registry"),
+            vec![
+                (acc("alice"), vec![mk_metadata(1), mk_metadata(2)]),
+                (acc("bob"), vec![mk_metadata(1)]),
+            ],
+        );
+        let receipts = get_created_receipts();
+        assert_eq!(receipts.len(), 1);
+        assert_eq!(receipts[0].receiver_id, acc("registry"));
+        match &receipts[0].actions[0] {
+            VmAction::FunctionCall {
+                function_name,
+                gas,
+                deposit,
+                ..
+            } => {
+                assert_eq!(function_name, "sbt_mint");
+                assert_eq!(*gas, mint_gas(3));
+                assert_eq!(*deposit, 3 * MINT_COST);
+            }
+            a => panic!("unexpected action {:?}", a),
+        }
+        assert_eq!(mint_gas(0), MINT_GAS);
+    }
+
+    #[test]
+    fn results() {
+        with_result(PromiseResult::Successful(b"[3,4]".to_vec()));
+        assert_eq!(minted_tokens(), Ok(vec![3, 4]));
+        assert_eq!(call_succeeded(), Ok(()));
+
+        with_result(PromiseResult::Successful(b"null".to_vec()));
+        assert_eq!(minted_tokens(), Err(RegistryCallError::InvalidResult));
+        assert_eq!(call_succeeded(), Ok(()));
+
+        with_result(PromiseResult::Failed);
+        assert_eq!(minted_tokens(), Err(RegistryCallError::Failed));
+        assert_eq!(
+            call_succeeded().unwrap_err().to_string(),
+            "registry call failed"
+        );
+
+        testing_env!(VMContextBuilder::new().build());
+        assert_eq!(minted_tokens(), Err(RegistryCallError::NoResult));
+    }
+}
@@ -0,0 +1,54 @@
+use near_sdk::serde::{Deserialize, Serialize};
+
+/// NEP-330 standard name.
+pub const NEP330_STANDARD_NAME: &str = "nep330";
+/// NEP-330 version implemented by the contracts.
+pub const NEP330_VERSION: &str = "1.1.0";
+
+/// NEP-330 contract source metadata, returned by the `contract_source_metadata` view, so
+/// explorers can verify deployed code provenance.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq, Clone))]
+pub struct ContractSourceMetadata {
+    /// contract crate version
+    pub version: Option<String>,
+    /// link to the source code repository
+    pub link: Option<String>,
+    /// standards implemented by the contract
+    pub standards: Vec<Standard>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq, Clone))]
+pub struct Standard {
+    pub standard: String,
+    pub version: String,
+}
+
+impl Standard {
+    pub fn new(standard: &str, version: &str) -> Self {
+        Self {
+            standard: standard.to_owned(),
+            version: version.to_owned(),
+        }
+    }
+}
+
+/// Creates contract source metadata of the calling crate, populated at build time with the
+/// crate version and repository. `nep330` standard is always included.
+/// Example: `contract_source_metadata!(Standard::new(STANDARD_NAME, SPEC_VERSION))`.
+#[macro_export]
+macro_rules! contract_source_metadata {
+    ($($standard:expr),* $(,)?) => {
+        $crate::ContractSourceMetadata {
+            version: Some(env!("CARGO_PKG_VERSION").to_owned()),
+            link: Some(env!("CARGO_PKG_REPOSITORY").to_owned()),
+            standards: vec![
+                $crate::Standard::new($crate::NEP330_STANDARD_NAME, $crate::NEP330_VERSION),
+                $($standard),*
+            ],
+        }
+    };
+}
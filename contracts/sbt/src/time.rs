@@ -0,0 +1,138 @@
+//! Time units. Token `issued_at` and `expires_at` (and the registry timestamps) are unix
+//! timestamps in miliseconds, while `env::block_timestamp()` is in nanoseconds and frontends
+//! usually display durations in seconds. `MilliSeconds` and `NanoSeconds` wrap the raw
+//! timestamps, so the expiry checks can't compare values of different units: conversions are
+//! explicit (`From`, `to_ms`, `to_ns`).
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::env;
+#[cfg(feature = "json")]
+use near_sdk::serde::{Deserialize, Serialize};
+
+/// 1ms in nano seconds.
+pub const MILI_SECOND_NS: u64 = 1_000_000;
+
+/// Unix timestamp (or duration) in nanoseconds.
+#[derive(
+    BorshSerialize, BorshDeserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default,
+)]
+#[cfg_attr(
+    feature = "json",
+    derive(Serialize, Deserialize),
+    serde(crate = "near_sdk::serde", transparent)
+)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub struct NanoSeconds(pub u64);
+
+/// Unix timestamp (or duration) in miliseconds.
+#[derive(
+    BorshSerialize, BorshDeserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default,
+)]
+#[cfg_attr(
+    feature = "json",
+    derive(Serialize, Deserialize),
+    serde(crate = "near_sdk::serde", transparent)
+)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub struct MilliSeconds(pub u64);
+
+impl NanoSeconds {
+    /// Current block timestamp.
+    pub fn now() -> Self {
+        Self(env::block_timestamp())
+    }
+
+    /// Converts to miliseconds, rounding down.
+    pub fn to_ms(self) -> MilliSeconds {
+        MilliSeconds(self.0 / MILI_SECOND_NS)
+    }
+}
+
+impl MilliSeconds {
+    /// Current block timestamp, rounded down to miliseconds.
+    pub fn now() -> Self {
+        NanoSeconds::now().to_ms()
+    }
+
+    /// Converts to nanoseconds, saturating at `u64::MAX`.
+    pub fn to_ns(self) -> NanoSeconds {
+        NanoSeconds(self.0.saturating_mul(MILI_SECOND_NS))
+    }
+
+    /// Returns the timestamp moved by `ms` miliseconds, saturating at `u64::MAX`.
+    pub fn add_ms(self, ms: u64) -> Self {
+        Self(self.0.saturating_add(ms))
+    }
+}
+
+impl From<NanoSeconds> for MilliSeconds {
+    fn from(ns: NanoSeconds) -> Self {
+        ns.to_ms()
+    }
+}
+
+impl From<MilliSeconds> for NanoSeconds {
+    fn from(ms: MilliSeconds) -> Self {
+        ms.to_ns()
+    }
+}
+
+/// Converts a timestamp in nanoseconds (eg `env::block_timestamp()`) to miliseconds.
+pub fn ns_to_ms(ns: u64) -> u64 {
+    NanoSeconds(ns).to_ms().0
+}
+
+/// Converts a timestamp in miliseconds to nanoseconds, saturating at `u64::MAX`.
+pub fn ms_to_ns(ms: u64) -> u64 {
+    MilliSeconds(ms).to_ns().0
+}
+
+/// Returns the remaining validity in seconds (rounded down) of a token expiring at
+/// `expires_at`, evaluated at `now`. Returns 0 if the token is expired.
+pub fn expires_in(expires_at: MilliSeconds, now: MilliSeconds) -> u64 {
+    expires_at.0.saturating_sub(now.0) / 1000
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversions() {
+        assert_eq!(ns_to_ms(1_999_999), 1);
+        assert_eq!(ms_to_ns(2), 2_000_000);
+        assert_eq!(ms_to_ns(u64::MAX), u64::MAX);
+        assert_eq!(ns_to_ms(ms_to_ns(1_700_000_000_000)), 1_700_000_000_000);
+
+        assert_eq!(MilliSeconds::from(NanoSeconds(2_500_000)), MilliSeconds(2));
+        assert_eq!(NanoSeconds::from(MilliSeconds(3)), NanoSeconds(3_000_000));
+        assert_eq!(MilliSeconds(u64::MAX).to_ns(), NanoSeconds(u64::MAX));
+        assert_eq!(MilliSeconds(u64::MAX - 1).add_ms(5), MilliSeconds(u64::MAX));
+        assert!(NanoSeconds(999_999).to_ms() < MilliSeconds(1));
+
+        let ms = |v| MilliSeconds(v);
+        assert_eq!(expires_in(ms(10_999), ms(1_000)), 9);
+        assert_eq!(expires_in(ms(1_000), ms(1_000)), 0);
+        assert_eq!(expires_in(ms(1_000), ms(5_000)), 0);
+    }
+
+    #[test]
+    fn now() {
+        near_sdk::testing_env!(near_sdk::test_utils::VMContextBuilder::new()
+            .block_timestamp(1_700_000_000_123_456_789)
+            .build());
+        assert_eq!(NanoSeconds::now(), NanoSeconds(1_700_000_000_123_456_789));
+        assert_eq!(MilliSeconds::now(), MilliSeconds(1_700_000_000_123));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn json() {
+        use near_sdk::serde_json;
+        assert_eq!(serde_json::to_string(&MilliSeconds(5)).unwrap(), "5");
+        assert_eq!(
+            serde_json::from_str::<NanoSeconds>("7").unwrap(),
+            NanoSeconds(7)
+        );
+    }
+}
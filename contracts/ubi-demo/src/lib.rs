@@ -0,0 +1,309 @@
+//! UBI demo: verified humans claim a fixed amount of NEAR once per epoch.
+//! Claims are recorded in the registry with `mark_used`, using the epoch as the context.
+//! The registry checks that the claimer is a human and keys the marker by the human proof
+//! token rather than the account, so a human can't claim twice in the same epoch, even
+//! using another account after a soul transfer.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::Serialize;
+use near_sdk::serde_json;
+use near_sdk::{
+    env, ext_contract, near_bindgen, require, AccountId, Balance, Gas, PanicOnDefault, Promise,
+    PromiseResult,
+};
+
+use cost::MARK_USED_COST;
+
+pub const MARK_USED_GAS: Gas = Gas(10 * Gas::ONE_TERA.0);
+pub const ON_MARK_USED_GAS: Gas = Gas(5 * Gas::ONE_TERA.0);
+
+#[near_bindgen]
+#[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
+pub struct Contract {
+    /// I Am Human registry verifying humans and recording claims.
+    pub registry: AccountId,
+    pub admin: AccountId,
+    /// amount of NEAR claimed by a human in a single epoch.
+    pub amount: Balance,
+    /// epoch duration in miliseconds.
+    pub epoch_ms: u64,
+    /// start of the first epoch (unix timestamp in miliseconds).
+    pub start_ms: u64,
+}
+
+#[derive(Serialize)]
+#[serde(crate = "near_sdk::serde")]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq))]
+pub struct Config {
+    pub registry: AccountId,
+    pub admin: AccountId,
+    pub amount: U128,
+    pub epoch_ms: u64,
+    pub start_ms: u64,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// The first epoch starts at the contract initialization.
+    #[init]
+    pub fn new(registry: AccountId, admin: AccountId, amount: U128, epoch_ms: u64) -> Self {
+        require!(epoch_ms > 0, "epoch_ms must be positive");
+        Self {
+            registry,
+            admin,
+            amount: amount.0,
+            epoch_ms,
+            start_ms: env::block_timestamp_ms(),
+        }
+    }
+
+    /**********
+     * QUERIES
+     **********/
+
+    pub fn config(&self) -> Config {
+        Config {
+            registry: self.registry.clone(),
+            admin: self.admin.clone(),
+            amount: self.amount.into(),
+            epoch_ms: self.epoch_ms,
+            start_ms: self.start_ms,
+        }
+    }
+
+    /// Returns the current epoch number, starting from 0.
+    pub fn current_epoch(&self) -> u64 {
+        (env::block_timestamp_ms() - self.start_ms) / self.epoch_ms
+    }
+
+    /// Returns the registry `mark_used` context of claims in the `epoch`. Use it with the
+    /// registry `is_used` to check if a human already claimed in the epoch.
+    pub fn epoch_context(&self, epoch: u64) -> String {
+        format!("epoch-{}", epoch)
+    }
+
+    /**********
+     * TRANSACTIONS
+     **********/
+
+    /// Claims the UBI of the current epoch. Calls the registry `mark_used` (which panics if
+    /// the caller is not a human) and sends the amount in the callback if the caller's human
+    /// didn't claim in the epoch yet. The callback returns the claimed amount (0 if rejected).
+    pub fn claim(&mut self) -> Promise {
+        require!(
+            self.available_balance() >= self.amount + MARK_USED_COST,
+            "UBI pool is empty"
+        );
+        let account = env::predecessor_account_id();
+        let epoch = self.current_epoch();
+        ext_registry::ext(self.registry.clone())
+            .with_attached_deposit(MARK_USED_COST)
+            .with_static_gas(MARK_USED_GAS)
+            .mark_used(account.clone(), self.epoch_context(epoch))
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(ON_MARK_USED_GAS)
+                    .on_mark_used(account, epoch),
+            )
+    }
+
+    #[private]
+    pub fn on_mark_used(&mut self, account: AccountId, epoch: u64) -> U128 {
+        let reason = match env::promise_result(0) {
+            PromiseResult::Successful(v) => match serde_json::from_slice::<bool>(&v) {
+                Ok(true) if self.available_balance() >= self.amount => {
+                    env::log_str(&format!(
+                        "{} claimed {} yNEAR in epoch {}",
+                        account, self.amount, epoch
+                    ));
+                    Promise::new(account).transfer(self.amount);
+                    return U128(self.amount);
+                }
+                Ok(true) => "UBI pool is empty",
+                _ => "already claimed",
+            },
+            _ => "not a human",
+        };
+        env::log_str(&format!(
+            "claim of {} in epoch {} rejected: {}",
+            account, epoch, reason
+        ));
+        U128(0)
+    }
+
+    /// Updates the amount claimed in an epoch. Must be called by the admin.
+    pub fn admin_set_amount(&mut self, amount: U128) {
+        require!(env::predecessor_account_id() == self.admin, "not an admin");
+        self.amount = amount.0;
+    }
+}
+
+impl Contract {
+    /// contract balance not locked for the storage.
+    fn available_balance(&self) -> Balance {
+        let storage = env::storage_usage() as Balance * env::storage_byte_cost();
+        env::account_balance().saturating_sub(storage)
+    }
+}
+
+#[ext_contract(ext_registry)]
+pub trait Registry {
+    fn mark_used(&mut self, account: AccountId, context: String) -> bool;
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::mock::VmAction;
+    use near_sdk::test_utils::{self, VMContextBuilder};
+    use near_sdk::{testing_env, RuntimeFeesConfig, VMConfig, VMContext, ONE_NEAR};
+
+    use super::*;
+
+    const AMOUNT: Balance = ONE_NEAR;
+    const EPOCH_MS: u64 = 1000;
+    const MS: u64 = 1_000_000;
+
+    fn registry() -> AccountId {
+        AccountId::new_unchecked("registry.near".to_string())
+    }
+
+    fn ubi() -> AccountId {
+        AccountId::new_unchecked("ubi.near".to_string())
+    }
+
+    fn admin() -> AccountId {
+        AccountId::new_unchecked("admin.near".to_string())
+    }
+
+    fn alice() -> AccountId {
+        AccountId::new_unchecked("alice.near".to_string())
+    }
+
+    fn setup(predecessor: &AccountId) -> (VMContext, Contract) {
+        let ctx = VMContextBuilder::new()
+            .current_account_id(ubi())
+            .predecessor_account_id(predecessor.clone())
+            .account_balance(10 * ONE_NEAR)
+            .block_timestamp(5 * MS)
+            .build();
+        testing_env!(ctx.clone());
+        let ctr = Contract::new(registry(), admin(), AMOUNT.into(), EPOCH_MS);
+        (ctx, ctr)
+    }
+
+    fn transfers() -> Vec<(AccountId, Balance)> {
+        test_utils::get_created_receipts()
+            .into_iter()
+            .flat_map(|r| {
+                r.actions.into_iter().filter_map(move |a| match a {
+                    VmAction::Transfer { deposit } => Some((r.receiver_id.clone(), deposit)),
+                    _ => None,
+                })
+            })
+            .collect()
+    }
+
+    /// sets the context of the callback receiving the `result`.
+    fn with_promise_result(ctx: &VMContext, result: PromiseResult) {
+        let mut ctx = ctx.clone();
+        ctx.predecessor_account_id = ubi();
+        testing_env!(
+            ctx,
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![result]
+        );
+    }
+
+    #[test]
+    fn current_epoch() {
+        let (mut ctx, ctr) = setup(&alice());
+        assert_eq!(ctr.config().start_ms, 5);
+        assert_eq!(ctr.current_epoch(), 0);
+        ctx.block_timestamp = (5 + EPOCH_MS - 1) * MS;
+        testing_env!(ctx.clone());
+        assert_eq!(ctr.current_epoch(), 0);
+        ctx.block_timestamp = (5 + 2 * EPOCH_MS) * MS;
+        testing_env!(ctx);
+        assert_eq!(ctr.current_epoch(), 2);
+        assert_eq!(ctr.epoch_context(2), "epoch-2");
+    }
+
+    #[test]
+    fn claim() {
+        let (mut ctx, mut ctr) = setup(&alice());
+        ctx.block_timestamp = (5 + 3 * EPOCH_MS) * MS;
+        testing_env!(ctx.clone());
+        ctr.claim();
+        let calls: Vec<(String, String, Balance)> = test_utils::get_created_receipts()
+            .into_iter()
+            .flat_map(|r| r.actions)
+            .filter_map(|a| match a {
+                VmAction::FunctionCall {
+                    function_name,
+                    args,
+                    deposit,
+                    ..
+                } => Some((function_name, String::from_utf8(args).unwrap(), deposit)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(calls[0].0, "mark_used");
+        assert_eq!(
+            calls[0].1,
+            r#"{"account":"alice.near","context":"epoch-3"}"#
+        );
+        assert_eq!(calls[0].2, MARK_USED_COST);
+        assert_eq!(calls[1].0, "on_mark_used");
+
+        with_promise_result(&ctx, PromiseResult::Successful(b"true".to_vec()));
+        assert_eq!(ctr.on_mark_used(alice(), 3), U128(AMOUNT));
+        assert_eq!(transfers(), vec![(alice(), AMOUNT)]);
+    }
+
+    #[test]
+    fn claim_rejected() {
+        let (ctx, mut ctr) = setup(&alice());
+        // the human already claimed in the epoch (eg from another account)
+        with_promise_result(&ctx, PromiseResult::Successful(b"false".to_vec()));
+        assert_eq!(ctr.on_mark_used(alice(), 0), U128(0));
+        assert_eq!(transfers(), vec![]);
+        assert_eq!(
+            test_utils::get_logs(),
+            vec!["claim of alice.near in epoch 0 rejected: already claimed"]
+        );
+
+        // mark_used panics if alice is not a human
+        with_promise_result(&ctx, PromiseResult::Failed);
+        assert_eq!(ctr.on_mark_used(alice(), 0), U128(0));
+        assert_eq!(
+            test_utils::get_logs(),
+            vec!["claim of alice.near in epoch 0 rejected: not a human"]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "UBI pool is empty")]
+    fn claim_empty() {
+        let (mut ctx, mut ctr) = setup(&alice());
+        ctx.account_balance = AMOUNT;
+        testing_env!(ctx);
+        ctr.claim();
+    }
+
+    #[test]
+    fn admin_set_amount() {
+        let (_, mut ctr) = setup(&admin());
+        ctr.admin_set_amount(U128(5));
+        assert_eq!(ctr.config().amount, U128(5));
+    }
+
+    #[test]
+    #[should_panic(expected = "not an admin")]
+    fn admin_set_amount_not_admin() {
+        let (_, mut ctr) = setup(&alice());
+        ctr.admin_set_amount(U128(5));
+    }
+}
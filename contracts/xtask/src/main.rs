@@ -0,0 +1,603 @@
+//! Development tasks for the workspace, run with `cargo xtask <task>`.
+//!
+//! * `build` - builds reproducible wasm files of the registry, the demo issuer and the human
+//!   faucet into `res/`,
+//!   and fails if a wasm file exceeds its size budget.
+//! * `deploy` - builds the contracts, deploys and initializes them on sandbox or testnet, and runs a smoke scenario (mint through the demo issuer, check
+//!   `is_human`, and get a drip from the human faucet through `is_human_call`).
+//!
+//! The contract calls are executed with the `near` CLI. Use `--dry-run` to only print them.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+
+use near_sdk::serde_json::{json, Value};
+use near_sdk::{Balance, Gas, ONE_NEAR};
+use sha2::{Digest, Sha256};
+
+use cost::{ISSUER_BOND, MINT_COST};
+use sbt::SPEC_VERSION;
+
+const WASM_TARGET: &str = "wasm32-unknown-unknown";
+/// workspace packages deployed by `xtask deploy`.
+const PACKAGES: [&str; 3] = ["registry", "demo-issuer", "human-faucet"];
+/// Max size (in bytes) of the release wasm of the deployed packages. The deployment cost
+/// and the per-call gas grow with the code size, so a budget should only be raised
+/// deliberately: by the change growing the code, with the measured size of the wasm and why
+/// the code can't be made smaller (eg optional modules behind a feature).
+const WASM_SIZE_BUDGETS: [(&str, usize); 3] = [
+    ("registry", 800_000),
+    ("demo-issuer", 120_000),
+    ("human-faucet", 115_000),
+];
+/// NEAR sent by a single faucet drip in the smoke scenario.
+const FAUCET_DRIP: Balance = ONE_NEAR / 10;
+const CALL_GAS: Gas = Gas(100 * Gas::ONE_TERA.0);
+
+const USAGE: &str = "usage: cargo xtask <build|deploy> [options]
+
+deploy options:
+  --network <sandbox|testnet>  network to deploy to (default: sandbox)
+  --master <account>           account creating the contract sub-accounts, and the registry
+                               authority (default on sandbox: test.near)
+  --node-url <url>             RPC (default on sandbox: http://127.0.0.1:3030)
+  --key-path <path>            master account key file (default on sandbox:
+                               /tmp/near-sandbox/validator_key.json)
+  --skip-build                 use the wasm files already in res/
+  --dry-run                    print the near CLI commands instead of running them";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Network {
+    Sandbox,
+    Testnet,
+}
+
+impl Network {
+    /// `NEAR_ENV` value used by the near CLI.
+    fn near_env(&self) -> &'static str {
+        match self {
+            Network::Sandbox => "local",
+            Network::Testnet => "testnet",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Config {
+    network: Network,
+    master: String,
+    node_url: Option<String>,
+    key_path: Option<String>,
+    skip_build: bool,
+    dry_run: bool,
+}
+
+impl Config {
+    fn registry(&self) -> String {
+        format!("registry.{}", self.master)
+    }
+
+    fn issuer(&self) -> String {
+        format!("demo.{}", self.master)
+    }
+
+    fn faucet(&self) -> String {
+        format!("faucet.{}", self.master)
+    }
+
+    fn parse(args: &[String]) -> Result<Config, String> {
+        let mut network = Network::Sandbox;
+        let mut master = None;
+        let mut node_url = None;
+        let mut key_path = None;
+        let mut skip_build = false;
+        let mut dry_run = false;
+        let mut it = args.iter();
+        while let Some(arg) = it.next() {
+            let mut value = || {
+                it.next()
+                    .cloned()
+                    .ok_or_else(|| format!("{} requires a value", arg))
+            };
+            match arg.as_str() {
+                "--network" => {
+                    network = match value()?.as_str() {
+                        "sandbox" => Network::Sandbox,
+                        "testnet" => Network::Testnet,
+                        n => return Err(format!("unknown network: {}", n)),
+                    }
+                }
+                "--master" => master = Some(value()?),
+                "--node-url" => node_url = Some(value()?),
+                "--key-path" => key_path = Some(value()?),
+                "--skip-build" => skip_build = true,
+                "--dry-run" => dry_run = true,
+                a => return Err(format!("unknown option: {}", a)),
+            }
+        }
+        let master = match (master, network) {
+            (Some(m), _) => m,
+            (None, Network::Sandbox) => "test.near".to_owned(),
+            (None, Network::Testnet) => return Err("--master is required on testnet".to_owned()),
+        };
+        if network == Network::Sandbox {
+            node_url.get_or_insert_with(|| "http://127.0.0.1:3030".to_owned());
+            key_path.get_or_insert_with(|| "/tmp/near-sandbox/validator_key.json".to_owned());
+        }
+        Ok(Config {
+            network,
+            master,
+            node_url,
+            key_path,
+            skip_build,
+            dry_run,
+        })
+    }
+}
+
+/// A single near CLI command of the deployment.
+#[derive(Debug, PartialEq)]
+enum Step {
+    CreateAccount {
+        account: String,
+        master: String,
+        balance_near: u32,
+    },
+    Deploy {
+        account: String,
+        wasm: String,
+        init_function: &'static str,
+        init_args: Value,
+    },
+    Call {
+        contract: String,
+        method: &'static str,
+        args: Value,
+        signer: String,
+        deposit: Balance,
+    },
+    /// view call, the last line of the output must be equal to `expect`.
+    View {
+        contract: String,
+        method: &'static str,
+        args: Value,
+        expect: &'static str,
+    },
+}
+
+impl Step {
+    fn args(&self) -> Vec<String> {
+        let s = |x: &str| x.to_owned();
+        match self {
+            Step::CreateAccount {
+                account,
+                master,
+                balance_near,
+            } => vec![
+                s("create-account"),
+                account.clone(),
+                s("--masterAccount"),
+                master.clone(),
+                s("--initialBalance"),
+                balance_near.to_string(),
+            ],
+            Step::Deploy {
+                account,
+                wasm,
+                init_function,
+                init_args,
+            } => vec![
+                s("deploy"),
+                account.clone(),
+                wasm.clone(),
+                s("--initFunction"),
+                s(init_function),
+                s("--initArgs"),
+                init_args.to_string(),
+            ],
+            Step::Call {
+                contract,
+                method,
+                args,
+                signer,
+                deposit,
+            } => vec![
+                s("call"),
+                contract.clone(),
+                s(method),
+                args.to_string(),
+                s("--accountId"),
+                signer.clone(),
+                s("--depositYocto"),
+                deposit.to_string(),
+                s("--gas"),
+                CALL_GAS.0.to_string(),
+            ],
+            Step::View {
+                contract,
+                method,
+                args,
+                ..
+            } => vec![s("view"), contract.clone(), s(method), args.to_string()],
+        }
+    }
+}
+
+/// Contract call sequence of the deployment followed by the smoke scenario.
+fn deploy_plan(cfg: &Config) -> Vec<Step> {
+    let registry = cfg.registry();
+    let issuer = cfg.issuer();
+    let faucet = cfg.faucet();
+    let wasm = |pkg: &str| format!("res/{}.wasm", pkg.replace('-', "_"));
+    vec![
+        Step::CreateAccount {
+            account: registry.clone(),
+            master: cfg.master.clone(),
+            balance_near: 10,
+        },
+        Step::CreateAccount {
+            account: issuer.clone(),
+            master: cfg.master.clone(),
+            balance_near: 5,
+        },
+        Step::Deploy {
+            account: registry.clone(),
+            wasm: wasm("registry"),
+            init_function: "new",
+            init_args: json!({
+                "authority": cfg.master,
+                "iah_issuer": issuer,
+                "iah_classes": [1],
+            }),
+        },
+        Step::Deploy {
+            account: issuer.clone(),
+            wasm: wasm("demo-issuer"),
+            init_function: "new",
+            init_args: json!({
+                "metadata": {
+                    "spec": SPEC_VERSION,
+                    "name": "I Am Human demo",
+                    "symbol": "IAH-DEMO",
+                },
+                "registry": registry,
+                "claim_ttl": 0,
+                "admin": cfg.master,
+            }),
+        },
+        Step::Call {
+            contract: registry.clone(),
+            method: "issuer_lock_bond",
+            args: json!({}),
+            signer: issuer.clone(),
+            deposit: ISSUER_BOND,
+        },
+        Step::Call {
+            contract: registry.clone(),
+            method: "admin_add_sbt_issuer",
+            args: json!({ "issuer": issuer }),
+            signer: cfg.master.clone(),
+            deposit: 0,
+        },
+        // smoke scenario
+        Step::View {
+            contract: registry.clone(),
+            method: "is_human",
+            args: json!({ "account": cfg.master }),
+            expect: "false",
+        },
+        Step::Call {
+            contract: issuer,
+            method: "sbt_mint",
+            args: json!({ "receiver": cfg.master }),
+            signer: cfg.master.clone(),
+            deposit: MINT_COST,
+        },
+        Step::View {
+            contract: registry.clone(),
+            method: "is_human",
+            args: json!({ "account": cfg.master }),
+            expect: "true",
+        },
+        // human gated call through the registry `is_human_call`
+        Step::CreateAccount {
+            account: faucet.clone(),
+            master: cfg.master.clone(),
+            balance_near: 5,
+        },
+        Step::Deploy {
+            account: faucet.clone(),
+            wasm: wasm("human-faucet"),
+            init_function: "new",
+            init_args: json!({
+                "registry": registry,
+                "admin": cfg.master,
+                "drip": FAUCET_DRIP.to_string(),
+                "cooldown_ms": 24 * 3600 * 1000,
+            }),
+        },
+        Step::Call {
+            contract: registry.clone(),
+            method: "admin_set_human_call_target",
+            args: json!({ "ctr": faucet, "policy": { "max_deposit": "0" } }),
+            signer: cfg.master.clone(),
+            deposit: 0,
+        },
+        Step::Call {
+            contract: registry,
+            method: "is_human_call",
+            args: json!({ "ctr": faucet, "function": "drip", "payload": "{}" }),
+            signer: cfg.master.clone(),
+            deposit: 0,
+        },
+        Step::View {
+            contract: faucet,
+            method: "can_drip",
+            args: json!({ "account": cfg.master }),
+            expect: "false",
+        },
+    ]
+}
+
+fn workspace_root() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .expect("xtask must be a workspace member")
+        .to_path_buf()
+}
+
+fn run(cmd: &mut Command) -> Result<String, String> {
+    let out = cmd
+        .output()
+        .map_err(|e| format!("can't run {:?}: {}", cmd, e))?;
+    if !out.status.success() {
+        return Err(format!(
+            "{:?} failed:\n{}{}",
+            cmd,
+            String::from_utf8_lossy(&out.stdout),
+            String::from_utf8_lossy(&out.stderr)
+        ));
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).into_owned())
+}
+
+/// Builds the deployed packages with the local paths stripped from the binary, and with the
+/// locked dependencies when `Cargo.lock` is present, so the same sources always produce the
+/// same wasm. Copies the wasm files to `res/` and prints their sha256. Fails if a wasm file
+/// exceeds its size budget.
+fn build() -> Result<(), String> {
+    let root = workspace_root();
+    let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".to_owned());
+    let mut rustflags = format!(
+        "-C link-arg=-s --remap-path-prefix={}=/workspace",
+        root.display()
+    );
+    if let Ok(home) = env::var("CARGO_HOME") {
+        rustflags += &format!(" --remap-path-prefix={}=/cargo", home);
+    }
+    let mut cmd = Command::new(cargo);
+    cmd.current_dir(&root).env("RUSTFLAGS", rustflags).args([
+        "build",
+        "--release",
+        "--target",
+        WASM_TARGET,
+    ]);
+    if root.join("Cargo.lock").exists() {
+        cmd.arg("--locked");
+    }
+    for pkg in PACKAGES {
+        cmd.args(["-p", pkg]);
+    }
+    run(&mut cmd)?;
+
+    let res = root.join("res");
+    fs::create_dir_all(&res).map_err(|e| e.to_string())?;
+    let mut over_budget = vec![];
+    for pkg in PACKAGES {
+        let name = format!("{}.wasm", pkg.replace('-', "_"));
+        let src = root
+            .join("target")
+            .join(WASM_TARGET)
+            .join("release")
+            .join(&name);
+        let code = fs::read(&src).map_err(|e| format!("can't read {}: {}", src.display(), e))?;
+        fs::write(res.join(&name), &code).map_err(|e| e.to_string())?;
+        let hash: String = Sha256::digest(&code)
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        println!("res/{}  {} bytes  sha256: {}", name, code.len(), hash);
+        if let Err(e) = check_wasm_size(pkg, code.len()) {
+            over_budget.push(e);
+        }
+    }
+    if !over_budget.is_empty() {
+        return Err(over_budget.join("\n"));
+    }
+    Ok(())
+}
+
+/// Returns an error if the `pkg` wasm `size` exceeds its budget.
+fn check_wasm_size(pkg: &str, size: usize) -> Result<(), String> {
+    let budget = WASM_SIZE_BUDGETS
+        .iter()
+        .find(|(p, _)| *p == pkg)
+        .map(|(_, b)| *b)
+        .ok_or_else(|| format!("no wasm size budget for {}", pkg))?;
+    if size > budget {
+        return Err(format!(
+            "{} wasm has {} bytes, exceeds the {} bytes budget",
+            pkg, size, budget
+        ));
+    }
+    Ok(())
+}
+
+fn deploy(cfg: &Config) -> Result<(), String> {
+    if !cfg.skip_build && !cfg.dry_run {
+        build()?;
+    }
+    let mut options = vec![];
+    if let Some(url) = &cfg.node_url {
+        options.extend(["--nodeUrl".to_owned(), url.clone()]);
+    }
+    if let Some(path) = &cfg.key_path {
+        options.extend(["--keyPath".to_owned(), path.clone()]);
+    }
+    for step in deploy_plan(cfg) {
+        let mut args = step.args();
+        args.extend(options.iter().cloned());
+        if cfg.dry_run {
+            let args: Vec<String> = args.iter().map(|a| format!("'{}'", a)).collect();
+            println!(
+                "NEAR_ENV={} near {}",
+                cfg.network.near_env(),
+                args.join(" ")
+            );
+            continue;
+        }
+        println!("> near {}", args.join(" "));
+        let out = run(Command::new("near")
+            .current_dir(workspace_root())
+            .env("NEAR_ENV", cfg.network.near_env())
+            .args(&args))?;
+        if let Step::View { expect, .. } = step {
+            let got = out.lines().rev().find(|l| !l.trim().is_empty());
+            if got.map(str::trim) != Some(expect) {
+                return Err(format!("smoke test: expected {}, got {:?}", expect, got));
+            }
+        }
+    }
+    if !cfg.dry_run {
+        println!(
+            "registry: {}, demo issuer: {}, faucet: {}, total deposit: {} NEAR",
+            cfg.registry(),
+            cfg.issuer(),
+            cfg.faucet(),
+            (20 * ONE_NEAR + MINT_COST) as f64 / ONE_NEAR as f64
+        );
+    }
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let res = match args.first().map(String::as_str) {
+        Some("build") => build(),
+        Some("deploy") => Config::parse(&args[1..]).and_then(|cfg| deploy(&cfg)),
+        _ => Err(USAGE.to_owned()),
+    };
+    if let Err(e) = res {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(a: &[&str]) -> Vec<String> {
+        a.iter().map(|x| x.to_string()).collect()
+    }
+
+    #[test]
+    fn parse_config() {
+        let cfg = Config::parse(&[]).unwrap();
+        assert_eq!(cfg.network, Network::Sandbox);
+        assert_eq!(cfg.master, "test.near");
+        assert!(cfg.node_url.is_some());
+
+        let cfg =
+            Config::parse(&args(&["--network", "testnet", "--master", "me.testnet"])).unwrap();
+        assert_eq!(cfg.network, Network::Testnet);
+        assert_eq!(cfg.registry(), "registry.me.testnet");
+        assert_eq!(cfg.node_url, None);
+
+        assert!(Config::parse(&args(&["--network", "testnet"])).is_err());
+        assert!(Config::parse(&args(&["--network", "mainnet"])).is_err());
+        assert!(Config::parse(&args(&["--master"])).is_err());
+    }
+
+    #[test]
+    fn wasm_size_budget() {
+        for pkg in PACKAGES {
+            assert_eq!(check_wasm_size(pkg, 100_000), Ok(()));
+        }
+        assert_eq!(check_wasm_size("registry", 800_000), Ok(()));
+        assert_eq!(
+            check_wasm_size("registry", 800_001),
+            Err("registry wasm has 800001 bytes, exceeds the 800000 bytes budget".to_owned())
+        );
+        assert!(check_wasm_size("ubi", 1).is_err());
+    }
+
+    #[test]
+    fn plan() {
+        let cfg = Config::parse(&args(&["--master", "alice.near"])).unwrap();
+        let plan = deploy_plan(&cfg);
+        assert_eq!(plan.len(), 14);
+        assert_eq!(
+            plan[2].args()[..4],
+            args(&[
+                "deploy",
+                "registry.alice.near",
+                "res/registry.wasm",
+                "--initFunction"
+            ])
+        );
+        assert_eq!(
+            plan[3].args()[2],
+            "res/demo_issuer.wasm",
+            "wasm file name uses underscores"
+        );
+        assert_eq!(
+            plan[4],
+            Step::Call {
+                contract: "registry.alice.near".to_owned(),
+                method: "issuer_lock_bond",
+                args: json!({}),
+                signer: "demo.alice.near".to_owned(),
+                deposit: ISSUER_BOND,
+            }
+        );
+        assert_eq!(
+            plan[7],
+            Step::Call {
+                contract: "demo.alice.near".to_owned(),
+                method: "sbt_mint",
+                args: json!({"receiver": "alice.near"}),
+                signer: "alice.near".to_owned(),
+                deposit: MINT_COST,
+            }
+        );
+        assert!(matches!(plan[8], Step::View { expect: "true", .. }));
+        assert_eq!(
+            plan[11].args()[..4],
+            args(&[
+                "call",
+                "registry.alice.near",
+                "admin_set_human_call_target",
+                r#"{"ctr":"faucet.alice.near","policy":{"max_deposit":"0"}}"#
+            ])
+        );
+        assert_eq!(
+            plan[12].args()[..4],
+            args(&[
+                "call",
+                "registry.alice.near",
+                "is_human_call",
+                r#"{"ctr":"faucet.alice.near","function":"drip","payload":"{}"}"#
+            ])
+        );
+        assert!(matches!(
+            plan[13],
+            Step::View {
+                expect: "false",
+                ..
+            }
+        ));
+    }
+}